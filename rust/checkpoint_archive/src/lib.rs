@@ -0,0 +1,101 @@
+/**
+ * Module     : lib.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+//! Archive of the older vote checkpoints of the governance token, which
+//! reads them back here for timestamps before the checkpoints it keeps.
+
+use candid::{candid_method, export_service, Nat};
+use governance_types::{min_prior_votes, prior_votes, CheckPoint};
+use ic_cdk_macros::*;
+use ic_kit::{ic, Principal};
+use std::collections::HashMap;
+
+/// governance token allowed to append checkpoints
+struct Token(Principal);
+
+impl Default for Token {
+    fn default() -> Self {
+        Token(Principal::anonymous())
+    }
+}
+
+type CheckPoints = HashMap<Principal, Vec<CheckPoint>>;
+
+#[init]
+#[candid_method(init)]
+fn init(token: Principal) {
+    ic::get_mut::<Token>().0 = token;
+}
+
+/// append checkpoints of `who` older than the ones the token keeps, in time order
+#[update(name = "appendCheckPoints")]
+#[candid_method(update, rename = "appendCheckPoints")]
+fn append_check_points(who: Principal, check_points: Vec<CheckPoint>) {
+    assert_eq!(ic::caller(), ic::get::<Token>().0);
+    let archived = ic::get_mut::<CheckPoints>().entry(who).or_default();
+    // a batch retried after a lost reply overlaps the checkpoints archived already
+    let last = archived.last().map(|cp| cp.timestamp.clone());
+    archived.extend(check_points.into_iter().filter(|cp| last.as_ref().map_or(true, |t| cp.timestamp > *t)));
+}
+
+#[query(name = "getPriorVotes")]
+#[candid_method(query, rename = "getPriorVotes")]
+fn get_prior_votes(who: Principal, timestamp: Nat) -> Nat {
+    match ic::get::<CheckPoints>().get(&who) {
+        Some(check_points) => prior_votes(check_points, &timestamp),
+        None => Nat::from(0),
+    }
+}
+
+/// lowest votes held at any time between from and to within the archived checkpoints
+#[query(name = "getMinPriorVotes")]
+#[candid_method(query, rename = "getMinPriorVotes")]
+fn get_min_prior_votes(who: Principal, from: Nat, to: Nat) -> Nat {
+    match ic::get::<CheckPoints>().get(&who) {
+        Some(check_points) => min_prior_votes(check_points, &from, &to),
+        None => Nat::from(0),
+    }
+}
+
+/// archived checkpoints of `who` from the last one at or before from up to to
+#[query(name = "getCheckPoints")]
+#[candid_method(query, rename = "getCheckPoints")]
+fn get_check_points(who: Principal, from: Nat, to: Nat) -> Vec<CheckPoint> {
+    match ic::get::<CheckPoints>().get(&who) {
+        Some(check_points) => {
+            let start = check_points.iter().rposition(|cp| cp.timestamp <= from).unwrap_or(0);
+            check_points[start..].iter().take_while(|cp| cp.timestamp <= to).cloned().collect()
+        }
+        None => vec![],
+    }
+}
+
+/// number of checkpoints archived for `who`
+#[query(name = "getArchivedCount")]
+#[candid_method(query, rename = "getArchivedCount")]
+fn get_archived_count(who: Principal) -> usize {
+    ic::get::<CheckPoints>().get(&who).map_or(0, |check_points| check_points.len())
+}
+
+#[query(name = "__get_candid_interface_tmp_hack")]
+fn export_candid() -> String {
+    export_service!();
+    __export_service()
+}
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    ic::stable_store((ic::get::<Token>().0, ic::get::<CheckPoints>())).unwrap();
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    let (token_stored, checkpoints_stored): (Principal, CheckPoints) = ic::stable_restore().unwrap();
+    ic::get_mut::<Token>().0 = token_stored;
+    *ic::get_mut::<CheckPoints>() = checkpoints_stored;
+}
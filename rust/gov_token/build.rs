@@ -0,0 +1,20 @@
+use std::env;
+use std::process::Command;
+
+/// expose the git commit of the build as GIT_HASH, taken from the environment if set,
+/// e.g. for reproducible builds outside a checkout, "unknown" if neither is available
+fn main() {
+    let hash = env::var("GIT_HASH").ok().or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .map(|hash| hash.trim().to_string())
+    });
+    println!("cargo:rustc-env=GIT_HASH={}", hash.unwrap_or_else(|| "unknown".to_string()));
+    println!("cargo:rerun-if-env-changed=GIT_HASH");
+    println!("cargo:rerun-if-changed=../../.git/HEAD");
+    println!("cargo:rerun-if-changed=../../.git/refs");
+}
@@ -5,26 +5,27 @@
 * Maintainer : DFinance Team <hello@dfinance.ai>
 * Stability  : Experimental
 */
-use candid::{candid_method, CandidType, Deserialize, Int, Nat, export_service};
-use cap_sdk::{handshake, insert, Event, IndefiniteEvent, IndefiniteEventBuilder, DetailsBuilder, TypedEvent, CapEnv};
+use candid::{candid_method, decode_args, encode_args, CandidType, Deserialize, Int, Nat, export_service};
+use cap_sdk::{handshake, insert, DetailValue, Event, IndefiniteEvent, IndefiniteEventBuilder, DetailsBuilder, TypedEvent, CapEnv};
 use cap_std::dip20::cap::DIP20Details;
 use cap_std::dip20::{Operation, TransactionStatus, TxRecord};
+use governance_types::{min_prior_votes, prior_votes, ramped_votes, BalanceChange, BalanceChangeKind, CheckPoint, TxError, TxReceipt};
+use ic_cdk::api::call::{call, CallResult};
 use ic_cdk_macros::*;
 use ic_kit::{ic, Principal};
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::collections::VecDeque;
 use std::convert::Into;
+use std::ops::Bound;
 use std::string::String;
 
-#[derive(CandidType, Default, Deserialize)]
+#[derive(CandidType, Default, Deserialize, Clone)]
 pub struct TxLog {
     pub ie_records: VecDeque<IndefiniteEvent>,
 }
 
-pub fn tx_log<'a>() -> &'a mut TxLog {
-    ic_kit::ic::get_mut::<TxLog>()
-}
-
 #[allow(non_snake_case)]
 #[derive(Deserialize, CandidType, Clone, Debug)]
 struct Metadata {
@@ -63,6 +64,193 @@ struct TokenInfo {
     cycles: u64,
 }
 
+#[allow(non_snake_case)]
+#[derive(Deserialize, CandidType, Clone, Debug)]
+struct HealthStatus {
+    cycles: u64,
+    heapMemorySize: u64,
+    stableMemorySize: u64,
+    // cap events waiting to be retried
+    pendingEvents: usize,
+    // 0 if never upgraded
+    lastUpgrade: u64,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, CandidType, Clone, Debug)]
+struct VersionInfo {
+    version: String,
+    gitHash: String,
+    // hex encoded sha256 of the candid interface
+    candidHash: String,
+    // times of the upgrades, oldest first
+    upgrades: Vec<u64>,
+}
+
+/// chunk of a backup of the balances, allowances, delegates and checkpoints
+#[derive(CandidType, Clone, Debug)]
+struct BackupChunk {
+    data: Vec<u8>,
+    /// number of chunks of the backup
+    chunks: usize,
+}
+
+/// admin duties which can be handed to principals other than the owner
+#[derive(Deserialize, CandidType, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Role {
+    Minter,
+    FeeManager,
+    Pauser,
+    MetadataManager,
+}
+
+const ROLES: [Role; 4] = [Role::Minter, Role::FeeManager, Role::Pauser, Role::MetadataManager];
+
+/// holder of each assigned role, the owner holds the unassigned ones
+#[derive(CandidType, Default, Deserialize, Clone)]
+struct Roles(HashMap<Role, Principal>);
+
+/// admin mutation, also inserted into CAP
+#[derive(Deserialize, CandidType, Clone, Debug)]
+struct AdminRecord {
+    caller: Principal,
+    operation: String,
+    details: Vec<(String, DetailValue)>,
+    timestamp: u64,
+}
+
+#[derive(CandidType, Default, Deserialize, Clone)]
+struct AdminLog(Vec<AdminRecord>);
+
+/// transactions sent and received by a principal, mints count as received and burns as sent
+#[allow(non_snake_case)]
+#[derive(Deserialize, CandidType, Clone, Debug, Default)]
+struct UserStats {
+    sentCount: u64,
+    receivedCount: u64,
+    sentVolume: Nat,
+    receivedVolume: Nat,
+}
+
+#[derive(CandidType, Default, Deserialize, Clone)]
+struct UserStatsMap(HashMap<Principal, UserStats>);
+
+/// holder count and delegated supply at the end of a day, or at the latest change for the current day
+#[allow(non_snake_case)]
+#[derive(Deserialize, CandidType, Clone, Debug)]
+struct HolderSnapshot {
+    day: u64,
+    holders: u64,
+    delegatedSupply: Nat,
+}
+
+/// daily snapshots of the last HOLDER_HISTORY_DAYS days with changes, oldest first
+#[derive(CandidType, Default, Deserialize, Clone)]
+struct HolderHistory(VecDeque<HolderSnapshot>);
+
+/// current holder count and delegated supply, rebuilt from the balances and checkpoints on upgrade
+#[derive(Default)]
+struct HolderTotals {
+    holders: u64,
+    delegated: Nat,
+}
+
+/// launch-phase transfer limits, 0 disables a limit, exempt accounts may send and receive freely
+#[allow(non_snake_case)]
+#[derive(Deserialize, CandidType, Clone, Debug, Default)]
+struct TransferLimits {
+    perTransaction: Nat,
+    perDay: Nat,
+    exempt: BTreeSet<Principal>,
+}
+
+/// account paying the fee of a transfer
+#[derive(Deserialize, CandidType, Clone, Copy, Debug, PartialEq)]
+enum FeePayer {
+    Sender,
+    /// the recipient, out of the value received
+    Recipient,
+    /// a sponsor who approved the caller for at least the fee
+    Sponsor(Principal),
+}
+
+/// day index and amount sent that day by each account, while a daily limit is set
+#[derive(CandidType, Default, Deserialize, Clone)]
+struct DailyTransfers(HashMap<Principal, (u64, Nat)>);
+
+/// canister notified of the balance changes of its own account, or of every account if global
+#[derive(Deserialize, CandidType, Clone, Debug)]
+struct Listener {
+    canister: Principal,
+    method: String,
+    global: bool,
+}
+
+#[derive(CandidType, Default, Deserialize, Clone)]
+struct Listeners(Vec<Listener>);
+
+/// cycles accepted by the token canister
+#[derive(Deserialize, CandidType, Clone, Debug)]
+struct CyclesDeposit {
+    from: Principal,
+    amount: u64,
+    timestamp: u64,
+}
+
+#[allow(non_snake_case)]
+#[derive(CandidType, Default, Deserialize, Clone)]
+struct CyclesDeposits {
+    deposits: Vec<CyclesDeposit>,
+    totalReceived: Nat,
+}
+
+/// scale of the dividends per token accumulator
+const DIVIDEND_SCALE: u64 = 1_000_000_000_000_000_000;
+
+/// pull-based dividends, deposits held by the token canister are split pro-rata among holders
+#[derive(CandidType, Default, Deserialize, Clone)]
+struct Dividends {
+    /// dividends per token accumulated since the first deposit, scaled by DIVIDEND_SCALE
+    per_share: Nat,
+    /// accumulator value each holder was last settled at
+    settled_per_share: HashMap<Principal, Nat>,
+    /// dividends settled but not claimed yet
+    owed: HashMap<Principal, Nat>,
+    total_deposited: Nat,
+    total_claimed: Nat,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, CandidType, Clone, Debug)]
+struct DividendInfo {
+    perShare: Nat,
+    totalDeposited: Nat,
+    totalClaimed: Nat,
+}
+
+/// size of a wasm memory page in bytes
+#[cfg(target_arch = "wasm32")]
+const WASM_PAGE_SIZE: u64 = 65536;
+/// maximum legs of a batch mint
+const MAX_BATCH_MINT: usize = 1000;
+/// maximum approvals returned by one page
+const MAX_APPROVALS_PAGE: usize = 1000;
+/// maximum listeners notified of balance changes
+const MAX_LISTENERS: usize = 20;
+/// queued CAP events inserted by one call, one flush runs at a time so it also inserts the events
+/// of calls made meanwhile
+const MAX_FLUSH_RECORDS: usize = 8;
+/// a flush running for longer is taken as trapped and no longer blocks the next one, 10 minutes
+const FLUSH_TIMEOUT: u64 = 600_000_000_000;
+/// days of holder snapshots kept
+const HOLDER_HISTORY_DAYS: usize = 365;
+/// maximum bytes of one backup chunk, 1 MiB
+const MAX_BACKUP_CHUNK: usize = 1 << 20;
+/// maximum pairs returned by one snapshot export chunk
+const MAX_EXPORT_CHUNK: usize = 1000;
+/// length of the window of the daily transfer limit, in nanoseconds
+const ONE_DAY: u64 = 24 * 3600 * 1_000_000_000;
+
 impl Default for StatsData {
     fn default() -> Self {
         StatsData {
@@ -83,28 +271,70 @@ impl Default for StatsData {
 type Balances = HashMap<Principal, Nat>;
 type Allowances = HashMap<Principal, HashMap<Principal, Nat>>;
 
-#[derive(CandidType, Debug, PartialEq)]
-pub enum TxError {
-    InsufficientBalance,
-    InsufficientAllowance,
-    Unauthorized,
-    LedgerTrap,
-    AmountTooSmall,
-    BlockUsed,
-    ErrorOperationStyle,
-    ErrorTo,
-    Other,
-}
-pub type TxReceipt = Result<Nat, TxError>;
-
-#[derive(Deserialize, CandidType, Debug, PartialEq)]
-struct CheckPoint {
-    timestamp: Nat,
-    votes: Nat,
-}
+
+/// number of holders by balance, rebuilt from the balances on upgrade
+#[derive(Default)]
+struct BalanceIndex(BTreeMap<Nat, usize>);
+
+/// delegatees ordered by current votes, rebuilt from the checkpoints on upgrade
+#[derive(Default)]
+struct DelegateeRanking(BTreeSet<(Nat, Principal)>);
+
 type Delegates = HashMap<Principal, Principal>;
 type CheckPoints = HashMap<Principal, Vec<CheckPoint>>;
 
+/// canister older checkpoints are moved to once a delegatee has more than the threshold
+#[derive(CandidType, Default, Deserialize, Clone)]
+struct CheckPointArchive {
+    canister: Option<Principal>,
+    /// checkpoints kept per delegatee before the older half is archived, 0 disables archiving
+    threshold: u64,
+    /// delegatees with checkpoints in the archive
+    archived: HashSet<Principal>,
+}
+
+/// delegatees whose checkpoints are being sent to the archive
+#[derive(Default)]
+struct ArchivingDelegatees(HashSet<Principal>);
+
+/// total supply from each checkpoint timestamp on, the votes field holds the supply
+#[derive(CandidType, Default, Deserialize, Clone)]
+struct SupplyCheckPoints(Vec<CheckPoint>);
+
+thread_local! {
+    static STATS : RefCell<StatsData> = RefCell::new(StatsData::default());
+    static BALANCES : RefCell<Balances> = RefCell::new(Balances::default());
+    static ALLOWANCES : RefCell<Allowances> = RefCell::new(Allowances::default());
+    static DELEGATES : RefCell<Delegates> = RefCell::new(Delegates::default());
+    static CHECKPOINTS : RefCell<CheckPoints> = RefCell::new(CheckPoints::default());
+    static TX_LOG : RefCell<TxLog> = RefCell::new(TxLog::default());
+    // start of the running flush of CAP events, 0 if none
+    static FLUSH_STARTED : Cell<u64> = Cell::new(0);
+    static DIVIDENDS : RefCell<Dividends> = RefCell::new(Dividends::default());
+    /// transfers are disabled while set, mint, burn and delegate still work
+    static SOULBOUND : Cell<bool> = Cell::new(false);
+    /// transfers and approvals are halted while set
+    static PAUSED : Cell<bool> = Cell::new(false);
+    static ROLE_HOLDERS : RefCell<Roles> = RefCell::new(Roles::default());
+    static ADMIN_LOG : RefCell<AdminLog> = RefCell::new(AdminLog::default());
+    static USER_STATS : RefCell<UserStatsMap> = RefCell::new(UserStatsMap::default());
+    static CYCLES_DEPOSITS : RefCell<CyclesDeposits> = RefCell::new(CyclesDeposits::default());
+    static TRANSFER_LIMITS : RefCell<TransferLimits> = RefCell::new(TransferLimits::default());
+    static DAILY_TRANSFERS : RefCell<DailyTransfers> = RefCell::new(DailyTransfers::default());
+    static LISTENERS : RefCell<Listeners> = RefCell::new(Listeners::default());
+    static SUPPLY_CHECKPOINTS : RefCell<SupplyCheckPoints> = RefCell::new(SupplyCheckPoints::default());
+    static HOLDER_HISTORY : RefCell<HolderHistory> = RefCell::new(HolderHistory::default());
+    static CHECKPOINT_ARCHIVE : RefCell<CheckPointArchive> = RefCell::new(CheckPointArchive::default());
+    static BALANCE_INDEX : RefCell<BalanceIndex> = RefCell::new(BalanceIndex::default());
+    static DELEGATEE_RANKING : RefCell<DelegateeRanking> = RefCell::new(DelegateeRanking::default());
+    static HOLDER_TOTALS : RefCell<HolderTotals> = RefCell::new(HolderTotals::default());
+    static ARCHIVING : RefCell<ArchivingDelegatees> = RefCell::new(ArchivingDelegatees::default());
+    /// chunks of a backup staged until it is restored
+    static RESTORE_BUFFER : RefCell<Vec<u8>> = RefCell::new(vec![]);
+    /// times of the upgrades, oldest first
+    static UPGRADES : RefCell<Vec<u64>> = RefCell::new(vec![]);
+}
+
 #[init]
 #[candid_method(init)]
 fn init(
@@ -118,62 +348,289 @@ fn init(
     fee_to: Principal,
     cap: Principal,
 ) {
-    let stats = ic::get_mut::<StatsData>();
-    stats.logo = logo;
-    stats.name = name;
-    stats.symbol = symbol;
-    stats.decimals = decimals;
-    stats.total_supply = total_supply.clone();
-    stats.owner = owner;
-    stats.fee = fee;
-    stats.fee_to = fee_to;
-    stats.history_size = 1;
-    stats.deploy_time = ic::time();
+    STATS.with(|s| {
+        let mut stats = s.borrow_mut();
+        stats.logo = logo;
+        stats.name = name;
+        stats.symbol = symbol;
+        stats.decimals = decimals;
+        stats.total_supply = total_supply.clone();
+        stats.owner = owner;
+        stats.fee = fee;
+        stats.fee_to = fee_to;
+        stats.history_size = 1;
+        stats.deploy_time = ic::time();
+    });
     handshake(1_000_000_000_000, Some(cap));
-    let balances = ic::get_mut::<Balances>();
-    balances.insert(owner, total_supply.clone());
-    let _ = add_record(
-        owner,
-        Operation::Mint,
-        owner,
-        owner,
-        total_supply,
-        Nat::from(0),
-        ic::time(),
-        TransactionStatus::Succeeded,
-    );
+    BALANCES.with(|b| b.borrow_mut().insert(owner, total_supply.clone()));
+    _index_balance(&Nat::from(0), &total_supply);
+    _count_tx(None, Some(&owner), &total_supply);
+    _write_supply_check_point();
+    // inserted by the first flush, init can't wait for CAP
+    let _ = _queue_record(owner, Operation::Mint, owner, owner, total_supply, Nat::from(0), ic::time());
 }
 
 fn _transfer(from: Principal, to: Principal, value: Nat) {
-    let balances = ic::get_mut::<Balances>();
+    _settle_dividend(from);
+    _settle_dividend(to);
     let from_balance = balance_of(from);
-    let from_balance_new = from_balance - value.clone();
-    if from_balance_new != 0 {
-        balances.insert(from, from_balance_new);
-    } else {
-        balances.remove(&from);
-    }
+    let from_balance_new = from_balance.clone() - value.clone();
+    _index_balance(&from_balance, &from_balance_new);
+    BALANCES.with(|b| {
+        let mut balances = b.borrow_mut();
+        if from_balance_new != 0 {
+            balances.insert(from, from_balance_new);
+        } else {
+            balances.remove(&from);
+        }
+    });
     let to_balance = balance_of(to);
-    let to_balance_new = to_balance + value;
+    let to_balance_new = to_balance.clone() + value;
+    _index_balance(&to_balance, &to_balance_new);
     if to_balance_new != 0 {
-        balances.insert(to, to_balance_new);
+        BALANCES.with(|b| b.borrow_mut().insert(to, to_balance_new));
+    }
+}
+
+/// credit the dividends accrued by `who` since its last settlement, before its balance changes
+fn _settle_dividend(who: Principal) {
+    // deposits held by the canister do not earn dividends
+    if who == ic::id() {
+        return;
+    }
+    let balance = balance_of(who);
+    DIVIDENDS.with(|d| {
+        let mut dividends = d.borrow_mut();
+        let per_share = dividends.per_share.clone();
+        let settled = dividends.settled_per_share.insert(who, per_share.clone()).unwrap_or_default();
+        let accrued = balance * (per_share - settled) / Nat::from(DIVIDEND_SCALE);
+        if accrued > 0u64 {
+            *dividends.owed.entry(who).or_default() += accrued;
+        }
+    });
+}
+
+fn _pending_dividend(who: Principal) -> Nat {
+    let balance = balance_of(who);
+    DIVIDENDS.with(|d| {
+        let dividends = d.borrow();
+        let settled = dividends.settled_per_share.get(&who).cloned().unwrap_or_default();
+        let owed = dividends.owed.get(&who).cloned().unwrap_or_default();
+        owed + balance * (dividends.per_share.clone() - settled) / Nat::from(DIVIDEND_SCALE)
+    })
+}
+
+/// move a holder from its old balance to the new one in the balance index
+fn _index_balance(old: &Nat, new: &Nat) {
+    BALANCE_INDEX.with(|i| {
+        let mut guard = i.borrow_mut();
+        let index = &mut guard.0;
+        if *old > 0u64 {
+            if let Some(count) = index.get_mut(old) {
+                *count -= 1;
+                if *count == 0 {
+                    index.remove(old);
+                }
+            }
+        }
+        if *new > 0u64 {
+            *index.entry(new.clone()).or_default() += 1;
+        }
+    });
+    HOLDER_TOTALS.with(|t| {
+        let mut totals = t.borrow_mut();
+        if *old > 0u64 {
+            totals.holders -= 1;
+        }
+        if *new > 0u64 {
+            totals.holders += 1;
+        }
+    });
+    _record_holder_snapshot();
+}
+
+/// update the snapshot of the current day with the current totals, dropping the oldest day once full
+fn _record_holder_snapshot() {
+    let snapshot = HOLDER_TOTALS.with(|t| {
+        let totals = t.borrow();
+        HolderSnapshot {
+            day: ic::time() / ONE_DAY,
+            holders: totals.holders,
+            delegatedSupply: totals.delegated.clone(),
+        }
+    });
+    HOLDER_HISTORY.with(|h| {
+        let mut guard = h.borrow_mut();
+        let history = &mut guard.0;
+        match history.back_mut() {
+            Some(last) if last.day == snapshot.day => { *last = snapshot; }
+            _ => {
+                if history.len() == HOLDER_HISTORY_DAYS {
+                    history.pop_front();
+                }
+                history.push_back(snapshot);
+            }
+        }
+    });
+}
+
+/// account a transaction in the stats of its sender and receiver
+fn _count_tx(from: Option<&Principal>, to: Option<&Principal>, value: &Nat) {
+    USER_STATS.with(|s| {
+        let mut stats = s.borrow_mut();
+        if let Some(from) = from {
+            let user = stats.0.entry(*from).or_default();
+            user.sentCount += 1;
+            user.sentVolume += value.clone();
+        }
+        if let Some(to) = to {
+            let user = stats.0.entry(*to).or_default();
+            user.receivedCount += 1;
+            user.receivedVolume += value.clone();
+        }
+    });
+}
+
+fn _role_holder(role: Role) -> Principal {
+    match ROLE_HOLDERS.with(|r| r.borrow().0.get(&role).cloned()) {
+        Some(holder) => holder,
+        None => STATS.with(|s| s.borrow().owner),
+    }
+}
+
+fn _has_role(role: Role, who: &Principal) -> bool {
+    _role_holder(role) == *who
+}
+
+/// check the transfer against the transfer limits and count it toward the sender's day
+fn _check_transfer_limits(from: Principal, to: Principal, value: &Nat) -> Result<(), TxError> {
+    let (exempt, per_transaction, per_day) = TRANSFER_LIMITS.with(|l| {
+        let limits = l.borrow();
+        (
+            limits.exempt.contains(&from) || limits.exempt.contains(&to),
+            limits.perTransaction.clone(),
+            limits.perDay.clone(),
+        )
+    });
+    if exempt {
+        return Ok(());
     }
+    if per_transaction != 0 && *value > per_transaction {
+        return Err(TxError::LimitExceeded);
+    }
+    if per_day == 0 {
+        return Ok(());
+    }
+    let day = ic::time() / ONE_DAY;
+    let sent = get_daily_transferred(from);
+    if sent.clone() + value.clone() > per_day {
+        return Err(TxError::LimitExceeded);
+    }
+    DAILY_TRANSFERS.with(|d| d.borrow_mut().0.insert(from, (day, sent + value.clone())));
+    Ok(())
+}
+
+fn _fee_payer(fee_payer: Option<FeePayer>, from: Principal, to: Principal) -> Principal {
+    match fee_payer {
+        None | Some(FeePayer::Sender) => from,
+        Some(FeePayer::Recipient) => to,
+        Some(FeePayer::Sponsor(sponsor)) => sponsor,
+    }
+}
+
+/// check a payer other than the sender can cover the fee, a sponsor through its allowance to the caller
+fn _check_fee_payer(payer: Principal, from: Principal, to: Principal, caller: Principal, value: &Nat, fee: &Nat) -> Result<(), TxError> {
+    if payer == from {
+        return Ok(());
+    }
+    if payer == to {
+        if balance_of(to) + value.clone() < *fee {
+            return Err(TxError::InsufficientBalance);
+        }
+        return Ok(());
+    }
+    if allowance(payer, caller) < *fee {
+        return Err(TxError::InsufficientAllowance);
+    }
+    if balance_of(payer) < *fee {
+        return Err(TxError::InsufficientBalance);
+    }
+    Ok(())
+}
+
+/// move the value, then charge the fee to the payer, votes included
+fn _transfer_with_fee(from: Principal, to: Principal, payer: Principal, value: Nat, fee_to: Principal, fee: Nat) {
+    _transfer(from, to, value.clone());
+    _charge_fee(payer, fee_to, fee.clone());
+    if payer == from {
+        _move_delegates(Some(&from), Some(&to), value, fee);
+    } else {
+        _move_delegates(Some(&from), Some(&to), value, Nat::from(0));
+        _move_delegates(Some(&payer), None, fee, Nat::from(0));
+    }
+}
+
+/// lower the allowance of the spender by the amount, removing it once spent
+fn _spend_allowance(owner: Principal, spender: Principal, amount: Nat) {
+    ALLOWANCES.with(|a| {
+        let mut allowances = a.borrow_mut();
+        if let Some(inner) = allowances.get_mut(&owner) {
+            let remaining = inner.get(&spender).cloned().unwrap_or_default() - amount;
+            if remaining != 0 {
+                inner.insert(spender, remaining);
+            } else {
+                inner.remove(&spender);
+                if inner.is_empty() {
+                    allowances.remove(&owner);
+                }
+            }
+        }
+    });
+}
+
+/// notify the listeners concerned of the balance change, without waiting for them
+fn _notify_listeners(kind: BalanceChangeKind, from: Option<Principal>, to: Option<Principal>, amount: &Nat, fee: &Nat) {
+    let change = BalanceChange {
+        kind,
+        from,
+        to,
+        amount: amount.clone(),
+        fee: fee.clone(),
+        timestamp: ic::time(),
+    };
+    for listener in get_listeners().iter() {
+        let concerned = Some(listener.canister);
+        if listener.global || from == concerned || to == concerned {
+            let _ = ic_cdk::api::call::notify(listener.canister, &listener.method, (change.clone(), ));
+        }
+    }
+}
+
+fn _mint(to: Principal, amount: Nat) {
+    _settle_dividend(to);
+    let to_balance = balance_of(to);
+    _index_balance(&to_balance, &(to_balance.clone() + amount.clone()));
+    BALANCES.with(|b| b.borrow_mut().insert(to, to_balance + amount.clone()));
+    _count_tx(None, Some(&to), &amount);
+    STATS.with(|s| {
+        let mut stats = s.borrow_mut();
+        stats.total_supply += amount;
+        stats.history_size += 1;
+    });
+    _write_supply_check_point();
 }
 
 fn _charge_fee(user: Principal, fee_to: Principal, fee: Nat) {
-    let stats = ic::get::<StatsData>();
-    if stats.fee > Nat::from(0) {
+    if STATS.with(|s| s.borrow().fee > Nat::from(0)) {
         _transfer(user, fee_to, fee);
     }
 }
 
 fn _delegate(delegator: Principal, delegatee: Principal) -> Nat {
-    let delegates = ic::get_mut::<Delegates>();
-    let current_delegate = ic::get::<Delegates>().get(&delegator);
+    let current_delegate = DELEGATES.with(|d| d.borrow_mut().insert(delegator, delegatee));
     let delegator_balance = balance_of(delegator);
 
-    delegates.insert(delegator, delegatee);
-    _move_delegates(current_delegate, Some(&delegatee), delegator_balance.clone(), Nat::from(0));
+    _move_delegates(current_delegate.as_ref(), Some(&delegatee), delegator_balance.clone(), Nat::from(0));
 
     delegator_balance
 }
@@ -195,25 +652,58 @@ fn _move_delegates(from: Option<&Principal>, to: Option<&Principal>, amount: Nat
 }
 
 fn _get_votes(who: &Principal) -> Nat {
-    let check_points = ic::get::<CheckPoints>();
-    match check_points.get(who) {
-        Some(check_point) => {
-            check_point.last().unwrap().votes.clone()
-        },
-        None => Nat::from(0)
-    }
+    CHECKPOINTS.with(|c| {
+        match c.borrow().get(who) {
+            Some(check_point) => {
+                check_point.last().unwrap().votes.clone()
+            },
+            None => Nat::from(0)
+        }
+    })
 }
 
 fn _write_check_point(who: &Principal, new_votes: Nat) {
-    let check_points = ic::get_mut::<CheckPoints>();
-    
-    let check_point = check_points.entry(who.to_owned()).or_insert(vec![]);
-    let timestamp = Nat::from(ic::time());
-    if !check_point.is_empty() && check_point.last().unwrap().timestamp == timestamp {
-        check_point.last_mut().unwrap().votes = new_votes;
-    } else {
-        check_point.push(CheckPoint {timestamp, votes: new_votes});
-    }
+    _rank_delegatee(who, &_get_votes(who), &new_votes);
+    CHECKPOINTS.with(|c| {
+        let mut check_points = c.borrow_mut();
+        let check_point = check_points.entry(who.to_owned()).or_insert(vec![]);
+        let timestamp = Nat::from(ic::time());
+        if !check_point.is_empty() && check_point.last().unwrap().timestamp == timestamp {
+            check_point.last_mut().unwrap().votes = new_votes;
+        } else {
+            check_point.push(CheckPoint {timestamp, votes: new_votes});
+        }
+    });
+}
+
+/// checkpoint the current total supply, merged with a checkpoint of the same timestamp
+fn _write_supply_check_point() {
+    let supply = total_supply();
+    SUPPLY_CHECKPOINTS.with(|c| {
+        let mut guard = c.borrow_mut();
+        let check_points = &mut guard.0;
+        let timestamp = Nat::from(ic::time());
+        match check_points.last_mut() {
+            Some(last) if last.timestamp == timestamp => last.votes = supply,
+            _ => check_points.push(CheckPoint {timestamp, votes: supply}),
+        }
+    });
+}
+
+/// move the delegatee from its old votes to the new ones in the ranking
+fn _rank_delegatee(who: &Principal, old_votes: &Nat, new_votes: &Nat) {
+    DELEGATEE_RANKING.with(|r| {
+        let mut ranking = r.borrow_mut();
+        ranking.0.remove(&(old_votes.clone(), *who));
+        if *new_votes > 0u64 {
+            ranking.0.insert((new_votes.clone(), *who));
+        }
+    });
+    HOLDER_TOTALS.with(|t| {
+        let mut totals = t.borrow_mut();
+        totals.delegated = totals.delegated.clone() + new_votes.clone() - old_votes.clone();
+    });
+    _record_holder_snapshot();
 }
 
 /// gets the current votes balance for `who`
@@ -223,28 +713,238 @@ fn get_current_votes(who: Principal) -> Nat {
     _get_votes(&who)
 }
 
-#[query(name = "getPriorVotes")]
-#[candid_method(query, rename = "getPriorVotes")]
-fn get_prior_votes(who: Principal, timestamp: Nat) -> Nat {
-    let check_points = ic::get::<CheckPoints>();
-    let account_check_points = match check_points.get(&who) {
-        Some(cp) => cp,
-        None => { return Nat::from(0); }
-    };
-    let current_check_point = account_check_points.last().unwrap();
-    if current_check_point.timestamp <= timestamp {
-        return current_check_point.votes.clone();
+/// votes of `who` at the timestamp, from the archive if older than the checkpoints kept here
+#[update(name = "getPriorVotes")]
+#[candid_method(update, rename = "getPriorVotes")]
+async fn get_prior_votes(who: Principal, timestamp: Nat) -> Nat {
+    match _archive_for(&who, &timestamp) {
+        Some(archive) => {
+            let result: CallResult<(Nat, )> = call(archive, "getPriorVotes", (who, timestamp, )).await;
+            match result {
+                Ok((votes, )) => votes,
+                Err(_) => ic_cdk::trap("checkpoint archive unavailable"),
+            }
+        }
+        None => _prior_votes(&who, &timestamp),
     }
-    let oldest_check_point = account_check_points.first().unwrap();
-    if oldest_check_point.timestamp > timestamp {
-        return oldest_check_point.votes.clone();
+}
+
+fn _prior_votes(who: &Principal, timestamp: &Nat) -> Nat {
+    CHECKPOINTS.with(|c| {
+        match c.borrow().get(who) {
+            Some(check_points) => prior_votes(check_points, timestamp),
+            None => Nat::from(0),
+        }
+    })
+}
+
+/// archive holding the checkpoints of `who` at the timestamp, none if kept here
+fn _archive_for(who: &Principal, timestamp: &Nat) -> Option<Principal> {
+    let (canister, archived) = CHECKPOINT_ARCHIVE.with(|a| {
+        let archive = a.borrow();
+        (archive.canister, archive.archived.contains(who))
+    });
+    if !archived {
+        return None;
+    }
+    let oldest_kept = CHECKPOINTS.with(|c| {
+        c.borrow().get(who).and_then(|check_points| check_points.first()).map(|cp| cp.timestamp.clone())
+    })?;
+    if oldest_kept <= *timestamp {
+        return None;
+    }
+    canister
+}
+
+/// total supply at the timestamp, none before the first supply checkpoint
+#[query(name = "getPriorTotalSupply")]
+#[candid_method(query, rename = "getPriorTotalSupply")]
+fn get_prior_total_supply(timestamp: Nat) -> Option<Nat> {
+    SUPPLY_CHECKPOINTS.with(|c| {
+        let guard = c.borrow();
+        let check_points = &guard.0;
+        let idx = match check_points.binary_search_by(|item| item.timestamp.cmp(&timestamp)) {
+            Ok(idx) => idx,
+            Err(0) => return None,
+            Err(idx) => idx - 1,
+        };
+        Some(check_points[idx].votes.clone())
+    })
+}
+
+/// current votes of `who` split into its own balance, if self-delegated, and the balances delegated by others
+#[query(name = "getVotesBreakdown")]
+#[candid_method(query, rename = "getVotesBreakdown")]
+fn get_votes_breakdown(who: Principal) -> (Nat, Nat) {
+    let votes = _get_votes(&who);
+    let own = if DELEGATES.with(|d| d.borrow().get(&who) == Some(&who)) {
+        balance_of(who).min(votes.clone())
+    } else {
+        Nat::from(0)
+    };
+    let delegated = votes - own.clone();
+    (own, delegated)
+}
+
+/// votes of `who` at each of the timestamps, in the same order
+#[update(name = "getPriorVotesMulti")]
+#[candid_method(update, rename = "getPriorVotesMulti")]
+async fn get_prior_votes_multi(who: Principal, timestamps: Vec<u64>) -> Vec<Nat> {
+    let mut votes = Vec::with_capacity(timestamps.len());
+    for timestamp in timestamps {
+        votes.push(get_prior_votes(who, Nat::from(timestamp)).await);
     }
-    
-    let idx = account_check_points
-        .binary_search_by(|item| item.timestamp.cmp(&timestamp))
-        .unwrap_or_else(|x| x - 1);
+    votes
+}
 
-    account_check_points[idx].votes.clone()
+/// the n delegatees with the most current votes, in decreasing order
+#[query(name = "getTopDelegatees")]
+#[candid_method(query, rename = "getTopDelegatees")]
+fn get_top_delegatees(n: usize) -> Vec<(Principal, Nat)> {
+    DELEGATEE_RANKING.with(|r| {
+        r.borrow().0
+            .iter()
+            .rev()
+            .take(n)
+            .map(|(votes, who)| (*who, votes.clone()))
+            .collect()
+    })
+}
+
+/// lowest votes held at any time between from and to, 0 if the first checkpoint is after from,
+/// the archived part of the range is read from the archive
+#[update(name = "getMinPriorVotes")]
+#[candid_method(update, rename = "getMinPriorVotes")]
+async fn get_min_prior_votes(who: Principal, from: Nat, to: Nat) -> Nat {
+    let archive = match _archive_for(&who, &from) {
+        Some(archive) => archive,
+        None => {
+            return CHECKPOINTS.with(|c| {
+                match c.borrow().get(&who) {
+                    Some(check_points) => min_prior_votes(check_points, &from, &to),
+                    None => Nat::from(0),
+                }
+            });
+        }
+    };
+    let result: CallResult<(Nat, )> = call(archive, "getMinPriorVotes", (who, from.clone(), to.clone(), )).await;
+    let mut min_votes = match result {
+        Ok((votes, )) => votes,
+        Err(_) => ic_cdk::trap("checkpoint archive unavailable"),
+    };
+    CHECKPOINTS.with(|c| {
+        if let Some(check_points) = c.borrow().get(&who) {
+            for cp in check_points.iter().filter(|cp| cp.timestamp > from && cp.timestamp <= to) {
+                if cp.votes < min_votes {
+                    min_votes = cp.votes.clone();
+                }
+            }
+        }
+    });
+    min_votes
+}
+
+/// votes of `who` at the timestamp weighted by how long they have been held, ramping up linearly to
+/// in full over `ramp`, the archived part of the ramp is read from the archive
+#[update(name = "getRampedPriorVotes")]
+#[candid_method(update, rename = "getRampedPriorVotes")]
+async fn get_ramped_prior_votes(who: Principal, timestamp: Nat, ramp: u64) -> Nat {
+    let from = if timestamp > ramp { timestamp.clone() - Nat::from(ramp) } else { Nat::from(0) };
+    let archive = match _archive_for(&who, &from) {
+        Some(archive) => archive,
+        None => {
+            return CHECKPOINTS.with(|c| {
+                match c.borrow().get(&who) {
+                    Some(check_points) => ramped_votes(check_points, &timestamp, ramp),
+                    None => Nat::from(0),
+                }
+            });
+        }
+    };
+    let result: CallResult<(Vec<CheckPoint>, )> = call(archive, "getCheckPoints", (who, from, timestamp.clone(), )).await;
+    let mut check_points = match result {
+        Ok((check_points, )) => check_points,
+        Err(_) => ic_cdk::trap("checkpoint archive unavailable"),
+    };
+    let last = check_points.last().map(|cp| cp.timestamp.clone());
+    CHECKPOINTS.with(|c| {
+        if let Some(kept) = c.borrow().get(&who) {
+            check_points.extend(kept.iter().filter(|cp| last.as_ref().map_or(true, |t| cp.timestamp > *t)).cloned());
+        }
+    });
+    ramped_votes(&check_points, &timestamp, ramp)
+}
+
+/// archive checkpoints with the owner's threshold, the canister can only change before anything is archived
+#[update(name = "setCheckPointArchive")]
+#[candid_method(update, rename = "setCheckPointArchive")]
+async fn set_check_point_archive(canister: Principal, threshold: u64) {
+    assert_eq!(ic::caller(), owner());
+    CHECKPOINT_ARCHIVE.with(|a| {
+        let mut archive = a.borrow_mut();
+        assert!(archive.archived.is_empty() || archive.canister == Some(canister));
+        archive.canister = Some(canister);
+        archive.threshold = threshold;
+    });
+    _record_admin("setCheckPointArchive", DetailsBuilder::new()
+        .insert("canister", canister)
+        .insert("threshold", threshold)
+        .build()
+    ).await;
+}
+
+#[query(name = "getCheckPointArchive")]
+#[candid_method(query, rename = "getCheckPointArchive")]
+fn get_check_point_archive() -> (Option<Principal>, u64) {
+    CHECKPOINT_ARCHIVE.with(|a| {
+        let archive = a.borrow();
+        (archive.canister, archive.threshold)
+    })
+}
+
+/// move the older half of the checkpoints of the delegatees above the threshold to the archive,
+/// anyone may call it, return the number of checkpoints archived
+#[update(name = "archiveCheckPoints")]
+#[candid_method(update, rename = "archiveCheckPoints")]
+async fn archive_check_points(delegatees: Vec<Principal>) -> u64 {
+    let (canister, threshold) = get_check_point_archive();
+    let canister = match canister {
+        Some(canister) if threshold > 0 => canister,
+        _ => { return 0; }
+    };
+    let mut archived = 0;
+    for who in delegatees {
+        let batch: Option<Vec<CheckPoint>> = CHECKPOINTS.with(|c| {
+            match c.borrow().get(&who) {
+                Some(check_points) if check_points.len() as u64 > threshold => {
+                    let keep = (threshold as usize / 2).max(1);
+                    Some(check_points[..check_points.len() - keep].to_vec())
+                }
+                _ => None,
+            }
+        });
+        let batch = match batch {
+            Some(batch) => batch,
+            None => { continue; }
+        };
+        if !ARCHIVING.with(|a| a.borrow_mut().0.insert(who)) {
+            continue;
+        }
+        let result: CallResult<()> = call(canister, "appendCheckPoints", (who, batch.clone(), )).await;
+        ARCHIVING.with(|a| a.borrow_mut().0.remove(&who));
+        if result.is_err() {
+            continue;
+        }
+        // checkpoints are only appended meanwhile, the batch is still the oldest ones
+        CHECKPOINTS.with(|c| {
+            if let Some(check_points) = c.borrow_mut().get_mut(&who) {
+                check_points.drain(..batch.len());
+            }
+        });
+        CHECKPOINT_ARCHIVE.with(|a| a.borrow_mut().archived.insert(who));
+        archived += batch.len() as u64;
+    }
+    archived
 }
 
 #[update(name = "delegate")]
@@ -255,6 +955,7 @@ async fn delegate(delegatee: Principal) -> TxReceipt {
         return Err(TxError::InsufficientBalance);
     }
     let value = _delegate(caller, delegatee);
+    STATS.with(|s| s.borrow_mut().history_size += 1);
 
     let event = IndefiniteEventBuilder::new()
        .caller(caller)
@@ -273,300 +974,695 @@ async fn delegate(delegatee: Principal) -> TxReceipt {
        .build()
        .unwrap();
 
-    insert_into_cap(event).await
+    let receipt = _queue_event(event);
+    _flush_records().await;
+    receipt
 }
 
+/// transfer from the caller, the fee is paid by the sender unless another payer is given
 #[update(name = "transfer")]
 #[candid_method(update)]
-async fn transfer(to: Principal, value: Nat) -> TxReceipt {
+async fn transfer(to: Principal, value: Nat, fee_payer: Option<FeePayer>) -> TxReceipt {
+    if is_soulbound() || is_paused() {
+        return Err(TxError::ErrorOperationStyle);
+    }
     let from = ic::caller();
-    let stats = ic::get_mut::<StatsData>();
-    if balance_of(from) < value.clone() + stats.fee.clone() {
+    let (fee, fee_to) = STATS.with(|s| {
+        let stats = s.borrow();
+        (stats.fee.clone(), stats.fee_to)
+    });
+    let payer = _fee_payer(fee_payer, from, to);
+    let needed = if payer == from { value.clone() + fee.clone() } else { value.clone() };
+    if balance_of(from) < needed {
         return Err(TxError::InsufficientBalance);
     }
-    _charge_fee(from, stats.fee_to, stats.fee.clone());
-    _transfer(from, to, value.clone());
-    _move_delegates(Some(&from), Some(&to), value.clone(), stats.fee.clone());
-    stats.history_size += 1;
-
-    add_record(
-        from,
-        Operation::Transfer,
-        from,
-        to,
-        value,
-        stats.fee.clone(),
-        ic::time(),
-        TransactionStatus::Succeeded,
-    )
-    .await
+    _check_fee_payer(payer, from, to, from, &value, &fee)?;
+    _check_transfer_limits(from, to, &value)?;
+    if payer != from && payer != to {
+        _spend_allowance(payer, from, fee.clone());
+    }
+    _transfer_with_fee(from, to, payer, value.clone(), fee_to, fee.clone());
+    _count_tx(Some(&from), Some(&to), &value);
+    STATS.with(|s| s.borrow_mut().history_size += 1);
+    _notify_listeners(BalanceChangeKind::Transfer, Some(from), Some(to), &value, &fee);
+
+    let receipt = _queue_record(from, Operation::Transfer, from, to, value, fee, ic::time());
+    _flush_records().await;
+    receipt
 }
 
+/// transfer on behalf of `from`, the fee is paid by `from` unless another payer is given
 #[update(name = "transferFrom")]
 #[candid_method(update, rename = "transferFrom")]
-async fn transfer_from(from: Principal, to: Principal, value: Nat) -> TxReceipt {
+async fn transfer_from(from: Principal, to: Principal, value: Nat, fee_payer: Option<FeePayer>) -> TxReceipt {
+    if is_soulbound() || is_paused() {
+        return Err(TxError::ErrorOperationStyle);
+    }
     let owner = ic::caller();
     let from_allowance = allowance(from, owner);
-    let stats = ic::get_mut::<StatsData>();
-    if from_allowance < value.clone() + stats.fee.clone() {
+    let (fee, fee_to) = STATS.with(|s| {
+        let stats = s.borrow();
+        (stats.fee.clone(), stats.fee_to)
+    });
+    let payer = _fee_payer(fee_payer, from, to);
+    let needed = if payer == from { value.clone() + fee.clone() } else { value.clone() };
+    if from_allowance < needed {
         return Err(TxError::InsufficientAllowance);
     }
     let from_balance = balance_of(from);
-    if from_balance < value.clone() + stats.fee.clone() {
+    if from_balance < needed {
         return Err(TxError::InsufficientBalance);
     }
-    _charge_fee(from, stats.fee_to, stats.fee.clone());
-    _transfer(from, to, value.clone());
-    _move_delegates(Some(&from), Some(&to), value.clone(), stats.fee.clone());
-    let allowances = ic::get_mut::<Allowances>();
-    match allowances.get(&from) {
-        Some(inner) => {
-            let result = inner.get(&owner).unwrap().clone();
-            let mut temp = inner.clone();
-            if result.clone() - value.clone() - stats.fee.clone() != 0 {
-                temp.insert(owner, result.clone() - value.clone() - stats.fee.clone());
-                allowances.insert(from, temp);
-            } else {
-                temp.remove(&owner);
-                if temp.len() == 0 {
-                    allowances.remove(&from);
-                } else {
-                    allowances.insert(from, temp);
-                }
-            }
-        }
-        None => {
-            assert!(false);
-        }
+    _check_fee_payer(payer, from, to, owner, &value, &fee)?;
+    _check_transfer_limits(from, to, &value)?;
+    if payer != from && payer != to {
+        _spend_allowance(payer, owner, fee.clone());
     }
-    stats.history_size += 1;
-
-    add_record(
-        owner,
-        Operation::TransferFrom,
-        from,
-        to,
-        value,
-        stats.fee.clone(),
-        ic::time(),
-        TransactionStatus::Succeeded,
-    )
-    .await
+    _transfer_with_fee(from, to, payer, value.clone(), fee_to, fee.clone());
+    _spend_allowance(from, owner, needed);
+    _count_tx(Some(&from), Some(&to), &value);
+    STATS.with(|s| s.borrow_mut().history_size += 1);
+    _notify_listeners(BalanceChangeKind::Transfer, Some(from), Some(to), &value, &fee);
+
+    let receipt = _queue_record(owner, Operation::TransferFrom, from, to, value, fee, ic::time());
+    _flush_records().await;
+    receipt
 }
 
 #[update(name = "approve")]
 #[candid_method(update)]
 async fn approve(spender: Principal, value: Nat) -> TxReceipt {
+    if is_paused() {
+        return Err(TxError::ErrorOperationStyle);
+    }
     let owner = ic::caller();
-    let stats = ic::get_mut::<StatsData>();
-    if balance_of(owner) < stats.fee.clone() {
+    let (fee, fee_to) = STATS.with(|s| {
+        let stats = s.borrow();
+        (stats.fee.clone(), stats.fee_to)
+    });
+    if balance_of(owner) < fee {
         return Err(TxError::InsufficientBalance);
     }
-    _charge_fee(owner, stats.fee_to, stats.fee.clone());
-    let v = value.clone() + stats.fee.clone();
-    let allowances = ic::get_mut::<Allowances>();
-    match allowances.get(&owner) {
-        Some(inner) => {
-            let mut temp = inner.clone();
-            if v.clone() != 0 {
-                temp.insert(spender, v.clone());
-                allowances.insert(owner, temp);
-            } else {
-                temp.remove(&spender);
-                if temp.len() == 0 {
-                    allowances.remove(&owner);
-                } else {
+    _charge_fee(owner, fee_to, fee.clone());
+    let v = value.clone() + fee.clone();
+    ALLOWANCES.with(|a| {
+        let mut allowances = a.borrow_mut();
+        match allowances.get(&owner) {
+            Some(inner) => {
+                let mut temp = inner.clone();
+                if v.clone() != 0 {
+                    temp.insert(spender, v.clone());
                     allowances.insert(owner, temp);
+                } else {
+                    temp.remove(&spender);
+                    if temp.len() == 0 {
+                        allowances.remove(&owner);
+                    } else {
+                        allowances.insert(owner, temp);
+                    }
                 }
             }
-        }
-        None => {
-            if v.clone() != 0 {
-                let mut inner = HashMap::new();
-                inner.insert(spender, v.clone());
-                let allowances = ic::get_mut::<Allowances>();
-                allowances.insert(owner, inner);
+            None => {
+                if v.clone() != 0 {
+                    let mut inner = HashMap::new();
+                    inner.insert(spender, v.clone());
+                    allowances.insert(owner, inner);
+                }
             }
         }
-    }
-    stats.history_size += 1;
+    });
+    STATS.with(|s| s.borrow_mut().history_size += 1);
 
-    add_record(
-        owner,
-        Operation::Approve,
-        owner,
-        spender,
-        v,
-        stats.fee.clone(),
-        ic::time(),
-        TransactionStatus::Succeeded,
-    )
-    .await
+    let receipt = _queue_record(owner, Operation::Approve, owner, spender, v, fee, ic::time());
+    _flush_records().await;
+    receipt
 }
 
 #[update(name = "mint")]
 #[candid_method(update, rename = "mint")]
 async fn mint(to: Principal, amount: Nat) -> TxReceipt {
     let caller = ic::caller();
-    let stats = ic::get_mut::<StatsData>();
-    if caller != stats.owner {
+    if !_has_role(Role::Minter, &caller) {
         return Err(TxError::Unauthorized);
     }
-    let to_balance = balance_of(to);
-    let balances = ic::get_mut::<Balances>();
-    balances.insert(to, to_balance + amount.clone());
-    stats.total_supply += amount.clone();
-    stats.history_size += 1;
-
-    add_record(
-        caller,
-        Operation::Mint,
-        caller,
-        to,
-        amount,
-        Nat::from(0),
-        ic::time(),
-        TransactionStatus::Succeeded,
-    )
-    .await
+    _mint(to, amount.clone());
+    _notify_listeners(BalanceChangeKind::Mint, None, Some(to), &amount, &Nat::from(0));
+
+    let receipt = _queue_record(caller, Operation::Mint, caller, to, amount, Nat::from(0), ic::time());
+    _flush_records().await;
+    receipt
+}
+
+/// mint to several principals at once, one record per leg, returns the index of the last record
+#[update(name = "batchMint")]
+#[candid_method(update, rename = "batchMint")]
+async fn batch_mint(mints: Vec<(Principal, Nat)>) -> TxReceipt {
+    let caller = ic::caller();
+    if !_has_role(Role::Minter, &caller) {
+        return Err(TxError::Unauthorized);
+    }
+    if mints.is_empty() || mints.len() > MAX_BATCH_MINT {
+        return Err(TxError::ErrorOperationStyle);
+    }
+    for (to, amount) in mints.iter() {
+        _mint(*to, amount.clone());
+        _notify_listeners(BalanceChangeKind::Mint, None, Some(*to), amount, &Nat::from(0));
+    }
+
+    let timestamp = ic::time();
+    let mut receipt = Err(TxError::Other);
+    for (to, amount) in mints {
+        receipt = _queue_record(caller, Operation::Mint, caller, to, amount, Nat::from(0), timestamp);
+    }
+    _flush_records().await;
+    receipt
 }
 
 #[update(name = "burn")]
 #[candid_method(update, rename = "burn")]
 async fn burn(amount: Nat) -> TxReceipt {
     let caller = ic::caller();
-    let stats = ic::get_mut::<StatsData>();
     let caller_balance = balance_of(caller);
     if caller_balance.clone() < amount.clone() {
         return Err(TxError::InsufficientBalance);
     }
-    let balances = ic::get_mut::<Balances>();
-    balances.insert(caller, caller_balance - amount.clone());
-    stats.total_supply -= amount.clone();
-    stats.history_size += 1;
+    _settle_dividend(caller);
+    _index_balance(&caller_balance, &(caller_balance.clone() - amount.clone()));
+    BALANCES.with(|b| b.borrow_mut().insert(caller, caller_balance - amount.clone()));
+    _count_tx(Some(&caller), None, &amount);
+    STATS.with(|s| {
+        let mut stats = s.borrow_mut();
+        stats.total_supply -= amount.clone();
+        stats.history_size += 1;
+    });
+    _write_supply_check_point();
+    _notify_listeners(BalanceChangeKind::Burn, Some(caller), None, &amount, &Nat::from(0));
+
+    let receipt = _queue_record(caller, Operation::Burn, caller, caller, amount, Nat::from(0i32), ic::time());
+    _flush_records().await;
+    receipt
+}
+
+/// deposit tokens of the owner as dividends, split pro-rata among the other holders
+#[update(name = "depositDividend")]
+#[candid_method(update, rename = "depositDividend")]
+async fn deposit_dividend(amount: Nat) -> TxReceipt {
+    let caller = ic::caller();
+    if caller != owner() {
+        return Err(TxError::Unauthorized);
+    }
+    if amount == 0u64 {
+        return Err(TxError::AmountTooSmall);
+    }
+    if balance_of(caller) < amount {
+        return Err(TxError::InsufficientBalance);
+    }
+    let pool = ic::id();
+    let eligible = total_supply() - balance_of(pool) - amount.clone();
+    if eligible == 0u64 {
+        return Err(TxError::Other);
+    }
+    _transfer(caller, pool, amount.clone());
+    _move_delegates(Some(&caller), None, amount.clone(), Nat::from(0));
+    _count_tx(Some(&caller), None, &amount);
+    DIVIDENDS.with(|d| {
+        let mut dividends = d.borrow_mut();
+        dividends.per_share += amount.clone() * Nat::from(DIVIDEND_SCALE) / eligible;
+        dividends.total_deposited += amount.clone();
+    });
+    STATS.with(|s| s.borrow_mut().history_size += 1);
 
-    add_record(
-        caller,
-        Operation::Burn,
-        caller,
-        caller,
-        amount,
-        Nat::from(0i32),
-        ic::time(),
-        TransactionStatus::Succeeded,
-    )
-    .await
+    let event = IndefiniteEventBuilder::new()
+        .caller(caller)
+        .operation(String::from("depositDividend"))
+        .details(
+            DetailsBuilder::new()
+                .insert("from", caller)
+                .insert("amount", amount)
+                .insert("timestamp", ic::time())
+                .insert("status", String::from("succeeded"))
+                .build()
+        )
+        .build()
+        .unwrap();
+
+    let receipt = _queue_event(event);
+    _flush_records().await;
+    receipt
+}
+
+/// transfer the dividends accrued by the caller
+#[update(name = "claimDividend")]
+#[candid_method(update, rename = "claimDividend")]
+async fn claim_dividend() -> TxReceipt {
+    let caller = ic::caller();
+    _settle_dividend(caller);
+    let amount = match DIVIDENDS.with(|d| d.borrow_mut().owed.remove(&caller)) {
+        Some(amount) => amount,
+        None => { return Err(TxError::AmountTooSmall); }
+    };
+    DIVIDENDS.with(|d| d.borrow_mut().total_claimed += amount.clone());
+    _transfer(ic::id(), caller, amount.clone());
+    _move_delegates(None, Some(&caller), amount.clone(), Nat::from(0));
+    _count_tx(None, Some(&caller), &amount);
+    STATS.with(|s| s.borrow_mut().history_size += 1);
+
+    let event = IndefiniteEventBuilder::new()
+        .caller(caller)
+        .operation(String::from("claimDividend"))
+        .details(
+            DetailsBuilder::new()
+                .insert("to", caller)
+                .insert("amount", amount)
+                .insert("timestamp", ic::time())
+                .insert("status", String::from("succeeded"))
+                .build()
+        )
+        .build()
+        .unwrap();
+
+    let receipt = _queue_event(event);
+    _flush_records().await;
+    receipt
+}
+
+/// dividends `who` can claim
+#[query(name = "dividendOf")]
+#[candid_method(query, rename = "dividendOf")]
+fn dividend_of(who: Principal) -> Nat {
+    _pending_dividend(who)
+}
+
+#[query(name = "getDividendInfo")]
+#[candid_method(query, rename = "getDividendInfo")]
+fn get_dividend_info() -> DividendInfo {
+    DIVIDENDS.with(|d| {
+        let dividends = d.borrow();
+        DividendInfo {
+            perShare: dividends.per_share.clone(),
+            totalDeposited: dividends.total_deposited.clone(),
+            totalClaimed: dividends.total_claimed.clone(),
+        }
+    })
 }
 
 #[update(name = "setName")]
 #[candid_method(update, rename = "setName")]
-fn set_name(name: String) {
-    let stats = ic::get_mut::<StatsData>();
-    assert_eq!(ic::caller(), stats.owner);
-    stats.name = name;
+async fn set_name(name: String) {
+    assert!(_has_role(Role::MetadataManager, &ic::caller()));
+    STATS.with(|s| s.borrow_mut().name = name.clone());
+    _record_admin("setName", DetailsBuilder::new().insert("name", name).build()).await;
 }
 
 #[update(name = "setLogo")]
 #[candid_method(update, rename = "setLogo")]
-fn set_logo(logo: String) {
-    let stats = ic::get_mut::<StatsData>();
-    assert_eq!(ic::caller(), stats.owner);
-    stats.logo = logo;
+async fn set_logo(logo: String) {
+    assert!(_has_role(Role::MetadataManager, &ic::caller()));
+    STATS.with(|s| s.borrow_mut().logo = logo.clone());
+    _record_admin("setLogo", DetailsBuilder::new().insert("logo", logo).build()).await;
 }
 
 #[update(name = "setFee")]
 #[candid_method(update, rename = "setFee")]
-fn set_fee(fee: Nat) {
-    let stats = ic::get_mut::<StatsData>();
-    assert_eq!(ic::caller(), stats.owner);
-    stats.fee = fee;
+async fn set_fee(fee: Nat) {
+    assert!(_has_role(Role::FeeManager, &ic::caller()));
+    STATS.with(|s| s.borrow_mut().fee = fee.clone());
+    _record_admin("setFee", DetailsBuilder::new().insert("fee", fee).build()).await;
 }
 
 #[update(name = "setFeeTo")]
 #[candid_method(update, rename = "setFeeTo")]
-fn set_fee_to(fee_to: Principal) {
-    let stats = ic::get_mut::<StatsData>();
-    assert_eq!(ic::caller(), stats.owner);
-    stats.fee_to = fee_to;
+async fn set_fee_to(fee_to: Principal) {
+    assert!(_has_role(Role::FeeManager, &ic::caller()));
+    STATS.with(|s| s.borrow_mut().fee_to = fee_to);
+    _record_admin("setFeeTo", DetailsBuilder::new().insert("feeTo", fee_to).build()).await;
 }
 
 #[update(name = "setOwner")]
 #[candid_method(update, rename = "setOwner")]
-fn set_owner(owner: Principal) {
-    let stats = ic::get_mut::<StatsData>();
-    assert_eq!(ic::caller(), stats.owner);
-    stats.owner = owner;
+async fn set_owner(owner: Principal) {
+    STATS.with(|s| {
+        let mut stats = s.borrow_mut();
+        assert_eq!(ic::caller(), stats.owner);
+        stats.owner = owner;
+    });
+    _record_admin("setOwner", DetailsBuilder::new().insert("owner", owner).build()).await;
+}
+
+/// hand a role to a principal, none gives it back to the owner
+#[update(name = "setRole")]
+#[candid_method(update, rename = "setRole")]
+async fn set_role(role: Role, holder: Option<Principal>) {
+    assert_eq!(ic::caller(), owner());
+    ROLE_HOLDERS.with(|r| {
+        let mut roles = r.borrow_mut();
+        match holder {
+            Some(holder) => { roles.0.insert(role, holder); }
+            None => { roles.0.remove(&role); }
+        }
+    });
+    _record_admin("setRole", DetailsBuilder::new()
+        .insert("role", format!("{:?}", role))
+        .insert("holder", _role_holder(role))
+        .build()
+    ).await;
+}
+
+/// holder of every role
+#[query(name = "getRoles")]
+#[candid_method(query, rename = "getRoles")]
+fn get_roles() -> Vec<(Role, Principal)> {
+    ROLES.iter().map(|role| (*role, _role_holder(*role))).collect()
+}
+
+/// halt transfers and approvals
+#[update(name = "setPaused")]
+#[candid_method(update, rename = "setPaused")]
+async fn set_paused(paused: bool) {
+    assert!(_has_role(Role::Pauser, &ic::caller()));
+    PAUSED.with(|p| p.set(paused));
+    _record_admin("setPaused", DetailsBuilder::new().insert("paused", paused.to_string()).build()).await;
+}
+
+#[query(name = "isPaused")]
+#[candid_method(query, rename = "isPaused")]
+fn is_paused() -> bool {
+    PAUSED.with(|p| p.get())
+}
+
+/// limit the amount of a transfer and the amount an account sends per day, 0 disables a limit
+#[update(name = "setTransferLimits")]
+#[candid_method(update, rename = "setTransferLimits")]
+async fn set_transfer_limits(per_transaction: Nat, per_day: Nat) {
+    assert_eq!(ic::caller(), owner());
+    TRANSFER_LIMITS.with(|l| {
+        let mut limits = l.borrow_mut();
+        limits.perTransaction = per_transaction.clone();
+        limits.perDay = per_day.clone();
+    });
+    if per_day == 0 {
+        DAILY_TRANSFERS.with(|d| d.borrow_mut().0.clear());
+    }
+    _record_admin(
+        "setTransferLimits",
+        DetailsBuilder::new()
+            .insert("perTransaction", per_transaction)
+            .insert("perDay", per_day)
+            .build(),
+    ).await;
+}
+
+/// exempt the account from the transfer limits, as sender and as recipient
+#[update(name = "setTransferLimitExempt")]
+#[candid_method(update, rename = "setTransferLimitExempt")]
+async fn set_transfer_limit_exempt(who: Principal, exempt: bool) {
+    assert_eq!(ic::caller(), owner());
+    TRANSFER_LIMITS.with(|l| {
+        let mut limits = l.borrow_mut();
+        if exempt {
+            limits.exempt.insert(who);
+        } else {
+            limits.exempt.remove(&who);
+        }
+    });
+    _record_admin(
+        "setTransferLimitExempt",
+        DetailsBuilder::new()
+            .insert("account", who)
+            .insert("exempt", exempt.to_string())
+            .build(),
+    ).await;
+}
+
+#[query(name = "getTransferLimits")]
+#[candid_method(query, rename = "getTransferLimits")]
+fn get_transfer_limits() -> TransferLimits {
+    TRANSFER_LIMITS.with(|l| l.borrow().clone())
+}
+
+/// amount the account sent today against the daily limit
+#[query(name = "getDailyTransferred")]
+#[candid_method(query, rename = "getDailyTransferred")]
+fn get_daily_transferred(who: Principal) -> Nat {
+    let day = ic::time() / ONE_DAY;
+    DAILY_TRANSFERS.with(|d| {
+        match d.borrow().0.get(&who) {
+            Some((last_day, sent)) if *last_day == day => sent.clone(),
+            _ => Nat::from(0),
+        }
+    })
+}
+
+/// notify the canister of the balance changes of its own account, or of every account if global,
+/// replacing its previous registration
+#[update(name = "setListener")]
+#[candid_method(update, rename = "setListener")]
+async fn set_listener(canister: Principal, method: String, global: bool) {
+    assert_eq!(ic::caller(), owner());
+    LISTENERS.with(|l| {
+        let mut guard = l.borrow_mut();
+        let listeners = &mut guard.0;
+        listeners.retain(|l| l.canister != canister);
+        assert!(listeners.len() < MAX_LISTENERS, "too many listeners");
+        listeners.push(Listener { canister, method: method.clone(), global });
+    });
+    _record_admin(
+        "setListener",
+        DetailsBuilder::new()
+            .insert("canister", canister)
+            .insert("method", method)
+            .insert("global", global.to_string())
+            .build(),
+    ).await;
+}
+
+#[update(name = "removeListener")]
+#[candid_method(update, rename = "removeListener")]
+async fn remove_listener(canister: Principal) {
+    assert_eq!(ic::caller(), owner());
+    LISTENERS.with(|l| l.borrow_mut().0.retain(|l| l.canister != canister));
+    _record_admin(
+        "removeListener",
+        DetailsBuilder::new()
+            .insert("canister", canister)
+            .build(),
+    ).await;
+}
+
+#[query(name = "getListeners")]
+#[candid_method(query, rename = "getListeners")]
+fn get_listeners() -> Vec<Listener> {
+    LISTENERS.with(|l| l.borrow().0.clone())
+}
+
+/// make the token non-transferable, e.g. for reputation-style governance
+#[update(name = "setSoulbound")]
+#[candid_method(update, rename = "setSoulbound")]
+async fn set_soulbound(soulbound: bool) {
+    assert_eq!(ic::caller(), owner());
+    SOULBOUND.with(|s| s.set(soulbound));
+    _record_admin("setSoulbound", DetailsBuilder::new().insert("soulbound", soulbound.to_string()).build()).await;
+}
+
+/// transactions sent and received by the principal
+#[query(name = "getUserStats")]
+#[candid_method(query, rename = "getUserStats")]
+fn get_user_stats(who: Principal) -> UserStats {
+    USER_STATS.with(|s| s.borrow().0.get(&who).cloned().unwrap_or_default())
+}
+
+/// accept the cycles attached to the call and record the deposit, return the accepted amount
+fn _accept_cycles() -> u64 {
+    let available = ic_cdk::api::call::msg_cycles_available();
+    if available == 0 {
+        return 0;
+    }
+    let accepted = ic_cdk::api::call::msg_cycles_accept(available);
+    CYCLES_DEPOSITS.with(|d| {
+        let mut deposits = d.borrow_mut();
+        deposits.deposits.push(CyclesDeposit {
+            from: ic::caller(),
+            amount: accepted,
+            timestamp: ic::time(),
+        });
+        deposits.totalReceived += Nat::from(accepted);
+    });
+    accepted
+}
+
+#[update(name = "wallet_receive")]
+#[candid_method(update, rename = "wallet_receive")]
+fn wallet_receive() {
+    _accept_cycles();
+}
+
+#[update(name = "acceptCycles")]
+#[candid_method(update, rename = "acceptCycles")]
+fn accept_cycles() -> u64 {
+    _accept_cycles()
+}
+
+/// cycles deposits, oldest first, and the total received
+#[query(name = "getCyclesDeposits")]
+#[candid_method(query, rename = "getCyclesDeposits")]
+fn get_cycles_deposits(start: usize, limit: usize) -> (Vec<CyclesDeposit>, Nat) {
+    CYCLES_DEPOSITS.with(|d| {
+        let deposits = d.borrow();
+        (
+            deposits.deposits.iter().skip(start).take(limit).cloned().collect(),
+            deposits.totalReceived.clone(),
+        )
+    })
+}
+
+/// admin mutations, oldest first
+#[query(name = "getAdminLog")]
+#[candid_method(query, rename = "getAdminLog")]
+fn get_admin_log(start: usize, limit: usize) -> Vec<AdminRecord> {
+    ADMIN_LOG.with(|l| l.borrow().0.iter().skip(start).take(limit).cloned().collect())
+}
+
+/// chunk of the candid encoding of the balances, allowances, delegates and checkpoints
+#[query(name = "backupState")]
+#[candid_method(query, rename = "backupState")]
+fn backup_state(chunk: usize) -> Result<BackupChunk, TxError> {
+    if ic::caller() != owner() {
+        return Err(TxError::Unauthorized);
+    }
+    let backup = BALANCES.with(|b| ALLOWANCES.with(|a| DELEGATES.with(|d| CHECKPOINTS.with(|c| {
+        encode_args((
+            &*b.borrow(),
+            &*a.borrow(),
+            &*d.borrow(),
+            &*c.borrow(),
+        ))
+    })))).map_err(|_| TxError::Other)?;
+    let chunks = (backup.len() + MAX_BACKUP_CHUNK - 1) / MAX_BACKUP_CHUNK;
+    if chunk >= chunks {
+        return Err(TxError::Other);
+    }
+    let start = chunk * MAX_BACKUP_CHUNK;
+    let end = (start + MAX_BACKUP_CHUNK).min(backup.len());
+    Ok(BackupChunk {
+        data: backup[start..end].to_vec(),
+        chunks,
+    })
+}
+
+/// stage chunks of a backup in order, the backup is restored with the last chunks,
+/// only while transfers are paused
+#[update(name = "restoreState")]
+#[candid_method(update, rename = "restoreState")]
+async fn restore_state(chunks: Vec<Vec<u8>>, complete: bool) -> Result<(), TxError> {
+    if ic::caller() != owner() {
+        return Err(TxError::Unauthorized);
+    }
+    if !is_paused() {
+        return Err(TxError::ErrorOperationStyle);
+    }
+    let backup = RESTORE_BUFFER.with(|b| {
+        let mut buffer = b.borrow_mut();
+        for chunk in chunks {
+            buffer.extend(chunk);
+        }
+        if complete { Some(std::mem::take(&mut *buffer)) } else { None }
+    });
+    let backup = match backup {
+        Some(backup) => backup,
+        None => { return Ok(()); }
+    };
+    let (balances, allowances, delegates, checkpoints): (Balances, Allowances, Delegates, CheckPoints) =
+        decode_args(&backup).map_err(|_| TxError::Other)?;
+    let total_supply = balances.values().fold(Nat::from(0), |total, balance| total + balance.clone());
+    let holders = balances.len();
+    BALANCES.with(|b| *b.borrow_mut() = balances);
+    ALLOWANCES.with(|a| *a.borrow_mut() = allowances);
+    DELEGATES.with(|d| *d.borrow_mut() = delegates);
+    CHECKPOINTS.with(|c| *c.borrow_mut() = checkpoints);
+    _rebuild_indices();
+    STATS.with(|s| s.borrow_mut().total_supply = total_supply.clone());
+    _write_supply_check_point();
+    _record_admin(
+        "restoreState",
+        DetailsBuilder::new()
+            .insert("holders", holders as u64)
+            .insert("totalSupply", total_supply)
+            .build(),
+    ).await;
+    Ok(())
+}
+
+#[query(name = "isSoulbound")]
+#[candid_method(query, rename = "isSoulbound")]
+fn is_soulbound() -> bool {
+    SOULBOUND.with(|s| s.get())
 }
 
 #[query(name = "balanceOf")]
 #[candid_method(query, rename = "balanceOf")]
 fn balance_of(id: Principal) -> Nat {
-    let balances = ic::get::<Balances>();
-    match balances.get(&id) {
-        Some(balance) => balance.clone(),
-        None => Nat::from(0),
-    }
+    BALANCES.with(|b| {
+        match b.borrow().get(&id) {
+            Some(balance) => balance.clone(),
+            None => Nat::from(0),
+        }
+    })
 }
 
 #[query(name = "allowance")]
 #[candid_method(query)]
 fn allowance(owner: Principal, spender: Principal) -> Nat {
-    let allowances = ic::get::<Allowances>();
-    match allowances.get(&owner) {
-        Some(inner) => match inner.get(&spender) {
-            Some(value) => value.clone(),
+    ALLOWANCES.with(|a| {
+        match a.borrow().get(&owner) {
+            Some(inner) => match inner.get(&spender) {
+                Some(value) => value.clone(),
+                None => Nat::from(0),
+            },
             None => Nat::from(0),
-        },
-        None => Nat::from(0),
-    }
+        }
+    })
 }
 
 #[query(name = "logo")]
 #[candid_method(query, rename = "logo")]
 fn get_logo() -> String {
-    let stats = ic::get::<StatsData>();
-    stats.logo.clone()
+    STATS.with(|s| s.borrow().logo.clone())
 }
 
 #[query(name = "name")]
 #[candid_method(query)]
 fn name() -> String {
-    let stats = ic::get::<StatsData>();
-    stats.name.clone()
+    STATS.with(|s| s.borrow().name.clone())
 }
 
 #[query(name = "symbol")]
 #[candid_method(query)]
 fn symbol() -> String {
-    let stats = ic::get::<StatsData>();
-    stats.symbol.clone()
+    STATS.with(|s| s.borrow().symbol.clone())
 }
 
 #[query(name = "decimals")]
 #[candid_method(query)]
 fn decimals() -> u8 {
-    let stats = ic::get::<StatsData>();
-    stats.decimals
+    STATS.with(|s| s.borrow().decimals)
 }
 
 #[query(name = "totalSupply")]
 #[candid_method(query, rename = "totalSupply")]
 fn total_supply() -> Nat {
-    let stats = ic::get::<StatsData>();
-    stats.total_supply.clone()
+    STATS.with(|s| s.borrow().total_supply.clone())
 }
 
 #[query(name = "owner")]
 #[candid_method(query)]
 fn owner() -> Principal {
-    let stats = ic::get::<StatsData>();
-    stats.owner
+    STATS.with(|s| s.borrow().owner)
 }
 
 #[query(name = "getMetadata")]
 #[candid_method(query, rename = "getMetadata")]
 fn get_metadata() -> Metadata {
-    let s = ic::get::<StatsData>().clone();
+    let s = STATS.with(|s| s.borrow().clone());
     Metadata {
         logo: s.logo,
         name: s.name,
@@ -581,33 +1677,62 @@ fn get_metadata() -> Metadata {
 #[query(name = "historySize")]
 #[candid_method(query, rename = "historySize")]
 fn history_size() -> usize {
-    let stats = ic::get::<StatsData>();
-    stats.history_size
+    STATS.with(|s| s.borrow().history_size)
 }
 
 #[query(name = "getTokenInfo")]
 #[candid_method(query, rename = "getTokenInfo")]
 fn get_token_info() -> TokenInfo {
-    let stats = ic::get::<StatsData>().clone();
-    let balance = ic::get::<Balances>();
+    let stats = STATS.with(|s| s.borrow().clone());
 
     return TokenInfo {
         metadata: get_metadata(),
         feeTo: stats.fee_to,
         historySize: stats.history_size,
         deployTime: stats.deploy_time,
-        holderNumber: balance.len(),
+        holderNumber: BALANCES.with(|b| b.borrow().len()),
         cycles: ic::balance(),
     };
 }
 
+#[query(name = "healthCheck")]
+#[candid_method(query, rename = "healthCheck")]
+fn health_check() -> HealthStatus {
+    #[cfg(target_arch = "wasm32")]
+    let heap_memory_size = core::arch::wasm32::memory_size(0) as u64 * WASM_PAGE_SIZE;
+    #[cfg(not(target_arch = "wasm32"))]
+    let heap_memory_size = 0;
+
+    HealthStatus {
+        cycles: ic::balance(),
+        heapMemorySize: heap_memory_size,
+        stableMemorySize: (ic_cdk::api::stable::stable_size() as u64) << 16,
+        pendingEvents: TX_LOG.with(|l| l.borrow().ie_records.len()),
+        lastUpgrade: UPGRADES.with(|u| u.borrow().last().copied().unwrap_or(0)),
+    }
+}
+
+#[query(name = "getVersion")]
+#[candid_method(query, rename = "getVersion")]
+fn get_version() -> VersionInfo {
+    let candid_hash = Sha256::digest(export_candid().as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    VersionInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        gitHash: env!("GIT_HASH").to_string(),
+        candidHash: candid_hash,
+        upgrades: UPGRADES.with(|u| u.borrow().clone()),
+    }
+}
+
 #[query(name = "getHolders")]
 #[candid_method(query, rename = "getHolders")]
 fn get_holders(start: usize, limit: usize) -> Vec<(Principal, Nat)> {
-    let mut balance = Vec::new();
-    for (k, v) in ic::get::<Balances>().clone() {
-        balance.push((k, v));
-    }
+    let mut balance: Vec<(Principal, Nat)> = BALANCES.with(|b| {
+        b.borrow().iter().map(|(k, v)| (*k, v.clone())).collect()
+    });
     balance.sort_by(|a, b| b.1.cmp(&a.1));
     let limit: usize = if start + limit > balance.len() {
         balance.len() - start
@@ -617,25 +1742,149 @@ fn get_holders(start: usize, limit: usize) -> Vec<(Principal, Nat)> {
     balance[start..start + limit].to_vec()
 }
 
+/// entries of the map ordered by principal, starting after the cursor principal,
+/// returns the cursor of the next chunk if any
+fn _export_chunk<V: Clone>(map: &HashMap<Principal, V>, cursor: Option<Principal>, limit: usize) -> (Vec<(Principal, V)>, Option<Principal>) {
+    let mut keys: Vec<&Principal> = map.keys()
+        .filter(|key| cursor.map_or(true, |c| **key > c))
+        .collect();
+    keys.sort();
+    let limit = limit.min(MAX_EXPORT_CHUNK);
+    let chunk: Vec<(Principal, V)> = keys.iter()
+        .take(limit)
+        .map(|key| (**key, map[*key].clone()))
+        .collect();
+    let next = if keys.len() > limit {
+        chunk.last().map(|(key, _)| *key)
+    } else {
+        None
+    };
+    (chunk, next)
+}
+
+/// current balances ordered by holder, for snapshot tools, chunked like `getUserApprovalsPage`
+#[query(name = "exportBalances")]
+#[candid_method(query, rename = "exportBalances")]
+fn export_balances(cursor: Option<Principal>, limit: usize) -> (Vec<(Principal, Nat)>, Option<Principal>) {
+    BALANCES.with(|b| _export_chunk(&b.borrow(), cursor, limit))
+}
+
+/// current (delegator, delegatee) pairs ordered by delegator, for snapshot tools
+#[query(name = "exportDelegations")]
+#[candid_method(query, rename = "exportDelegations")]
+fn export_delegations(cursor: Option<Principal>, limit: usize) -> (Vec<(Principal, Principal)>, Option<Principal>) {
+    DELEGATES.with(|d| _export_chunk(&d.borrow(), cursor, limit))
+}
+
+/// number of holders with a balance strictly above the threshold
+#[query(name = "getHolderCountAbove")]
+#[candid_method(query, rename = "getHolderCountAbove")]
+fn get_holder_count_above(threshold: Nat) -> usize {
+    BALANCE_INDEX.with(|i| {
+        i.borrow().0
+            .range((Bound::Excluded(threshold), Bound::Unbounded))
+            .map(|(_, count)| count)
+            .sum()
+    })
+}
+
+/// daily holder count and delegated supply over the last days, oldest first, days without changes are skipped
+#[query(name = "getHolderHistory")]
+#[candid_method(query, rename = "getHolderHistory")]
+fn get_holder_history(days: u64) -> Vec<HolderSnapshot> {
+    let since = (ic::time() / ONE_DAY).saturating_sub(days);
+    HOLDER_HISTORY.with(|h| {
+        h.borrow().0
+            .iter()
+            .filter(|snapshot| snapshot.day > since)
+            .cloned()
+            .collect()
+    })
+}
+
+/// number of holders by power of ten, each bucket holding balances in [lower bound, 10 * lower bound)
+#[query(name = "getBalanceHistogram")]
+#[candid_method(query, rename = "getBalanceHistogram")]
+fn get_balance_histogram() -> Vec<(Nat, usize)> {
+    let mut histogram: Vec<(Nat, usize)> = Vec::new();
+    BALANCE_INDEX.with(|i| {
+        for (balance, count) in i.borrow().0.iter() {
+            let digits = balance.0.to_string().len();
+            let lower = Nat::from(10u64).0.pow(digits as u32 - 1);
+            match histogram.last_mut() {
+                Some((bucket, total)) if bucket.0 == lower => { *total += count; }
+                _ => { histogram.push((Nat::from(lower), *count)); }
+            }
+        }
+    });
+    histogram
+}
+
 #[query(name = "getAllowanceSize")]
 #[candid_method(query, rename = "getAllowanceSize")]
 fn get_allowance_size() -> usize {
-    let mut size = 0;
-    let allowances = ic::get::<Allowances>();
-    for (_, v) in allowances.iter() {
-        size += v.len();
-    }
-    size
+    ALLOWANCES.with(|a| {
+        let mut size = 0;
+        let allowances = a.borrow();
+        for (_, v) in allowances.iter() {
+            size += v.len();
+        }
+        size
+    })
 }
 
 #[query(name = "getUserApprovals")]
 #[candid_method(query, rename = "getUserApprovals")]
 fn get_user_approvals(who: Principal) -> Vec<(Principal, Nat)> {
-    let allowances = ic::get::<Allowances>();
-    match allowances.get(&who) {
-        Some(allow) => return Vec::from_iter(allow.clone().into_iter()),
-        None => return Vec::new(),
-    }
+    ALLOWANCES.with(|a| {
+        let allowances = a.borrow();
+        match allowances.get(&who) {
+            Some(allow) => return Vec::from_iter(allow.clone().into_iter()),
+            None => return Vec::new(),
+        }
+    })
+}
+
+/// approvals of the principal ordered by spender, starting after the cursor spender,
+/// returns the cursor of the next page if any
+#[query(name = "getUserApprovalsPage")]
+#[candid_method(query, rename = "getUserApprovalsPage")]
+fn get_user_approvals_page(who: Principal, cursor: Option<Principal>, limit: usize) -> (Vec<(Principal, Nat)>, Option<Principal>) {
+    ALLOWANCES.with(|a| {
+        let allowances = a.borrow();
+        let allow = match allowances.get(&who) {
+            Some(allow) => allow,
+            None => return (Vec::new(), None),
+        };
+        let mut spenders: Vec<&Principal> = allow.keys()
+            .filter(|spender| cursor.map_or(true, |c| **spender > c))
+            .collect();
+        spenders.sort();
+        let limit = limit.min(MAX_APPROVALS_PAGE);
+        let page: Vec<(Principal, Nat)> = spenders.iter()
+            .take(limit)
+            .map(|spender| (**spender, allow[*spender].clone()))
+            .collect();
+        let next = if spenders.len() > limit {
+            page.last().map(|(spender, _)| *spender)
+        } else {
+            None
+        };
+        (page, next)
+    })
+}
+
+/// sum of the allowances the principal granted
+#[query(name = "getOutgoingAllowanceTotal")]
+#[candid_method(query, rename = "getOutgoingAllowanceTotal")]
+fn get_outgoing_allowance_total(who: Principal) -> Nat {
+    ALLOWANCES.with(|a| {
+        let allowances = a.borrow();
+        match allowances.get(&who) {
+            Some(allow) => allow.values().fold(Nat::from(0), |total, value| total + value.clone()),
+            None => Nat::from(0),
+        }
+    })
 }
 
 #[query(name = "__get_candid_interface_tmp_hack")]
@@ -644,23 +1893,86 @@ fn export_candid() -> String {
     __export_service()
 }
 
-#[pre_upgrade]
-fn pre_upgrade() {
-    ic::stable_store((
-        ic::get::<StatsData>().clone(),
-        ic::get::<Balances>(),
-        ic::get::<Allowances>(),
-        ic::get::<Delegates>(),
-        ic::get::<CheckPoints>(),
-        tx_log(),
-        CapEnv::to_archive()
-    ))
-    .unwrap();
+/// rebuild the balance index and the delegatee ranking from the balances and checkpoints
+fn _rebuild_indices() {
+    HOLDER_TOTALS.with(|t| *t.borrow_mut() = HolderTotals::default());
+    BALANCE_INDEX.with(|i| i.borrow_mut().0.clear());
+    BALANCES.with(|b| {
+        for balance in b.borrow().values() {
+            _index_balance(&Nat::from(0), balance);
+        }
+    });
+
+    DELEGATEE_RANKING.with(|r| r.borrow_mut().0.clear());
+    CHECKPOINTS.with(|c| {
+        for (who, check_point) in c.borrow().iter() {
+            if let Some(last) = check_point.last() {
+                _rank_delegatee(who, &Nat::from(0), &last.votes);
+            }
+        }
+    });
 }
 
-#[post_upgrade]
-fn post_upgrade() {
-    let (metadata_stored, balances_stored, allowances_stored, delegates_stored, checkpoints_stored, tx_log_stored, cap_env): (
+/// state kept across upgrades, stored next to the cap archive. a change of its layout adds a
+/// version which post_upgrade migrates to, fields added to a version must default
+#[derive(Deserialize, CandidType)]
+enum StableState {
+    V1(StateV1),
+}
+
+#[derive(Deserialize, CandidType, Default)]
+struct StateV1 {
+    stats: StatsData,
+    balances: Balances,
+    allowances: Allowances,
+    delegates: Delegates,
+    checkpoints: CheckPoints,
+    tx_log: TxLog,
+    #[serde(default)]
+    dividends: Dividends,
+    #[serde(default)]
+    soulbound: bool,
+    #[serde(default)]
+    roles: Roles,
+    #[serde(default)]
+    paused: bool,
+    #[serde(default)]
+    admin_log: AdminLog,
+    #[serde(default)]
+    user_stats: UserStatsMap,
+    #[serde(default)]
+    cycles_deposits: CyclesDeposits,
+    #[serde(default)]
+    transfer_limits: TransferLimits,
+    #[serde(default)]
+    daily_transfers: DailyTransfers,
+    #[serde(default)]
+    listeners: Listeners,
+    #[serde(default)]
+    supply_check_points: SupplyCheckPoints,
+    #[serde(default)]
+    holder_history: HolderHistory,
+    #[serde(default)]
+    check_point_archive: CheckPointArchive,
+    #[serde(default)]
+    upgrades: Vec<u64>,
+}
+
+impl StableState {
+    /// the state in the latest layout
+    fn migrate(self) -> StateV1 {
+        match self {
+            StableState::V1(state) => state,
+        }
+    }
+}
+
+/// read the state stored by pre_upgrade, falling back to the unversioned tuple of the first release
+fn _restore_state() -> (StateV1, CapEnv) {
+    if let Ok((state, cap_env)) = ic::stable_restore::<(StableState, CapEnv)>() {
+        return (state.migrate(), cap_env);
+    }
+    let (stats, balances, allowances, delegates, checkpoints, tx_log, cap_env): (
         StatsData,
         Balances,
         Allowances,
@@ -668,29 +1980,88 @@ fn post_upgrade() {
         CheckPoints,
         TxLog,
         CapEnv
-    ) = ic::stable_restore().unwrap();
-    let stats = ic::get_mut::<StatsData>();
-    *stats = metadata_stored;
-
-    let balances = ic::get_mut::<Balances>();
-    *balances = balances_stored;
+    ) = ic::stable_restore().expect("unknown stable state layout");
+    let state = StateV1 {
+        stats,
+        balances,
+        allowances,
+        delegates,
+        checkpoints,
+        tx_log,
+        ..Default::default()
+    };
+    (state, cap_env)
+}
 
-    let allowances = ic::get_mut::<Allowances>();
-    *allowances = allowances_stored;
+#[pre_upgrade]
+fn pre_upgrade() {
+    let state = StateV1 {
+        stats: STATS.with(|s| s.borrow().to_owned()),
+        balances: BALANCES.with(|b| b.borrow().to_owned()),
+        allowances: ALLOWANCES.with(|a| a.borrow().to_owned()),
+        delegates: DELEGATES.with(|d| d.borrow().to_owned()),
+        checkpoints: CHECKPOINTS.with(|c| c.borrow().to_owned()),
+        tx_log: TX_LOG.with(|l| l.borrow().to_owned()),
+        dividends: DIVIDENDS.with(|d| d.borrow().to_owned()),
+        soulbound: is_soulbound(),
+        roles: ROLE_HOLDERS.with(|r| r.borrow().to_owned()),
+        paused: is_paused(),
+        admin_log: ADMIN_LOG.with(|l| l.borrow().to_owned()),
+        user_stats: USER_STATS.with(|s| s.borrow().to_owned()),
+        cycles_deposits: CYCLES_DEPOSITS.with(|d| d.borrow().to_owned()),
+        transfer_limits: TRANSFER_LIMITS.with(|l| l.borrow().to_owned()),
+        daily_transfers: DAILY_TRANSFERS.with(|d| d.borrow().to_owned()),
+        listeners: LISTENERS.with(|l| l.borrow().to_owned()),
+        supply_check_points: SUPPLY_CHECKPOINTS.with(|c| c.borrow().to_owned()),
+        holder_history: HOLDER_HISTORY.with(|h| h.borrow().to_owned()),
+        check_point_archive: CHECKPOINT_ARCHIVE.with(|a| a.borrow().to_owned()),
+        upgrades: UPGRADES.with(|u| u.borrow().to_owned()),
+    };
+    ic::stable_store((StableState::V1(state), CapEnv::to_archive())).unwrap();
+}
 
-    let deletages = ic::get_mut::<Delegates>();
-    *deletages = delegates_stored;
+#[post_upgrade]
+fn post_upgrade() {
+    let (state, cap_env) = _restore_state();
+    STATS.with(|s| *s.borrow_mut() = state.stats);
+    BALANCES.with(|b| *b.borrow_mut() = state.balances);
+    ALLOWANCES.with(|a| *a.borrow_mut() = state.allowances);
+    DELEGATES.with(|d| *d.borrow_mut() = state.delegates);
+    CHECKPOINTS.with(|c| *c.borrow_mut() = state.checkpoints);
 
-    let checkpoints = ic::get_mut::<CheckPoints>();
-    *checkpoints = checkpoints_stored;
+    _rebuild_indices();
 
-    let tx_log = tx_log();
-    *tx_log = tx_log_stored;
+    TX_LOG.with(|l| *l.borrow_mut() = state.tx_log);
 
     CapEnv::load_from_archive(cap_env);
+
+    DIVIDENDS.with(|d| *d.borrow_mut() = state.dividends);
+    SOULBOUND.with(|s| s.set(state.soulbound));
+    ROLE_HOLDERS.with(|r| *r.borrow_mut() = state.roles);
+    PAUSED.with(|p| p.set(state.paused));
+    ADMIN_LOG.with(|l| *l.borrow_mut() = state.admin_log);
+    USER_STATS.with(|s| *s.borrow_mut() = state.user_stats);
+    CYCLES_DEPOSITS.with(|d| *d.borrow_mut() = state.cycles_deposits);
+    TRANSFER_LIMITS.with(|l| *l.borrow_mut() = state.transfer_limits);
+    DAILY_TRANSFERS.with(|d| *d.borrow_mut() = state.daily_transfers);
+    LISTENERS.with(|l| *l.borrow_mut() = state.listeners);
+
+    SUPPLY_CHECKPOINTS.with(|c| *c.borrow_mut() = state.supply_check_points);
+    if SUPPLY_CHECKPOINTS.with(|c| c.borrow().0.is_empty()) {
+        _write_supply_check_point();
+    }
+
+    HOLDER_HISTORY.with(|h| *h.borrow_mut() = state.holder_history);
+    CHECKPOINT_ARCHIVE.with(|a| *a.borrow_mut() = state.check_point_archive);
+
+    UPGRADES.with(|u| {
+        let mut upgrades = u.borrow_mut();
+        *upgrades = state.upgrades;
+        upgrades.push(ic::time());
+    });
 }
 
-async fn add_record(
+fn _tx_event(
     caller: Principal,
     op: Operation,
     from: Principal,
@@ -699,8 +2070,8 @@ async fn add_record(
     fee: Nat,
     timestamp: u64,
     status: TransactionStatus,
-) -> TxReceipt {
-    insert_into_cap(Into::<IndefiniteEvent>::into(Into::<Event>::into(Into::<
+) -> IndefiniteEvent {
+    Into::<IndefiniteEvent>::into(Into::<Event>::into(Into::<
         TypedEvent<DIP20Details>,
     >::into(
         TxRecord {
@@ -714,27 +2085,71 @@ async fn add_record(
             status,
             operation: op,
         },
-    ))))
-    .await
+    )))
 }
 
-pub async fn insert_into_cap(ie: IndefiniteEvent) -> TxReceipt {
-    let tx_log = tx_log();
-    if let Some(failed_ie) = tx_log.ie_records.pop_front() {
-        let _ = insert_into_cap_priv(failed_ie).await;
-    }
-    insert_into_cap_priv(ie).await
+/// queue the CAP event of a transaction already applied, see `_queue_event` for the receipt
+fn _queue_record(
+    caller: Principal,
+    op: Operation,
+    from: Principal,
+    to: Principal,
+    amount: Nat,
+    fee: Nat,
+    timestamp: u64,
+) -> TxReceipt {
+    _queue_event(_tx_event(caller, op, from, to, amount, fee, timestamp, TransactionStatus::Succeeded))
 }
 
-async fn insert_into_cap_priv(ie: IndefiniteEvent) -> TxReceipt {
-    let insert_res = insert(ie.clone())
-        .await
-        .map(|tx_id| Nat::from(tx_id))
-        .map_err(|_| TxError::Other);
+/// queue the CAP event of the last transaction. the receipt of every transaction is its index in the
+/// local history, history_size - 1, and never a CAP id, so the outcome is settled before the first
+/// await and does not depend on CAP
+fn _queue_event(event: IndefiniteEvent) -> TxReceipt {
+    TX_LOG.with(|l| l.borrow_mut().ie_records.push_back(event));
+    Ok(Nat::from(history_size() - 1))
+}
 
-    if insert_res.is_err() {
-        tx_log().ie_records.push_back(ie.clone());
+/// insert queued CAP events oldest first, at most MAX_FLUSH_RECORDS. one flush runs at a time and an
+/// event leaves the queue once inserted, so CAP receives the events in the order they were applied
+/// and a failed event stays first for later calls
+async fn _flush_records() {
+    let now = ic::time();
+    let running = FLUSH_STARTED.with(|f| f.get() != 0 && now < f.get() + FLUSH_TIMEOUT);
+    if running {
+        return;
+    }
+    FLUSH_STARTED.with(|f| f.set(now));
+    for _ in 0..MAX_FLUSH_RECORDS {
+        let event = match TX_LOG.with(|l| l.borrow().ie_records.front().cloned()) {
+            Some(event) => event,
+            None => break,
+        };
+        if insert(event).await.is_err() {
+            break;
+        }
+        TX_LOG.with(|l| l.borrow_mut().ie_records.pop_front());
     }
+    FLUSH_STARTED.with(|f| f.set(0));
+}
 
-    insert_res
+/// insert the CAP event of an admin mutation and keep it in the local admin log
+async fn _record_admin(operation: &str, details: Vec<(String, DetailValue)>) {
+    let caller = ic::caller();
+    ADMIN_LOG.with(|l| {
+        l.borrow_mut().0.push(AdminRecord {
+            caller,
+            operation: operation.to_string(),
+            details: details.clone(),
+            timestamp: ic::time(),
+        })
+    });
+    let event = IndefiniteEventBuilder::new()
+        .caller(caller)
+        .operation(operation.to_string())
+        .details(details)
+        .build()
+        .unwrap();
+    // admin events have no history index, they are queued behind the transactions applied before them
+    TX_LOG.with(|l| l.borrow_mut().ie_records.push_back(event));
+    _flush_records().await;
 }
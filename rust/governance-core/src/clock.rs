@@ -0,0 +1,66 @@
+/**
+ * Module     : clock.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// source of the timestamps passed to GovernorBravo and Timelock, in nanoseconds
+pub trait TimeProvider {
+    fn now(&self) -> u64;
+}
+
+/// time of the IC, system time outside of wasm
+#[derive(Clone, Copy, Default, Debug)]
+pub struct IcClock;
+
+impl TimeProvider for IcClock {
+    #[cfg(target_family = "wasm")]
+    fn now(&self) -> u64 {
+        ic_cdk::api::time()
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    fn now(&self) -> u64 {
+        use std::time::{SystemTime, UNIX_EPOCH};
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_nanos() as u64
+    }
+}
+
+/// manually driven clock, clones share the same time
+#[derive(Clone, Default, Debug)]
+pub struct MockClock {
+    time: Rc<Cell<u64>>,
+}
+
+impl MockClock {
+    pub fn new(timestamp: u64) -> Self {
+        Self {
+            time: Rc::new(Cell::new(timestamp)),
+        }
+    }
+
+    pub fn set(&self, timestamp: u64) {
+        self.time.set(timestamp);
+    }
+
+    /// move the clock forward, return the new time
+    pub fn advance(&self, duration: u64) -> u64 {
+        let timestamp = self.time.get() + duration;
+        self.time.set(timestamp);
+        timestamp
+    }
+}
+
+impl TimeProvider for MockClock {
+    fn now(&self) -> u64 {
+        self.time.get()
+    }
+}
@@ -0,0 +1,187 @@
+/**
+ * Module     : counting.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+//! Vote counting strategies. A strategy decides how many votes a voter's
+//! holding counts for and whether a tally carries the proposal, the governor
+//! only applies the quorum and the tie rule on top.
+
+use candid::{CandidType, Deserialize, Nat};
+use governance_types::Tally;
+
+use crate::governance::GovernorBravo;
+
+type GovernResult<R> = Result<R, &'static str>;
+
+/// basis points of a whole
+pub const MAX_BPS: u64 = 10_000;
+
+pub trait CountingStrategy {
+    /// votes counted on the proposal for a voter holding the votes
+    fn counted_votes(&self, votes: Nat) -> GovernResult<Nat>;
+    /// whether the tally carries the proposal, none on a tie settled by the tie rule
+    fn carried(&self, tally: &Tally) -> Option<bool>;
+}
+
+/// support must outweigh against
+fn simple_majority(tally: &Tally) -> Option<bool> {
+    if tally.support_votes == tally.against_votes {
+        return None;
+    }
+    Some(tally.support_votes > tally.against_votes)
+}
+
+/// support must reach the share of the total, in basis points
+fn share_reached(support: &Nat, total: Nat, threshold_bps: u64) -> bool {
+    *support > 0u64 && support.clone() * Nat::from(MAX_BPS) >= total * Nat::from(threshold_bps)
+}
+
+/// one vote per token vote, simple majority
+pub struct SimpleMajority;
+
+impl CountingStrategy for SimpleMajority {
+    fn counted_votes(&self, votes: Nat) -> GovernResult<Nat> {
+        Ok(votes)
+    }
+
+    fn carried(&self, tally: &Tally) -> Option<bool> {
+        simple_majority(tally)
+    }
+}
+
+/// one vote per voter holding at least the minimum votes, simple majority
+pub struct OnePrincipalOneVote {
+    pub min_votes: u64,
+}
+
+impl CountingStrategy for OnePrincipalOneVote {
+    fn counted_votes(&self, votes: Nat) -> GovernResult<Nat> {
+        if votes < self.min_votes {
+            return Err("votes below the minimum to vote");
+        }
+        Ok(Nat::from(1))
+    }
+
+    fn carried(&self, tally: &Tally) -> Option<bool> {
+        simple_majority(tally)
+    }
+}
+
+/// one vote per token vote, support must reach the share of support and against votes
+pub struct Supermajority {
+    pub threshold_bps: u64,
+}
+
+impl CountingStrategy for Supermajority {
+    fn counted_votes(&self, votes: Nat) -> GovernResult<Nat> {
+        Ok(votes)
+    }
+
+    fn carried(&self, tally: &Tally) -> Option<bool> {
+        let total = tally.support_votes.clone() + tally.against_votes.clone();
+        Some(share_reached(&tally.support_votes, total, self.threshold_bps))
+    }
+}
+
+/// square root of the token votes, simple majority
+pub struct Quadratic;
+
+impl CountingStrategy for Quadratic {
+    fn counted_votes(&self, votes: Nat) -> GovernResult<Nat> {
+        Ok(Nat(votes.0.sqrt()))
+    }
+
+    fn carried(&self, tally: &Tally) -> Option<bool> {
+        simple_majority(tally)
+    }
+}
+
+/// one vote per token vote, support must reach the share of all votes cast, abstentions included
+pub struct Approval {
+    pub threshold_bps: u64,
+}
+
+impl CountingStrategy for Approval {
+    fn counted_votes(&self, votes: Nat) -> GovernResult<Nat> {
+        Ok(votes)
+    }
+
+    fn carried(&self, tally: &Tally) -> Option<bool> {
+        let total = tally.support_votes.clone() + tally.against_votes.clone() + tally.abstain_votes.clone();
+        Some(share_reached(&tally.support_votes, total, self.threshold_bps))
+    }
+}
+
+/// how the votes on a proposal are counted, set per governor or per proposal type
+#[derive(Deserialize, CandidType, Clone, Copy, PartialEq, Debug)]
+pub enum CountingMode {
+    /// one vote per token vote, simple majority
+    TokenWeighted,
+    /// one vote per voter holding at least the given votes, simple majority
+    OnePrincipalOneVote(u64),
+    /// support must reach the given basis points of support and against votes
+    Supermajority(u64),
+    /// square root of the token votes, simple majority
+    Quadratic,
+    /// support must reach the given basis points of all votes cast
+    Approval(u64),
+    /// one vote per token vote, votes held for less than the given duration count linearly less, simple majority
+    Ramped(u64),
+}
+
+impl Default for CountingMode {
+    fn default() -> Self {
+        CountingMode::TokenWeighted
+    }
+}
+
+impl CountingMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CountingMode::TokenWeighted => "tokenWeighted",
+            CountingMode::OnePrincipalOneVote(_) => "onePrincipalOneVote",
+            CountingMode::Supermajority(_) => "supermajority",
+            CountingMode::Quadratic => "quadratic",
+            CountingMode::Approval(_) => "approval",
+            CountingMode::Ramped(_) => "ramped",
+        }
+    }
+
+    /// check the thresholds are meaningful shares
+    pub fn validate(&self) -> GovernResult<()> {
+        match *self {
+            CountingMode::Supermajority(bps) if bps <= MAX_BPS / 2 || bps > MAX_BPS => {
+                Err("supermajority threshold must be above half and at most the whole")
+            }
+            CountingMode::Approval(bps) if bps == 0 || bps > MAX_BPS => {
+                Err("approval threshold must be above zero and at most the whole")
+            }
+            CountingMode::Ramped(ramp) if ramp == 0 || ramp > GovernorBravo::MAX_HOLDING_DURATION => {
+                Err("ramp must be above zero and at most the maximum holding duration")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn strategy(&self) -> Box<dyn CountingStrategy> {
+        match *self {
+            CountingMode::TokenWeighted | CountingMode::Ramped(_) => Box::new(SimpleMajority),
+            CountingMode::OnePrincipalOneVote(min_votes) => Box::new(OnePrincipalOneVote { min_votes }),
+            CountingMode::Supermajority(threshold_bps) => Box::new(Supermajority { threshold_bps }),
+            CountingMode::Quadratic => Box::new(Quadratic),
+            CountingMode::Approval(threshold_bps) => Box::new(Approval { threshold_bps }),
+        }
+    }
+
+    /// duration over which votes ramp up to count in full, none if they count in full at once
+    pub fn ramp(&self) -> Option<u64> {
+        match *self {
+            CountingMode::Ramped(ramp) => Some(ramp),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,2693 @@
+/**
+ * Module     : governance.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+use candid::{CandidType, Deserialize, Nat, Principal};
+use crate::legacy;
+use crate::stable::{Memory, Position, StableMemory};
+pub use crate::counting::{CountingMode, MAX_BPS};
+use crate::timelock::{AnnouncedChange, Backend, BACKEND_KINDS, ExecutionWindow, GovernorParam, ONE_DAY, ParamChange, Task, Timelock};
+pub use governance_types::{ProposalState, Tally, VoteType};
+
+type GovernResult<R> = Result<R, &'static str>;
+
+#[derive(Deserialize, CandidType, Clone)]
+pub struct GovernorBravo {
+    pub admin: Principal,
+    pub pending_admin: Option<Principal>,
+
+    /// name for the governance
+    name: String,
+
+    /// number of votes in support of a proposal required
+    /// in order for a quorum to be reached and for a vote to succeed
+    quorum_votes: Nat,
+    /// quorum in basis points of the total supply at proposal creation, 0 to use quorum_votes
+    #[serde(default)]
+    quorum_fraction: u64,
+    /// bounds of the quorum a single proposal may be given, none disables overrides
+    #[serde(default)]
+    quorum_override_bounds: Option<(Nat, Nat)>,
+    /// bounds of the voting period a proposer may request, none disables overrides
+    #[serde(default)]
+    voting_period_bounds: Option<(u64, u64)>,
+    /// which votes are counted against the quorum
+    #[serde(default)]
+    quorum_mode: QuorumMode,
+    /// outcome of proposals with as many support as against votes
+    #[serde(default)]
+    tie_rule: TieRule,
+    /// delay before voting on a proposal may take place, once proposed
+    voting_delay: u64,
+    /// duration of voting on a proposal
+    voting_period: u64,
+    /// number of votes required in order for a voter to become a proposer
+    proposal_threshold: Nat,
+    /// votes only count if held for this long before the vote, 0 to disable
+    #[serde(default)]
+    min_holding_duration: u64,
+    /// proposer votes must have been above the proposal threshold for this long, 0 to disable
+    #[serde(default)]
+    min_delegation_age: u64,
+    /// token balance required to comment on proposals, 0 to disable
+    #[serde(default)]
+    min_comment_balance: u64,
+    /// a task identical to a defeated proposal's may only be proposed again this long after voting ended, 0 to disable
+    #[serde(default)]
+    defeat_cooldown: u64,
+    /// gov tokens a proposer locks until the proposal is settled, 0 to disable
+    #[serde(default)]
+    proposal_deposit: Nat,
+    /// account receiving slashed deposits, none keeps them in the governor
+    #[serde(default)]
+    deposit_treasury: Option<Principal>,
+    /// deposits of refused proposals whose refund failed, claimable by the depositor
+    #[serde(default)]
+    unpaid_refunds: HashMap<Principal, Nat>,
+    /// whether voting closes once its outcome can no longer change
+    #[serde(default)]
+    early_finish: bool,
+    /// counting mode of proposal types without their own
+    #[serde(default)]
+    default_counting: CountingMode,
+    /// counting mode by proposal type, the default counting mode if absent
+    #[serde(default)]
+    counting_modes: HashMap<String, CountingMode>,
+    /// execution window by proposal type, the grace period after the eta if absent
+    #[serde(default)]
+    execution_windows: HashMap<String, ExecutionWindow>,
+    /// cycles a task may attach, 0 to disable
+    #[serde(default)]
+    max_task_cycles: u64,
+    /// cycles a task calling the target may attach, below the governor-wide cap
+    #[serde(default)]
+    target_cycle_limits: HashMap<Principal, u64>,
+    /// record of all proposals ever proposed
+    proposals: Vec<Proposal>,
+    /// latest proposal for each proposer
+    latest_proposal_ids: HashMap<Principal, usize>,
+    /// proposals ordered by the end of voting
+    #[serde(default)]
+    end_time_index: BTreeSet<(u64, usize)>,
+    /// proposals ordered by votes cast, abstentions included
+    #[serde(default)]
+    votes_index: BTreeSet<(Nat, usize)>,
+
+    /// whether this bravo has initialized
+    initialized: bool,
+    /// satellite canisters whose tallies are merged into proposals once voting ends
+    #[serde(default)]
+    satellites: Vec<Principal>,
+    /// principals allowed to execute queued proposals, anyone if empty
+    #[serde(default)]
+    executors: Vec<Principal>,
+
+    pub gov_token: Principal,
+    pub timelock: Timelock,
+    pub stable_memory: StableMemory,
+    /// record of the text shown instead of redacted vote reasons, written on the first redaction
+    #[serde(default)]
+    reason_tombstone: Option<Position>,
+}
+
+/// votes counted against the quorum of a proposal
+#[derive(Deserialize, CandidType, Clone, Copy, PartialEq, Debug)]
+pub enum QuorumMode {
+    /// support votes only
+    Support,
+    /// support, against and abstain votes
+    Participation,
+}
+
+impl Default for QuorumMode {
+    fn default() -> Self {
+        QuorumMode::Support
+    }
+}
+
+/// outcome of a proposal with equal support and against votes
+#[derive(Deserialize, CandidType, Clone, Copy, PartialEq, Debug)]
+pub enum TieRule {
+    /// support must be strictly greater than against
+    Defeat,
+    /// support greater or equal to against is enough
+    Succeed,
+    /// ties follow the proposer's own vote, defeated if the proposer did not support
+    Proposer,
+}
+
+impl Default for TieRule {
+    fn default() -> Self {
+        TieRule::Defeat
+    }
+}
+
+/// what a proposal does once it succeeds
+#[derive(Deserialize, CandidType, Clone, Copy, PartialEq, Debug)]
+pub enum ProposalKind {
+    /// the task is queued in the timelock and executed
+    Executable,
+    /// non-binding signal vote without a task, closed once succeeded
+    Text,
+    /// vote between labeled options without a task, closed once an option won
+    Options,
+}
+
+impl Default for ProposalKind {
+    fn default() -> Self {
+        ProposalKind::Executable
+    }
+}
+
+/// order of the proposals listed by `get_proposal_pages`
+#[derive(Deserialize, CandidType, Clone, Copy, PartialEq, Debug)]
+pub enum ProposalSort {
+    IdAsc,
+    IdDesc,
+    EndTimeAsc,
+    EndTimeDesc,
+    /// votes cast, abstentions included
+    TotalVotesAsc,
+    TotalVotesDesc,
+}
+
+impl Default for ProposalSort {
+    fn default() -> Self {
+        ProposalSort::IdDesc
+    }
+}
+
+#[derive(CandidType)]
+pub struct GovernorBravoInfo {
+    admin: Principal,
+    pending_admin: Option<Principal>,
+    /// name for the governance
+    name: String,
+    /// number of votes in support of a proposal required
+    /// in order for a quorum to be reached and for a vote to succeed
+    quorum_votes: Nat,
+    /// quorum in basis points of the total supply at proposal creation, 0 to use quorum_votes
+    quorum_fraction: u64,
+    /// bounds of the quorum a single proposal may be given, none disables overrides
+    quorum_override_bounds: Option<(Nat, Nat)>,
+    /// bounds of the voting period a proposer may request, none disables overrides
+    voting_period_bounds: Option<(u64, u64)>,
+    /// which votes are counted against the quorum
+    quorum_mode: QuorumMode,
+    /// outcome of proposals with as many support as against votes
+    tie_rule: TieRule,
+    /// delay before voting on a proposal may take place, once proposed
+    voting_delay: u64,
+    /// duration of voting on a proposal
+    voting_period: u64,
+    /// number of votes required in order for a voter to become a proposer
+    proposal_threshold: Nat,
+    /// votes only count if held for this long before the vote, 0 to disable
+    min_holding_duration: u64,
+    /// proposer votes must have been above the proposal threshold for this long, 0 to disable
+    min_delegation_age: u64,
+    /// token balance required to comment on proposals, 0 to disable
+    min_comment_balance: u64,
+    /// a task identical to a defeated proposal's may only be proposed again this long after voting ended, 0 to disable
+    defeat_cooldown: u64,
+    /// gov tokens a proposer locks until the proposal is settled, 0 to disable
+    proposal_deposit: Nat,
+    /// account receiving slashed deposits, none keeps them in the governor
+    deposit_treasury: Option<Principal>,
+    /// whether voting closes once its outcome can no longer change
+    early_finish: bool,
+    /// counting mode of proposal types without their own
+    default_counting: CountingMode,
+    /// counting mode by proposal type, the default counting mode if absent
+    counting_modes: Vec<(String, CountingMode)>,
+    /// execution window by proposal type, the grace period after the eta if absent
+    execution_windows: Vec<(String, ExecutionWindow)>,
+    /// cycles a task may attach, 0 to disable
+    max_task_cycles: u64,
+    /// cycles a task calling the target may attach, below the governor-wide cap
+    target_cycle_limits: Vec<(Principal, u64)>,
+    /// number of proposal record ever proposed
+    proposals_num: usize,
+    /// satellite canisters whose tallies are merged into proposals once voting ends
+    satellites: Vec<Principal>,
+    /// principals allowed to execute queued proposals, anyone if empty
+    executors: Vec<Principal>,
+    /// delay between queueing and execution
+    timelock_delay: u64,
+    /// period a queued task stays executable after its eta
+    grace_period: u64,
+    /// number of tasks waiting in the timelock
+    queued_tasks: usize,
+    /// earliest eta of the queued tasks
+    next_eta: Option<u64>,
+
+    gov_token: Principal,
+    stable_memory: StableMemory,
+}
+
+#[derive(Deserialize, CandidType, Clone)]
+pub struct Proposal {
+    /// id of the proposal
+    id: usize,
+    /// Creator of the proposal
+    pub proposer: Principal,
+    /// Title of this proposal
+    title: String,
+    // may limit its length
+    /// Description of this proposal
+    description: Position,
+    /// proposal task to action
+    pub task: Task,
+    /// The time at which voting begins: holders must delegate their votes prior to this timestamp
+    start_time: u64,
+    /// The time at which voting ends: votes must be cast prior to this timestamp
+    end_time: u64,
+    /// Current number of votes in favor of this proposal
+    support_votes: Nat,
+    /// Current number of votes in opposition to this proposal
+    against_votes: Nat,
+    /// Current number of votes for abstaining for this proposal
+    abstain_votes: Nat,
+    /// Flag marking whether the proposal has been canceled
+    canceled: bool,
+    /// Flag marking whether the proposal is executing
+    executing: bool,
+    /// Flag marking whether the proposal has been executed
+    executed: bool,
+    /// Receipts of ballots for the entire set of voters
+    pub receipts: HashMap<Principal, Receipt>,
+    /// Satellites whose tallies have not been merged yet
+    #[serde(default)]
+    pending_satellites: Vec<Principal>,
+    /// how votes are counted, fixed at creation
+    #[serde(default)]
+    counting: CountingMode,
+    /// governor parameters the proposal is judged by, fixed at creation
+    #[serde(default)]
+    config: ProposalConfig,
+    /// cycles attached to the task over all execution attempts
+    #[serde(default)]
+    attached_cycles: u64,
+    /// cycles refunded by the target over all execution attempts
+    #[serde(default)]
+    refunded_cycles: u64,
+    /// cycles added to the task after proposing, by funder
+    #[serde(default)]
+    funded_cycles: HashMap<Principal, u64>,
+    /// votes cast off-chain merged into the tallies
+    #[serde(default)]
+    offchain_tally: Option<Tally>,
+    /// quorum required instead of the governor's, set before voting starts
+    #[serde(default)]
+    quorum_override: Option<Nat>,
+    /// discussion thread, deleted comments are kept as tombstones
+    #[serde(default)]
+    comments: Vec<Comment>,
+    /// implicit states already recorded as transitions
+    #[serde(default)]
+    recorded_states: Vec<ProposalState>,
+    /// final state given by the previous governor, for imported proposals
+    #[serde(default)]
+    imported_state: Option<ProposalState>,
+    /// highest total supply seen while voting, bounding the votes that can still be cast
+    #[serde(default)]
+    max_votes: Option<Nat>,
+    /// executed before lower priorities once executable, 0 by default
+    #[serde(default)]
+    priority: u8,
+    /// calls of a multi-call task which succeeded, skipped when execution is retried
+    #[serde(default)]
+    executed_calls: usize,
+    /// text proposals carry a placeholder task which is never queued
+    #[serde(default)]
+    kind: ProposalKind,
+    /// tokens locked by the proposer, none if proposed without a deposit
+    #[serde(default)]
+    deposit: Option<Deposit>,
+    /// labels of the options of a multi-choice proposal, whose votes are also counted as support
+    #[serde(default)]
+    options: Vec<String>,
+    /// votes by option, in the order of the options
+    #[serde(default)]
+    option_votes: Vec<Nat>,
+}
+
+#[derive(Deserialize, CandidType, Clone)]
+pub struct ProposalInfo {
+    /// id of the proposal
+    pub id: usize,
+    /// Creator of the proposal
+    pub proposer: Principal,
+    /// Title of this proposal
+    pub title: String,
+    // may limit its length
+    /// Description of this proposal
+    pub description: String,
+    /// proposal task to action
+    pub task: Task,
+    /// The time at which voting begins: holders must delegate their votes prior to this timestamp
+    pub start_time: u64,
+    /// The time at which voting ends: votes must be cast prior to this timestamp
+    pub end_time: u64,
+    /// Current number of votes in favor of this proposal
+    pub support_votes: Nat,
+    /// Current number of votes in opposition to this proposal
+    pub against_votes: Nat,
+    /// Current number of votes for abstaining for this proposal
+    pub abstain_votes: Nat,
+    /// Flag marking whether the proposal has been canceled
+    pub canceled: bool,
+    /// Flag marking whether the proposal is executing
+    pub executing: bool,
+    /// Flag marking whether the proposal has been executed
+    pub executed: bool,
+    /// Satellites whose tallies have not been merged yet
+    pub pending_satellites: Vec<Principal>,
+    /// how votes are counted, fixed at creation
+    pub counting: CountingMode,
+    /// governor parameters the proposal is judged by, fixed at creation
+    pub config: ProposalConfig,
+    /// cycles attached to the task over all execution attempts
+    pub attached_cycles: u64,
+    /// cycles refunded by the target over all execution attempts
+    pub refunded_cycles: u64,
+    /// cycles added to the task after proposing, by funder
+    pub funded_cycles: Vec<(Principal, u64)>,
+    /// quorum required instead of the governor's
+    pub quorum_override: Option<Nat>,
+    /// executed before lower priorities once executable
+    pub priority: u8,
+    /// calls of a multi-call task which succeeded
+    pub executed_calls: usize,
+    pub kind: ProposalKind,
+    pub deposit: Option<Deposit>,
+    pub options: Vec<String>,
+    pub option_votes: Vec<Nat>,
+    /// option with the most votes, none without votes or on a tie
+    pub winning_option: Option<usize>,
+}
+
+/// closed proposal of a previous governor, imported with its final tallies and state
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub struct ProposalImport {
+    pub proposer: Principal,
+    pub title: String,
+    pub description: String,
+    pub task: Task,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub support_votes: Nat,
+    pub against_votes: Nat,
+    pub abstain_votes: Nat,
+    /// one of canceled, defeated, executed or expired
+    pub state: ProposalState,
+}
+
+#[derive(Deserialize, CandidType, Clone, Copy, PartialEq, Debug)]
+pub enum DepositStatus {
+    Held,
+    Refunded,
+    Slashed,
+}
+
+/// gov tokens locked by the proposer until the proposal is settled
+#[derive(Deserialize, CandidType, Clone, Debug, PartialEq)]
+pub struct Deposit {
+    pub depositor: Principal,
+    pub amount: Nat,
+    /// canceled by a guardian veto or by someone other than the proposer, slashed instead of refunded
+    pub forfeited: bool,
+    pub status: DepositStatus,
+}
+
+/// governor parameters at the creation of a proposal, later changes do not apply to it
+#[derive(Deserialize, CandidType, Clone, Debug, PartialEq, Default)]
+pub struct ProposalConfig {
+    /// votes required for a quorum, unless overridden for the proposal
+    pub quorum_votes: Nat,
+    /// basis points of the total supply the quorum votes were computed from, 0 if absolute
+    pub quorum_fraction: u64,
+    /// votes below which anyone may cancel the proposal
+    pub proposal_threshold: Nat,
+    pub voting_period: u64,
+    /// whether voting closes once its outcome can no longer change
+    pub early_finish: bool,
+    /// which votes are counted against the quorum
+    #[serde(default)]
+    pub quorum_mode: QuorumMode,
+    /// outcome of a tie between support and against votes
+    #[serde(default)]
+    pub tie_rule: TieRule,
+}
+
+#[derive(CandidType, Clone)]
+pub struct ProposalDigest {
+    /// id of the proposal
+    id: usize,
+    /// Creator of the proposal
+    proposer: Principal,
+    /// Title of this proposal
+    title: String,
+    // may limit its length
+    /// The time at which voting begins: holders must delegate their votes prior to this timestamp
+    start_time: u64,
+    /// The time at which voting ends: votes must be cast prior to this timestamp
+    end_time: u64,
+    /// Current number of votes in favor of this proposal
+    support_votes: Nat,
+    /// Current number of votes in opposition to this proposal
+    against_votes: Nat,
+    /// Current number of votes for abstaining for this proposal
+    abstain_votes: Nat,
+    /// Number of voter
+    receipt_num: usize,
+}
+
+impl ProposalDigest {
+    pub fn id(&self) -> usize {
+        self.id
+    }
+}
+
+#[derive(Deserialize, CandidType, Clone)]
+pub struct Comment {
+    /// id of the comment, its index in the thread
+    id: usize,
+    author: Principal,
+    /// text of the comment in stable memory
+    text: Position,
+    created_at: u64,
+    /// time of the last edit, if any
+    edited_at: Option<u64>,
+    deleted: bool,
+}
+
+#[derive(CandidType, Clone, Debug)]
+pub struct CommentInfo {
+    pub id: usize,
+    pub author: Principal,
+    pub text: String,
+    pub created_at: u64,
+    pub edited_at: Option<u64>,
+}
+
+/// remaining durations of a proposal in nanoseconds, 0 once passed
+#[derive(CandidType, Clone, Debug)]
+pub struct Timeline {
+    /// canister time the durations are computed from
+    pub now: u64,
+    pub until_voting_start: u64,
+    pub until_voting_end: u64,
+    /// none until the proposal is queued
+    pub until_eta: Option<u64>,
+    /// end of the grace period, none until the proposal is queued
+    pub until_expiry: Option<u64>,
+}
+
+/// progress of a proposal towards its quorum and majority
+#[derive(CandidType, Clone, Debug)]
+pub struct QuorumStatus {
+    pub support_votes: Nat,
+    pub against_votes: Nat,
+    pub abstain_votes: Nat,
+    /// votes required for the quorum of the proposal
+    pub quorum: Nat,
+    /// votes counted against the quorum under the quorum mode
+    pub quorum_votes: Nat,
+    /// share of the quorum reached in basis points, above MAX_BPS once exceeded
+    pub quorum_bps: u64,
+    pub quorum_reached: bool,
+    pub majority_reached: bool,
+    /// whether the proposal would pass if voting ended now
+    pub would_pass: bool,
+}
+
+/// result of `propose_eligibility`
+#[derive(CandidType, Clone, Debug)]
+pub struct ProposeEligibility {
+    pub eligible: bool,
+    /// votes of the proposer, the lowest held over the minimum delegation age if set
+    pub votes: Nat,
+    /// votes must be above it to propose
+    pub proposal_threshold: Nat,
+    /// live proposal of the proposer preventing a new one
+    pub live_proposal: Option<usize>,
+    /// why the proposer cannot propose
+    pub reason: Option<String>,
+}
+
+/// queued proposal waiting for execution
+#[derive(CandidType, Clone, Debug)]
+pub struct UpcomingExecution {
+    pub id: usize,
+    pub title: String,
+    pub eta: u64,
+    /// 0 once the proposal is executable
+    pub until_eta: u64,
+    /// end of the grace period
+    pub until_expiry: u64,
+    pub priority: u8,
+}
+
+/// broken invariant found by `check_invariants`
+#[derive(CandidType, Clone, Debug)]
+pub struct Violation {
+    /// proposal concerned, if any
+    pub proposal: Option<usize>,
+    pub message: String,
+}
+
+impl Violation {
+    fn new(proposal: Option<usize>, message: String) -> Self {
+        Self {
+            proposal,
+            message,
+        }
+    }
+}
+
+impl Proposal {
+    fn new(
+        id: usize,
+        proposer: Principal,
+        title: String,
+        description: Position,
+        task: Task,
+        start_time: u64,
+        end_time: u64,
+    ) -> Self {
+        Self {
+            id,
+            proposer,
+            title,
+            description,
+            task,
+            start_time,
+            end_time,
+            support_votes: Nat::from(0),
+            against_votes: Nat::from(0),
+            abstain_votes: Nat::from(0),
+            canceled: false,
+            executed: false,
+            executing: false,
+            receipts: HashMap::new(),
+            pending_satellites: vec![],
+            counting: CountingMode::TokenWeighted,
+            config: ProposalConfig::default(),
+            attached_cycles: 0,
+            refunded_cycles: 0,
+            funded_cycles: HashMap::new(),
+            offchain_tally: None,
+            quorum_override: None,
+            comments: vec![],
+            recorded_states: vec![],
+            imported_state: None,
+            max_votes: None,
+            priority: 0,
+            executed_calls: 0,
+            kind: ProposalKind::Executable,
+            deposit: None,
+            options: vec![],
+            option_votes: vec![],
+        }
+    }
+
+    fn to_info(&self, description: String) -> ProposalInfo {
+        ProposalInfo {
+            id: self.id,
+            proposer: self.proposer,
+            title: self.title.clone(),
+            description,
+            task: self.task.clone(),
+            start_time: self.start_time,
+            end_time: self.end_time,
+            support_votes: self.support_votes.to_owned(),
+            against_votes: self.against_votes.to_owned(),
+            abstain_votes: self.abstain_votes.to_owned(),
+            canceled: self.canceled,
+            executing: self.executing,
+            executed: self.executed,
+            pending_satellites: self.pending_satellites.clone(),
+            counting: self.counting,
+            config: self.config.clone(),
+            attached_cycles: self.attached_cycles,
+            refunded_cycles: self.refunded_cycles,
+            funded_cycles: self.funded_cycles.iter().map(|(f, c)| (*f, *c)).collect(),
+            quorum_override: self.quorum_override.clone(),
+            priority: self.priority,
+            executed_calls: self.executed_calls,
+            kind: self.kind,
+            deposit: self.deposit.clone(),
+            options: self.options.clone(),
+            option_votes: self.option_votes.clone(),
+            winning_option: self.winning_option(),
+        }
+    }
+
+    /// take the votes of the receipt out of the tallies, before the voter's new ballot is counted
+    fn withdraw(&mut self, receipt: &Receipt) {
+        let votes = receipt.votes.clone();
+        if let Some(option) = receipt.option {
+            self.option_votes[option] = self.option_votes[option].clone() - votes.clone();
+        }
+        match receipt.vote_type {
+            VoteType::Support => { self.support_votes = self.support_votes.clone() - votes; }
+            VoteType::Against => { self.against_votes = self.against_votes.clone() - votes; }
+            VoteType::Abstain => { self.abstain_votes = self.abstain_votes.clone() - votes; }
+        }
+    }
+
+    /// option with strictly the most votes, none without votes or on a tie
+    fn winning_option(&self) -> Option<usize> {
+        let (winner, votes) = self.option_votes.iter().enumerate().max_by(|a, b| a.1.cmp(b.1))?;
+        if *votes == 0u64 || self.option_votes.iter().filter(|v| *v == votes).count() > 1 {
+            return None;
+        }
+        Some(winner)
+    }
+
+    fn digest(&self) -> ProposalDigest {
+        ProposalDigest {
+            id: self.id,
+            proposer: self.proposer,
+            title: self.title.clone(),
+            start_time: self.start_time,
+            end_time: self.end_time,
+            support_votes: self.support_votes.to_owned(),
+            against_votes: self.against_votes.to_owned(),
+            abstain_votes: self.abstain_votes.to_owned(),
+            receipt_num: self.receipts.len(),
+        }
+    }
+
+    fn total_votes(&self) -> Nat {
+        self.support_votes.clone() + self.against_votes.clone() + self.abstain_votes.clone()
+    }
+
+    fn tally(&self) -> Tally {
+        Tally {
+            support_votes: self.support_votes.to_owned(),
+            against_votes: self.against_votes.to_owned(),
+            abstain_votes: self.abstain_votes.to_owned(),
+        }
+    }
+}
+
+#[derive(Deserialize, CandidType, Clone)]
+pub struct Receipt {
+    /// Whether or not the voter supports the proposal or abstains
+    vote_type: VoteType,
+    /// votes number
+    votes: Nat,
+    /// optional: voting reason
+    reason: Option<Position>,
+    /// token votes of the voter split by origin, if known
+    breakdown: Option<VotesBreakdown>,
+    /// option voted for on a multi-choice proposal
+    option: Option<usize>,
+    /// times the voter changed its vote, none if never
+    changes: Option<u32>,
+}
+
+/// token votes of a voter from its own balance and from delegators
+#[derive(Deserialize, CandidType, Clone, Debug, PartialEq)]
+pub struct VotesBreakdown {
+    pub own: Nat,
+    pub delegated: Nat,
+}
+
+#[derive(Deserialize, CandidType, Clone)]
+pub struct ReceiptInfo {
+    pub vote_type: VoteType,
+    pub votes: Nat,
+    pub reason: Option<String>,
+    pub breakdown: Option<VotesBreakdown>,
+    pub option: Option<usize>,
+    /// times the voter changed its vote
+    pub changes: u32,
+}
+
+#[derive(Deserialize, CandidType, Clone)]
+pub struct ReceiptDigest {
+    vote_type: VoteType,
+    votes: Nat,
+}
+
+/// number of voters by vote type
+#[derive(Deserialize, CandidType, Clone, Debug, Default, PartialEq)]
+pub struct ReceiptCounts {
+    pub support: u64,
+    pub against: u64,
+    pub abstain: u64,
+}
+
+impl Receipt {
+    fn new(vote_type: VoteType, votes: Nat, reason: Option<Position>) -> Self {
+        Self {
+            vote_type,
+            votes,
+            reason,
+            breakdown: None,
+            option: None,
+            changes: None,
+        }
+    }
+
+    fn digest(&self) -> ReceiptDigest {
+        ReceiptDigest {
+            votes: self.votes.clone(),
+            vote_type: self.vote_type.clone(),
+        }
+    }
+
+    fn to_info(&self, reason: Option<String>) -> ReceiptInfo {
+        ReceiptInfo {
+            vote_type: self.vote_type.clone(),
+            votes: self.votes.clone(),
+            reason,
+            breakdown: self.breakdown.clone(),
+            option: self.option,
+            changes: self.changes.unwrap_or(0),
+        }
+    }
+}
+
+impl GovernorBravo {
+    /// minimum proposal threshold, 50000 TOKEN
+    pub const MIN_PROPOSAL_THRESHOLD: u64 = 50000e8 as u64;
+    /// maximum proposal threshold, 100000 TOKEN
+    pub const MAX_PROPOSAL_THRESHOLD: u64 = 100000e8 as u64;
+    /// minimum voting period, 1 day
+    pub const MIN_VOTING_PERIOD: u64 = ONE_DAY;
+    /// maximum voting period, 2 weeks
+    pub const MAX_VOTING_PERIOD: u64 = 14 * ONE_DAY;
+    /// minimum voting delay, 1 ns
+    pub const MIN_VOTING_DELAY: u64 = 1;
+    /// maximum voting delay: 7 day
+    pub const MAX_VOTING_DELAY: u64 = 7 * ONE_DAY;
+    /// maximum bytes returned by one stable memory export, 1 MiB
+    pub const MAX_EXPORT_CHUNK: usize = 1 << 20;
+    /// maximum proposals or receipts returned by one history export
+    pub const MAX_EXPORT_ROWS: usize = 100;
+    /// maximum proposals imported by one call
+    pub const MAX_IMPORT_ROWS: usize = 100;
+    /// maximum bytes of a comment
+    pub const MAX_COMMENT_LEN: usize = 2000;
+    /// maximum options of a multi-choice proposal
+    pub const MAX_OPTIONS: usize = 20;
+    /// text shown instead of a redacted vote reason
+    pub const REDACTED_REASON: &'static str = "[redacted]";
+    /// maximum holding duration settable by proposal, 30 days
+    pub const MAX_HOLDING_DURATION: u64 = 30 * ONE_DAY;
+
+    /// initialize a Governor Bravo
+    pub fn initialize(
+        &mut self,
+        admin: Principal,
+        name: String,
+        quorum_votes: Nat,
+        voting_delay: u64,
+        voting_period: u64,
+        proposal_threshold: Nat,
+        timelock_delay: u64,
+        gov_token: Principal,
+    ) {
+        if self.initialized {
+            return;
+        }
+        self.initialized = true;
+        self.admin = admin;
+        self.name = name;
+        self.quorum_votes = quorum_votes;
+        self.voting_period = voting_period;
+        self.voting_delay = voting_delay;
+        self.proposal_threshold = proposal_threshold;
+        self.gov_token = gov_token;
+        self.timelock.set_delay(timelock_delay);
+    }
+
+    /// propose a proposal, return id of proposal created
+    pub fn propose(
+        &mut self,
+        proposer: Principal,
+        proposer_votes: Nat,
+        title: String,
+        description: String,
+        task: Task,
+        timestamp: u64,
+    ) -> GovernResult<usize> {
+        self.propose_with(proposer, proposer_votes, title, description, task, None, None, timestamp)
+    }
+
+    /// propose a proposal with the execution priority and the voting period requested by the proposer,
+    /// nothing is created if either is refused, return id of proposal created
+    pub fn propose_with(
+        &mut self,
+        proposer: Principal,
+        proposer_votes: Nat,
+        title: String,
+        description: String,
+        task: Task,
+        priority: Option<u8>,
+        voting_period: Option<u64>,
+        timestamp: u64,
+    ) -> GovernResult<usize> {
+        self.check_new_proposal(proposer, &proposer_votes, &task, timestamp)?;
+        if let Some(period) = voting_period {
+            self.check_voting_period(period)?;
+        }
+
+        let pos = self.stable_memory.write_record(description.as_bytes())?;
+        let id = self.insert_proposal(proposer, title, pos, task, voting_period, timestamp);
+        self.proposals[id].priority = priority.unwrap_or(0);
+        Ok(id)
+    }
+
+    /// propose a non-binding signal vote, closed instead of queued once succeeded, return id of proposal created
+    pub fn propose_text(
+        &mut self,
+        proposer: Principal,
+        proposer_votes: Nat,
+        title: String,
+        description: String,
+        timestamp: u64,
+    ) -> GovernResult<usize> {
+        self.check_proposer(proposer, &proposer_votes, timestamp)?;
+
+        let pos = self.stable_memory.write_record(description.as_bytes())?;
+        let id = self.insert_proposal(proposer, title, pos, Task::text(), None, timestamp);
+        self.proposals[id].kind = ProposalKind::Text;
+        Ok(id)
+    }
+
+    /// propose a vote between labeled options, closed with the option of most votes once voting ended,
+    /// return id of proposal created
+    pub fn propose_options(
+        &mut self,
+        proposer: Principal,
+        proposer_votes: Nat,
+        title: String,
+        description: String,
+        options: Vec<String>,
+        timestamp: u64,
+    ) -> GovernResult<usize> {
+        self.check_proposer(proposer, &proposer_votes, timestamp)?;
+        Self::check_options(&options)?;
+
+        let pos = self.stable_memory.write_record(description.as_bytes())?;
+        let id = self.insert_proposal(proposer, title, pos, Task::text(), None, timestamp);
+        self.set_options(id, options);
+        Ok(id)
+    }
+
+    fn check_options(options: &[String]) -> GovernResult<()> {
+        if options.len() < 2 || options.len() > Self::MAX_OPTIONS {
+            return Err("a multi-choice proposal needs between 2 and 20 options");
+        }
+        let labels: BTreeSet<&String> = options.iter().collect();
+        if labels.len() != options.len() || options.iter().any(|o| o.is_empty()) {
+            return Err("options must be distinct and not empty");
+        }
+        Ok(())
+    }
+
+    /// make the new proposal multi-choice, satellites only tally support, against and abstain votes
+    fn set_options(&mut self, id: usize, options: Vec<String>) {
+        let proposal = &mut self.proposals[id];
+        proposal.kind = ProposalKind::Options;
+        proposal.option_votes = vec![Nat::from(0); options.len()];
+        proposal.options = options;
+        proposal.pending_satellites.clear();
+    }
+
+    /// propose again the title, description and task of a defeated or expired proposal,
+    /// the description is shared with the original, return id of proposal created
+    pub fn repropose(
+        &mut self,
+        id: usize,
+        proposer: Principal,
+        proposer_votes: Nat,
+        timestamp: u64,
+    ) -> GovernResult<usize> {
+        match self.get_state(id, timestamp)? {
+            ProposalState::Defeated | ProposalState::Expired => {}
+            _ => { return Err("only defeated or expired proposals can be reproposed"); }
+        }
+        let original = &self.proposals[id];
+        let title = original.title.clone();
+        let description = original.description.clone();
+        let kind = original.kind;
+        let options = original.options.clone();
+        let mut task = original.task.clone();
+        task.eta = 0;
+        task.window = ExecutionWindow::default();
+        if kind == ProposalKind::Executable {
+            self.check_new_proposal(proposer, &proposer_votes, &task, timestamp)?;
+        } else {
+            self.check_proposer(proposer, &proposer_votes, timestamp)?;
+        }
+
+        let new_id = self.insert_proposal(proposer, title, description, task, None, timestamp);
+        self.proposals[new_id].kind = kind;
+        if kind == ProposalKind::Options {
+            self.set_options(new_id, options);
+        }
+        Ok(new_id)
+    }
+
+    /// import closed proposals of a previous governor, before any proposal is made here,
+    /// tallies are kept without receipts, return ids of proposals imported
+    pub fn import_proposals(&mut self, imports: Vec<ProposalImport>, timestamp: u64) -> GovernResult<Vec<usize>> {
+        if imports.len() > Self::MAX_IMPORT_ROWS {
+            return Err("too many proposals in one import");
+        }
+        if self.proposals.iter().any(|p| p.imported_state.is_none()) {
+            return Err("proposals can only be imported before the first proposal");
+        }
+        for import in imports.iter() {
+            match import.state {
+                ProposalState::Canceled | ProposalState::Defeated
+                    | ProposalState::Executed | ProposalState::Expired => {}
+                _ => { return Err("only canceled, defeated, executed or expired proposals can be imported"); }
+            }
+            if import.start_time > import.end_time {
+                return Err("voting starts after it ends");
+            }
+            if import.end_time > timestamp {
+                return Err("voting on an imported proposal must have ended");
+            }
+        }
+
+        let mut ids = vec![];
+        for import in imports {
+            let pos = self.stable_memory.write_record(import.description.as_bytes())?;
+            let id = self.proposals.len();
+            let mut proposal = Proposal::new(
+                id, import.proposer, import.title, pos, import.task,
+                import.start_time, import.end_time,
+            );
+            proposal.counting = self.counting_mode(proposal.task.backend.kind());
+            proposal.config = ProposalConfig {
+                quorum_votes: self.quorum_votes.clone(),
+                quorum_fraction: 0,
+                proposal_threshold: self.proposal_threshold.clone(),
+                voting_period: import.end_time - import.start_time,
+                early_finish: false,
+                quorum_mode: self.quorum_mode,
+                tie_rule: self.tie_rule,
+            };
+            // the tallies come without receipts, as votes cast elsewhere
+            let tally = Tally {
+                support_votes: import.support_votes,
+                against_votes: import.against_votes,
+                abstain_votes: import.abstain_votes,
+            };
+            proposal.support_votes = tally.support_votes.clone();
+            proposal.against_votes = tally.against_votes.clone();
+            proposal.abstain_votes = tally.abstain_votes.clone();
+            proposal.offchain_tally = Some(tally);
+            proposal.canceled = import.state == ProposalState::Canceled;
+            proposal.executed = import.state == ProposalState::Executed;
+            // the previous governor already went through the transitions
+            proposal.recorded_states = match import.state {
+                ProposalState::Defeated => { vec![ProposalState::Defeated] }
+                ProposalState::Executed => { vec![ProposalState::Succeeded] }
+                ProposalState::Expired => { vec![ProposalState::Succeeded, ProposalState::Expired] }
+                _ => { vec![] }
+            };
+            proposal.imported_state = Some(import.state);
+            self.index_proposal(&proposal);
+            self.proposals.push(proposal);
+            ids.push(id);
+        }
+        Ok(ids)
+    }
+
+    /// checks shared by every new proposal
+    fn check_new_proposal(&self, proposer: Principal, proposer_votes: &Nat, task: &Task, timestamp: u64) -> GovernResult<()> {
+        self.check_proposer(proposer, proposer_votes, timestamp)?;
+
+        task.validate()?;
+        self.check_task_cycles(task)?;
+        if let Backend::ParamChange(change) = &task.backend {
+            Self::check_param_change(change)?;
+        }
+        if self.live_duplicate(task, timestamp).is_some() {
+            return Err("an identical task is already in a live proposal");
+        }
+        if self.cooling_down(task, timestamp).is_some() {
+            return Err("an identical task was defeated within the cooldown");
+        }
+        Ok(())
+    }
+
+    /// add the proposal, voting for the period if given instead of the governor's, already checked against the bounds
+    fn insert_proposal(&mut self, proposer: Principal, title: String, description: Position, task: Task, voting_period: Option<u64>, timestamp: u64) -> usize {
+        let id = self.proposals.len();
+        let voting_period = voting_period.unwrap_or(self.voting_period);
+        let mut proposal = Proposal::new(
+            id, proposer, title, description, task,
+            timestamp + self.voting_delay,
+            timestamp + self.voting_delay + voting_period,
+        );
+        proposal.pending_satellites = self.satellites.clone();
+        proposal.counting = self.counting_mode(proposal.task.backend.kind());
+        proposal.config = ProposalConfig {
+            quorum_votes: self.quorum_votes.clone(),
+            quorum_fraction: 0,
+            proposal_threshold: self.proposal_threshold.clone(),
+            voting_period,
+            early_finish: self.early_finish,
+            quorum_mode: self.quorum_mode,
+            tie_rule: self.tie_rule,
+        };
+        self.index_proposal(&proposal);
+        self.proposals.push(proposal);
+        self.latest_proposal_ids.insert(proposer, id);
+        id
+    }
+
+    /// add a new proposal to the sort indices
+    fn index_proposal(&mut self, proposal: &Proposal) {
+        self.end_time_index.insert((proposal.end_time, proposal.id));
+        self.votes_index.insert((proposal.total_votes(), proposal.id));
+    }
+
+    /// move the proposal in the votes index after its tallies changed from the previous total
+    fn reindex_votes(&mut self, id: usize, previous: Nat) {
+        self.votes_index.remove(&(previous, id));
+        self.votes_index.insert((self.proposals[id].total_votes(), id));
+    }
+
+    /// votes counted on the proposal for a voter holding the votes
+    pub fn counted_votes(&self, id: usize, votes: Nat) -> GovernResult<Nat> {
+        let proposal = self.proposals.get(id).ok_or("invalid proposal id")?;
+        proposal.counting.strategy().counted_votes(votes)
+    }
+
+    /// duration over which the votes on the proposal ramp up to count in full, none if they count in full at once
+    pub fn vote_ramp(&self, id: usize) -> GovernResult<Option<u64>> {
+        let proposal = self.proposals.get(id).ok_or("invalid proposal id")?;
+        Ok(proposal.counting.ramp())
+    }
+
+    /// whether voting on the proposal may close early, as snapshotted when it was created
+    pub fn proposal_early_finish(&self, id: usize) -> GovernResult<bool> {
+        let proposal = self.proposals.get(id).ok_or("invalid proposal id")?;
+        Ok(proposal.config.early_finish)
+    }
+
+    /// check the proposer holds enough votes and has no live proposal,
+    /// with a minimum delegation age the votes are the lowest held over it
+    pub fn check_proposer(&self, proposer: Principal, proposer_votes: &Nat, timestamp: u64) -> GovernResult<()> {
+        // allow addresses above proposal threshold to propose
+        if *proposer_votes <= self.proposal_threshold {
+            if self.min_delegation_age > 0 {
+                return Err("proposer votes not above proposal threshold for the minimum delegation age");
+            }
+            return Err("proposer votes below proposal threshold");
+        }
+
+        if let Some(lpi) = self.latest_proposal_ids.get(&proposer) {
+            // one proposer can only propose an one living proposal
+            let proposal_state = self.get_state(*lpi, timestamp)?;
+            match proposal_state {
+                ProposalState::Pending => {
+                    return Err("one live proposal per proposer, found an already pending proposal");
+                }
+                ProposalState::Active => {
+                    return Err("one live proposal per proposer, found an already active proposal");
+                }
+                ProposalState::Tallying => {
+                    return Err("one live proposal per proposer, found a proposal awaiting its tally");
+                }
+                ProposalState::Executing => {
+                    return Err("one live proposal per proposer, found an executing proposal");
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// whether the proposer could propose now, with the reason if not
+    pub fn propose_eligibility(&self, proposer: Principal, proposer_votes: Nat, timestamp: u64) -> ProposeEligibility {
+        let live_proposal = self.latest_proposal_ids.get(&proposer)
+            .filter(|id| matches!(
+                self.get_state(**id, timestamp),
+                Ok(ProposalState::Pending) | Ok(ProposalState::Active) | Ok(ProposalState::Tallying)
+                    | Ok(ProposalState::Executing)
+            ))
+            .copied();
+        let reason = self.check_proposer(proposer, &proposer_votes, timestamp).err().map(|e| e.to_string());
+        ProposeEligibility {
+            eligible: reason.is_none(),
+            votes: proposer_votes,
+            proposal_threshold: self.proposal_threshold.clone(),
+            live_proposal,
+            reason,
+        }
+    }
+
+    /// queue an proposal into time lock, return expected time
+    pub fn queue(&mut self, id: usize, timestamp: u64) -> GovernResult<u64> {
+        let proposal_state = self.get_state(id, timestamp)?;
+        if proposal_state != ProposalState::Succeeded {
+            return Err("proposal can only be queued if it is succeeded");
+        }
+
+        let eta = timestamp + self.timelock.delay;
+        let window = self.execution_window(self.proposals[id].task.backend.kind());
+        let proposal = &mut self.proposals[id];
+        proposal.task.eta = eta;
+        proposal.task.window = window;
+        self.timelock.queue_transaction(proposal.task.to_owned());
+
+        return Ok(eta);
+    }
+
+    /// execute the task in proposal, return the result in bytes array
+    pub fn pre_execute(&mut self, id: usize, timestamp: u64) -> GovernResult<()> {
+        let proposal_state = self.get_state(id, timestamp)?;
+        if proposal_state != ProposalState::Queued {
+            return Err("proposal can only be executed if it is queued");
+        }
+
+        let proposal = &mut self.proposals[id];
+        // execution may not have opened yet, stay queued then
+        self.timelock.pre_execute_transaction(&proposal.task, timestamp)?;
+        proposal.executing = true;
+        Ok(())
+    }
+
+    pub fn post_execute(&mut self, id: usize, result: bool, timestamp: u64) -> GovernResult<()> {
+        let proposal_state = self.get_state(id, timestamp)?;
+        if proposal_state != ProposalState::Executing {
+            return Err("proposal is not executing");
+        }
+
+        let proposal = &mut self.proposals[id];
+        proposal.executing = false;
+        proposal.executed = result;
+        self.timelock.post_execute_transaction(proposal.task.to_owned(), result);
+        Ok(())
+    }
+
+    /// calls of the multi-call task of the executing proposal which succeeded, resumed from on retries
+    pub fn executed_calls(&self, id: usize) -> GovernResult<usize> {
+        let proposal = self.proposals.get(id).ok_or("invalid proposal id")?;
+        Ok(proposal.executed_calls)
+    }
+
+    /// record the next call of the multi-call task of the executing proposal succeeded
+    pub fn record_executed_call(&mut self, id: usize, timestamp: u64) -> GovernResult<()> {
+        if self.get_state(id, timestamp)? != ProposalState::Executing {
+            return Err("proposal is not executing");
+        }
+        self.proposals[id].executed_calls += 1;
+        Ok(())
+    }
+
+    /// account the cycles attached to an execution attempt and refunded by the target
+    pub fn record_execution_cycles(&mut self, id: usize, attached: u64, refunded: u64) -> GovernResult<()> {
+        let proposal = self.proposals.get_mut(id).ok_or("invalid proposal id")?;
+        proposal.attached_cycles += attached;
+        proposal.refunded_cycles += refunded;
+        Ok(())
+    }
+
+    /// add cycles of the funder to the task of a canister call not queued yet,
+    /// up to the cycle limits, return the cycles added
+    pub fn fund_task(&mut self, id: usize, funder: Principal, cycles: u64, timestamp: u64) -> GovernResult<u64> {
+        match self.get_state(id, timestamp)? {
+            ProposalState::Pending | ProposalState::Active | ProposalState::Tallying | ProposalState::Succeeded => {}
+            _ => { return Err("only proposals not queued yet can be funded"); }
+        }
+        if self.proposals[id].kind != ProposalKind::Executable {
+            return Err("text and multi-choice proposals have no task to fund");
+        }
+        let task = &self.proposals[id].task;
+        if task.backend != Backend::Canister {
+            return Err("only canister call tasks attach cycles");
+        }
+        let mut room = u64::MAX - task.cycles;
+        if self.max_task_cycles != 0 {
+            room = room.min(self.max_task_cycles.saturating_sub(task.cycles));
+        }
+        if let Some(limit) = self.target_cycle_limits.get(&task.target) {
+            room = room.min(limit.saturating_sub(task.cycles));
+        }
+        let added = cycles.min(room);
+        if added == 0 {
+            return Err("task cycles already at the limit");
+        }
+        let proposal = &mut self.proposals[id];
+        proposal.task.cycles += added;
+        *proposal.funded_cycles.entry(funder).or_default() += added;
+        Ok(added)
+    }
+
+    /// cancel a proposal regardless of its proposer, for guardians
+    pub fn veto(&mut self, id: usize, timestamp: u64) -> GovernResult<()> {
+        let proposal_state = self.get_state(id, timestamp)?;
+        match proposal_state {
+            ProposalState::Executing => { return Err("cannot veto executing proposal"); }
+            ProposalState::Executed => { return Err("cannot veto executed proposal"); }
+            ProposalState::Canceled => { return Err("proposal already canceled"); }
+            ProposalState::Closed => { return Err("cannot veto closed proposal"); }
+            _ => {}
+        }
+        let proposal = &mut self.proposals[id];
+        proposal.canceled = true;
+        if let Some(deposit) = proposal.deposit.as_mut() {
+            deposit.forfeited = true;
+        }
+        self.timelock.cancel_transaction(&proposal.task);
+        Ok(())
+    }
+
+    /// cancels a proposal only if sender is the proposer, or proposer delegates dropped below proposal threshold
+    pub fn cancel(&mut self, id: usize, timestamp: u64, caller: Principal, proposer_votes: Nat) -> GovernResult<()> {
+        let proposal_state = self.get_state(id, timestamp)?;
+        if proposal_state == ProposalState::Executing {
+            return Err("cannot cancel executing proposal");
+        } else if proposal_state == ProposalState::Executed {
+            return Err("cannot cancel executed proposal");
+        } else if proposal_state == ProposalState::Closed {
+            return Err("cannot cancel closed proposal");
+        }
+
+        let proposal = &mut self.proposals[id];
+        if caller != proposal.proposer {
+            if proposer_votes > proposal.config.proposal_threshold {
+                return Err("proposer above threshold");
+            }
+        }
+        // canceling must not turn a slashed deposit into a refund
+        if proposal_state != ProposalState::Canceled
+            && (caller != proposal.proposer || proposal_state == ProposalState::Defeated) {
+            if let Some(deposit) = proposal.deposit.as_mut() {
+                deposit.forfeited = true;
+            }
+        }
+        proposal.canceled = true;
+        self.timelock.cancel_transaction(&proposal.task);
+        Ok(())
+    }
+
+    pub fn cast_vote(
+        &mut self,
+        id: usize,
+        vote_type: VoteType,
+        votes: Nat,
+        reason: Option<String>,
+        caller: Principal,
+        timestamp: u64,
+    ) -> GovernResult<Receipt> {
+        if self.proposals.get(id).map_or(false, |p| p.kind == ProposalKind::Options) {
+            return Err("votes on a multi-choice proposal go to one of its options");
+        }
+        self.cast_ballot(id, vote_type, None, votes, reason, caller, timestamp)
+    }
+
+    /// vote for an option of a multi-choice proposal, the votes also count as support towards the quorum
+    pub fn cast_vote_option(
+        &mut self,
+        id: usize,
+        option: usize,
+        votes: Nat,
+        reason: Option<String>,
+        caller: Principal,
+        timestamp: u64,
+    ) -> GovernResult<Receipt> {
+        let proposal = self.proposals.get(id).ok_or("invalid proposal id")?;
+        if proposal.kind != ProposalKind::Options {
+            return Err("proposal is not multi-choice");
+        }
+        if option >= proposal.options.len() {
+            return Err("invalid option");
+        }
+        self.cast_ballot(id, VoteType::Support, Some(option), votes, reason, caller, timestamp)
+    }
+
+    fn cast_ballot(
+        &mut self,
+        id: usize,
+        vote_type: VoteType,
+        option: Option<usize>,
+        votes: Nat,
+        reason: Option<String>,
+        caller: Principal,
+        timestamp: u64,
+    ) -> GovernResult<Receipt> {
+        let proposal_state = self.get_state(id, timestamp)?;
+        if proposal_state != ProposalState::Active {
+            return Err("voting is closed");
+        }
+
+        let votes = self.counted_votes(id, votes)?;
+        let reason = match reason {
+            Some(r) => { Some(self.stable_memory.write_record(r.as_bytes())?) }
+            None => { None }
+        };
+        // the reason of a replaced ballot is freed once the new one is written,
+        // unless it is the tombstone shared by redacted reasons
+        let tombstone = self.reason_tombstone.as_ref().map(|t| t.offset);
+        let old_reason = self.proposals[id].receipts.get(&caller)
+            .and_then(|old| old.reason.clone())
+            .filter(|pos| Some(pos.offset) != tombstone);
+        if let Some(pos) = old_reason {
+            self.stable_memory.free_record(&pos)?;
+            if let Some(old) = self.proposals[id].receipts.get_mut(&caller) {
+                old.reason = None;
+            }
+        }
+
+        let proposal = &mut self.proposals[id];
+        let previous = proposal.total_votes();
+        // a new ballot replaces the previous one, whose votes leave the tallies first
+        let changes = match proposal.receipts.get(&caller).cloned() {
+            Some(old) => {
+                proposal.withdraw(&old);
+                Some(old.changes.unwrap_or(0) + 1)
+            }
+            None => { None }
+        };
+        if let Some(option) = option {
+            proposal.option_votes[option] += votes.clone();
+        }
+        match vote_type {
+            VoteType::Support => {
+                proposal.support_votes += votes.clone();
+            }
+            VoteType::Against => {
+                proposal.against_votes += votes.clone();
+            }
+            VoteType::Abstain => {
+                proposal.abstain_votes += votes.clone();
+            }
+        }
+
+        let mut receipt = Receipt::new(vote_type, votes, reason);
+        receipt.option = option;
+        receipt.changes = changes;
+        proposal.receipts.insert(caller, receipt.clone());
+        self.reindex_votes(id, previous);
+
+        Ok(receipt)
+    }
+
+    /// raise the votes that can be cast on the proposal to the total supply, if above
+    pub fn record_max_votes(&mut self, id: usize, total_supply: Nat) -> GovernResult<()> {
+        let proposal = self.proposals.get_mut(id).ok_or("invalid proposal id")?;
+        if proposal.max_votes.as_ref().map_or(true, |max| total_supply > *max) {
+            proposal.max_votes = Some(total_supply);
+        }
+        Ok(())
+    }
+
+    /// record where the votes of the voter's receipt came from
+    pub fn set_receipt_breakdown(&mut self, id: usize, voter: Principal, breakdown: VotesBreakdown) -> GovernResult<Receipt> {
+        let proposal = self.proposals.get_mut(id).ok_or("invalid proposal id")?;
+        let receipt = proposal.receipts.get_mut(&voter).ok_or("voter has not voted")?;
+        receipt.breakdown = Some(breakdown);
+        Ok(receipt.clone())
+    }
+
+    /// satellites whose tallies are still missing for the proposal
+    pub fn pending_satellites(&self, id: usize) -> GovernResult<Vec<Principal>> {
+        match self.proposals.get(id) {
+            Some(p) => { Ok(p.pending_satellites.clone()) }
+            None => { Err("invalid proposal id") }
+        }
+    }
+
+    /// merge the tally reported by a satellite into the proposal, once voting has ended
+    pub fn merge_satellite_tally(&mut self, id: usize, satellite: Principal, tally: Tally, timestamp: u64) -> GovernResult<()> {
+        let proposal_state = self.get_state(id, timestamp)?;
+        if proposal_state != ProposalState::Tallying {
+            return Err("proposal is not waiting for satellite tallies");
+        }
+
+        let proposal = &mut self.proposals[id];
+        match proposal.pending_satellites.iter().position(|s| *s == satellite) {
+            Some(i) => {
+                proposal.pending_satellites.remove(i);
+            }
+            None => {
+                return Err("satellite tally already merged or not expected");
+            }
+        }
+        let previous = proposal.total_votes();
+        proposal.support_votes += tally.support_votes;
+        proposal.against_votes += tally.against_votes;
+        proposal.abstain_votes += tally.abstain_votes;
+        self.reindex_votes(id, previous);
+        Ok(())
+    }
+
+    /// merge votes cast off-chain into the proposal, until it is queued
+    pub fn merge_offchain_tally(&mut self, id: usize, tally: Tally, timestamp: u64) -> GovernResult<()> {
+        let proposal_state = self.get_state(id, timestamp)?;
+        match proposal_state {
+            ProposalState::Active => {}
+            _ => { return Err("off-chain votes can only be merged while voting is active"); }
+        }
+        if self.proposals[id].kind == ProposalKind::Options {
+            return Err("off-chain tallies have no votes by option");
+        }
+        let proposal = &mut self.proposals[id];
+        let previous = proposal.total_votes();
+        proposal.support_votes += tally.support_votes.clone();
+        proposal.against_votes += tally.against_votes.clone();
+        proposal.abstain_votes += tally.abstain_votes.clone();
+        match proposal.offchain_tally.as_mut() {
+            Some(merged) => {
+                merged.support_votes += tally.support_votes;
+                merged.against_votes += tally.against_votes;
+                merged.abstain_votes += tally.abstain_votes;
+            }
+            None => { proposal.offchain_tally = Some(tally); }
+        }
+        self.reindex_votes(id, previous);
+        Ok(())
+    }
+
+    pub fn add_satellite(&mut self, satellite: Principal) -> GovernResult<()> {
+        if self.satellites.contains(&satellite) {
+            return Err("satellite already registered");
+        }
+        self.satellites.push(satellite);
+        Ok(())
+    }
+
+    /// unregister a satellite, proposals stop waiting for its tally
+    pub fn remove_satellite(&mut self, satellite: Principal) -> GovernResult<()> {
+        if !self.satellites.contains(&satellite) {
+            return Err("satellite not registered");
+        }
+        self.satellites.retain(|s| *s != satellite);
+        for proposal in self.proposals.iter_mut() {
+            proposal.pending_satellites.retain(|s| *s != satellite);
+        }
+        Ok(())
+    }
+
+    pub fn get_proposal(&self, id: usize) -> GovernResult<ProposalInfo> {
+        match self.proposals.get(id) {
+            Some(p) => {
+                let buf = self.stable_memory.read_record(&p.description)?;
+                let str = String::from_utf8(buf).map_err(|_| "Err utf-8 format")?;
+                Ok(p.to_info(str))
+            }
+            None => { Err("invalid proposal id") }
+        }
+    }
+
+    /// write the text of a comment to stable memory
+    fn write_comment(&mut self, text: String) -> GovernResult<Position> {
+        if text.is_empty() || text.len() > Self::MAX_COMMENT_LEN {
+            return Err("comment must be between 1 and 2000 bytes");
+        }
+        self.stable_memory.write_record(text.as_bytes())
+    }
+
+    /// comment on the proposal, return id of the comment
+    pub fn add_comment(&mut self, id: usize, author: Principal, text: String, timestamp: u64) -> GovernResult<usize> {
+        if id >= self.proposals.len() { return Err("invalid proposal id"); }
+        let pos = self.write_comment(text)?;
+        let comments = &mut self.proposals[id].comments;
+        let comment_id = comments.len();
+        comments.push(Comment {
+            id: comment_id,
+            author,
+            text: pos,
+            created_at: timestamp,
+            edited_at: None,
+            deleted: false,
+        });
+        Ok(comment_id)
+    }
+
+    fn author_comment(&mut self, id: usize, comment_id: usize, author: Principal) -> GovernResult<&mut Comment> {
+        let proposal = self.proposals.get_mut(id).ok_or("invalid proposal id")?;
+        let comment = match proposal.comments.get_mut(comment_id) {
+            Some(c) if !c.deleted => { c }
+            _ => { return Err("comment not found"); }
+        };
+        if comment.author != author {
+            return Err("only the author can change the comment");
+        }
+        Ok(comment)
+    }
+
+    /// replace the text of a comment, the previous text is freed once the new one is written
+    pub fn edit_comment(&mut self, id: usize, comment_id: usize, author: Principal, text: String, timestamp: u64) -> GovernResult<()> {
+        self.author_comment(id, comment_id, author)?;
+        let pos = self.write_comment(text)?;
+        let comment = self.author_comment(id, comment_id, author)?;
+        let old = std::mem::replace(&mut comment.text, pos);
+        comment.edited_at = Some(timestamp);
+        self.stable_memory.free_record(&old)
+    }
+
+    /// delete a comment, its text is erased and the tombstone keeps the position
+    pub fn delete_comment(&mut self, id: usize, comment_id: usize, author: Principal) -> GovernResult<()> {
+        let comment = self.author_comment(id, comment_id, author)?;
+        comment.deleted = true;
+        let pos = comment.text.clone();
+        self.stable_memory.erase_record(&pos)
+    }
+
+    /// get specific number of comments not deleted, in posting sequence
+    /// page: from which page, start from 0
+    /// num: number of item in a page
+    pub fn get_comment_pages(&self, id: usize, page: usize, num: usize) -> GovernResult<Vec<CommentInfo>> {
+        let proposal = self.proposals.get(id).ok_or("invalid proposal id")?;
+        proposal.comments.iter()
+            .filter(|c| !c.deleted)
+            .skip(page.saturating_mul(num))
+            .take(num)
+            .map(|c| {
+                let buf = self.stable_memory.read_record(&c.text)?;
+                Ok(CommentInfo {
+                    id: c.id,
+                    author: c.author,
+                    text: String::from_utf8(buf).map_err(|_| "Err utf-8 format")?,
+                    created_at: c.created_at,
+                    edited_at: c.edited_at,
+                })
+            })
+            .collect()
+    }
+
+    pub fn get_proposal_digest(&self, id: usize) -> GovernResult<ProposalDigest> {
+        match self.proposals.get(id) {
+            Some(p) => { Ok(p.digest()) }
+            None => { Err("invalid proposal id") }
+        }
+    }
+
+    /// page of proposals in the given order
+    /// page: from which page, start from 0
+    /// num: number of item in a page
+    pub fn get_proposal_pages(&self, page: usize, num: usize, sort: ProposalSort, timestamp: u64) -> GovernResult<Vec<(ProposalDigest, ProposalState)>> {
+        let ids: Box<dyn Iterator<Item = usize> + '_> = match sort {
+            ProposalSort::IdAsc => { Box::new(0..self.proposals.len()) }
+            ProposalSort::IdDesc => { Box::new((0..self.proposals.len()).rev()) }
+            ProposalSort::EndTimeAsc => { Box::new(self.end_time_index.iter().map(|(_, id)| *id)) }
+            ProposalSort::EndTimeDesc => { Box::new(self.end_time_index.iter().rev().map(|(_, id)| *id)) }
+            ProposalSort::TotalVotesAsc => { Box::new(self.votes_index.iter().map(|(_, id)| *id)) }
+            ProposalSort::TotalVotesDesc => { Box::new(self.votes_index.iter().rev().map(|(_, id)| *id)) }
+        };
+        ids.skip(page.saturating_mul(num)).take(num).map(|id| {
+            Ok((self.proposals[id].digest(), self.get_state(id, timestamp)?))
+        }).collect()
+    }
+
+    pub fn get_receipt(&self, id: usize, voter: Principal) -> GovernResult<ReceiptInfo> {
+        match self.proposals.get(id) {
+            Some(p) => {
+                match p.receipts.get(&voter) {
+                    Some(r) => {
+
+                        let reason = match &r.reason {
+                            Some(pos) =>  {
+                                let buf = self.stable_memory.read_record(pos)?;
+                                let str = String::from_utf8(buf).unwrap_or("".to_string());
+                                Some(str)
+                            }
+                            None => { None }
+                        };
+                        Ok(r.to_info(reason))
+                    }
+                    None => { Err("receipt not found") }
+                }
+            }
+            None => { Err("invalid proposal id") }
+        }
+    }
+
+    /// page of the receipts of the proposal, only those of the vote type if given
+    /// page: from which page, start from 0
+    /// num: number of item in a page
+    pub fn get_receipt_pages(&self, id: usize, page: usize, num: usize, vote_type: Option<VoteType>) -> GovernResult<Vec<(Principal, ReceiptDigest)>> {
+        match self.proposals.get(id) {
+            Some(p) => {
+                Ok(p.receipts.iter()
+                    .filter(|(_, receipt)| vote_type.as_ref().map_or(true, |t| receipt.vote_type == *t))
+                    .skip(page.saturating_mul(num))
+                    .take(num)
+                    .map(|(x, y)| (x.to_owned(), y.digest()))
+                    .collect::<Vec<(Principal, ReceiptDigest)>>())
+            }
+            None => {
+                Err("invalid proposal id")
+            }
+        }
+    }
+
+    /// replace the reason of the voter's receipt by the tombstone and erase its text,
+    /// the vote type and votes stay as cast
+    pub fn redact_reason(&mut self, id: usize, voter: Principal) -> GovernResult<()> {
+        let proposal = self.proposals.get(id).ok_or("invalid proposal id")?;
+        let receipt = proposal.receipts.get(&voter).ok_or("receipt not found")?;
+        let reason = receipt.reason.clone().ok_or("receipt has no reason")?;
+        let tombstone = match &self.reason_tombstone {
+            Some(tombstone) if tombstone.offset == reason.offset => { return Err("reason already redacted"); }
+            Some(tombstone) => tombstone.clone(),
+            None => {
+                let tombstone = self.stable_memory.write_record(Self::REDACTED_REASON.as_bytes())?;
+                self.reason_tombstone = Some(tombstone.clone());
+                tombstone
+            }
+        };
+        self.stable_memory.erase_record(&reason)?;
+        if let Some(receipt) = self.proposals[id].receipts.get_mut(&voter) {
+            receipt.reason = Some(tombstone);
+        }
+        Ok(())
+    }
+
+    /// number of voters of the proposal by vote type
+    pub fn get_receipt_counts(&self, id: usize) -> GovernResult<ReceiptCounts> {
+        let proposal = self.proposals.get(id).ok_or("invalid proposal id")?;
+        let mut counts = ReceiptCounts::default();
+        for receipt in proposal.receipts.values() {
+            match receipt.vote_type {
+                VoteType::Support => { counts.support += 1; }
+                VoteType::Against => { counts.against += 1; }
+                VoteType::Abstain => { counts.abstain += 1; }
+            }
+        }
+        Ok(counts)
+    }
+
+    /// proposals with their descriptions in id order, for bulk exports
+    /// offset: id of the first proposal
+    /// limit: number of proposals wanted, capped by MAX_EXPORT_ROWS
+    pub fn export_proposals(&self, offset: usize, limit: usize, timestamp: u64) -> GovernResult<Vec<(ProposalInfo, ProposalState)>> {
+        let end = offset
+            .saturating_add(limit.min(Self::MAX_EXPORT_ROWS))
+            .min(self.proposals.len());
+        let mut res = vec![];
+        for id in offset..end {
+            res.push((self.get_proposal(id)?, self.get_state(id, timestamp)?));
+        }
+        Ok(res)
+    }
+
+    /// receipts of a proposal with their reasons, ordered by voter, for bulk exports
+    /// offset: index of the first receipt
+    /// limit: number of receipts wanted, capped by MAX_EXPORT_ROWS
+    pub fn export_receipts(&self, id: usize, offset: usize, limit: usize) -> GovernResult<(Vec<(Principal, ReceiptInfo)>, usize)> {
+        let proposal = self.proposals.get(id).ok_or("invalid proposal id")?;
+        let mut voters = proposal.receipts.keys().cloned().collect::<Vec<Principal>>();
+        voters.sort();
+        let end = offset
+            .saturating_add(limit.min(Self::MAX_EXPORT_ROWS))
+            .min(voters.len());
+        let mut res = vec![];
+        for voter in voters.iter().take(end).skip(offset) {
+            res.push((*voter, self.get_receipt(id, *voter)?));
+        }
+        Ok((res, voters.len()))
+    }
+
+    /// read raw bytes of the written stable memory region, for off-chain backups
+    /// offset: where to start reading
+    /// len: number of bytes wanted, capped by MAX_EXPORT_CHUNK and the written region
+    pub fn export_stable_range(&self, offset: usize, len: usize) -> GovernResult<Vec<u8>> {
+        if offset > self.stable_memory.offset {
+            return Err("offset out of bounds");
+        }
+        let len = len
+            .min(Self::MAX_EXPORT_CHUNK)
+            .min(self.stable_memory.offset - offset);
+        let mut buf = vec![0u8; len];
+        self.stable_memory.read(offset, buf.as_mut_slice()).map_err(|_| "Stable memory error")?;
+        Ok(buf)
+    }
+
+    /// bytes of every record written to stable memory, for full backups
+    pub fn stable_records(&self) -> GovernResult<Vec<u8>> {
+        let mut buf = vec![0u8; self.stable_memory.offset];
+        self.stable_memory.read(0, buf.as_mut_slice()).map_err(|_| "Stable memory error")?;
+        Ok(buf)
+    }
+
+    /// replace the governor by a backup and the stable memory records taken with it
+    pub fn restore(&mut self, backup: GovernorBravo, records: &[u8]) -> GovernResult<()> {
+        if backup.stable_memory.offset != records.len() {
+            return Err("backup records do not match the governor");
+        }
+        // the pages allocated to this canister stay, whatever the backup recorded
+        let mut memory = self.stable_memory.clone();
+        memory.offset = 0;
+        memory.write(records).map_err(|_| "Stable memory error")?;
+        *self = backup;
+        self.stable_memory = memory;
+        Ok(())
+    }
+
+    pub fn get_task(&self, id: usize) -> GovernResult<Task> {
+        match self.proposals.get(id) {
+            Some(p) => {
+                Ok(p.task.clone())
+            }
+            None => {
+                Err("Invalid proposal id")
+            }
+        }
+    }
+
+    /// countdowns of the proposal computed at the timestamp
+    pub fn timeline(&self, id: usize, timestamp: u64) -> GovernResult<Timeline> {
+        let proposal = self.proposals.get(id).ok_or("invalid proposal id")?;
+        let eta = proposal.task.eta;
+        Ok(Timeline {
+            now: timestamp,
+            until_voting_start: proposal.start_time.saturating_sub(timestamp),
+            until_voting_end: proposal.end_time.saturating_sub(timestamp),
+            until_eta: if eta == 0 { None } else { Some(eta.saturating_sub(timestamp)) },
+            until_expiry: if eta == 0 { None } else { Some(proposal.task.expires_at().saturating_sub(timestamp)) },
+        })
+    }
+
+    /// queued proposals in execution order, the executable ones first by priority, the others by eta
+    fn execution_order(&self, timestamp: u64) -> Vec<&Proposal> {
+        let mut queued: Vec<&Proposal> = self.proposals.iter()
+            .filter(|p| p.task.eta != 0 && matches!(self.get_state(p.id, timestamp), Ok(ProposalState::Queued)))
+            .collect();
+        queued.sort_by_key(|p| {
+            let open = p.task.opens_at() <= timestamp;
+            (!open, if open { 0 } else { p.task.eta }, Reverse(p.priority), p.task.eta, p.id)
+        });
+        queued
+    }
+
+    /// queued proposals in execution order, at most limit capped by MAX_EXPORT_ROWS
+    pub fn upcoming_executions(&self, limit: usize, timestamp: u64) -> Vec<UpcomingExecution> {
+        self.execution_order(timestamp)
+            .into_iter()
+            .take(limit.min(Self::MAX_EXPORT_ROWS))
+            .map(|p| UpcomingExecution {
+                id: p.id,
+                title: p.title.clone(),
+                eta: p.task.eta,
+                until_eta: p.task.eta.saturating_sub(timestamp),
+                until_expiry: p.task.expires_at().saturating_sub(timestamp),
+                priority: p.priority,
+            })
+            .collect()
+    }
+
+    /// executable proposal to execute first, none if no queued proposal is executable
+    pub fn next_execution(&self, timestamp: u64) -> Option<usize> {
+        self.executable_proposals(timestamp).first().copied()
+    }
+
+    /// ids of the queued proposals whose execution is open and not expired, in execution order
+    pub fn executable_proposals(&self, timestamp: u64) -> Vec<usize> {
+        self.execution_order(timestamp)
+            .into_iter()
+            .filter(|p| p.task.opens_at() <= timestamp && p.task.expires_at() >= timestamp)
+            .map(|p| p.id)
+            .collect()
+    }
+
+    /// ids of the proposals succeeded and waiting to be queued
+    pub fn succeeded_proposals(&self, timestamp: u64) -> Vec<usize> {
+        (0..self.proposals.len())
+            .filter(|id| matches!(self.get_state(*id, timestamp), Ok(ProposalState::Succeeded)))
+            .collect()
+    }
+
+    pub fn get_state(&self, id: usize, timestamp: u64) -> GovernResult<ProposalState> {
+        if id >= self.proposals.len() { return Err("invalid proposal id"); }
+        let proposal = &self.proposals[id];
+        if let Some(state) = &proposal.imported_state {
+            return Ok(state.clone());
+        }
+        return Ok(
+            if proposal.canceled {
+                ProposalState::Canceled
+            } else if proposal.start_time > timestamp {
+                ProposalState::Pending
+            } else if proposal.end_time > timestamp && self.decided_outcome(proposal).is_none() {
+                ProposalState::Active
+            } else if !proposal.pending_satellites.is_empty() {
+                ProposalState::Tallying
+            } else if proposal.kind == ProposalKind::Options {
+                if self.quorum_votes_of(proposal) >= self.quorum(proposal) && proposal.winning_option().is_some() {
+                    ProposalState::Closed
+                } else {
+                    ProposalState::Defeated
+                }
+            } else if !self.majority_reached(proposal) || self.quorum_votes_of(proposal) < self.quorum(proposal) {
+                ProposalState::Defeated
+            } else if proposal.kind == ProposalKind::Text {
+                ProposalState::Closed
+            } else if proposal.task.eta == 0 {
+                ProposalState::Succeeded
+            } else if proposal.executed {
+                ProposalState::Executed
+            } else if proposal.executing {
+                ProposalState::Executing
+            } else if proposal.task.expires_at() < timestamp {
+                ProposalState::Expired
+            } else {
+                ProposalState::Queued
+            }
+        );
+    }
+
+    /// implicit states reached by proposals and not recorded yet, each is recorded once per proposal
+    pub fn record_transitions(&mut self, timestamp: u64) -> Vec<(usize, ProposalState)> {
+        let mut transitions = vec![];
+        for id in 0..self.proposals.len() {
+            let reached = match self.get_state(id, timestamp) {
+                Ok(ProposalState::Defeated) => { vec![ProposalState::Defeated] }
+                Ok(ProposalState::Succeeded) | Ok(ProposalState::Queued)
+                    | Ok(ProposalState::Executing) | Ok(ProposalState::Executed) => { vec![ProposalState::Succeeded] }
+                Ok(ProposalState::Expired) => { vec![ProposalState::Succeeded, ProposalState::Expired] }
+                Ok(ProposalState::Closed) => { vec![ProposalState::Succeeded, ProposalState::Closed] }
+                _ => { continue; }
+            };
+            let proposal = &mut self.proposals[id];
+            for state in reached {
+                if !proposal.recorded_states.contains(&state) {
+                    proposal.recorded_states.push(state.clone());
+                    transitions.push((id, state));
+                }
+            }
+        }
+        transitions
+    }
+
+    /// drop the tasks of queued proposals left unexecuted past the grace period, return their ids
+    pub fn prune_expired(&mut self, timestamp: u64) -> Vec<usize> {
+        self.timelock.announced_changes.retain(|c| c.expires_at() >= timestamp);
+        self.timelock.announced_calls.retain(|c| c.expires_at() >= timestamp);
+        let stale: Vec<Task> = self.timelock.queued_transactions.iter()
+            .filter(|t| t.expires_at() < timestamp)
+            .cloned()
+            .collect();
+        if stale.is_empty() {
+            return vec![];
+        }
+        for task in stale.iter() {
+            self.timelock.cancel_transaction(task);
+        }
+        self.proposals.iter()
+            .filter(|p| stale.contains(&p.task))
+            .map(|p| p.id)
+            .collect()
+    }
+
+    /// id of a live proposal with the same action as the task, if any
+    pub fn live_duplicate(&self, task: &Task, timestamp: u64) -> Option<usize> {
+        let hash = task.action_hash();
+        self.proposals.iter()
+            .filter(|p| p.kind == ProposalKind::Executable && p.task.action_hash() == hash)
+            .find(|p| matches!(
+                self.get_state(p.id, timestamp),
+                Ok(ProposalState::Pending) | Ok(ProposalState::Active) | Ok(ProposalState::Tallying)
+                    | Ok(ProposalState::Succeeded) | Ok(ProposalState::Queued) | Ok(ProposalState::Executing)
+            ))
+            .map(|p| p.id)
+    }
+
+    /// id of a proposal with the same action as the task defeated within the cooldown, if any
+    pub fn cooling_down(&self, task: &Task, timestamp: u64) -> Option<usize> {
+        if self.defeat_cooldown == 0 {
+            return None;
+        }
+        let hash = task.action_hash();
+        self.proposals.iter()
+            .filter(|p| p.kind == ProposalKind::Executable && p.task.action_hash() == hash && p.end_time.saturating_add(self.defeat_cooldown) > timestamp)
+            .find(|p| matches!(self.get_state(p.id, timestamp), Ok(ProposalState::Defeated)))
+            .map(|p| p.id)
+    }
+
+    /// votes required by the proposal, as configured when it was proposed
+    fn quorum(&self, proposal: &Proposal) -> Nat {
+        proposal.quorum_override.clone().unwrap_or_else(|| proposal.config.quorum_votes.clone())
+    }
+
+    /// whether the tally carries the proposal under its counting, ties are settled by the tie rule
+    fn majority_reached(&self, proposal: &Proposal) -> bool {
+        proposal.counting.strategy().carried(&proposal.tally())
+            .or_else(|| self.tie_outcome(proposal))
+            .unwrap_or(false)
+    }
+
+    /// outcome of a tie under the tie rule of the proposal, none while it depends on a proposer who has not voted
+    fn tie_outcome(&self, proposal: &Proposal) -> Option<bool> {
+        match proposal.config.tie_rule {
+            TieRule::Defeat => { Some(false) }
+            TieRule::Succeed => { Some(true) }
+            TieRule::Proposer => {
+                proposal.receipts.get(&proposal.proposer).map(|r| r.vote_type == VoteType::Support)
+            }
+        }
+    }
+
+    /// outcome of an active proposal the remaining votes can no longer change, when voting closes early.
+    /// only counting one vote per token vote is bounded by the total supply
+    fn decided_outcome(&self, proposal: &Proposal) -> Option<bool> {
+        if !proposal.config.early_finish || !proposal.pending_satellites.is_empty() || proposal.kind == ProposalKind::Options {
+            return None;
+        }
+        match proposal.counting {
+            CountingMode::TokenWeighted | CountingMode::Supermajority(_) | CountingMode::Approval(_) | CountingMode::Ramped(_) => {}
+            CountingMode::OnePrincipalOneVote(_) | CountingMode::Quadratic => { return None; }
+        }
+        let max_votes = proposal.max_votes.clone()?;
+        let total_votes = proposal.total_votes();
+        let remaining = if max_votes > total_votes { max_votes - total_votes } else { Nat::from(0) };
+        let strategy = proposal.counting.strategy();
+        let quorum = self.quorum(proposal);
+        let quorum_votes = self.quorum_votes_of(proposal);
+
+        let mut worst = proposal.tally();
+        worst.against_votes += remaining.clone();
+        let carried_worst = strategy.carried(&worst).or_else(|| self.tie_outcome(proposal));
+        if carried_worst == Some(true) && quorum_votes >= quorum {
+            return Some(true);
+        }
+        let mut best = proposal.tally();
+        best.support_votes += remaining.clone();
+        let carried_best = strategy.carried(&best).or_else(|| self.tie_outcome(proposal));
+        if carried_best == Some(false) || quorum_votes + remaining < quorum {
+            return Some(false);
+        }
+        None
+    }
+
+    /// votes of the proposal counted against its quorum, under the quorum mode it was proposed with
+    fn quorum_votes_of(&self, proposal: &Proposal) -> Nat {
+        match proposal.config.quorum_mode {
+            QuorumMode::Support => { proposal.support_votes.clone() }
+            QuorumMode::Participation => {
+                proposal.support_votes.clone() + proposal.against_votes.clone() + proposal.abstain_votes.clone()
+            }
+        }
+    }
+
+    /// whether the votes of the proposal reached its quorum
+    pub fn quorum_reached(&self, id: usize) -> GovernResult<bool> {
+        let proposal = self.proposals.get(id).ok_or("invalid proposal id")?;
+        Ok(self.quorum_votes_of(proposal) >= self.quorum(proposal))
+    }
+
+    /// tallies of the proposal against its quorum and majority rules
+    pub fn quorum_status(&self, id: usize) -> GovernResult<QuorumStatus> {
+        let proposal = self.proposals.get(id).ok_or("invalid proposal id")?;
+        let quorum = self.quorum(proposal);
+        let quorum_votes = self.quorum_votes_of(proposal);
+        let quorum_bps = if quorum == 0u64 {
+            MAX_BPS
+        } else {
+            let bps = quorum_votes.clone() * Nat::from(MAX_BPS) / quorum.clone();
+            u64::try_from(&bps.0).unwrap_or(u64::MAX)
+        };
+        let quorum_reached = quorum_votes >= quorum;
+        let majority_reached = self.majority_reached(proposal);
+        Ok(QuorumStatus {
+            support_votes: proposal.support_votes.clone(),
+            against_votes: proposal.against_votes.clone(),
+            abstain_votes: proposal.abstain_votes.clone(),
+            quorum,
+            quorum_votes,
+            quorum_bps,
+            quorum_reached,
+            majority_reached,
+            would_pass: quorum_reached && majority_reached,
+        })
+    }
+
+    /// number of proposals ever proposed
+    pub fn proposal_count(&self) -> usize {
+        self.proposals.len()
+    }
+
+    /// ids of proposals left executing, e.g. after a trap during the task call
+    pub fn executing_proposals(&self) -> Vec<usize> {
+        self.proposals.iter()
+            .filter(|p| p.executing && !p.executed)
+            .map(|p| p.id)
+            .collect()
+    }
+
+    /// verify the internal consistency of the governor, return every violation found
+    pub fn check_invariants(&self, timestamp: u64) -> Vec<Violation> {
+        let mut violations = vec![];
+        let written = self.stable_memory.offset;
+        // records must lie within the written memory and match their header
+        let in_bounds = |pos: &Position| pos.offset.checked_add(pos.len).map_or(false, |end| end <= written)
+            && self.stable_memory.read_record(pos).is_ok();
+
+        if written > self.stable_memory.size() {
+            violations.push(Violation::new(None, format!("stable memory offset {} beyond size {}", written, self.stable_memory.size())));
+        }
+        for (index, proposal) in self.proposals.iter().enumerate() {
+            let id = Some(index);
+            if proposal.id != index {
+                violations.push(Violation::new(id, format!("proposal stored at {} has id {}", index, proposal.id)));
+            }
+            if !in_bounds(&proposal.description) {
+                violations.push(Violation::new(id, "description record out of bounds or corrupted".to_string()));
+            }
+            for (n, comment) in proposal.comments.iter().enumerate() {
+                if comment.id != n || !in_bounds(&comment.text) {
+                    violations.push(Violation::new(id, format!("comment {} has an invalid id or position", n)));
+                }
+            }
+            if proposal.canceled && proposal.executed {
+                violations.push(Violation::new(id, "proposal both canceled and executed".to_string()));
+            }
+            if proposal.start_time > proposal.end_time {
+                violations.push(Violation::new(id, "voting starts after it ends".to_string()));
+            }
+            for satellite in proposal.pending_satellites.iter() {
+                if !self.satellites.contains(satellite) {
+                    violations.push(Violation::new(id, format!("pending satellite {} not registered", satellite)));
+                }
+            }
+
+            let (mut support, mut against, mut abstain) = match &proposal.offchain_tally {
+                Some(t) => { (t.support_votes.clone(), t.against_votes.clone(), t.abstain_votes.clone()) }
+                None => { (Nat::from(0), Nat::from(0), Nat::from(0)) }
+            };
+            for (voter, receipt) in proposal.receipts.iter() {
+                match receipt.vote_type {
+                    VoteType::Support => { support += receipt.votes.clone(); }
+                    VoteType::Against => { against += receipt.votes.clone(); }
+                    VoteType::Abstain => { abstain += receipt.votes.clone(); }
+                }
+                if let Some(pos) = &receipt.reason {
+                    if !in_bounds(pos) {
+                        violations.push(Violation::new(id, format!("reason record of {} out of bounds or corrupted", voter)));
+                    }
+                }
+            }
+            // satellite tallies are merged on top of the receipts and off-chain votes, so tallies may only exceed them with satellites
+            let tallies = [
+                ("support", support, &proposal.support_votes),
+                ("against", against, &proposal.against_votes),
+                ("abstain", abstain, &proposal.abstain_votes),
+            ];
+            for (label, receipts, tally) in tallies.iter() {
+                if receipts > *tally || (self.satellites.is_empty() && receipts != *tally) {
+                    violations.push(Violation::new(id, format!("{} receipts sum to {} but tally is {}", label, receipts.0, tally.0)));
+                }
+            }
+
+            let queued = self.timelock.queued_transactions.contains(&proposal.task);
+            if let Ok(ProposalState::Queued) = self.get_state(index, timestamp) {
+                if !queued {
+                    violations.push(Violation::new(id, "queued proposal missing from the timelock".to_string()));
+                }
+            }
+        }
+
+        for task in self.timelock.queued_transactions.iter() {
+            // tasks of expired proposals stay queued until pruned
+            let owned = self.proposals.iter().any(|p| {
+                p.task == *task && matches!(
+                    self.get_state(p.id, timestamp),
+                    Ok(ProposalState::Queued) | Ok(ProposalState::Expired)
+                )
+            });
+            if !owned {
+                violations.push(Violation::new(None, format!("queued task {}.{} has no queued proposal", task.target, task.method)));
+            }
+        }
+
+        for (proposer, id) in self.latest_proposal_ids.iter() {
+            match self.proposals.get(*id) {
+                Some(p) if p.proposer == *proposer => {}
+                Some(_) => {
+                    violations.push(Violation::new(Some(*id), format!("latest proposal of {} proposed by someone else", proposer)));
+                }
+                None => {
+                    violations.push(Violation::new(None, format!("latest proposal id {} of {} does not exist", id, proposer)));
+                }
+            }
+        }
+        violations
+    }
+
+    /// sum of support, against and abstain votes over all proposals
+    pub fn vote_totals(&self) -> (Nat, Nat, Nat) {
+        self.proposals.iter().fold(
+            (Nat::from(0), Nat::from(0), Nat::from(0)),
+            |(support, against, abstain), p| {
+                (
+                    support + p.support_votes.clone(),
+                    against + p.against_votes.clone(),
+                    abstain + p.abstain_votes.clone(),
+                )
+            },
+        )
+    }
+
+    pub fn set_quorum_votes(&mut self, quorum: Nat) {
+        self.quorum_votes = quorum;
+    }
+
+    /// make the quorum of later proposals a fraction of the total supply, 0 to use the quorum votes
+    pub fn set_quorum_fraction(&mut self, bps: u64) -> GovernResult<()> {
+        if bps > MAX_BPS {
+            return Err("quorum fraction above 100%");
+        }
+        self.quorum_fraction = bps;
+        Ok(())
+    }
+
+    pub fn quorum_fraction(&self) -> u64 {
+        self.quorum_fraction
+    }
+
+    /// fix the quorum of a new proposal to the quorum fraction of the total supply, at least 1 vote
+    pub fn apply_quorum_fraction(&mut self, id: usize, total_supply: Nat) -> GovernResult<()> {
+        let proposal = self.proposals.get(id).ok_or("invalid proposal id")?;
+        if proposal.total_votes() > 0u64 || proposal.imported_state.is_some() {
+            return Err("quorum can only be fixed before votes are cast");
+        }
+        let bps = self.quorum_fraction;
+        if bps == 0 {
+            return Ok(());
+        }
+        let quorum = total_supply * Nat::from(bps) / Nat::from(MAX_BPS);
+        let config = &mut self.proposals[id].config;
+        config.quorum_votes = if quorum < 1u64 { Nat::from(1) } else { quorum };
+        config.quorum_fraction = bps;
+        Ok(())
+    }
+
+    /// check the new value of a parameter change is within the bounds of the parameter
+    pub fn check_param_change(change: &ParamChange) -> GovernResult<()> {
+        let value = &change.new_value;
+        let in_bounds = match change.field {
+            GovernorParam::QuorumVotes => { *value >= 1u64 }
+            GovernorParam::QuorumFraction => { *value <= MAX_BPS }
+            GovernorParam::ProposalThreshold => {
+                *value >= Self::MIN_PROPOSAL_THRESHOLD && *value <= Self::MAX_PROPOSAL_THRESHOLD
+            }
+            field => {
+                let (min, max) = match field {
+                    GovernorParam::VotingDelay => { (Self::MIN_VOTING_DELAY, Self::MAX_VOTING_DELAY) }
+                    GovernorParam::VotingPeriod => { (Self::MIN_VOTING_PERIOD, Self::MAX_VOTING_PERIOD) }
+                    GovernorParam::TimelockDelay => { (Timelock::MIN_DELAY, Timelock::MAX_DELAY) }
+                    _ => { (0, Self::MAX_HOLDING_DURATION) }
+                };
+                *value >= min && *value <= max
+            }
+        };
+        if !in_bounds {
+            return Err("parameter value out of bounds");
+        }
+        Ok(())
+    }
+
+    /// announce an admin parameter change, it takes effect once applied after the timelock delay
+    pub fn announce_param_change(&mut self, change: ParamChange, announcer: Principal, timestamp: u64) -> GovernResult<AnnouncedChange> {
+        Self::check_param_change(&change)?;
+        Ok(self.timelock.announce_change(change, announcer, timestamp))
+    }
+
+    /// apply the announced change once its eta has passed, return it with the previous value
+    pub fn apply_announced_change(&mut self, id: usize, timestamp: u64) -> GovernResult<(ParamChange, Nat)> {
+        let announced = self.timelock.take_change(id, timestamp)?;
+        let previous = self.apply_param_change(&announced.change)?;
+        Ok((announced.change, previous))
+    }
+
+    pub fn cancel_announced_change(&mut self, id: usize) -> GovernResult<AnnouncedChange> {
+        self.timelock.cancel_change(id)
+    }
+
+    /// apply the parameter change, return the previous value
+    pub fn apply_param_change(&mut self, change: &ParamChange) -> GovernResult<Nat> {
+        Self::check_param_change(change)?;
+        let value = change.new_value.clone();
+        // durations and fractions are within bounds, hence fit in u64
+        let duration = || u64::try_from(&value.0).map_err(|_| "parameter value out of bounds");
+        let previous = match change.field {
+            GovernorParam::QuorumVotes => { std::mem::replace(&mut self.quorum_votes, value.clone()) }
+            GovernorParam::ProposalThreshold => { std::mem::replace(&mut self.proposal_threshold, value.clone()) }
+            GovernorParam::VotingDelay => { Nat::from(std::mem::replace(&mut self.voting_delay, duration()?)) }
+            GovernorParam::VotingPeriod => { Nat::from(std::mem::replace(&mut self.voting_period, duration()?)) }
+            GovernorParam::TimelockDelay => { Nat::from(std::mem::replace(&mut self.timelock.delay, duration()?)) }
+            GovernorParam::MinHoldingDuration => { Nat::from(std::mem::replace(&mut self.min_holding_duration, duration()?)) }
+            GovernorParam::MinDelegationAge => { Nat::from(std::mem::replace(&mut self.min_delegation_age, duration()?)) }
+            GovernorParam::QuorumFraction => { Nat::from(std::mem::replace(&mut self.quorum_fraction, duration()?)) }
+        };
+        Ok(previous)
+    }
+
+    /// allow proposals to be given a quorum within the bounds, none disables overrides
+    pub fn set_quorum_override_bounds(&mut self, bounds: Option<(Nat, Nat)>) -> GovernResult<()> {
+        if let Some((min, max)) = &bounds {
+            if min > max {
+                return Err("minimum quorum above maximum quorum");
+            }
+        }
+        self.quorum_override_bounds = bounds;
+        Ok(())
+    }
+
+    /// override the quorum of the proposal until voting starts, none restores the governor's
+    pub fn set_quorum_override(&mut self, id: usize, quorum: Option<Nat>, timestamp: u64) -> GovernResult<()> {
+        if self.get_state(id, timestamp)? != ProposalState::Pending {
+            return Err("quorum can only be overridden before voting starts");
+        }
+        if let Some(quorum) = &quorum {
+            match &self.quorum_override_bounds {
+                Some((min, max)) if quorum >= min && quorum <= max => {}
+                Some(_) => { return Err("quorum override out of bounds"); }
+                None => { return Err("quorum overrides are disabled"); }
+            }
+        }
+        self.proposals[id].quorum_override = quorum;
+        Ok(())
+    }
+
+    /// allow proposers to request a voting period within the bounds, none disables overrides
+    pub fn set_voting_period_bounds(&mut self, bounds: Option<(u64, u64)>) -> GovernResult<()> {
+        if let Some((min, max)) = bounds {
+            if min > max {
+                return Err("minimum voting period above maximum voting period");
+            }
+        }
+        self.voting_period_bounds = bounds;
+        Ok(())
+    }
+
+    /// vote on the proposal for the period instead of the governor's, until voting starts
+    pub fn set_voting_period(&mut self, id: usize, period: u64, timestamp: u64) -> GovernResult<()> {
+        if self.get_state(id, timestamp)? != ProposalState::Pending {
+            return Err("voting period can only be overridden before voting starts");
+        }
+        self.check_voting_period(period)?;
+        let proposal = &mut self.proposals[id];
+        self.end_time_index.remove(&(proposal.end_time, id));
+        proposal.end_time = proposal.start_time + period;
+        proposal.config.voting_period = period;
+        self.end_time_index.insert((proposal.end_time, id));
+        Ok(())
+    }
+
+    fn check_voting_period(&self, period: u64) -> GovernResult<()> {
+        match self.voting_period_bounds {
+            Some((min, max)) if period >= min && period <= max => { Ok(()) }
+            Some(_) => { Err("voting period out of bounds") }
+            None => { Err("voting period overrides are disabled") }
+        }
+    }
+
+    /// set the execution priority of the proposal until it is executed, higher executes first
+    pub fn set_priority(&mut self, id: usize, priority: u8, timestamp: u64) -> GovernResult<()> {
+        match self.get_state(id, timestamp)? {
+            ProposalState::Pending | ProposalState::Active | ProposalState::Tallying
+                | ProposalState::Succeeded | ProposalState::Queued => {}
+            _ => { return Err("priority can only be set before execution"); }
+        }
+        self.proposals[id].priority = priority;
+        Ok(())
+    }
+
+    pub fn set_vote_delay(&mut self, delay: u64) {
+        self.voting_delay = delay;
+    }
+
+    pub fn set_vote_period(&mut self, period: u64) {
+        self.voting_period = period;
+    }
+
+    pub fn set_proposal_threshold(&mut self, threshold: Nat) {
+        self.proposal_threshold = threshold;
+    }
+
+    pub fn set_min_holding_duration(&mut self, duration: u64) {
+        self.min_holding_duration = duration;
+    }
+
+    pub fn min_holding_duration(&self) -> u64 {
+        self.min_holding_duration
+    }
+
+    pub fn set_min_delegation_age(&mut self, age: u64) {
+        self.min_delegation_age = age;
+    }
+
+    pub fn min_delegation_age(&self) -> u64 {
+        self.min_delegation_age
+    }
+
+    pub fn set_quorum_mode(&mut self, mode: QuorumMode) {
+        self.quorum_mode = mode;
+    }
+
+    pub fn quorum_mode(&self) -> QuorumMode {
+        self.quorum_mode
+    }
+
+    pub fn set_tie_rule(&mut self, rule: TieRule) {
+        self.tie_rule = rule;
+    }
+
+    pub fn tie_rule(&self) -> TieRule {
+        self.tie_rule
+    }
+
+    pub fn set_min_comment_balance(&mut self, balance: u64) {
+        self.min_comment_balance = balance;
+    }
+
+    pub fn min_comment_balance(&self) -> u64 {
+        self.min_comment_balance
+    }
+
+    pub fn set_defeat_cooldown(&mut self, cooldown: u64) {
+        self.defeat_cooldown = cooldown;
+    }
+
+    pub fn defeat_cooldown(&self) -> u64 {
+        self.defeat_cooldown
+    }
+
+    /// set the deposit of later proposals and where slashed deposits go
+    pub fn set_proposal_deposit(&mut self, amount: Nat, treasury: Option<Principal>) {
+        self.proposal_deposit = amount;
+        self.deposit_treasury = treasury;
+    }
+
+    pub fn proposal_deposit(&self) -> Nat {
+        self.proposal_deposit.clone()
+    }
+
+    /// record the deposit taken from the proposer of a new proposal
+    pub fn record_deposit(&mut self, id: usize, depositor: Principal, amount: Nat) -> GovernResult<()> {
+        let proposal = self.proposals.get_mut(id).ok_or("invalid proposal id")?;
+        if proposal.deposit.is_some() {
+            return Err("deposit already recorded");
+        }
+        proposal.deposit = Some(Deposit {
+            depositor,
+            amount,
+            forfeited: false,
+            status: DepositStatus::Held,
+        });
+        Ok(())
+    }
+
+    /// refund the deposit once the proposal succeeded or was canceled by its proposer,
+    /// slash it once defeated or canceled by anyone else, return the account to pay, none to keep it,
+    /// and the settled deposit
+    pub fn settle_deposit(&mut self, id: usize, timestamp: u64) -> GovernResult<(Option<Principal>, Deposit)> {
+        let state = self.get_state(id, timestamp)?;
+        let treasury = self.deposit_treasury;
+        let deposit = self.proposals[id].deposit.as_mut().ok_or("proposal has no deposit")?;
+        if deposit.status != DepositStatus::Held {
+            return Err("deposit already settled");
+        }
+        let slashed = match state {
+            ProposalState::Pending | ProposalState::Active | ProposalState::Tallying => {
+                return Err("deposit can only be settled once voting ended");
+            }
+            ProposalState::Defeated => { true }
+            ProposalState::Canceled => { deposit.forfeited }
+            ProposalState::Succeeded | ProposalState::Queued | ProposalState::Executing
+                | ProposalState::Executed | ProposalState::Expired | ProposalState::Closed => { false }
+        };
+        if slashed {
+            deposit.status = DepositStatus::Slashed;
+            Ok((treasury, deposit.clone()))
+        } else {
+            deposit.status = DepositStatus::Refunded;
+            Ok((Some(deposit.depositor), deposit.clone()))
+        }
+    }
+
+    /// hold the deposit again after paying it out failed, so it can be settled again
+    pub fn unsettle_deposit(&mut self, id: usize) -> GovernResult<()> {
+        let proposal = self.proposals.get_mut(id).ok_or("invalid proposal id")?;
+        let deposit = proposal.deposit.as_mut().ok_or("proposal has no deposit")?;
+        deposit.status = DepositStatus::Held;
+        Ok(())
+    }
+
+    /// keep a deposit whose refund failed so the depositor can claim it later
+    pub fn record_unpaid_refund(&mut self, depositor: Principal, amount: Nat) {
+        let unpaid = self.unpaid_refunds.remove(&depositor).unwrap_or_else(|| Nat::from(0));
+        self.unpaid_refunds.insert(depositor, unpaid + amount);
+    }
+
+    /// take the unpaid refunds of the depositor, to be recorded again if paying them fails
+    pub fn take_unpaid_refund(&mut self, depositor: Principal) -> GovernResult<Nat> {
+        self.unpaid_refunds.remove(&depositor).ok_or("no unpaid refund")
+    }
+
+    /// close voting on later proposals once their outcome can no longer change
+    pub fn set_early_finish(&mut self, enabled: bool) {
+        self.early_finish = enabled;
+    }
+
+    pub fn early_finish(&self) -> bool {
+        self.early_finish
+    }
+
+    /// set how votes are counted on proposals of the type, e.g. "canister"
+    pub fn set_counting_mode(&mut self, kind: String, mode: CountingMode) -> GovernResult<()> {
+        if !BACKEND_KINDS.contains(&kind.as_str()) {
+            return Err("unknown proposal type");
+        }
+        mode.validate()?;
+        if mode == self.default_counting {
+            self.counting_modes.remove(&kind);
+        } else {
+            self.counting_modes.insert(kind, mode);
+        }
+        Ok(())
+    }
+
+    pub fn counting_mode(&self, kind: &str) -> CountingMode {
+        self.counting_modes.get(kind).copied().unwrap_or(self.default_counting)
+    }
+
+    /// set how votes are counted on proposals of types without their own counting mode
+    pub fn set_default_counting_mode(&mut self, mode: CountingMode) -> GovernResult<()> {
+        mode.validate()?;
+        self.default_counting = mode;
+        Ok(())
+    }
+
+    pub fn default_counting_mode(&self) -> CountingMode {
+        self.default_counting
+    }
+
+    /// set when queued proposals of the type may be executed, none restores the grace period after the eta
+    pub fn set_execution_window(&mut self, kind: String, window: Option<ExecutionWindow>) -> GovernResult<()> {
+        if !BACKEND_KINDS.contains(&kind.as_str()) {
+            return Err("unknown proposal type");
+        }
+        match window {
+            Some(window) => {
+                if window.length == 0 {
+                    return Err("execution window length must be positive");
+                }
+                if window.offset > Timelock::MAX_DELAY || window.length > Timelock::GRACE_PERIOD {
+                    return Err("execution window out of bounds");
+                }
+                self.execution_windows.insert(kind, window);
+            }
+            None => { self.execution_windows.remove(&kind); }
+        }
+        Ok(())
+    }
+
+    pub fn execution_window(&self, kind: &str) -> ExecutionWindow {
+        self.execution_windows.get(kind).copied().unwrap_or_default()
+    }
+
+    /// cap the cycles a task may attach, 0 to disable
+    pub fn set_max_task_cycles(&mut self, cycles: u64) {
+        self.max_task_cycles = cycles;
+    }
+
+    /// cap the cycles a task calling the target may attach, none removes the target's cap
+    pub fn set_target_cycle_limit(&mut self, target: Principal, limit: Option<u64>) {
+        match limit {
+            Some(limit) => { self.target_cycle_limits.insert(target, limit); }
+            None => { self.target_cycle_limits.remove(&target); }
+        }
+    }
+
+    /// check the cycles of the task are within the governor-wide and the target's caps
+    fn check_task_cycles(&self, task: &Task) -> GovernResult<()> {
+        if let Backend::Calls(calls) = &task.backend {
+            let total = calls.iter().try_fold(0u64, |total, call| total.checked_add(call.cycles)).ok_or("task cycles overflow")?;
+            if self.max_task_cycles != 0 && total > self.max_task_cycles {
+                return Err("task cycles above the governor's limit");
+            }
+            return calls.iter().try_for_each(|call| self.check_target_cycles(&call.target, call.cycles));
+        }
+        if self.max_task_cycles != 0 && task.cycles > self.max_task_cycles {
+            return Err("task cycles above the governor's limit");
+        }
+        self.check_target_cycles(&task.target, task.cycles)
+    }
+
+    fn check_target_cycles(&self, target: &Principal, cycles: u64) -> GovernResult<()> {
+        match self.target_cycle_limits.get(target) {
+            Some(limit) if cycles > *limit => { Err("task cycles above the target's limit") }
+            _ => { Ok(()) }
+        }
+    }
+
+    /// restrict execution to the executors, an empty list lets anyone execute
+    pub fn set_executors(&mut self, executors: Vec<Principal>) {
+        let mut executors = executors;
+        executors.sort();
+        executors.dedup();
+        self.executors = executors;
+    }
+
+    pub fn can_execute(&self, caller: &Principal) -> bool {
+        self.executors.is_empty() || self.executors.contains(caller)
+    }
+
+    pub fn set_pending_admin(&mut self, pending_admin: Principal) {
+        self.pending_admin = Some(pending_admin);
+    }
+
+    pub fn accept_admin(&mut self) {
+        assert!(self.pending_admin.is_some());
+        self.admin = self.pending_admin.unwrap();
+        self.pending_admin = None;
+    }
+
+    pub fn digest(&self) -> GovernorBravoInfo {
+        GovernorBravoInfo {
+            admin: self.admin,
+            pending_admin: self.pending_admin,
+            name: self.name.clone(),
+            quorum_votes: self.quorum_votes.clone(),
+            quorum_fraction: self.quorum_fraction,
+            quorum_override_bounds: self.quorum_override_bounds.clone(),
+            voting_period_bounds: self.voting_period_bounds,
+            quorum_mode: self.quorum_mode,
+            tie_rule: self.tie_rule,
+            voting_delay: self.voting_delay,
+            voting_period: self.voting_period,
+            proposal_threshold: self.proposal_threshold.clone(),
+            min_holding_duration: self.min_holding_duration,
+            min_delegation_age: self.min_delegation_age,
+            min_comment_balance: self.min_comment_balance,
+            defeat_cooldown: self.defeat_cooldown,
+            proposal_deposit: self.proposal_deposit.clone(),
+            deposit_treasury: self.deposit_treasury,
+            early_finish: self.early_finish,
+            default_counting: self.default_counting,
+            counting_modes: self.counting_modes.iter().map(|(k, m)| (k.clone(), *m)).collect(),
+            execution_windows: self.execution_windows.iter().map(|(k, w)| (k.clone(), *w)).collect(),
+            max_task_cycles: self.max_task_cycles,
+            target_cycle_limits: self.target_cycle_limits.iter().map(|(t, l)| (*t, *l)).collect(),
+            proposals_num: self.proposals.len(),
+            satellites: self.satellites.clone(),
+            executors: self.executors.clone(),
+            timelock_delay: self.timelock.delay,
+            grace_period: Timelock::GRACE_PERIOD,
+            queued_tasks: self.timelock.queued_transactions.len(),
+            next_eta: self.timelock.queued_transactions.iter().map(|t| t.eta).min(),
+            gov_token: self.gov_token,
+            stable_memory: self.stable_memory.clone(),
+        }
+    }
+}
+
+impl From<legacy::GovernorBravo> for GovernorBravo {
+    /// migrate the governor of the first release, its proposals are judged by the parameters it had
+    fn from(legacy: legacy::GovernorBravo) -> Self {
+        let config = ProposalConfig {
+            quorum_votes: Nat::from(legacy.quorum_votes),
+            proposal_threshold: Nat::from(legacy.proposal_threshold),
+            voting_period: legacy.voting_period,
+            ..Default::default()
+        };
+        let mut bravo = GovernorBravo {
+            admin: legacy.admin,
+            pending_admin: legacy.pending_admin,
+            name: legacy.name,
+            quorum_votes: config.quorum_votes.clone(),
+            voting_delay: legacy.voting_delay,
+            voting_period: legacy.voting_period,
+            proposal_threshold: config.proposal_threshold.clone(),
+            latest_proposal_ids: legacy.latest_proposal_ids,
+            initialized: legacy.initialized,
+            gov_token: legacy.gov_token,
+            timelock: legacy.timelock,
+            stable_memory: legacy.stable_memory,
+            ..Default::default()
+        };
+        for mut proposal in legacy.proposals {
+            proposal.config = config.clone();
+            bravo.index_proposal(&proposal);
+            bravo.proposals.push(proposal);
+        }
+        bravo
+    }
+}
+
+impl Default for GovernorBravo {
+    fn default() -> Self {
+        Self {
+            admin: Principal::anonymous(),
+            pending_admin: None,
+
+            name: "".to_string(),
+            quorum_votes: Nat::from(0),
+            quorum_fraction: 0,
+            quorum_override_bounds: None,
+            voting_period_bounds: None,
+            quorum_mode: QuorumMode::Support,
+            tie_rule: TieRule::Defeat,
+            voting_delay: 0,
+            voting_period: 0,
+            proposal_threshold: Nat::from(0),
+            min_holding_duration: 0,
+            min_delegation_age: 0,
+            min_comment_balance: 0,
+            defeat_cooldown: 0,
+            proposal_deposit: Nat::from(0),
+            deposit_treasury: None,
+            unpaid_refunds: HashMap::new(),
+            early_finish: false,
+            default_counting: CountingMode::TokenWeighted,
+            counting_modes: HashMap::new(),
+            execution_windows: HashMap::new(),
+            max_task_cycles: 0,
+            target_cycle_limits: HashMap::new(),
+            proposals: vec![],
+            latest_proposal_ids: HashMap::new(),
+            end_time_index: BTreeSet::new(),
+            votes_index: BTreeSet::new(),
+            initialized: false,
+            satellites: vec![],
+            executors: vec![],
+            gov_token: Principal::anonymous(),
+            timelock: Timelock::default(),
+            stable_memory: Default::default(),
+            reason_tombstone: None,
+        }
+    }
+}
\ No newline at end of file
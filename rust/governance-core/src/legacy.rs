@@ -0,0 +1,37 @@
+/**
+ * Module     : legacy.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+//! Layouts stored by releases before the upgrade state was versioned, only
+//! decoded to migrate canisters upgraded from them.
+
+use std::collections::HashMap;
+use candid::{CandidType, Deserialize, Principal};
+use crate::governance::Proposal;
+use crate::stable::StableMemory;
+use crate::timelock::Timelock;
+
+/// governor of the first release, stored alone with the cap archive. its proposals and timelock
+/// decode into the current types, fields added since then take their defaults
+#[derive(Deserialize, CandidType, Clone)]
+pub struct GovernorBravo {
+    pub admin: Principal,
+    pub pending_admin: Option<Principal>,
+    pub name: String,
+    /// nat64 until quorums became nat
+    pub quorum_votes: u64,
+    pub voting_delay: u64,
+    pub voting_period: u64,
+    /// nat64 until thresholds became nat
+    pub proposal_threshold: u64,
+    pub proposals: Vec<Proposal>,
+    pub latest_proposal_ids: HashMap<Principal, usize>,
+    pub initialized: bool,
+    pub gov_token: Principal,
+    pub timelock: Timelock,
+    pub stable_memory: StableMemory,
+}
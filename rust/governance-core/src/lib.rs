@@ -0,0 +1,24 @@
+/**
+ * Module     : lib.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+//! Governor Bravo state machine and timelock, free of canister entry points.
+//! Proposal descriptions and vote reasons live in stable memory, which is
+//! backed by the heap outside of wasm so the logic can be unit-tested natively.
+
+pub mod clock;
+pub mod counting;
+pub mod governance;
+pub mod legacy;
+pub mod stable;
+pub mod timelock;
+#[cfg(test)]
+mod test;
+
+pub use clock::{IcClock, MockClock, TimeProvider};
+pub use governance::{GovernorBravo, ProposalState, VoteType};
+pub use timelock::{Task, Timelock};
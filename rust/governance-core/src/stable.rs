@@ -0,0 +1,207 @@
+use candid::{Deserialize, CandidType};
+#[cfg(target_family = "wasm")]
+pub use ic_cdk::api::stable::StableMemoryError;
+#[cfg(target_family = "wasm")]
+use ic_cdk::api::stable::{stable_grow, stable_read, stable_write};
+#[cfg(not(target_family = "wasm"))]
+pub use heap::StableMemoryError;
+#[cfg(not(target_family = "wasm"))]
+use heap::{stable_grow, stable_read, stable_write};
+
+/// bytes written before each record: its length and CRC-32, both little endian u32
+pub const RECORD_HEADER_LEN: usize = 8;
+
+pub trait Memory<E> {
+    fn capacity(&self) -> u32;
+    fn size(&self) -> usize;
+    fn grow(&mut self, pages: u32) -> Result<(), E>;
+    fn read(&self, offset: usize, dst: &mut [u8]) -> Result<usize, E>;
+    fn write(&mut self, src: &[u8]) -> Result<usize, E>;
+}
+
+#[derive(Deserialize, CandidType, Default, Clone)]
+pub struct StableMemory {
+    /// current offset in stable memory
+    pub offset: usize,
+    /// current pages count in stable memory
+    capacity: u32,
+}
+
+#[derive(Deserialize, CandidType, Default, Clone)]
+pub struct Position {
+    pub(crate) offset: usize,
+    pub(crate) len: usize,
+    /// whether a header precedes the bytes, records written before checksums were added have none
+    #[serde(default)]
+    pub(crate) checked: bool,
+}
+
+impl Position {
+    fn new(offset: usize, len: usize)  -> Self {
+        Self {
+            offset,
+            len,
+            checked: true,
+        }
+    }
+}
+
+impl Memory<StableMemoryError> for StableMemory {
+    /// get current pages count
+    fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// get current memory size in bytes
+    fn size(&self) -> usize {
+        (self.capacity as usize) << 16
+    }
+
+    /// attempts to grow the memory by adding new pages
+    fn grow(&mut self, pages: u32) -> Result<(), StableMemoryError> {
+        let old_page_count = stable_grow(pages)?;
+        self.capacity = old_page_count + pages;
+        Ok(())
+    }
+
+    /// read bytes from offset to fill the buf, return bytes read
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, StableMemoryError> {
+        if offset + buf.len() > self.offset {
+            return Err(StableMemoryError::OutOfBounds)
+        }
+        stable_read(offset as u32, buf);
+        Ok(buf.len())
+    }
+
+    /// write bytes to stable memory, return bytes written
+    fn write(&mut self, buf: &[u8]) -> Result<usize, StableMemoryError> {
+        if self.offset + buf.len() >  self.size() {
+            self.grow((buf.len() >> 16) as u32 + 1)?;
+        }
+        stable_write(self.offset as u32, buf);
+        self.offset += buf.len();
+        Ok(buf.len())
+    }
+}
+
+impl StableMemory {
+    /// write the bytes behind their length and checksum, return the position of the bytes
+    pub fn write_record(&mut self, data: &[u8]) -> Result<Position, &'static str> {
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        header[..4].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        header[4..].copy_from_slice(&crc32(data).to_le_bytes());
+        self.write(&header).map_err(|_| "Stable memory error")?;
+        let offset = self.offset;
+        let len = self.write(data).map_err(|_| "Stable memory error")?;
+        Ok(Position::new(offset, len))
+    }
+
+    /// read the bytes of the record at the position, checking them against its header if it has one
+    pub fn read_record(&self, pos: &Position) -> Result<Vec<u8>, &'static str> {
+        if !pos.checked {
+            let mut buf = vec![0u8; pos.len];
+            self.read(pos.offset, buf.as_mut_slice()).map_err(|_| "Stable memory error")?;
+            return Ok(buf);
+        }
+        let start = pos.offset.checked_sub(RECORD_HEADER_LEN).ok_or("stable memory record out of bounds")?;
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        self.read(start, &mut header).map_err(|_| "Stable memory error")?;
+        let mut buf = vec![0u8; pos.len];
+        self.read(pos.offset, buf.as_mut_slice()).map_err(|_| "Stable memory error")?;
+        let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let checksum = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if len as usize != pos.len || checksum != crc32(&buf) {
+            return Err("stable memory record corrupted");
+        }
+        Ok(buf)
+    }
+
+    /// overwrite the bytes of the record at the position with zeros, keeping its length and a valid checksum
+    pub fn erase_record(&mut self, pos: &Position) -> Result<(), &'static str> {
+        self.read_record(pos)?;
+        let zeros = vec![0u8; pos.len];
+        if !pos.checked {
+            stable_write(pos.offset as u32, &zeros);
+            return Ok(());
+        }
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        header[..4].copy_from_slice(&(pos.len as u32).to_le_bytes());
+        header[4..].copy_from_slice(&crc32(&zeros).to_le_bytes());
+        stable_write((pos.offset - RECORD_HEADER_LEN) as u32, &header);
+        stable_write(pos.offset as u32, &zeros);
+        Ok(())
+    }
+
+    /// release the record at the position, the memory is reused if it is the last record written,
+    /// otherwise its bytes are erased as records are only appended
+    pub fn free_record(&mut self, pos: &Position) -> Result<(), &'static str> {
+        let header_len = if pos.checked { RECORD_HEADER_LEN } else { 0 };
+        if pos.offset + pos.len == self.offset && pos.offset >= header_len {
+            self.read_record(pos)?;
+            self.offset = pos.offset - header_len;
+            return Ok(());
+        }
+        self.erase_record(pos)
+    }
+}
+
+/// CRC-32 (IEEE) of the bytes
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// heap-backed stable memory, so the logic runs natively outside of a canister
+#[cfg(not(target_family = "wasm"))]
+mod heap {
+    use std::cell::RefCell;
+
+    /// 4 GiB, the 32-bit stable memory limit
+    const MAX_PAGES: u32 = 1 << 16;
+
+    thread_local! {
+        static MEMORY : RefCell<Vec<u8>> = RefCell::new(vec![]);
+    }
+
+    #[derive(Debug)]
+    pub enum StableMemoryError {
+        OutOfMemory,
+        OutOfBounds,
+    }
+
+    /// grow the memory by pages, return the previous pages count
+    pub fn stable_grow(pages: u32) -> Result<u32, StableMemoryError> {
+        MEMORY.with(|memory| {
+            let mut memory = memory.borrow_mut();
+            let old_page_count = (memory.len() >> 16) as u32;
+            if old_page_count as u64 + pages as u64 > MAX_PAGES as u64 {
+                return Err(StableMemoryError::OutOfMemory);
+            }
+            let size = ((old_page_count + pages) as usize) << 16;
+            memory.resize(size, 0);
+            Ok(old_page_count)
+        })
+    }
+
+    pub fn stable_read(offset: u32, buf: &mut [u8]) {
+        MEMORY.with(|memory| {
+            let memory = memory.borrow();
+            let offset = offset as usize;
+            buf.copy_from_slice(&memory[offset..offset + buf.len()]);
+        })
+    }
+
+    pub fn stable_write(offset: u32, buf: &[u8]) {
+        MEMORY.with(|memory| {
+            let mut memory = memory.borrow_mut();
+            let offset = offset as usize;
+            memory[offset..offset + buf.len()].copy_from_slice(buf);
+        })
+    }
+}
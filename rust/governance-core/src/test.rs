@@ -0,0 +1,1065 @@
+use candid::{Nat, Principal};
+use crate::clock::{MockClock, TimeProvider};
+use crate::governance::{CountingMode, DepositStatus, GovernorBravo, ProposalImport, ProposalKind, ProposalSort, ProposalState, QuorumMode, Tally, TieRule, VoteType};
+use crate::timelock::Task;
+use governance_types::{ramped_votes, CheckPoint};
+
+const DELAY: u64 = 10;
+const PERIOD: u64 = 100;
+
+fn alice() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn set_up() -> GovernorBravo {
+    let mut bravo = GovernorBravo::default();
+    bravo.initialize(alice(), "Test".to_string(), Nat::from(100), DELAY, PERIOD, Nat::from(500), 1000, Principal::anonymous());
+    bravo
+}
+
+fn task() -> Task {
+    Task::new(Principal::management_canister(), "test".to_string(), vec![], 0)
+}
+
+#[test]
+fn test_description_in_stable_memory() {
+    let mut bravo = set_up();
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    let proposal = bravo.get_proposal(id).unwrap();
+    assert_eq!(proposal.description, "description");
+}
+
+#[test]
+fn test_checksummed_records() {
+    use crate::stable::{Memory, Position, StableMemory};
+
+    let mut memory = StableMemory::default();
+    let pos = memory.write_record(b"description").unwrap();
+    assert_eq!(memory.read_record(&pos).unwrap(), b"description".to_vec());
+    // a mis-tracked position surfaces as an error instead of wrong text
+    let shifted = Position { offset: pos.offset + 1, len: pos.len - 1, checked: true };
+    assert!(memory.read_record(&shifted).is_err());
+    let truncated = Position { offset: pos.offset, len: pos.len - 1, checked: true };
+    assert!(memory.read_record(&truncated).is_err());
+
+    // records written before checksums have no header and are read as is
+    let offset = memory.offset;
+    memory.write(b"legacy").unwrap();
+    let legacy = Position { offset, len: 6, checked: false };
+    assert_eq!(memory.read_record(&legacy).unwrap(), b"legacy".to_vec());
+    memory.erase_record(&legacy).unwrap();
+    assert_eq!(memory.read_record(&legacy).unwrap(), vec![0u8; 6]);
+    assert_eq!(memory.read_record(&pos).unwrap(), b"description".to_vec());
+}
+
+#[test]
+fn test_restore() {
+    let mut bravo = set_up();
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    bravo.cast_vote(id, VoteType::Support, Nat::from(200), Some("reason".to_string()), alice(), DELAY).unwrap();
+    let backup = bravo.clone();
+    let records = bravo.stable_records().unwrap();
+
+    let mut restored = set_up();
+    restored.propose(alice(), Nat::from(5000), "other".to_string(), "overwritten".to_string(), task(), 0).unwrap();
+    assert!(restored.restore(backup.clone(), &records[1..]).is_err());
+    restored.restore(backup, &records).unwrap();
+    assert_eq!(restored.get_proposal(id).unwrap().description, "description");
+    assert_eq!(restored.get_receipt(id, alice()).unwrap().reason, Some("reason".to_string()));
+    assert!(restored.check_invariants(DELAY).is_empty());
+}
+
+#[test]
+fn test_state_machine() {
+    let mut bravo = set_up();
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    assert_eq!(bravo.get_state(id, 0).unwrap(), ProposalState::Pending);
+    assert_eq!(bravo.get_state(id, DELAY).unwrap(), ProposalState::Active);
+
+    bravo.cast_vote(id, VoteType::Support, Nat::from(200), None, alice(), DELAY).unwrap();
+    let end = DELAY + PERIOD;
+    assert_eq!(bravo.get_state(id, end).unwrap(), ProposalState::Succeeded);
+
+    bravo.queue(id, end).unwrap();
+    assert_eq!(bravo.get_state(id, end).unwrap(), ProposalState::Queued);
+}
+
+#[test]
+fn test_defeated_below_quorum() {
+    let mut bravo = set_up();
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    bravo.cast_vote(id, VoteType::Support, Nat::from(50), None, alice(), DELAY).unwrap();
+    assert_eq!(bravo.get_state(id, DELAY + PERIOD).unwrap(), ProposalState::Defeated);
+}
+
+#[test]
+fn test_lifecycle_with_mock_clock() {
+    let mut bravo = set_up();
+    let clock = MockClock::new(0);
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), clock.now()).unwrap();
+    clock.advance(DELAY);
+    assert_eq!(bravo.get_state(id, clock.now()).unwrap(), ProposalState::Active);
+
+    bravo.cast_vote(id, VoteType::Support, Nat::from(200), None, alice(), clock.now()).unwrap();
+    clock.advance(PERIOD);
+    bravo.queue(id, clock.now()).unwrap();
+    clock.advance(bravo.timelock.delay);
+    bravo.pre_execute(id, clock.now()).unwrap();
+    bravo.post_execute(id, true, clock.now()).unwrap();
+    assert_eq!(bravo.get_state(id, clock.now()).unwrap(), ProposalState::Executed);
+}
+
+#[test]
+fn test_invariants_hold() {
+    let mut bravo = set_up();
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    bravo.cast_vote(id, VoteType::Support, Nat::from(200), Some("reason".to_string()), alice(), DELAY).unwrap();
+
+    bravo.queue(id, DELAY + PERIOD).unwrap();
+    assert!(bravo.check_invariants(DELAY + PERIOD).is_empty());
+
+    bravo.timelock.queued_transactions.clear();
+    assert_eq!(bravo.check_invariants(DELAY + PERIOD).len(), 1);
+}
+
+#[test]
+fn test_record_transitions() {
+    use crate::timelock::Timelock;
+
+    let mut bravo = set_up();
+    let bob = Principal::from_slice(&[2]);
+    let passed = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    let failed = bravo.propose(bob, Nat::from(5000), "other".to_string(), "description".to_string(),
+        Task::new(Principal::management_canister(), "other".to_string(), vec![], 0), 0).unwrap();
+    bravo.cast_vote(passed, VoteType::Support, Nat::from(200), None, alice(), DELAY).unwrap();
+    assert!(bravo.record_transitions(DELAY).is_empty());
+
+    let end = DELAY + PERIOD;
+    assert_eq!(bravo.record_transitions(end), vec![(passed, ProposalState::Succeeded), (failed, ProposalState::Defeated)]);
+    assert!(bravo.record_transitions(end).is_empty());
+
+    let eta = bravo.queue(passed, end).unwrap();
+    assert!(bravo.record_transitions(end).is_empty());
+    assert_eq!(bravo.record_transitions(eta + Timelock::GRACE_PERIOD + 1), vec![(passed, ProposalState::Expired)]);
+}
+
+#[test]
+fn test_import_proposals() {
+    let mut bravo = set_up();
+    let import = |state: ProposalState, end_time: u64| ProposalImport {
+        proposer: alice(),
+        title: "title".to_string(),
+        description: "imported".to_string(),
+        task: task(),
+        start_time: 0,
+        end_time,
+        support_votes: Nat::from(300),
+        against_votes: Nat::from(100),
+        abstain_votes: Nat::from(0),
+        state,
+    };
+    assert!(bravo.import_proposals(vec![import(ProposalState::Active, PERIOD)], PERIOD).is_err());
+    assert!(bravo.import_proposals(vec![import(ProposalState::Executed, 2 * PERIOD)], PERIOD).is_err());
+
+    let ids = bravo.import_proposals(vec![import(ProposalState::Executed, PERIOD), import(ProposalState::Canceled, PERIOD)], PERIOD).unwrap();
+    assert_eq!(ids, vec![0, 1]);
+    assert_eq!(bravo.get_state(0, PERIOD).unwrap(), ProposalState::Executed);
+    assert_eq!(bravo.get_state(1, PERIOD).unwrap(), ProposalState::Canceled);
+    let proposal = bravo.get_proposal(0).unwrap();
+    assert_eq!(proposal.description, "imported");
+    assert_eq!(proposal.support_votes, Nat::from(300));
+    assert!(bravo.record_transitions(PERIOD).is_empty());
+    assert!(bravo.check_invariants(PERIOD).is_empty());
+
+    // history is imported before the first proposal only
+    bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), PERIOD).unwrap();
+    assert!(bravo.import_proposals(vec![import(ProposalState::Defeated, PERIOD)], PERIOD).is_err());
+}
+
+#[test]
+fn test_proposal_sort() {
+    let mut bravo = set_up();
+    let bob = Principal::from_slice(&[2]);
+    let first = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 5).unwrap();
+    let second = bravo.propose(bob, Nat::from(5000), "other".to_string(), "description".to_string(),
+        Task::new(Principal::management_canister(), "other".to_string(), vec![], 0), 0).unwrap();
+    bravo.cast_vote(first, VoteType::Support, Nat::from(100), None, alice(), DELAY + 5).unwrap();
+    bravo.cast_vote(second, VoteType::Against, Nat::from(300), None, bob, DELAY + 5).unwrap();
+
+    let ids = |sort: ProposalSort| bravo.get_proposal_pages(0, 10, sort, DELAY + 5).unwrap()
+        .into_iter().map(|(digest, _)| digest.id()).collect::<Vec<usize>>();
+    assert_eq!(ids(ProposalSort::IdDesc), vec![second, first]);
+    assert_eq!(ids(ProposalSort::IdAsc), vec![first, second]);
+    assert_eq!(ids(ProposalSort::EndTimeAsc), vec![second, first]);
+    assert_eq!(ids(ProposalSort::TotalVotesDesc), vec![second, first]);
+
+    bravo.cast_vote(first, VoteType::Abstain, Nat::from(400), None, bob, DELAY + 5).unwrap();
+    assert_eq!(bravo.get_proposal_pages(0, 1, ProposalSort::TotalVotesDesc, DELAY + 5).unwrap()[0].0.id(), first);
+    assert_eq!(bravo.get_proposal_pages(1, 1, ProposalSort::TotalVotesDesc, DELAY + 5).unwrap()[0].0.id(), second);
+}
+
+#[test]
+fn test_quorum_status() {
+    let mut bravo = set_up();
+    let bob = Principal::from_slice(&[2]);
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    bravo.cast_vote(id, VoteType::Support, Nat::from(50), None, alice(), DELAY).unwrap();
+    let status = bravo.quorum_status(id).unwrap();
+    assert_eq!(status.quorum, Nat::from(100));
+    assert_eq!(status.quorum_bps, 5000);
+    assert!(status.majority_reached && !status.quorum_reached && !status.would_pass);
+
+    bravo.cast_vote(id, VoteType::Against, Nat::from(60), None, bob, DELAY).unwrap();
+    bravo.set_quorum_mode(QuorumMode::Participation);
+    let status = bravo.quorum_status(id).unwrap();
+    assert_eq!(status.quorum_votes, Nat::from(110));
+    assert_eq!(status.quorum_bps, 11000);
+    assert!(status.quorum_reached && !status.majority_reached && !status.would_pass);
+    assert!(bravo.quorum_status(id + 1).is_err());
+}
+
+#[test]
+fn test_early_finish() {
+    let mut bravo = set_up();
+    let bob = Principal::from_slice(&[2]);
+    bravo.set_early_finish(true);
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    bravo.cast_vote(id, VoteType::Support, Nat::from(300), None, alice(), DELAY).unwrap();
+    // without a supply bound voting runs its full period
+    assert_eq!(bravo.get_state(id, DELAY).unwrap(), ProposalState::Active);
+    bravo.record_max_votes(id, Nat::from(1000)).unwrap();
+    assert_eq!(bravo.get_state(id, DELAY).unwrap(), ProposalState::Active);
+    bravo.cast_vote(id, VoteType::Against, Nat::from(200), None, bob, DELAY).unwrap();
+    // a lower supply does not lower the bound
+    bravo.record_max_votes(id, Nat::from(600)).unwrap();
+    assert_eq!(bravo.get_state(id, DELAY).unwrap(), ProposalState::Active);
+    bravo.cast_vote(id, VoteType::Support, Nat::from(200), None, Principal::from_slice(&[3]), DELAY).unwrap();
+    // 500 support against at most 500 against, a tie defeats
+    assert_eq!(bravo.get_state(id, DELAY).unwrap(), ProposalState::Active);
+    bravo.set_tie_rule(TieRule::Succeed);
+    assert_eq!(bravo.get_state(id, DELAY).unwrap(), ProposalState::Succeeded);
+    assert!(bravo.cast_vote(id, VoteType::Against, Nat::from(300), None, Principal::from_slice(&[4]), DELAY).is_err());
+
+    // the rule is snapshotted when proposing
+    bravo.set_early_finish(false);
+    let id = bravo.propose(alice(), Nat::from(5000), "other".to_string(), "description".to_string(),
+        Task::new(Principal::management_canister(), "other".to_string(), vec![], 0), DELAY).unwrap();
+    let start = 2 * DELAY;
+    bravo.cast_vote(id, VoteType::Support, Nat::from(900), None, alice(), start).unwrap();
+    bravo.record_max_votes(id, Nat::from(1000)).unwrap();
+    assert_eq!(bravo.get_state(id, start).unwrap(), ProposalState::Active);
+}
+
+#[test]
+fn test_one_principal_one_vote() {
+    let mut bravo = set_up();
+    assert!(bravo.set_counting_mode("poll".to_string(), CountingMode::OnePrincipalOneVote(10)).is_err());
+    bravo.set_counting_mode("canister".to_string(), CountingMode::OnePrincipalOneVote(10)).unwrap();
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+
+    assert!(bravo.cast_vote(id, VoteType::Support, Nat::from(5), None, alice(), DELAY).is_err());
+    bravo.cast_vote(id, VoteType::Support, Nat::from(5000), None, alice(), DELAY).unwrap();
+    bravo.cast_vote(id, VoteType::Against, Nat::from(20), None, Principal::from_slice(&[2]), DELAY).unwrap();
+    let proposal = bravo.get_proposal(id).unwrap();
+    assert_eq!(proposal.support_votes, Nat::from(1));
+    assert_eq!(proposal.against_votes, Nat::from(1));
+}
+
+#[test]
+fn test_counting_strategies() {
+    let mut bravo = set_up();
+    let bob = Principal::from_slice(&[2]);
+    assert!(bravo.set_default_counting_mode(CountingMode::Supermajority(5000)).is_err());
+    assert!(bravo.set_counting_mode("canister".to_string(), CountingMode::Approval(0)).is_err());
+
+    // two thirds of support and against votes
+    bravo.set_default_counting_mode(CountingMode::Supermajority(6667)).unwrap();
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    bravo.cast_vote(id, VoteType::Support, Nat::from(600), None, alice(), DELAY).unwrap();
+    bravo.cast_vote(id, VoteType::Against, Nat::from(400), None, bob, DELAY).unwrap();
+    assert_eq!(bravo.get_state(id, DELAY + PERIOD).unwrap(), ProposalState::Defeated);
+
+    // the mode is snapshotted when proposing
+    bravo.set_counting_mode("canister".to_string(), CountingMode::Quadratic).unwrap();
+    assert_eq!(bravo.get_proposal(id).unwrap().counting, CountingMode::Supermajority(6667));
+    let id = bravo.propose(alice(), Nat::from(5000), "other".to_string(), "description".to_string(),
+        Task::new(Principal::management_canister(), "other".to_string(), vec![], 0), DELAY + PERIOD).unwrap();
+    let start = 2 * DELAY + PERIOD;
+    bravo.cast_vote(id, VoteType::Support, Nat::from(40000), None, alice(), start).unwrap();
+    bravo.cast_vote(id, VoteType::Against, Nat::from(90000), None, bob, start).unwrap();
+    let proposal = bravo.get_proposal(id).unwrap();
+    assert_eq!(proposal.support_votes, Nat::from(200));
+    assert_eq!(proposal.against_votes, Nat::from(300));
+    assert_eq!(bravo.get_state(id, start + PERIOD).unwrap(), ProposalState::Defeated);
+
+    // abstentions count against the approval share
+    bravo.set_counting_mode("canister".to_string(), CountingMode::Approval(5000)).unwrap();
+    let id = bravo.propose(alice(), Nat::from(5000), "third".to_string(), "description".to_string(),
+        Task::new(Principal::management_canister(), "third".to_string(), vec![], 0), start + PERIOD).unwrap();
+    let start = 2 * DELAY + 2 * PERIOD + DELAY;
+    bravo.cast_vote(id, VoteType::Support, Nat::from(400), None, alice(), start).unwrap();
+    bravo.cast_vote(id, VoteType::Abstain, Nat::from(500), None, bob, start).unwrap();
+    assert_eq!(bravo.get_state(id, start + PERIOD).unwrap(), ProposalState::Defeated);
+}
+
+#[test]
+fn test_ramped_counting() {
+    let mut bravo = set_up();
+    assert!(bravo.set_default_counting_mode(CountingMode::Ramped(0)).is_err());
+    assert!(bravo.set_default_counting_mode(CountingMode::Ramped(GovernorBravo::MAX_HOLDING_DURATION + 1)).is_err());
+    bravo.set_default_counting_mode(CountingMode::Ramped(100)).unwrap();
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    assert_eq!(bravo.vote_ramp(id).unwrap(), Some(100));
+    assert_eq!(bravo.counted_votes(id, Nat::from(300)).unwrap(), Nat::from(300));
+
+    let check_point = |timestamp: u64, votes: u64| CheckPoint { timestamp: Nat::from(timestamp), votes: Nat::from(votes) };
+    // 200 votes delegated halfway through the ramp count for half
+    let check_points = vec![check_point(0, 100), check_point(1000, 300)];
+    assert_eq!(ramped_votes(&check_points, &Nat::from(1050), 100), Nat::from(200));
+    assert_eq!(ramped_votes(&check_points, &Nat::from(1100), 100), Nat::from(300));
+    assert_eq!(ramped_votes(&check_points[1..], &Nat::from(1050), 100), Nat::from(150));
+    // votes given away stop counting at once
+    let check_points = vec![check_point(0, 300), check_point(1000, 100)];
+    assert_eq!(ramped_votes(&check_points, &Nat::from(1050), 100), Nat::from(100));
+}
+
+#[test]
+fn test_voting_period_override() {
+    let mut bravo = set_up();
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    assert!(bravo.set_voting_period(id, 2 * PERIOD, 0).is_err());
+    assert!(bravo.set_voting_period_bounds(Some((3 * PERIOD, 2 * PERIOD))).is_err());
+    bravo.set_voting_period_bounds(Some((PERIOD, 2 * PERIOD))).unwrap();
+    assert!(bravo.set_voting_period(id, 3 * PERIOD, 0).is_err());
+    bravo.set_voting_period(id, 2 * PERIOD, 0).unwrap();
+    assert!(bravo.set_voting_period(id, PERIOD, DELAY).is_err());
+
+    // voting stays open for the requested period
+    assert_eq!(bravo.get_state(id, DELAY + PERIOD).unwrap(), ProposalState::Active);
+    bravo.cast_vote(id, VoteType::Support, Nat::from(200), None, alice(), DELAY + PERIOD).unwrap();
+    assert_eq!(bravo.get_state(id, DELAY + 2 * PERIOD).unwrap(), ProposalState::Succeeded);
+    assert_eq!(bravo.get_proposal(id).unwrap().config.voting_period, 2 * PERIOD);
+}
+
+#[test]
+fn test_quorum_override() {
+    let mut bravo = set_up();
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    assert!(bravo.set_quorum_override(id, Some(Nat::from(300)), 0).is_err());
+    assert!(bravo.set_quorum_override_bounds(Some((Nat::from(500), Nat::from(100)))).is_err());
+    bravo.set_quorum_override_bounds(Some((Nat::from(100), Nat::from(1000)))).unwrap();
+    assert!(bravo.set_quorum_override(id, Some(Nat::from(2000)), 0).is_err());
+    bravo.set_quorum_override(id, Some(Nat::from(300)), 0).unwrap();
+    assert!(bravo.set_quorum_override(id, None, DELAY).is_err());
+
+    bravo.cast_vote(id, VoteType::Support, Nat::from(200), None, alice(), DELAY).unwrap();
+    assert_eq!(bravo.get_state(id, DELAY + PERIOD).unwrap(), ProposalState::Defeated);
+}
+
+#[test]
+fn test_comments() {
+    let mut bravo = set_up();
+    let bob = Principal::from_slice(&[2]);
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    assert!(bravo.add_comment(id, bob, "".to_string(), 0).is_err());
+    let first = bravo.add_comment(id, bob, "first".to_string(), 0).unwrap();
+    let second = bravo.add_comment(id, alice(), "second".to_string(), 1).unwrap();
+
+    assert!(bravo.edit_comment(id, first, alice(), "edited".to_string(), 2).is_err());
+    bravo.edit_comment(id, first, bob, "edited".to_string(), 2).unwrap();
+    let comments = bravo.get_comment_pages(id, 0, 10).unwrap();
+    assert_eq!(comments[0].text, "edited");
+    assert_eq!(comments[0].edited_at, Some(2));
+    let contains = |bravo: &GovernorBravo, text: &[u8]| {
+        bravo.stable_records().unwrap().windows(text.len()).any(|w| w == text)
+    };
+    // the replaced text is erased from stable memory
+    assert!(!contains(&bravo, b"first"));
+
+    bravo.delete_comment(id, first, bob).unwrap();
+    assert!(bravo.delete_comment(id, first, bob).is_err());
+    assert!(!contains(&bravo, b"edited"));
+    assert!(contains(&bravo, b"second"));
+    let comments = bravo.get_comment_pages(id, 0, 1).unwrap();
+    assert_eq!(comments.len(), 1);
+    assert_eq!(comments[0].id, second);
+    assert!(bravo.check_invariants(0).is_empty());
+}
+
+#[test]
+fn test_quorum_reached() {
+    let mut bravo = set_up();
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    assert!(!bravo.quorum_reached(id).unwrap());
+    bravo.cast_vote(id, VoteType::Support, Nat::from(60), None, alice(), DELAY).unwrap();
+    assert!(!bravo.quorum_reached(id).unwrap());
+    bravo.cast_vote(id, VoteType::Support, Nat::from(60), None, Principal::from_slice(&[2]), DELAY).unwrap();
+    assert!(bravo.quorum_reached(id).unwrap());
+    assert!(bravo.quorum_reached(id + 1).is_err());
+}
+
+#[test]
+fn test_prune_expired() {
+    use crate::timelock::Timelock;
+
+    let mut bravo = set_up();
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    bravo.cast_vote(id, VoteType::Support, Nat::from(200), None, alice(), DELAY).unwrap();
+    let eta = bravo.queue(id, DELAY + PERIOD).unwrap();
+    assert!(bravo.prune_expired(eta + Timelock::GRACE_PERIOD).is_empty());
+
+    let expired = eta + Timelock::GRACE_PERIOD + 1;
+    assert_eq!(bravo.get_state(id, expired).unwrap(), ProposalState::Expired);
+    assert_eq!(bravo.prune_expired(expired), vec![id]);
+    assert!(bravo.timelock.queued_transactions.is_empty());
+    assert_eq!(bravo.get_state(id, expired).unwrap(), ProposalState::Expired);
+    assert!(bravo.check_invariants(expired).is_empty());
+}
+
+#[test]
+fn test_executors() {
+    let mut bravo = set_up();
+    let bob = Principal::from_slice(&[2]);
+    assert!(bravo.can_execute(&bob));
+    bravo.set_executors(vec![alice(), alice()]);
+    assert!(bravo.can_execute(&alice()));
+    assert!(!bravo.can_execute(&bob));
+    bravo.set_executors(vec![]);
+    assert!(bravo.can_execute(&bob));
+}
+
+#[test]
+fn test_param_change() {
+    use crate::timelock::{GovernorParam, ParamChange};
+
+    let mut bravo = set_up();
+    let out_of_bounds = Task::param_change(ParamChange { field: GovernorParam::VotingPeriod, new_value: Nat::from(1) });
+    assert!(bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), out_of_bounds, 0).is_err());
+
+    let change = ParamChange { field: GovernorParam::VotingPeriod, new_value: Nat::from(GovernorBravo::MIN_VOTING_PERIOD) };
+    bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), Task::param_change(change.clone()), 0).unwrap();
+    assert_eq!(bravo.apply_param_change(&change).unwrap(), Nat::from(PERIOD));
+    assert_eq!(bravo.apply_param_change(&change).unwrap(), Nat::from(GovernorBravo::MIN_VOTING_PERIOD));
+    // quorum votes beyond u64
+    let big = Nat::from(u64::MAX) * Nat::from(10u64);
+    let quorum = ParamChange { field: GovernorParam::QuorumVotes, new_value: big.clone() };
+    assert_eq!(bravo.apply_param_change(&quorum).unwrap(), Nat::from(100));
+    assert_eq!(bravo.apply_param_change(&quorum).unwrap(), big);
+}
+
+#[test]
+fn test_min_delegation_age() {
+    use crate::timelock::{GovernorParam, ParamChange};
+
+    let mut bravo = set_up();
+    assert_eq!(bravo.propose(alice(), Nat::from(500), "title".to_string(), "description".to_string(), task(), 0),
+        Err("proposer votes below proposal threshold"));
+    let too_long = ParamChange { field: GovernorParam::MinDelegationAge, new_value: Nat::from(GovernorBravo::MAX_HOLDING_DURATION + 1) };
+    assert!(bravo.apply_param_change(&too_long).is_err());
+    let age = ParamChange { field: GovernorParam::MinDelegationAge, new_value: Nat::from(PERIOD) };
+    assert_eq!(bravo.apply_param_change(&age).unwrap(), Nat::from(0));
+    assert_eq!(bravo.min_delegation_age(), PERIOD);
+    // the votes passed are the lowest held over the age
+    assert_eq!(bravo.propose(alice(), Nat::from(500), "title".to_string(), "description".to_string(), task(), 0),
+        Err("proposer votes not above proposal threshold for the minimum delegation age"));
+    assert!(bravo.propose(alice(), Nat::from(501), "title".to_string(), "description".to_string(), task(), 0).is_ok());
+}
+
+#[test]
+fn test_config_snapshot() {
+    let mut bravo = set_up();
+    let bob = Principal::from_slice(&[2]);
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    bravo.set_quorum_votes(Nat::from(1000));
+    bravo.set_proposal_threshold(Nat::from(10000));
+    bravo.set_vote_period(10 * PERIOD);
+
+    let config = bravo.get_proposal(id).unwrap().config;
+    assert_eq!(config.quorum_votes, Nat::from(100));
+    assert_eq!(config.proposal_threshold, Nat::from(500));
+    assert_eq!(config.voting_period, PERIOD);
+    // judged against the quorum at creation
+    bravo.cast_vote(id, VoteType::Support, Nat::from(200), None, alice(), DELAY).unwrap();
+    assert!(bravo.quorum_reached(id).unwrap());
+    // the proposer is above the threshold at creation, though below the current one
+    assert!(bravo.cancel(id, DELAY, bob, Nat::from(5000)).is_err());
+    assert_eq!(bravo.get_state(id, DELAY + PERIOD).unwrap(), ProposalState::Succeeded);
+}
+
+#[test]
+fn test_counting_rules_snapshot() {
+    let mut bravo = set_up();
+    let bob = Principal::from_slice(&[2]);
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    bravo.set_quorum_mode(QuorumMode::Participation);
+    bravo.set_tie_rule(TieRule::Succeed);
+    let config = bravo.get_proposal(id).unwrap().config;
+    assert_eq!(config.quorum_mode, QuorumMode::Support);
+    assert_eq!(config.tie_rule, TieRule::Defeat);
+
+    // against votes do not reach the quorum and the tie stays defeated, as when proposed
+    bravo.cast_vote(id, VoteType::Against, Nat::from(200), None, bob, DELAY).unwrap();
+    assert!(!bravo.quorum_reached(id).unwrap());
+    bravo.cast_vote(id, VoteType::Support, Nat::from(200), None, alice(), DELAY).unwrap();
+    assert_eq!(bravo.get_state(id, DELAY + PERIOD).unwrap(), ProposalState::Defeated);
+}
+
+#[test]
+fn test_announced_param_change() {
+    use crate::timelock::{GovernorParam, ParamChange, Timelock};
+
+    let mut bravo = set_up();
+    let out_of_bounds = ParamChange { field: GovernorParam::VotingPeriod, new_value: Nat::from(1) };
+    assert!(bravo.announce_param_change(out_of_bounds, alice(), 0).is_err());
+
+    let quorum = ParamChange { field: GovernorParam::QuorumVotes, new_value: Nat::from(300) };
+    let announced = bravo.announce_param_change(quorum.clone(), alice(), 0).unwrap();
+    // the timelock delay of the set up
+    assert_eq!(announced.eta, 1000);
+    assert!(bravo.apply_announced_change(announced.id, 999).is_err());
+    assert_eq!(bravo.apply_announced_change(announced.id, 1000).unwrap(), (quorum.clone(), Nat::from(100)));
+    assert!(bravo.apply_announced_change(announced.id, 1000).is_err());
+
+    let canceled = bravo.announce_param_change(quorum.clone(), alice(), 0).unwrap();
+    assert_ne!(canceled.id, announced.id);
+    bravo.cancel_announced_change(canceled.id).unwrap();
+    assert!(bravo.apply_announced_change(canceled.id, 1000).is_err());
+
+    let stale = bravo.announce_param_change(quorum, alice(), 0).unwrap();
+    assert!(bravo.apply_announced_change(stale.id, stale.eta + Timelock::GRACE_PERIOD + 1).is_err());
+    bravo.prune_expired(stale.eta + Timelock::GRACE_PERIOD + 1);
+    assert!(bravo.timelock.announced_changes.is_empty());
+}
+
+#[test]
+fn test_announced_call() {
+    use crate::timelock::Timelock;
+
+    let mut bravo = set_up();
+    let announced = bravo.timelock.announce_call("setTieRule".to_string(), vec![1], alice(), 0);
+    assert_eq!(announced.eta, 1000);
+    assert!(bravo.timelock.take_call(announced.id, 999).is_err());
+    let taken = bravo.timelock.take_call(announced.id, 1000).unwrap();
+    assert_eq!(taken, announced);
+    // a failed call can be made again
+    bravo.timelock.restore_call(taken);
+    assert!(bravo.timelock.take_call(announced.id, 1000).is_ok());
+
+    let canceled = bravo.timelock.announce_call("setExecutors".to_string(), vec![], alice(), 0);
+    assert_ne!(canceled.id, announced.id);
+    bravo.timelock.cancel_call(canceled.id).unwrap();
+    assert!(bravo.timelock.take_call(canceled.id, 1000).is_err());
+
+    let stale = bravo.timelock.announce_call("setExecutors".to_string(), vec![], alice(), 0);
+    bravo.prune_expired(stale.eta + Timelock::GRACE_PERIOD + 1);
+    assert!(bravo.timelock.announced_calls.is_empty());
+}
+
+#[test]
+fn test_upcoming_executions() {
+    let mut bravo = set_up();
+    let bob = Principal::from_slice(&[2]);
+    let first = bravo.propose(alice(), Nat::from(5000), "first".to_string(), "description".to_string(), task(), 0).unwrap();
+    let second = bravo.propose(bob, Nat::from(5000), "second".to_string(), "description".to_string(), Task::new(Principal::management_canister(), "other".to_string(), vec![], 0), 0).unwrap();
+    bravo.cast_vote(first, VoteType::Support, Nat::from(200), None, alice(), DELAY).unwrap();
+    bravo.cast_vote(second, VoteType::Support, Nat::from(200), None, alice(), DELAY).unwrap();
+    let end = DELAY + PERIOD;
+    assert_eq!(bravo.succeeded_proposals(end), vec![first, second]);
+    bravo.queue(second, end).unwrap();
+    bravo.queue(first, end + 1).unwrap();
+    assert!(bravo.succeeded_proposals(end + 1).is_empty());
+
+    let upcoming = bravo.upcoming_executions(10, end + 1);
+    assert_eq!(upcoming.iter().map(|u| u.id).collect::<Vec<usize>>(), vec![second, first]);
+    assert_eq!(upcoming[0].until_eta, bravo.timelock.delay - 1);
+    assert_eq!(bravo.upcoming_executions(1, end + 1).len(), 1);
+    assert_eq!(bravo.next_execution(end + 1), None);
+
+    // once both are executable the higher priority goes first
+    bravo.set_priority(first, 2, end + 1).unwrap();
+    let open = end + 1 + bravo.timelock.delay;
+    let upcoming = bravo.upcoming_executions(10, open);
+    assert_eq!(upcoming.iter().map(|u| u.id).collect::<Vec<usize>>(), vec![first, second]);
+    assert_eq!(upcoming[0].priority, 2);
+    assert_eq!(bravo.next_execution(open), Some(first));
+    assert_eq!(bravo.executable_proposals(open), vec![first, second]);
+    bravo.pre_execute(first, open).unwrap();
+    bravo.post_execute(first, true, open).unwrap();
+    assert_eq!(bravo.next_execution(open), Some(second));
+    assert!(bravo.set_priority(first, 0, open).is_err());
+}
+
+#[test]
+fn test_duplicate_task_rejected() {
+    let mut bravo = set_up();
+    let bob = Principal::from_slice(&[2]);
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    assert!(bravo.propose(bob, Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).is_err());
+    assert_eq!(bravo.live_duplicate(&task(), 0), Some(id));
+
+    // defeated proposals do not block proposing the task again
+    let end = DELAY + PERIOD;
+    assert_eq!(bravo.live_duplicate(&task(), end), None);
+    bravo.propose(bob, Nat::from(5000), "title".to_string(), "description".to_string(), task(), end).unwrap();
+}
+
+#[test]
+fn test_defeat_cooldown() {
+    let mut bravo = set_up();
+    bravo.set_defeat_cooldown(PERIOD);
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    bravo.cast_vote(id, VoteType::Against, Nat::from(200), None, alice(), DELAY).unwrap();
+    let end = DELAY + PERIOD;
+    assert_eq!(bravo.cooling_down(&task(), end), Some(id));
+    assert!(bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), end).is_err());
+    assert!(bravo.repropose(id, alice(), Nat::from(5000), end).is_err());
+
+    // other tasks are not held back, the same one is once the cooldown is over
+    bravo.propose(alice(), Nat::from(5000), "other".to_string(), "description".to_string(),
+        Task::new(Principal::management_canister(), "other".to_string(), vec![], 0), end).unwrap();
+    assert_eq!(bravo.cooling_down(&task(), end + PERIOD), None);
+    bravo.repropose(id, Principal::from_slice(&[2]), Nat::from(5000), end + PERIOD).unwrap();
+}
+
+#[test]
+fn test_text_proposal() {
+    let mut bravo = set_up();
+    let bob = Principal::from_slice(&[2]);
+    assert!(bravo.propose_text(alice(), Nat::from(100), "title".to_string(), "description".to_string(), 0).is_err());
+    let id = bravo.propose_text(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), 0).unwrap();
+    // signal votes carry no task, so they never count as duplicates
+    let other = bravo.propose_text(bob, Nat::from(5000), "other".to_string(), "description".to_string(), 0).unwrap();
+    assert_eq!(bravo.get_proposal(id).unwrap().kind, ProposalKind::Text);
+    assert!(bravo.fund_task(id, bob, 100, 0).is_err());
+
+    bravo.cast_vote(id, VoteType::Support, Nat::from(200), None, alice(), DELAY).unwrap();
+    bravo.cast_vote(other, VoteType::Against, Nat::from(200), None, alice(), DELAY).unwrap();
+    let end = DELAY + PERIOD;
+    assert_eq!(bravo.get_state(id, end).unwrap(), ProposalState::Closed);
+    assert_eq!(bravo.get_state(other, end).unwrap(), ProposalState::Defeated);
+    assert!(bravo.succeeded_proposals(end).is_empty());
+    assert!(bravo.queue(id, end).is_err());
+    assert!(bravo.cancel(id, end, alice(), Nat::from(5000)).is_err());
+    assert_eq!(bravo.record_transitions(end), vec![
+        (id, ProposalState::Succeeded), (id, ProposalState::Closed), (other, ProposalState::Defeated),
+    ]);
+
+    let again = bravo.repropose(other, bob, Nat::from(5000), end).unwrap();
+    assert_eq!(bravo.get_proposal(again).unwrap().kind, ProposalKind::Text);
+}
+
+#[test]
+fn test_vote_change() {
+    let mut bravo = set_up();
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    let written = bravo.stable_memory.offset;
+    bravo.cast_vote(id, VoteType::Support, Nat::from(200), Some("reason".to_string()), alice(), DELAY).unwrap();
+    bravo.cast_vote(id, VoteType::Against, Nat::from(150), None, alice(), DELAY + 1).unwrap();
+    // the replaced reason was the last record, its memory is reused
+    assert_eq!(bravo.stable_memory.offset, written);
+    bravo.cast_vote(id, VoteType::Abstain, Nat::from(120), None, alice(), DELAY + 2).unwrap();
+    let proposal = bravo.get_proposal(id).unwrap();
+    assert_eq!(proposal.support_votes, Nat::from(0));
+    assert_eq!(proposal.against_votes, Nat::from(0));
+    assert_eq!(proposal.abstain_votes, Nat::from(120));
+    let receipt = bravo.get_receipt(id, alice()).unwrap();
+    assert_eq!(receipt.changes, 2);
+    assert_eq!(receipt.reason, None);
+    assert!(bravo.cast_vote(id, VoteType::Support, Nat::from(200), None, alice(), DELAY + PERIOD).is_err());
+
+    // the previous option of a multi-choice ballot loses the votes
+    let options = vec!["red".to_string(), "green".to_string()];
+    let id = bravo.propose_options(Principal::from_slice(&[2]), Nat::from(5000), "options".to_string(), "description".to_string(), options, 0).unwrap();
+    bravo.cast_vote_option(id, 0, Nat::from(200), None, alice(), DELAY).unwrap();
+    bravo.cast_vote_option(id, 1, Nat::from(200), None, alice(), DELAY).unwrap();
+    let proposal = bravo.get_proposal(id).unwrap();
+    assert_eq!(proposal.option_votes, vec![Nat::from(0), Nat::from(200)]);
+    assert_eq!(proposal.support_votes, Nat::from(200));
+
+    // a new reason is written before the replaced one is freed
+    let carol = Principal::from_slice(&[3]);
+    bravo.cast_vote_option(id, 0, Nat::from(100), Some("red".to_string()), carol, DELAY).unwrap();
+    bravo.cast_vote_option(id, 1, Nat::from(100), Some("green".to_string()), carol, DELAY).unwrap();
+    assert_eq!(bravo.get_receipt(id, carol).unwrap().reason, Some("green".to_string()));
+}
+
+#[test]
+fn test_multi_choice_proposal() {
+    let mut bravo = set_up();
+    let bob = Principal::from_slice(&[2]);
+    let carol = Principal::from_slice(&[3]);
+    let options = vec!["red".to_string(), "green".to_string(), "blue".to_string()];
+    assert!(bravo.propose_options(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), vec!["red".to_string()], 0).is_err());
+    assert!(bravo.propose_options(alice(), Nat::from(5000), "title".to_string(), "description".to_string(),
+        vec!["red".to_string(), "red".to_string()], 0).is_err());
+    let id = bravo.propose_options(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), options.clone(), 0).unwrap();
+    let tied = bravo.propose_options(bob, Nat::from(5000), "tied".to_string(), "description".to_string(), options, 0).unwrap();
+
+    assert!(bravo.cast_vote(id, VoteType::Support, Nat::from(200), None, alice(), DELAY).is_err());
+    assert!(bravo.cast_vote_option(id, 3, Nat::from(200), None, alice(), DELAY).is_err());
+    bravo.cast_vote_option(id, 1, Nat::from(200), None, alice(), DELAY).unwrap();
+    bravo.cast_vote_option(id, 2, Nat::from(50), None, bob, DELAY).unwrap();
+    bravo.cast_vote_option(tied, 0, Nat::from(100), None, alice(), DELAY).unwrap();
+    bravo.cast_vote_option(tied, 2, Nat::from(100), None, carol, DELAY).unwrap();
+    assert_eq!(bravo.get_receipt(id, alice()).unwrap().option, Some(1));
+
+    let end = DELAY + PERIOD;
+    let proposal = bravo.get_proposal(id).unwrap();
+    assert_eq!(proposal.option_votes, vec![Nat::from(0), Nat::from(200), Nat::from(50)]);
+    assert_eq!(proposal.winning_option, Some(1));
+    assert_eq!(bravo.get_state(id, end).unwrap(), ProposalState::Closed);
+    // no winner on a tie
+    assert_eq!(bravo.get_proposal(tied).unwrap().winning_option, None);
+    assert_eq!(bravo.get_state(tied, end).unwrap(), ProposalState::Defeated);
+
+    let again = bravo.repropose(tied, bob, Nat::from(5000), end).unwrap();
+    let proposal = bravo.get_proposal(again).unwrap();
+    assert_eq!(proposal.kind, ProposalKind::Options);
+    assert_eq!(proposal.option_votes, vec![Nat::from(0); 3]);
+}
+
+#[test]
+fn test_proposal_deposit() {
+    let mut bravo = set_up();
+    let bob = Principal::from_slice(&[2]);
+    let treasury = Principal::from_slice(&[9]);
+    bravo.set_proposal_deposit(Nat::from(50), Some(treasury));
+    let passed = bravo.propose(alice(), Nat::from(5000), "passed".to_string(), "description".to_string(), task(), 0).unwrap();
+    let failed = bravo.propose(bob, Nat::from(5000), "failed".to_string(), "description".to_string(),
+        Task::new(Principal::management_canister(), "other".to_string(), vec![], 0), 0).unwrap();
+    bravo.record_deposit(passed, alice(), Nat::from(50)).unwrap();
+    bravo.record_deposit(failed, bob, Nat::from(50)).unwrap();
+    assert!(bravo.record_deposit(passed, alice(), Nat::from(50)).is_err());
+    assert!(bravo.settle_deposit(passed, 0).is_err());
+
+    bravo.cast_vote(passed, VoteType::Support, Nat::from(200), None, alice(), DELAY).unwrap();
+    bravo.cast_vote(failed, VoteType::Against, Nat::from(200), None, alice(), DELAY).unwrap();
+    let end = DELAY + PERIOD;
+    let (recipient, deposit) = bravo.settle_deposit(passed, end).unwrap();
+    assert_eq!((recipient, deposit.status), (Some(alice()), DepositStatus::Refunded));
+    assert!(bravo.settle_deposit(passed, end).is_err());
+
+    // the proposer canceling a defeated proposal does not get the deposit back
+    bravo.cancel(failed, end, bob, Nat::from(5000)).unwrap();
+    let (recipient, deposit) = bravo.settle_deposit(failed, end).unwrap();
+    assert_eq!((recipient, deposit.status), (Some(treasury), DepositStatus::Slashed));
+    bravo.unsettle_deposit(failed).unwrap();
+    assert_eq!(bravo.get_proposal(failed).unwrap().deposit.unwrap().status, DepositStatus::Held);
+
+    // withdrawn by the proposer before voting ends, refunded
+    let withdrawn = bravo.propose(alice(), Nat::from(5000), "withdrawn".to_string(), "description".to_string(),
+        Task::new(Principal::management_canister(), "third".to_string(), vec![], 0), end).unwrap();
+    bravo.record_deposit(withdrawn, alice(), Nat::from(50)).unwrap();
+    bravo.cancel(withdrawn, end, alice(), Nat::from(5000)).unwrap();
+    assert_eq!(bravo.settle_deposit(withdrawn, end).unwrap().1.status, DepositStatus::Refunded);
+}
+
+#[test]
+fn test_quorum_fraction() {
+    use crate::governance::MAX_BPS;
+    use crate::timelock::{GovernorParam, ParamChange};
+
+    let mut bravo = set_up();
+    let bob = Principal::from_slice(&[2]);
+    assert!(bravo.set_quorum_fraction(MAX_BPS + 1).is_err());
+    bravo.set_quorum_fraction(1000).unwrap();
+    let small = bravo.propose(alice(), Nat::from(5000), "small".to_string(), "description".to_string(), task(), 0).unwrap();
+    let large = bravo.propose(bob, Nat::from(5000), "large".to_string(), "description".to_string(),
+        Task::new(Principal::management_canister(), "other".to_string(), vec![], 0), 0).unwrap();
+    // 10% of the supply at creation, later supply changes do not apply
+    bravo.apply_quorum_fraction(small, Nat::from(1500)).unwrap();
+    bravo.apply_quorum_fraction(large, Nat::from(5000)).unwrap();
+    assert_eq!(bravo.get_proposal(small).unwrap().config.quorum_votes, Nat::from(150));
+    assert_eq!(bravo.get_proposal(large).unwrap().config.quorum_fraction, 1000);
+
+    bravo.cast_vote(small, VoteType::Support, Nat::from(200), None, alice(), DELAY).unwrap();
+    bravo.cast_vote(large, VoteType::Support, Nat::from(200), None, alice(), DELAY).unwrap();
+    assert!(bravo.apply_quorum_fraction(small, Nat::from(1)).is_err());
+    let end = DELAY + PERIOD;
+    assert_eq!(bravo.get_state(small, end).unwrap(), ProposalState::Succeeded);
+    assert_eq!(bravo.get_state(large, end).unwrap(), ProposalState::Defeated);
+
+    let too_high = ParamChange { field: GovernorParam::QuorumFraction, new_value: Nat::from(MAX_BPS + 1) };
+    assert!(GovernorBravo::check_param_change(&too_high).is_err());
+    bravo.apply_param_change(&ParamChange { field: GovernorParam::QuorumFraction, new_value: Nat::from(0) }).unwrap();
+    assert_eq!(bravo.quorum_fraction(), 0);
+}
+
+#[test]
+fn test_propose_eligibility() {
+    let mut bravo = set_up();
+    let eligibility = bravo.propose_eligibility(alice(), Nat::from(100), 0);
+    assert!(!eligibility.eligible && eligibility.reason.is_some());
+
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    let eligibility = bravo.propose_eligibility(alice(), Nat::from(5000), 0);
+    assert!(!eligibility.eligible);
+    assert_eq!(eligibility.live_proposal, Some(id));
+    assert!(bravo.propose_eligibility(alice(), Nat::from(5000), DELAY + PERIOD).eligible);
+}
+
+#[test]
+fn test_participation_quorum() {
+    let mut bravo = set_up();
+    bravo.set_quorum_mode(QuorumMode::Participation);
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    bravo.cast_vote(id, VoteType::Support, Nat::from(60), None, alice(), DELAY).unwrap();
+    bravo.cast_vote(id, VoteType::Abstain, Nat::from(30), None, Principal::from_slice(&[2]), DELAY).unwrap();
+    assert!(!bravo.quorum_reached(id).unwrap());
+    bravo.cast_vote(id, VoteType::Against, Nat::from(20), None, Principal::from_slice(&[3]), DELAY).unwrap();
+    assert!(bravo.quorum_reached(id).unwrap());
+    assert_eq!(bravo.get_state(id, DELAY + PERIOD).unwrap(), ProposalState::Succeeded);
+
+    // support votes alone stay below the quorum
+    bravo.set_quorum_mode(QuorumMode::Support);
+    assert_eq!(bravo.get_state(id, DELAY + PERIOD).unwrap(), ProposalState::Defeated);
+}
+
+#[test]
+fn test_tie_rule() {
+    let mut bravo = set_up();
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    bravo.cast_vote(id, VoteType::Support, Nat::from(150), None, alice(), DELAY).unwrap();
+    bravo.cast_vote(id, VoteType::Against, Nat::from(150), None, Principal::from_slice(&[2]), DELAY).unwrap();
+    assert_eq!(bravo.get_state(id, DELAY + PERIOD).unwrap(), ProposalState::Defeated);
+    bravo.set_tie_rule(TieRule::Succeed);
+    assert_eq!(bravo.get_state(id, DELAY + PERIOD).unwrap(), ProposalState::Succeeded);
+    // the proposer voted for
+    bravo.set_tie_rule(TieRule::Proposer);
+    assert_eq!(bravo.get_state(id, DELAY + PERIOD).unwrap(), ProposalState::Succeeded);
+
+    let bob = Principal::from_slice(&[2]);
+    let id = bravo.propose(bob, Nat::from(5000), "other".to_string(), "description".to_string(),
+        Task::new(Principal::management_canister(), "other".to_string(), vec![], 0), 0).unwrap();
+    bravo.cast_vote(id, VoteType::Support, Nat::from(150), None, alice(), DELAY).unwrap();
+    bravo.cast_vote(id, VoteType::Against, Nat::from(150), None, bob, DELAY).unwrap();
+    assert_eq!(bravo.get_state(id, DELAY + PERIOD).unwrap(), ProposalState::Defeated);
+}
+
+#[test]
+fn test_task_cycle_limits() {
+    let mut bravo = set_up();
+    let target = Principal::management_canister();
+    bravo.set_max_task_cycles(1_000);
+    let costly = Task::new(target, "test".to_string(), vec![], 2_000);
+    assert!(bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), costly, 0).is_err());
+
+    bravo.set_target_cycle_limit(target, Some(100));
+    let above_target = Task::new(target, "test".to_string(), vec![], 500);
+    assert!(bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), above_target.clone(), 0).is_err());
+    bravo.set_target_cycle_limit(target, None);
+    bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), above_target, 0).unwrap();
+}
+
+#[test]
+fn test_multi_call_task() {
+    use crate::timelock::Call;
+
+    let mut bravo = set_up();
+    let call = |method: &str, cycles: u64| Call {
+        target: Principal::management_canister(),
+        method: method.to_string(),
+        arguments: vec![],
+        cycles,
+    };
+    let propose = |bravo: &mut GovernorBravo, calls: Vec<Call>, timestamp: u64| {
+        bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), Task::calls(calls), timestamp)
+    };
+    assert!(propose(&mut bravo, vec![], 0).is_err());
+    // the cycles of all calls count against the governor's limit
+    bravo.set_max_task_cycles(1_000);
+    assert!(propose(&mut bravo, vec![call("setFee", 600), call("transfer", 600)], 0).is_err());
+
+    let id = propose(&mut bravo, vec![call("setFee", 0), call("transfer", 0)], 0).unwrap();
+    bravo.cast_vote(id, VoteType::Support, Nat::from(200), None, alice(), DELAY).unwrap();
+    let eta = bravo.queue(id, DELAY + PERIOD).unwrap();
+
+    // the second call fails, a retry resumes from it
+    bravo.pre_execute(id, eta).unwrap();
+    bravo.record_executed_call(id, eta).unwrap();
+    bravo.post_execute(id, false, eta).unwrap();
+    assert_eq!(bravo.get_state(id, eta).unwrap(), ProposalState::Queued);
+    assert_eq!(bravo.executed_calls(id).unwrap(), 1);
+    assert!(bravo.record_executed_call(id, eta).is_err());
+    bravo.pre_execute(id, eta).unwrap();
+    bravo.record_executed_call(id, eta).unwrap();
+    bravo.post_execute(id, true, eta).unwrap();
+    assert_eq!(bravo.get_proposal(id).unwrap().executed_calls, 2);
+}
+
+#[test]
+fn test_fund_task() {
+    let mut bravo = set_up();
+    let bob = Principal::from_slice(&[2]);
+    bravo.set_max_task_cycles(1_000);
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(),
+        Task::new(Principal::management_canister(), "test".to_string(), vec![], 200), 0).unwrap();
+    assert_eq!(bravo.fund_task(id, bob, 300, 0).unwrap(), 300);
+    assert_eq!(bravo.fund_task(id, alice(), 300, DELAY).unwrap(), 300);
+    // only the cycles up to the limit are taken
+    assert_eq!(bravo.fund_task(id, bob, 300, DELAY).unwrap(), 200);
+    assert!(bravo.fund_task(id, bob, 300, DELAY).is_err());
+    let proposal = bravo.get_proposal(id).unwrap();
+    assert_eq!(proposal.task.cycles, 1_000);
+    let mut funded = proposal.funded_cycles.clone();
+    funded.sort();
+    assert_eq!(funded, vec![(alice(), 300), (bob, 500)]);
+    // the funded task still blocks its unfunded twin
+    let twin = Task::new(Principal::management_canister(), "test".to_string(), vec![], 200);
+    assert_eq!(bravo.live_duplicate(&twin, DELAY), Some(id));
+    assert!(bravo.propose(bob, Nat::from(5000), "title".to_string(), "description".to_string(), twin, DELAY).is_err());
+
+    bravo.cast_vote(id, VoteType::Support, Nat::from(200), None, alice(), DELAY).unwrap();
+    bravo.queue(id, DELAY + PERIOD).unwrap();
+    assert!(bravo.fund_task(id, bob, 1, DELAY + PERIOD).is_err());
+}
+
+#[test]
+fn test_execution_window() {
+    use crate::timelock::{ExecutionWindow, ONE_DAY};
+
+    let mut bravo = set_up();
+    let window = ExecutionWindow { offset: ONE_DAY, length: 2 * ONE_DAY };
+    assert!(bravo.set_execution_window("unknown".to_string(), Some(window)).is_err());
+    assert!(bravo.set_execution_window("canister".to_string(), Some(ExecutionWindow { offset: 0, length: 0 })).is_err());
+    bravo.set_execution_window("canister".to_string(), Some(window)).unwrap();
+
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    bravo.cast_vote(id, VoteType::Support, Nat::from(200), None, alice(), DELAY).unwrap();
+    let eta = bravo.queue(id, DELAY + PERIOD).unwrap();
+    assert!(bravo.pre_execute(id, eta).is_err());
+    let expired = eta + 3 * ONE_DAY + 1;
+    assert_eq!(bravo.get_state(id, expired).unwrap(), ProposalState::Expired);
+    bravo.pre_execute(id, eta + ONE_DAY).unwrap();
+}
+
+#[test]
+fn test_receipt_breakdown() {
+    use crate::governance::VotesBreakdown;
+
+    let mut bravo = set_up();
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    let breakdown = VotesBreakdown { own: Nat::from(40), delegated: Nat::from(20) };
+    assert!(bravo.set_receipt_breakdown(id, alice(), breakdown.clone()).is_err());
+    bravo.cast_vote(id, VoteType::Support, Nat::from(60), None, alice(), DELAY).unwrap();
+    assert!(bravo.get_receipt(id, alice()).unwrap().breakdown.is_none());
+    bravo.set_receipt_breakdown(id, alice(), breakdown.clone()).unwrap();
+    assert_eq!(bravo.get_receipt(id, alice()).unwrap().breakdown, Some(breakdown));
+}
+
+#[test]
+fn test_receipt_filter() {
+    use crate::governance::ReceiptCounts;
+
+    let mut bravo = set_up();
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    let voters: Vec<Principal> = (2..6u8).map(|i| Principal::from_slice(&[i])).collect();
+    bravo.cast_vote(id, VoteType::Support, Nat::from(60), None, alice(), DELAY).unwrap();
+    bravo.cast_vote(id, VoteType::Against, Nat::from(10), None, voters[0], DELAY).unwrap();
+    bravo.cast_vote(id, VoteType::Against, Nat::from(10), None, voters[1], DELAY).unwrap();
+    bravo.cast_vote(id, VoteType::Against, Nat::from(10), None, voters[2], DELAY).unwrap();
+    bravo.cast_vote(id, VoteType::Abstain, Nat::from(10), None, voters[3], DELAY).unwrap();
+
+    assert_eq!(bravo.get_receipt_counts(id).unwrap(), ReceiptCounts { support: 1, against: 3, abstain: 1 });
+    assert_eq!(bravo.get_receipt_pages(id, 0, 10, None).unwrap().len(), 5);
+    let mut against: Vec<Principal> = bravo.get_receipt_pages(id, 0, 2, Some(VoteType::Against)).unwrap()
+        .into_iter()
+        .chain(bravo.get_receipt_pages(id, 1, 2, Some(VoteType::Against)).unwrap())
+        .map(|(voter, _)| voter)
+        .collect();
+    against.sort();
+    assert_eq!(against, voters[..3].to_vec());
+    assert!(bravo.get_receipt_pages(id, 2, 2, Some(VoteType::Against)).unwrap().is_empty());
+    assert!(bravo.get_receipt_counts(id + 1).is_err());
+}
+
+#[test]
+fn test_redact_reason() {
+    let mut bravo = set_up();
+    let bob = Principal::from_slice(&[2]);
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    bravo.cast_vote(id, VoteType::Against, Nat::from(200), Some("abusive".to_string()), alice(), DELAY).unwrap();
+    bravo.cast_vote(id, VoteType::Support, Nat::from(100), None, bob, DELAY).unwrap();
+    assert!(bravo.redact_reason(id, bob).is_err());
+
+    bravo.redact_reason(id, alice()).unwrap();
+    let receipt = bravo.get_receipt(id, alice()).unwrap();
+    assert_eq!(receipt.reason, Some(GovernorBravo::REDACTED_REASON.to_string()));
+    assert_eq!(receipt.votes, Nat::from(200));
+    assert_eq!(receipt.vote_type, VoteType::Against);
+    assert!(bravo.redact_reason(id, alice()).is_err());
+
+    // the text is gone from stable memory, the records stay consistent
+    let written = bravo.export_stable_range(0, bravo.stable_memory.offset).unwrap();
+    assert!(!written.windows(7).any(|w| w == b"abusive"));
+}
+
+#[test]
+fn test_repropose() {
+    let mut bravo = set_up();
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    assert!(bravo.repropose(id, alice(), Nat::from(5000), DELAY).is_err());
+    bravo.cast_vote(id, VoteType::Against, Nat::from(200), None, alice(), DELAY).unwrap();
+    let ended = DELAY + PERIOD;
+    assert_eq!(bravo.get_state(id, ended).unwrap(), ProposalState::Defeated);
+    assert!(bravo.repropose(id, alice(), Nat::from(100), ended).is_err());
+
+    let offset = bravo.stable_memory.offset;
+    let new_id = bravo.repropose(id, alice(), Nat::from(5000), ended).unwrap();
+    assert_eq!(bravo.stable_memory.offset, offset);
+    let proposal = bravo.get_proposal(new_id).unwrap();
+    assert_eq!(proposal.description, "description");
+    assert_eq!(bravo.get_state(new_id, ended).unwrap(), ProposalState::Pending);
+    // the copy is live now
+    assert!(bravo.repropose(id, alice(), Nat::from(5000), ended).is_err());
+}
+
+#[test]
+fn test_one_live_proposal_while_tallying() {
+    let mut bravo = set_up();
+    bravo.add_satellite(Principal::from_slice(&[7])).unwrap();
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    let end = DELAY + PERIOD;
+    assert_eq!(bravo.get_state(id, end).unwrap(), ProposalState::Tallying);
+    assert!(bravo.check_proposer(alice(), &Nat::from(5000), end).is_err());
+    assert!(!bravo.propose_eligibility(alice(), Nat::from(5000), end).eligible);
+}
+
+#[test]
+fn test_offchain_tally_while_active() {
+    let mut bravo = set_up();
+    let id = bravo.propose(alice(), Nat::from(5000), "title".to_string(), "description".to_string(), task(), 0).unwrap();
+    let tally = || Tally { support_votes: Nat::from(200), against_votes: Nat::from(0), abstain_votes: Nat::from(0) };
+    assert!(bravo.merge_offchain_tally(id, tally(), 0).is_err());
+    bravo.merge_offchain_tally(id, tally(), DELAY + 1).unwrap();
+    assert_eq!(bravo.get_state(id, DELAY + PERIOD + 1).unwrap(), ProposalState::Succeeded);
+    // votes can't be imported once the outcome is known
+    assert!(bravo.merge_offchain_tally(id, tally(), DELAY + PERIOD + 1).is_err());
+}
+
+#[test]
+fn test_legacy_nat64_parameters() {
+    use std::collections::HashMap;
+    use crate::legacy;
+    use crate::stable::StableMemory;
+    use crate::timelock::Timelock;
+
+    let bravo = GovernorBravo::from(legacy::GovernorBravo {
+        admin: alice(),
+        pending_admin: None,
+        name: "Test".to_string(),
+        quorum_votes: 100,
+        voting_delay: DELAY,
+        voting_period: PERIOD,
+        proposal_threshold: u64::MAX,
+        proposals: vec![],
+        latest_proposal_ids: HashMap::new(),
+        initialized: true,
+        gov_token: Principal::anonymous(),
+        timelock: Timelock::default(),
+        stable_memory: StableMemory::default(),
+    });
+    // the nat64 threshold carries over and can be exceeded by nat votes
+    assert!(bravo.check_proposer(alice(), &Nat::from(u64::MAX), 0).is_err());
+    assert!(bravo.check_proposer(alice(), &(Nat::from(u64::MAX) + Nat::from(1)), 0).is_ok());
+}
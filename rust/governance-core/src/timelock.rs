@@ -0,0 +1,536 @@
+/**
+ * Module     : timelock.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use candid::{CandidType, Deserialize, Nat, Principal};
+
+#[derive(Deserialize, CandidType, Hash, PartialEq, Eq, Clone, Debug)]
+pub struct Oracle {
+    /// https url fetched at execution time
+    pub url: String,
+    /// query method of the governor transforming the response, `oracleTransform` if not set
+    pub transform: Option<String>,
+    /// maximum response size in bytes
+    pub max_response_bytes: Option<u64>,
+    /// cycles attached to the http outcall
+    pub cycles: u64,
+}
+
+#[derive(Deserialize, CandidType, Hash, PartialEq, Eq, Clone, Debug)]
+pub struct EthereumTx {
+    /// chain id of the ethereum network
+    pub chain_id: u64,
+    /// 20 bytes address of the callee, empty for contract creation
+    pub to: Vec<u8>,
+    /// wei transferred with the transaction
+    pub value: Nat,
+    /// calldata
+    pub data: Vec<u8>,
+    /// gas limit of the transaction
+    pub gas_limit: u64,
+    /// EIP-1559 max fee per gas in wei
+    pub max_fee_per_gas: u64,
+    /// EIP-1559 max priority fee per gas in wei
+    pub max_priority_fee_per_gas: u64,
+}
+
+#[derive(Deserialize, CandidType, Hash, PartialEq, Eq, Clone, Debug)]
+pub struct BitcoinTransfer {
+    /// P2PKH address of the recipient
+    pub address: String,
+    /// satoshi to transfer
+    pub amount: u64,
+}
+
+#[derive(Deserialize, CandidType, Hash, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Asset {
+    /// the governance token
+    GovToken,
+    /// ICP held on the ICP ledger by the default account of the governor
+    Icp,
+    /// asset on a ledger registered with the treasury
+    Ledger(Principal),
+}
+
+#[derive(Deserialize, CandidType, Hash, PartialEq, Eq, Clone, Debug)]
+pub struct TreasurySpend {
+    /// asset transferred out of the treasury
+    pub asset: Asset,
+    /// recipient, for ICP the default account of the principal
+    pub to: Principal,
+    /// amount in the smallest unit of the asset
+    pub amount: Nat,
+}
+
+#[derive(Deserialize, CandidType, Hash, PartialEq, Eq, Clone, Debug)]
+pub struct GrantStream {
+    /// asset paid out of the treasury
+    pub asset: Asset,
+    /// recipient claiming the accrued amount
+    pub recipient: Principal,
+    /// amount accrued per second, in the smallest unit of the asset
+    pub rate: Nat,
+    /// duration of the stream in nanoseconds, starting at execution
+    pub duration: u64,
+}
+
+/// governor parameter changed by a `ParamChange` task
+#[derive(Deserialize, CandidType, Hash, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum GovernorParam {
+    QuorumVotes,
+    VotingDelay,
+    VotingPeriod,
+    ProposalThreshold,
+    TimelockDelay,
+    MinHoldingDuration,
+    MinDelegationAge,
+    /// quorum in basis points of the total supply, 0 for the absolute quorum votes
+    QuorumFraction,
+}
+
+#[derive(Deserialize, CandidType, Hash, PartialEq, Eq, Clone, Debug)]
+pub struct ParamChange {
+    pub field: GovernorParam,
+    /// checked against the bounds of the parameter at propose time
+    pub new_value: Nat,
+}
+
+/// one canister call of a multi-call task
+#[derive(Deserialize, CandidType, Hash, PartialEq, Eq, Clone, Debug)]
+pub struct Call {
+    /// principal of target canister
+    pub target: Principal,
+    /// method name to call
+    pub method: String,
+    /// encoded arguments
+    pub arguments: Vec<u8>,
+    /// with cycles
+    pub cycles: u64,
+}
+
+/// where a task is executed
+#[derive(Deserialize, CandidType, Hash, PartialEq, Eq, Clone, Debug)]
+pub enum Backend {
+    /// call `method` of the `target` canister
+    Canister,
+    /// sign the transaction with threshold ecdsa and broadcast it to ethereum
+    Ethereum(EthereumTx),
+    /// transfer bitcoin held by the chain-key bitcoin treasury
+    Bitcoin(BitcoinTransfer),
+    /// transfer tokens held by the governor
+    Treasury(TreasurySpend),
+    /// open a stream paying the recipient out of the treasury over time
+    Stream(GrantStream),
+    /// set a governor parameter, applied by the governor itself
+    ParamChange(ParamChange),
+    /// call the canisters in order, a failed execution resumes from the call which failed
+    Calls(Vec<Call>),
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Backend::Canister
+    }
+}
+
+/// every backend kind
+pub const BACKEND_KINDS: [&str; 7] = ["canister", "ethereum", "bitcoin", "treasury", "stream", "param", "calls"];
+
+/// maximum calls of a multi-call task
+pub const MAX_CALLS: usize = 10;
+
+impl Backend {
+    /// lowercase name of the backend, the proposal type used by per type settings
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Backend::Canister => { "canister" }
+            Backend::Ethereum(_) => { "ethereum" }
+            Backend::Bitcoin(_) => { "bitcoin" }
+            Backend::Treasury(_) => { "treasury" }
+            Backend::Stream(_) => { "stream" }
+            Backend::ParamChange(_) => { "param" }
+            Backend::Calls(_) => { "calls" }
+        }
+    }
+}
+
+/// when a queued task may be executed, relative to its eta
+#[derive(Deserialize, CandidType, Hash, PartialEq, Eq, Clone, Copy, Debug)]
+pub struct ExecutionWindow {
+    /// wait after the eta before execution opens
+    pub offset: u64,
+    /// how long execution stays open once it opens
+    pub length: u64,
+}
+
+impl Default for ExecutionWindow {
+    fn default() -> Self {
+        ExecutionWindow {
+            offset: 0,
+            length: Timelock::GRACE_PERIOD,
+        }
+    }
+}
+
+#[derive(Deserialize, CandidType, Hash, PartialEq, Eq, Clone, Debug)]
+pub struct Task {
+    /// principal of target canister
+    pub target: Principal,
+    /// method name to call
+    pub method: String,
+    /// encoded arguments
+    pub arguments: Vec<u8>,
+    /// with cycles
+    pub cycles: u64,
+    /// optional oracle whose response is appended to the arguments at execution time
+    pub oracle: Option<Oracle>,
+    /// execution backend of the task
+    #[serde(default)]
+    pub backend: Backend,
+    /// timestamp that the proposal will be available for execution, set once the vote succeed
+    pub eta: u64,
+    /// execution window after the eta, fixed when queued
+    #[serde(default)]
+    pub window: ExecutionWindow,
+}
+
+impl Task {
+    pub fn new(
+        target: Principal,
+        method: String,
+        arguments: Vec<u8>,
+        cycles: u64,
+    ) -> Self {
+        Self {
+            target,
+            method,
+            arguments,
+            cycles,
+            oracle: None,
+            backend: Backend::Canister,
+            eta: 0,
+            window: ExecutionWindow::default(),
+        }
+    }
+
+    /// task executed by a backend other than a canister call
+    fn with_backend(backend: Backend) -> Self {
+        Self {
+            target: Principal::anonymous(),
+            method: "".to_string(),
+            arguments: vec![],
+            cycles: 0,
+            oracle: None,
+            backend,
+            eta: 0,
+            window: ExecutionWindow::default(),
+        }
+    }
+
+    pub fn ethereum(tx: EthereumTx) -> Self {
+        Self::with_backend(Backend::Ethereum(tx))
+    }
+
+    pub fn bitcoin(transfer: BitcoinTransfer) -> Self {
+        Self::with_backend(Backend::Bitcoin(transfer))
+    }
+
+    pub fn treasury(spend: TreasurySpend) -> Self {
+        Self::with_backend(Backend::Treasury(spend))
+    }
+
+    pub fn stream(grant: GrantStream) -> Self {
+        Self::with_backend(Backend::Stream(grant))
+    }
+
+    pub fn param_change(change: ParamChange) -> Self {
+        Self::with_backend(Backend::ParamChange(change))
+    }
+
+    pub fn calls(calls: Vec<Call>) -> Self {
+        Self::with_backend(Backend::Calls(calls))
+    }
+
+    /// placeholder task of text proposals, never queued nor executed
+    pub fn text() -> Self {
+        Self::new(Principal::anonymous(), String::new(), vec![], 0)
+    }
+
+    pub fn with_oracle(mut self, oracle: Oracle) -> Self {
+        self.oracle = Some(oracle);
+        self
+    }
+
+    /// earliest execution time
+    pub fn opens_at(&self) -> u64 {
+        self.eta + self.window.offset
+    }
+
+    /// latest execution time, the task is stale afterwards
+    pub fn expires_at(&self) -> u64 {
+        self.opens_at() + self.window.length
+    }
+
+    /// hash of the action, ignoring the eta and window, for duplicate detection within the running canister.
+    /// cycles are left out too, funding a task does not make it a different action
+    pub fn action_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.target.hash(&mut hasher);
+        self.method.hash(&mut hasher);
+        self.arguments.hash(&mut hasher);
+        self.oracle.hash(&mut hasher);
+        self.backend.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// check the task is well formed before it is proposed
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if let Some(oracle) = &self.oracle {
+            if !oracle.url.starts_with("https://") {
+                return Err("oracle url must use https");
+            }
+        }
+        match &self.backend {
+            Backend::Canister => {}
+            Backend::Ethereum(tx) => {
+                if !tx.to.is_empty() && tx.to.len() != 20 {
+                    return Err("ethereum address must be 20 bytes");
+                }
+                if self.oracle.is_some() {
+                    return Err("oracle is only supported for canister tasks");
+                }
+            }
+            Backend::Bitcoin(transfer) => {
+                if transfer.amount == 0 {
+                    return Err("bitcoin transfer amount must be positive");
+                }
+                if self.oracle.is_some() {
+                    return Err("oracle is only supported for canister tasks");
+                }
+            }
+            Backend::Treasury(spend) => {
+                if spend.amount == Nat::from(0) {
+                    return Err("treasury spend amount must be positive");
+                }
+                if self.oracle.is_some() {
+                    return Err("oracle is only supported for canister tasks");
+                }
+            }
+            Backend::Stream(grant) => {
+                if grant.rate == Nat::from(0) || grant.duration == 0 {
+                    return Err("stream rate and duration must be positive");
+                }
+                if self.oracle.is_some() {
+                    return Err("oracle is only supported for canister tasks");
+                }
+            }
+            // bounds are checked by the governor
+            Backend::ParamChange(_) => {
+                if self.oracle.is_some() {
+                    return Err("oracle is only supported for canister tasks");
+                }
+            }
+            Backend::Calls(calls) => {
+                if calls.is_empty() || calls.len() > MAX_CALLS {
+                    return Err("multi-call task must have between one and the maximum calls");
+                }
+                if self.oracle.is_some() {
+                    return Err("oracle is only supported for canister tasks");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// parameter change announced by the admin, applied once the delay has passed
+#[derive(Deserialize, CandidType, Clone, Debug, PartialEq)]
+pub struct AnnouncedChange {
+    pub id: usize,
+    pub change: ParamChange,
+    pub announcer: Principal,
+    pub eta: u64,
+}
+
+impl AnnouncedChange {
+    /// last moment the change may be applied
+    pub fn expires_at(&self) -> u64 {
+        self.eta.saturating_add(Timelock::GRACE_PERIOD)
+    }
+}
+
+/// call of a governor setter announced by the admin, made by the governor itself once the delay has passed,
+/// for settings which do not fit a parameter change
+#[derive(Deserialize, CandidType, Clone, Debug, PartialEq)]
+pub struct AnnouncedCall {
+    pub id: usize,
+    /// setter of the governor
+    pub method: String,
+    /// candid encoded arguments
+    pub arguments: Vec<u8>,
+    pub announcer: Principal,
+    pub eta: u64,
+}
+
+impl AnnouncedCall {
+    /// last moment the call may be made
+    pub fn expires_at(&self) -> u64 {
+        self.eta.saturating_add(Timelock::GRACE_PERIOD)
+    }
+}
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub struct Timelock {
+    pub delay: u64,
+    pub queued_transactions: HashSet<Task>,
+    /// admin parameter changes waiting out the delay
+    #[serde(default)]
+    pub announced_changes: Vec<AnnouncedChange>,
+    /// admin setter calls waiting out the delay
+    #[serde(default)]
+    pub announced_calls: Vec<AnnouncedCall>,
+    /// id of the next announced change or call
+    #[serde(default)]
+    next_change_id: usize,
+}
+
+pub const ONE_DAY: u64 = 24 * 3600 * 1_000_000_000;
+
+impl Timelock {
+    /// grace period for execution
+    pub const GRACE_PERIOD: u64 = 14 * ONE_DAY;
+    /// minimum delay for time lock execution
+    pub const MIN_DELAY: u64 = 2 * ONE_DAY;
+    /// maximum delay for time lock execution
+    pub const MAX_DELAY: u64 = 30 * ONE_DAY;
+
+    fn new(delay: u64) -> Self {
+        Timelock {
+            delay,
+            queued_transactions: HashSet::new(),
+            announced_changes: vec![],
+            announced_calls: vec![],
+            next_change_id: 0,
+        }
+    }
+
+    pub fn set_delay(&mut self, delay: u64) {
+        self.delay = delay;
+    }
+
+    pub fn queue_transaction(&mut self, task: Task) {
+        self.queued_transactions.insert(task);
+    }
+
+    pub fn cancel_transaction(&mut self, task: &Task) {
+        self.queued_transactions.remove(&task);
+    }
+
+    pub fn pre_execute_transaction(&mut self, task: &Task, timestamp: u64) -> Result<(), &'static str> {
+        if !self.queued_transactions.contains(task) {
+            return Err("Transaction hasn't been queued");
+        }
+        if timestamp < task.opens_at() {
+            return Err("Transaction hasn't surpassed time lock");
+        };
+        if timestamp > task.expires_at() {
+            return Err("Transaction is stale");
+        }
+
+        self.queued_transactions.remove(task);
+        Ok(())
+    }
+
+    pub fn post_execute_transaction(&mut self, task: Task, result: bool) {
+        if !result {
+            self.queued_transactions.insert(task);
+        }
+    }
+
+    /// announce the change, it may be applied once the delay has passed
+    pub fn announce_change(&mut self, change: ParamChange, announcer: Principal, timestamp: u64) -> AnnouncedChange {
+        let announced = AnnouncedChange {
+            id: self.next_change_id,
+            change,
+            announcer,
+            eta: timestamp + self.delay,
+        };
+        self.next_change_id += 1;
+        self.announced_changes.push(announced.clone());
+        announced
+    }
+
+    /// remove the announced change for it to be applied
+    pub fn take_change(&mut self, id: usize, timestamp: u64) -> Result<AnnouncedChange, &'static str> {
+        let index = self.announced_changes.iter().position(|c| c.id == id).ok_or("Change hasn't been announced")?;
+        let announced = &self.announced_changes[index];
+        if timestamp < announced.eta {
+            return Err("Change hasn't surpassed time lock");
+        }
+        if timestamp > announced.expires_at() {
+            return Err("Change is stale");
+        }
+        Ok(self.announced_changes.remove(index))
+    }
+
+    pub fn cancel_change(&mut self, id: usize) -> Result<AnnouncedChange, &'static str> {
+        let index = self.announced_changes.iter().position(|c| c.id == id).ok_or("Change hasn't been announced")?;
+        Ok(self.announced_changes.remove(index))
+    }
+
+    /// announce the setter call, it may be made once the delay has passed
+    pub fn announce_call(&mut self, method: String, arguments: Vec<u8>, announcer: Principal, timestamp: u64) -> AnnouncedCall {
+        let announced = AnnouncedCall {
+            id: self.next_change_id,
+            method,
+            arguments,
+            announcer,
+            eta: timestamp + self.delay,
+        };
+        self.next_change_id += 1;
+        self.announced_calls.push(announced.clone());
+        announced
+    }
+
+    /// remove the announced call for it to be made
+    pub fn take_call(&mut self, id: usize, timestamp: u64) -> Result<AnnouncedCall, &'static str> {
+        let index = self.announced_calls.iter().position(|c| c.id == id).ok_or("Call hasn't been announced")?;
+        let announced = &self.announced_calls[index];
+        if timestamp < announced.eta {
+            return Err("Call hasn't surpassed time lock");
+        }
+        if timestamp > announced.expires_at() {
+            return Err("Call is stale");
+        }
+        Ok(self.announced_calls.remove(index))
+    }
+
+    /// put back a taken call whose execution failed, so it can be made again until it expires
+    pub fn restore_call(&mut self, announced: AnnouncedCall) {
+        self.announced_calls.push(announced);
+    }
+
+    pub fn cancel_call(&mut self, id: usize) -> Result<AnnouncedCall, &'static str> {
+        let index = self.announced_calls.iter().position(|c| c.id == id).ok_or("Call hasn't been announced")?;
+        Ok(self.announced_calls.remove(index))
+    }
+}
+
+impl Default for Timelock {
+    fn default() -> Self {
+        Self {
+            delay: 0,
+            queued_transactions: HashSet::new(),
+            announced_changes: vec![],
+            announced_calls: vec![],
+            next_change_id: 0,
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,40 @@
+/**
+ * Module     : events.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+//! Operations and detail keys of the CAP events inserted by the governor.
+
+pub const PROPOSE: &str = "propose";
+pub const QUEUE: &str = "queue";
+pub const CANCEL: &str = "cancel";
+pub const EXECUTE: &str = "execute";
+pub const VOTE: &str = "vote";
+pub const SET_PENDING_ADMIN: &str = "setPendingAdmin";
+pub const ACCEPT_ADMIN: &str = "acceptAdmin";
+pub const MERGE_SATELLITE_TALLY: &str = "mergeSatelliteTally";
+/// support votes of a proposal crossed its quorum
+pub const QUORUM_REACHED: &str = "quorumReached";
+/// task of an expired proposal dropped from the timelock
+pub const EXPIRE: &str = "expire";
+/// proposal first observed in a state computed from its votes and times
+pub const TRANSITION: &str = "transition";
+
+/// id of the proposal the event refers to
+pub const PROPOSAL_ID: &str = "proposalId";
+/// id of a newly created proposal, in `propose` events
+pub const ID: &str = "id";
+pub const ETA: &str = "eta";
+pub const VOTES: &str = "votes";
+/// `VoteType::label` of the vote
+pub const VOTE_TYPE: &str = "voteType";
+/// index of the option voted for on a multi-choice proposal
+pub const OPTION: &str = "option";
+pub const PENDING_ADMIN: &str = "pendingAdmin";
+pub const SATELLITE: &str = "satellite";
+pub const SUPPORT_VOTES: &str = "supportVotes";
+/// state reached, in `transition` events
+pub const STATE: &str = "state";
@@ -0,0 +1,162 @@
+/**
+ * Module     : lib.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+//! Candid types shared by the governance token, the governor and their clients.
+
+use candid::{CandidType, Deserialize, Nat, Principal};
+
+pub mod events;
+
+#[derive(Deserialize, CandidType, PartialEq, Clone, Debug)]
+pub enum ProposalState {
+    Pending,
+    Active,
+    Tallying,
+    Canceled,
+    Defeated,
+    Succeeded,
+    Queued,
+    Executing,
+    Executed,
+    Expired,
+    /// text proposal which succeeded, nothing is executed
+    Closed,
+}
+
+#[derive(PartialEq, Deserialize, CandidType, Clone, Debug)]
+pub enum VoteType {
+    Support,
+    Against,
+    Abstain,
+}
+
+impl VoteType {
+    /// lowercase label used in events and exports
+    pub fn label(&self) -> &'static str {
+        match self {
+            VoteType::Support => { "support" }
+            VoteType::Against => { "against" }
+            VoteType::Abstain => { "abstain" }
+        }
+    }
+}
+
+/// errors of the governance token
+#[derive(Deserialize, CandidType, Debug, PartialEq)]
+pub enum TxError {
+    InsufficientBalance,
+    InsufficientAllowance,
+    Unauthorized,
+    LedgerTrap,
+    AmountTooSmall,
+    BlockUsed,
+    ErrorOperationStyle,
+    ErrorTo,
+    Other,
+    /// above the per-transaction or the daily transfer limit
+    LimitExceeded,
+}
+
+/// result of the governance token updates, the transaction index on success
+pub type TxReceipt = Result<Nat, TxError>;
+
+/// votes of a delegatee from `timestamp` on, recorded by the governance token
+#[derive(Deserialize, CandidType, Clone, Debug, PartialEq)]
+pub struct CheckPoint {
+    pub timestamp: Nat,
+    pub votes: Nat,
+}
+
+/// votes at the timestamp from checkpoints in time order, the oldest votes before the first one,
+/// shared by the governance token and its checkpoint archive
+pub fn prior_votes(check_points: &[CheckPoint], timestamp: &Nat) -> Nat {
+    let (oldest, current) = match (check_points.first(), check_points.last()) {
+        (Some(oldest), Some(current)) => (oldest, current),
+        _ => { return Nat::from(0); }
+    };
+    if current.timestamp <= *timestamp {
+        return current.votes.clone();
+    }
+    if oldest.timestamp > *timestamp {
+        return oldest.votes.clone();
+    }
+    let idx = check_points
+        .binary_search_by(|item| item.timestamp.cmp(timestamp))
+        .unwrap_or_else(|x| x - 1);
+    check_points[idx].votes.clone()
+}
+
+/// lowest votes held at any time between from and to, 0 if the first checkpoint is after from
+pub fn min_prior_votes(check_points: &[CheckPoint], from: &Nat, to: &Nat) -> Nat {
+    if check_points.first().map_or(true, |cp| cp.timestamp > *from) {
+        return Nat::from(0);
+    }
+    let mut min_votes = prior_votes(check_points, from);
+    for cp in check_points.iter().filter(|cp| cp.timestamp > *from && cp.timestamp <= *to) {
+        if cp.votes < min_votes {
+            min_votes = cp.votes.clone();
+        }
+    }
+    min_votes
+}
+
+/// votes at the timestamp weighted by how long they have been held, votes held for less than the ramp
+/// count linearly from nothing to in full, i.e. the lowest votes held since each time within the ramp averaged
+pub fn ramped_votes(check_points: &[CheckPoint], timestamp: &Nat, ramp: u64) -> Nat {
+    if ramp == 0 {
+        return prior_votes(check_points, timestamp);
+    }
+    let from = if *timestamp > ramp { timestamp.clone() - Nat::from(ramp) } else { Nat::from(0) };
+    let mut end = timestamp.clone();
+    let mut held: Option<Nat> = None;
+    let mut weighted = Nat::from(0);
+    for cp in check_points.iter().rev().filter(|cp| cp.timestamp <= *timestamp) {
+        let start = if cp.timestamp > from { cp.timestamp.clone() } else { from.clone() };
+        let votes = match held {
+            Some(held) if held < cp.votes => held,
+            _ => cp.votes.clone(),
+        };
+        weighted = weighted + votes.clone() * (end - start.clone());
+        if start == from {
+            break;
+        }
+        held = Some(votes);
+        end = start;
+    }
+    // nothing was held before the first checkpoint
+    weighted / Nat::from(ramp)
+}
+
+/// kind of a balance change notified by the governance token
+#[derive(Deserialize, CandidType, Clone, Copy, Debug, PartialEq)]
+pub enum BalanceChangeKind {
+    Transfer,
+    Mint,
+    Burn,
+}
+
+/// balance change the governance token notifies its listeners of, one-way
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub struct BalanceChange {
+    pub kind: BalanceChangeKind,
+    /// none for mints
+    pub from: Option<Principal>,
+    /// none for burns
+    pub to: Option<Principal>,
+    pub amount: Nat,
+    pub fee: Nat,
+    pub timestamp: u64,
+}
+
+/// tally reported by a satellite canister for a proposal
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub struct Tally {
+    pub support_votes: Nat,
+    pub against_votes: Nat,
+    pub abstain_votes: Nat,
+}
@@ -0,0 +1,69 @@
+/**
+ * Module     : audit.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+use ic_kit::candid::{CandidType, Deserialize};
+use ic_kit::Principal;
+use sha2::{Digest, Sha256};
+
+/// maximum records returned by one page
+const MAX_AUDIT_PAGE: usize = 100;
+
+/// guarded admin call, chained to the previous record by its hash
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub struct AuditRecord {
+    pub id: u64,
+    pub caller: Principal,
+    pub method: String,
+    /// candid encoded arguments of the call
+    pub args: Vec<u8>,
+    pub timestamp: u64,
+    /// sha256 of the previous hash and this record, rewriting a record changes every later hash
+    pub hash: Vec<u8>,
+}
+
+/// append-only log of the guarded admin calls, kept on the canister independently of CAP
+#[derive(Deserialize, CandidType, Clone, Default)]
+pub struct AuditLog {
+    records: Vec<AuditRecord>,
+}
+
+impl AuditLog {
+    pub(crate) fn record(&mut self, caller: Principal, method: &str, args: Vec<u8>, timestamp: u64) {
+        let id = self.records.len() as u64;
+        let mut hasher = Sha256::new();
+        hasher.update(self.head());
+        hasher.update(id.to_le_bytes());
+        for field in [caller.as_slice(), method.as_bytes(), args.as_slice()] {
+            hasher.update((field.len() as u64).to_le_bytes());
+            hasher.update(field);
+        }
+        hasher.update(timestamp.to_le_bytes());
+        self.records.push(AuditRecord {
+            id,
+            caller,
+            method: method.to_string(),
+            args,
+            timestamp,
+            hash: hasher.finalize().to_vec(),
+        });
+    }
+
+    /// hash of the latest record, empty before the first one
+    pub(crate) fn head(&self) -> Vec<u8> {
+        self.records.last().map_or(vec![], |r| r.hash.clone())
+    }
+
+    /// records from the id on, oldest first, at most limit capped by MAX_AUDIT_PAGE
+    pub(crate) fn page(&self, start: usize, limit: usize) -> Vec<AuditRecord> {
+        self.records.iter().skip(start).take(limit.min(MAX_AUDIT_PAGE)).cloned().collect()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.records.len()
+    }
+}
@@ -0,0 +1,379 @@
+/**
+ * Module     : bitcoin.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+use std::collections::HashSet;
+use ic_cdk::api::call::{call_with_payment, CallResult};
+use ic_kit::candid::{CandidType, Deserialize};
+use ic_kit::Principal;
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256};
+use crate::ecdsa;
+
+const GET_BALANCE_CYCLES: u64 = 100_000_000;
+const GET_UTXOS_CYCLES: u64 = 10_000_000_000;
+const GET_FEE_PERCENTILES_CYCLES: u64 = 100_000_000;
+const SEND_TRANSACTION_BASE_CYCLES: u64 = 5_000_000_000;
+const SEND_TRANSACTION_PER_BYTE_CYCLES: u64 = 20_000_000;
+/// outputs below this value are not relayed by bitcoin nodes
+const DUST_THRESHOLD: u64 = 546;
+/// fee rate used when the network reports no percentiles, in millisatoshi per byte
+const DEFAULT_FEE_PER_BYTE: u64 = 2000;
+const SIGHASH_ALL: u8 = 0x01;
+
+#[derive(Deserialize, CandidType, Clone, Copy, PartialEq, Debug)]
+pub enum BitcoinNetwork {
+    #[serde(rename = "mainnet")]
+    Mainnet,
+    #[serde(rename = "testnet")]
+    Testnet,
+    #[serde(rename = "regtest")]
+    Regtest,
+}
+
+impl Default for BitcoinNetwork {
+    fn default() -> Self {
+        BitcoinNetwork::Regtest
+    }
+}
+
+#[derive(Deserialize, CandidType, Clone, Hash, PartialEq, Eq, Debug)]
+pub struct Outpoint {
+    pub(crate) txid: Vec<u8>,
+    pub(crate) vout: u32,
+}
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub struct Utxo {
+    pub(crate) outpoint: Outpoint,
+    pub(crate) value: u64,
+    pub(crate) height: u32,
+}
+
+#[derive(CandidType, Clone, Debug)]
+struct GetBalanceRequest {
+    address: String,
+    network: BitcoinNetwork,
+    min_confirmations: Option<u32>,
+}
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+enum UtxoFilter {
+    #[serde(rename = "min_confirmations")]
+    MinConfirmations(u32),
+    #[serde(rename = "page")]
+    Page(Vec<u8>),
+}
+
+#[derive(CandidType, Clone, Debug)]
+struct GetUtxosRequest {
+    address: String,
+    network: BitcoinNetwork,
+    filter: Option<UtxoFilter>,
+}
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+struct GetUtxosResponse {
+    utxos: Vec<Utxo>,
+    tip_block_hash: Vec<u8>,
+    tip_height: u32,
+    next_page: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Clone, Debug)]
+struct GetCurrentFeePercentilesRequest {
+    network: BitcoinNetwork,
+}
+
+#[derive(CandidType, Clone, Debug)]
+struct SendTransactionRequest {
+    transaction: Vec<u8>,
+    network: BitcoinNetwork,
+}
+
+#[derive(Deserialize, CandidType, Clone, Default)]
+pub struct BitcoinWallet {
+    /// bitcoin network the treasury lives on
+    pub(crate) network: BitcoinNetwork,
+    /// name of the threshold ecdsa key
+    pub(crate) key_name: String,
+    /// cached sec1 compressed public key
+    pub(crate) public_key: Option<Vec<u8>>,
+    /// outpoints spent by sent transactions which are not yet mined
+    pub(crate) pending_outpoints: HashSet<Outpoint>,
+}
+
+impl BitcoinWallet {
+    pub(crate) fn set_config(&mut self, network: BitcoinNetwork, key_name: String) {
+        if self.key_name != key_name {
+            self.public_key = None;
+        }
+        if self.network != network {
+            self.pending_outpoints.clear();
+        }
+        self.network = network;
+        self.key_name = key_name;
+    }
+
+    /// pick unspent outputs covering amount and fee, and mark them as pending
+    pub(crate) fn select_utxos(&mut self, utxos: Vec<Utxo>, amount: u64, fee_per_byte: u64) -> Result<(Vec<Utxo>, u64), &'static str> {
+        let unspent: HashSet<Outpoint> = utxos.iter().map(|u| u.outpoint.clone()).collect();
+        self.pending_outpoints.retain(|o| unspent.contains(o));
+
+        let mut selected = vec![];
+        let mut total = 0u64;
+        let mut fee = 0u64;
+        for utxo in utxos.into_iter().filter(|u| !self.pending_outpoints.contains(&u.outpoint)) {
+            total += utxo.value;
+            selected.push(utxo);
+            fee = fee_per_byte * estimate_size(selected.len(), 2);
+            if total >= amount + fee {
+                break;
+            }
+        }
+        if total < amount + fee {
+            return Err("Insufficient bitcoin balance");
+        }
+        for utxo in selected.iter() {
+            self.pending_outpoints.insert(utxo.outpoint.clone());
+        }
+        Ok((selected, fee))
+    }
+
+    /// forget pending outpoints of a transaction that was not sent
+    pub(crate) fn release(&mut self, utxos: &[Utxo]) {
+        for utxo in utxos {
+            self.pending_outpoints.remove(&utxo.outpoint);
+        }
+    }
+}
+
+pub(crate) fn derivation_path() -> Vec<Vec<u8>> {
+    vec![b"bitcoin".to_vec()]
+}
+
+fn sha256(data: &[u8]) -> Vec<u8> {
+    Sha256::digest(data).to_vec()
+}
+
+fn double_sha256(data: &[u8]) -> Vec<u8> {
+    sha256(&sha256(data))
+}
+
+fn hash160(data: &[u8]) -> Vec<u8> {
+    Ripemd160::digest(&sha256(data)).to_vec()
+}
+
+fn version_byte(network: BitcoinNetwork) -> u8 {
+    match network {
+        BitcoinNetwork::Mainnet => { 0x00 }
+        BitcoinNetwork::Testnet | BitcoinNetwork::Regtest => { 0x6f }
+    }
+}
+
+/// P2PKH address of a sec1 compressed public key
+pub(crate) fn address(network: BitcoinNetwork, public_key: &[u8]) -> String {
+    let mut payload = vec![version_byte(network)];
+    payload.extend_from_slice(&hash160(public_key));
+    let checksum = double_sha256(&payload);
+    payload.extend_from_slice(&checksum[..4]);
+    bs58::encode(payload).into_string()
+}
+
+/// decode a P2PKH address of the network, return the public key hash
+pub(crate) fn decode_address(network: BitcoinNetwork, address: &str) -> Result<Vec<u8>, &'static str> {
+    let payload = bs58::decode(address).into_vec().map_err(|_| "Invalid bitcoin address")?;
+    if payload.len() != 25 {
+        return Err("Invalid bitcoin address");
+    }
+    if payload[0] != version_byte(network) {
+        return Err("Only P2PKH addresses of the configured network are supported");
+    }
+    if double_sha256(&payload[..21])[..4] != payload[21..] {
+        return Err("Invalid bitcoin address checksum");
+    }
+    Ok(payload[1..21].to_vec())
+}
+
+fn p2pkh_script(pubkey_hash: &[u8]) -> Vec<u8> {
+    let mut script = vec![0x76, 0xa9, 0x14];
+    script.extend_from_slice(pubkey_hash);
+    script.extend_from_slice(&[0x88, 0xac]);
+    script
+}
+
+fn varint(out: &mut Vec<u8>, n: u64) {
+    if n < 0xfd {
+        out.push(n as u8);
+    } else if n <= 0xffff {
+        out.push(0xfd);
+        out.extend_from_slice(&(n as u16).to_le_bytes());
+    } else if n <= 0xffff_ffff {
+        out.push(0xfe);
+        out.extend_from_slice(&(n as u32).to_le_bytes());
+    } else {
+        out.push(0xff);
+        out.extend_from_slice(&n.to_le_bytes());
+    }
+}
+
+/// upper bound of a P2PKH transaction size in bytes
+fn estimate_size(inputs: usize, outputs: usize) -> u64 {
+    (10 + 148 * inputs + 34 * outputs) as u64
+}
+
+fn serialize(inputs: &[Utxo], script_sigs: &[Vec<u8>], outputs: &[(u64, Vec<u8>)]) -> Vec<u8> {
+    let mut out = vec![];
+    out.extend_from_slice(&1u32.to_le_bytes());
+    varint(&mut out, inputs.len() as u64);
+    for (utxo, script) in inputs.iter().zip(script_sigs.iter()) {
+        out.extend_from_slice(&utxo.outpoint.txid);
+        out.extend_from_slice(&utxo.outpoint.vout.to_le_bytes());
+        varint(&mut out, script.len() as u64);
+        out.extend_from_slice(script);
+        out.extend_from_slice(&0xffff_ffffu32.to_le_bytes());
+    }
+    varint(&mut out, outputs.len() as u64);
+    for (value, script) in outputs {
+        out.extend_from_slice(&value.to_le_bytes());
+        varint(&mut out, script.len() as u64);
+        out.extend_from_slice(script);
+    }
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out
+}
+
+fn der_integer(out: &mut Vec<u8>, be: &[u8]) {
+    let start = be.iter().position(|b| *b != 0).unwrap_or(be.len() - 1);
+    let mut int = be[start..].to_vec();
+    if int[0] & 0x80 != 0 {
+        int.insert(0, 0);
+    }
+    out.push(0x02);
+    out.push(int.len() as u8);
+    out.extend_from_slice(&int);
+}
+
+/// DER encode a 64 bytes (r, s) signature
+fn der_signature(signature: &[u8]) -> Vec<u8> {
+    let mut body = vec![];
+    der_integer(&mut body, &signature[..32]);
+    der_integer(&mut body, &signature[32..]);
+    let mut out = vec![0x30, body.len() as u8];
+    out.extend_from_slice(&body);
+    out
+}
+
+pub(crate) async fn get_balance(network: BitcoinNetwork, address: String) -> Result<u64, &'static str> {
+    let request = GetBalanceRequest {
+        address,
+        network,
+        min_confirmations: None,
+    };
+    let result: CallResult<(u64, )> = call_with_payment(
+        Principal::management_canister(),
+        "bitcoin_get_balance",
+        (request, ),
+        GET_BALANCE_CYCLES,
+    ).await;
+    result.map(|res| res.0).map_err(|_| "Error in getting bitcoin balance")
+}
+
+pub(crate) async fn get_utxos(network: BitcoinNetwork, address: String) -> Result<Vec<Utxo>, &'static str> {
+    let mut utxos = vec![];
+    let mut filter = None;
+    loop {
+        let request = GetUtxosRequest {
+            address: address.clone(),
+            network,
+            filter,
+        };
+        let result: CallResult<(GetUtxosResponse, )> = call_with_payment(
+            Principal::management_canister(),
+            "bitcoin_get_utxos",
+            (request, ),
+            GET_UTXOS_CYCLES,
+        ).await;
+        let (response, ) = result.map_err(|_| "Error in getting bitcoin utxos")?;
+        utxos.extend(response.utxos);
+        match response.next_page {
+            Some(page) => { filter = Some(UtxoFilter::Page(page)); }
+            None => { return Ok(utxos); }
+        }
+    }
+}
+
+/// median fee rate of the network in satoshi per byte
+pub(crate) async fn fee_per_byte(network: BitcoinNetwork) -> Result<u64, &'static str> {
+    let request = GetCurrentFeePercentilesRequest { network };
+    let result: CallResult<(Vec<u64>, )> = call_with_payment(
+        Principal::management_canister(),
+        "bitcoin_get_current_fee_percentiles",
+        (request, ),
+        GET_FEE_PERCENTILES_CYCLES,
+    ).await;
+    let (percentiles, ) = result.map_err(|_| "Error in getting bitcoin fee percentiles")?;
+    let millisatoshi = percentiles.get(50).cloned().unwrap_or(DEFAULT_FEE_PER_BYTE);
+    Ok((millisatoshi / 1000).max(1))
+}
+
+/// sign the spending of the selected utxos and send it, return the txid in display order
+pub(crate) async fn send(
+    wallet: &BitcoinWallet,
+    public_key: &[u8],
+    inputs: &[Utxo],
+    destination: &[u8],
+    amount: u64,
+    fee: u64,
+) -> Result<Vec<u8>, &'static str> {
+    let own_script = p2pkh_script(&hash160(public_key));
+    let total: u64 = inputs.iter().map(|u| u.value).sum();
+    let mut outputs = vec![(amount, p2pkh_script(destination))];
+    let change = total - amount - fee;
+    if change > DUST_THRESHOLD {
+        outputs.push((change, own_script.clone()));
+    }
+
+    let mut script_sigs = vec![vec![]; inputs.len()];
+    for i in 0..inputs.len() {
+        let mut unsigned = vec![vec![]; inputs.len()];
+        unsigned[i] = own_script.clone();
+        let mut preimage = serialize(inputs, &unsigned, &outputs);
+        preimage.extend_from_slice(&(SIGHASH_ALL as u32).to_le_bytes());
+        let sighash = double_sha256(&preimage);
+
+        let signature = ecdsa::sign(&wallet.key_name, derivation_path(), sighash).await?;
+        if signature.len() != 64 {
+            return Err("Invalid signature");
+        }
+        let mut sig = der_signature(&signature);
+        sig.push(SIGHASH_ALL);
+
+        let mut script = vec![sig.len() as u8];
+        script.extend_from_slice(&sig);
+        script.push(public_key.len() as u8);
+        script.extend_from_slice(public_key);
+        script_sigs[i] = script;
+    }
+
+    let transaction = serialize(inputs, &script_sigs, &outputs);
+    let mut txid = double_sha256(&transaction);
+    txid.reverse();
+    let cycles = SEND_TRANSACTION_BASE_CYCLES + SEND_TRANSACTION_PER_BYTE_CYCLES * transaction.len() as u64;
+    let request = SendTransactionRequest {
+        transaction,
+        network: wallet.network,
+    };
+    let result: CallResult<()> = call_with_payment(
+        Principal::management_canister(),
+        "bitcoin_send_transaction",
+        (request, ),
+        cycles,
+    ).await;
+    result.map_err(|_| "Error in sending bitcoin transaction")?;
+    Ok(txid)
+}
@@ -2,7 +2,9 @@ use cap_sdk::{DetailsBuilder, IndefiniteEvent, IndefiniteEventBuilder};
 use cap_sdk::DetailValue::Slice;
 use ic_kit::candid::Nat;
 use ic_kit::Principal;
-use crate::VoteType;
+use governance_types::events;
+use crate::{ProposalState, VoteType};
+use crate::timelock::{Backend, Task};
 
 pub trait GovEvent {
     fn to_indefinite_event(&self) -> IndefiniteEvent;
@@ -13,10 +15,7 @@ pub struct ProposeEvent {
     id: u64,
     title: String,
     description: String,
-    target: Principal,
-    method: String,
-    arguments: Vec<u8>,
-    cycles: u64,
+    task: Task,
 }
 
 impl ProposeEvent {
@@ -25,40 +24,38 @@ impl ProposeEvent {
         id: u64,
         title: String,
         description: String,
-        target: Principal,
-        method: String,
-        arguments: Vec<u8>,
-        cycles: u64,
+        task: Task,
     ) -> Self {
         Self {
             caller,
             id,
             title,
             description,
-            target,
-            method,
-            arguments,
-            cycles,
+            task,
         }
     }
 }
 
 impl GovEvent for ProposeEvent {
     fn to_indefinite_event(&self) -> IndefiniteEvent {
+        let mut details = DetailsBuilder::new()
+            .insert(events::ID, self.id)
+            .insert("title", self.title.to_owned())
+            .insert("description", self.description.to_owned())
+            .insert("target", self.task.target)
+            .insert("method", self.task.method.to_owned())
+            .insert("arguments", Slice(self.task.arguments.to_owned()))
+            .insert("cycles", self.task.cycles);
+        if let Some(oracle) = &self.task.oracle {
+            details = details.insert("oracle", oracle.url.to_owned());
+        }
+        if let Backend::Calls(calls) = &self.task.backend {
+            details = details.insert("calls", calls.len() as u64);
+        }
         IndefiniteEventBuilder::new()
             .caller(self.caller)
-            .operation("propose".to_string())
-            .details(
-                DetailsBuilder::new()
-                    .insert("id", self.id)
-                    .insert("title", self.title.to_owned())
-                    .insert("description", self.description.to_owned())
-                    .insert("target", self.target)
-                    .insert("method", self.method.to_owned())
-                    .insert("arguments", Slice(self.arguments.to_owned()))
-                    .insert("cycles", self.cycles)
-                    .build()
-            )
+            .operation(events::PROPOSE)
+            .details(details.build())
             .build()
             .unwrap()
     }
@@ -84,11 +81,11 @@ impl GovEvent for  QueueEvent {
     fn to_indefinite_event(&self) -> IndefiniteEvent {
         IndefiniteEventBuilder::new()
             .caller(self.caller)
-            .operation("queue".to_string())
+            .operation(events::QUEUE)
             .details(
                 DetailsBuilder::new()
-                    .insert("proposalId", self.proposal_id)
-                    .insert("eta", self.eta)
+                    .insert(events::PROPOSAL_ID, self.proposal_id)
+                    .insert(events::ETA, self.eta)
                     .build()
             )
             .build()
@@ -114,10 +111,10 @@ impl GovEvent for CancelEvent {
     fn to_indefinite_event(&self) -> IndefiniteEvent {
         IndefiniteEventBuilder::new()
             .caller(self.caller)
-            .operation("cancel".to_string())
+            .operation(events::CANCEL)
             .details(
                 DetailsBuilder::new()
-                    .insert("proposalId", self.proposal_id)
+                    .insert(events::PROPOSAL_ID, self.proposal_id)
                     .build()
             )
             .build()
@@ -145,10 +142,10 @@ impl GovEvent for ExecuteEvent {
     fn to_indefinite_event(&self) -> IndefiniteEvent {
         IndefiniteEventBuilder::new()
             .caller(self.caller)
-            .operation("execute".to_string())
+            .operation(events::EXECUTE)
             .details(
                 DetailsBuilder::new()
-                    .insert("proposalId", self.proposal_id)
+                    .insert(events::PROPOSAL_ID, self.proposal_id)
                     .build()
             )
             .build()
@@ -161,36 +158,34 @@ pub struct VoteEvent {
     proposal_id: u64,
     votes: Nat,
     vote_type: VoteType,
+    option: Option<usize>,
 }
 
 impl VoteEvent {
-    pub(crate) fn new(caller: Principal, proposal_id: u64, votes: Nat, vote_type: VoteType) -> Self {
+    pub(crate) fn new(caller: Principal, proposal_id: u64, votes: Nat, vote_type: VoteType, option: Option<usize>) -> Self {
         Self {
             caller,
             proposal_id,
             votes,
-            vote_type
+            vote_type,
+            option,
         }
     }
 }
 
 impl GovEvent for VoteEvent {
     fn to_indefinite_event(&self) -> IndefiniteEvent {
-        let vote_type = match self.vote_type {
-            VoteType::Support => { "support" }
-            VoteType::Against => { "against" }
-            VoteType::Abstain => { "abstain" }
-        };
+        let mut details = DetailsBuilder::new()
+            .insert(events::PROPOSAL_ID, self.proposal_id)
+            .insert(events::VOTES, self.votes.clone())
+            .insert(events::VOTE_TYPE, self.vote_type.label().to_string());
+        if let Some(option) = self.option {
+            details = details.insert(events::OPTION, option as u64);
+        }
         IndefiniteEventBuilder::new()
             .caller(self.caller)
-            .operation("vote")
-            .details(
-                DetailsBuilder::new()
-                    .insert("proposalId", self.proposal_id)
-                    .insert("votes", self.votes.clone())
-                    .insert("voteType", vote_type.to_string())
-                    .build()
-            )
+            .operation(events::VOTE)
+            .details(details.build())
             .build()
             .unwrap()
     }
@@ -214,10 +209,10 @@ impl GovEvent for SetPendingAdminEvent {
     fn to_indefinite_event(&self) -> IndefiniteEvent {
         IndefiniteEventBuilder::new()
             .caller(self.caller)
-            .operation("setPendingAdmin".to_string())
+            .operation(events::SET_PENDING_ADMIN)
             .details(
                 DetailsBuilder::new()
-                    .insert("pendingAdmin", self.pending_admin)
+                    .insert(events::PENDING_ADMIN, self.pending_admin)
                     .build()
             )
             .build()
@@ -241,8 +236,133 @@ impl GovEvent for AcceptAdminEvent {
     fn to_indefinite_event(&self) -> IndefiniteEvent {
         IndefiniteEventBuilder::new()
             .caller(self.caller)
-            .operation("acceptAdmin".to_string())
+            .operation(events::ACCEPT_ADMIN)
+            .build()
+            .unwrap()
+    }
+}
+
+pub struct SatelliteTallyEvent {
+    caller: Principal,
+    proposal_id: u64,
+    satellite: Principal,
+}
+
+impl SatelliteTallyEvent {
+    pub(crate) fn new(caller: Principal, proposal_id: u64, satellite: Principal) -> Self {
+        Self {
+            caller,
+            proposal_id,
+            satellite
+        }
+    }
+}
+
+impl GovEvent for SatelliteTallyEvent {
+    fn to_indefinite_event(&self) -> IndefiniteEvent {
+        IndefiniteEventBuilder::new()
+            .caller(self.caller)
+            .operation(events::MERGE_SATELLITE_TALLY)
+            .details(
+                DetailsBuilder::new()
+                    .insert(events::PROPOSAL_ID, self.proposal_id)
+                    .insert(events::SATELLITE, self.satellite)
+                    .build()
+            )
             .build()
             .unwrap()
     }
-}
\ No newline at end of file
+}
+
+pub struct QuorumReachedEvent {
+    caller: Principal,
+    proposal_id: u64,
+    support_votes: Nat,
+}
+
+impl QuorumReachedEvent {
+    pub(crate) fn new(caller: Principal, proposal_id: u64, support_votes: Nat) -> Self {
+        Self {
+            caller,
+            proposal_id,
+            support_votes
+        }
+    }
+}
+
+impl GovEvent for QuorumReachedEvent {
+    fn to_indefinite_event(&self) -> IndefiniteEvent {
+        IndefiniteEventBuilder::new()
+            .caller(self.caller)
+            .operation(events::QUORUM_REACHED)
+            .details(
+                DetailsBuilder::new()
+                    .insert(events::PROPOSAL_ID, self.proposal_id)
+                    .insert(events::SUPPORT_VOTES, self.support_votes.clone())
+                    .build()
+            )
+            .build()
+            .unwrap()
+    }
+}
+
+pub struct ExpireEvent {
+    caller: Principal,
+    proposal_id: u64,
+}
+
+impl ExpireEvent {
+    pub(crate) fn new(caller: Principal, proposal_id: u64) -> Self {
+        Self {
+            caller,
+            proposal_id
+        }
+    }
+}
+
+impl GovEvent for ExpireEvent {
+    fn to_indefinite_event(&self) -> IndefiniteEvent {
+        IndefiniteEventBuilder::new()
+            .caller(self.caller)
+            .operation(events::EXPIRE)
+            .details(
+                DetailsBuilder::new()
+                    .insert(events::PROPOSAL_ID, self.proposal_id)
+                    .build()
+            )
+            .build()
+            .unwrap()
+    }
+}
+
+pub struct TransitionEvent {
+    caller: Principal,
+    proposal_id: u64,
+    state: ProposalState,
+}
+
+impl TransitionEvent {
+    pub(crate) fn new(caller: Principal, proposal_id: u64, state: ProposalState) -> Self {
+        Self {
+            caller,
+            proposal_id,
+            state,
+        }
+    }
+}
+
+impl GovEvent for TransitionEvent {
+    fn to_indefinite_event(&self) -> IndefiniteEvent {
+        IndefiniteEventBuilder::new()
+            .caller(self.caller)
+            .operation(events::TRANSITION)
+            .details(
+                DetailsBuilder::new()
+                    .insert(events::PROPOSAL_ID, self.proposal_id)
+                    .insert(events::STATE, format!("{:?}", self.state))
+                    .build()
+            )
+            .build()
+            .unwrap()
+    }
+}
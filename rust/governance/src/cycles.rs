@@ -0,0 +1,175 @@
+/**
+ * Module     : cycles.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+use std::collections::HashMap;
+use ic_cdk::api::call::{call_with_payment, CallResult};
+use ic_cdk::call;
+use ic_kit::candid::{CandidType, Deserialize};
+use ic_kit::Principal;
+
+/// cycles the governor keeps for itself when topping up, 1T
+const MIN_RESERVE: u64 = 1_000_000_000_000;
+/// a target is not topped up again within 10 minutes
+const TOP_UP_COOLDOWN: u64 = 600 * 1_000_000_000;
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub struct TopUpTarget {
+    pub canister: Principal,
+    /// top up once the reported cycles fall below
+    pub threshold: u64,
+    /// cycles deposited per top up
+    pub amount: u64,
+    /// cycles deposited so far
+    pub total_topped_up: u64,
+    pub last_top_up: u64,
+}
+
+#[derive(Deserialize, CandidType, Clone, Default)]
+pub struct CyclesTreasury {
+    /// cycles received through wallet_receive
+    pub(crate) total_received: u128,
+    /// cycles attached to executed tasks and refunded by their targets
+    pub(crate) total_refunded: u128,
+    pub(crate) targets: HashMap<Principal, TopUpTarget>,
+    /// cycles deposited for task execution, by depositor
+    pub(crate) deposits: HashMap<Principal, u128>,
+    /// deposited cycles not yet attached to tasks, refunds flow back into it
+    pub(crate) pool: u64,
+    /// cycles of the pool attached to executed tasks
+    pub(crate) total_spent: u128,
+}
+
+#[derive(CandidType, Clone, Debug)]
+pub struct CyclesTreasuryInfo {
+    pub balance: u64,
+    pub total_received: u128,
+    pub total_refunded: u128,
+    pub targets: Vec<TopUpTarget>,
+    pub pool: u64,
+    pub total_deposited: u128,
+    pub total_spent: u128,
+}
+
+/// cycles reported by the `healthCheck` query of governance managed canisters
+#[derive(Deserialize, CandidType)]
+struct ReportedCycles {
+    cycles: u64,
+}
+
+#[derive(CandidType)]
+struct CanisterIdRecord {
+    canister_id: Principal,
+}
+
+impl CyclesTreasury {
+    pub(crate) fn receive(&mut self, amount: u64) {
+        self.total_received += amount as u128;
+    }
+
+    pub(crate) fn record_refund(&mut self, amount: u64) {
+        self.total_refunded += amount as u128;
+        self.pool += amount;
+    }
+
+    /// credit the depositor and add the cycles to the execution pool
+    pub(crate) fn deposit(&mut self, depositor: Principal, amount: u64) {
+        *self.deposits.entry(depositor).or_default() += amount as u128;
+        self.pool += amount;
+    }
+
+    pub(crate) fn deposit_of(&self, depositor: &Principal) -> u128 {
+        self.deposits.get(depositor).copied().unwrap_or(0)
+    }
+
+    /// take the cycles attached to a task out of the pool
+    pub(crate) fn spend(&mut self, amount: u64) -> Result<(), &'static str> {
+        if amount > self.pool {
+            return Err("cycles pool below the task cycles");
+        }
+        self.pool -= amount;
+        self.total_spent += amount as u128;
+        Ok(())
+    }
+
+    pub(crate) fn set_target(&mut self, canister: Principal, threshold: u64, amount: u64) {
+        let target = self.targets.entry(canister).or_insert(TopUpTarget {
+            canister,
+            threshold: 0,
+            amount: 0,
+            total_topped_up: 0,
+            last_top_up: 0,
+        });
+        target.threshold = threshold;
+        target.amount = amount;
+    }
+
+    pub(crate) fn remove_target(&mut self, canister: Principal) -> Result<(), &'static str> {
+        match self.targets.remove(&canister) {
+            Some(_) => { Ok(()) }
+            None => { Err("top up target not found") }
+        }
+    }
+
+    pub(crate) fn info(&self, balance: u64) -> CyclesTreasuryInfo {
+        CyclesTreasuryInfo {
+            balance,
+            total_received: self.total_received,
+            total_refunded: self.total_refunded,
+            targets: self.targets.values().cloned().collect(),
+            pool: self.pool,
+            total_deposited: self.deposits.values().sum(),
+            total_spent: self.total_spent,
+        }
+    }
+
+    /// targets not topped up recently
+    pub(crate) fn due_targets(&self, timestamp: u64) -> Vec<TopUpTarget> {
+        self.targets.values()
+            .filter(|t| t.amount > 0 && timestamp >= t.last_top_up + TOP_UP_COOLDOWN)
+            .cloned()
+            .collect()
+    }
+
+    /// reserve a top up of the target, false if the governor would fall below its reserve
+    pub(crate) fn reserve_top_up(&mut self, canister: Principal, balance: u64, timestamp: u64) -> bool {
+        match self.targets.get_mut(&canister) {
+            Some(target) if balance >= MIN_RESERVE + target.amount => {
+                target.last_top_up = timestamp;
+                true
+            }
+            _ => { false }
+        }
+    }
+
+    pub(crate) fn record_top_up(&mut self, canister: Principal, amount: u64, success: bool) {
+        if let Some(target) = self.targets.get_mut(&canister) {
+            if success {
+                target.total_topped_up += amount;
+            } else {
+                // let the next sweep retry
+                target.last_top_up = 0;
+            }
+        }
+    }
+}
+
+/// cycles the canister reports through its `healthCheck` query
+pub(crate) async fn reported_cycles(canister: Principal) -> Result<u64, &'static str> {
+    let result: CallResult<(ReportedCycles, )> = call(canister, "healthCheck", ()).await;
+    result.map(|(r, )| r.cycles).map_err(|_| "Error in getting reported cycles")
+}
+
+pub(crate) async fn deposit(canister: Principal, amount: u64) -> Result<(), &'static str> {
+    let result: CallResult<()> = call_with_payment(
+        Principal::management_canister(),
+        "deposit_cycles",
+        (CanisterIdRecord { canister_id: canister }, ),
+        amount,
+    ).await;
+    result.map_err(|_| "Error in depositing cycles")
+}
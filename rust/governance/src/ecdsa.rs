@@ -0,0 +1,95 @@
+/**
+ * Module     : ecdsa.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+use ic_cdk::api::call::{call, call_with_payment, CallResult};
+use ic_kit::candid::{CandidType, Deserialize};
+use ic_kit::Principal;
+
+/// cycles attached to a sign_with_ecdsa call
+pub const SIGN_WITH_ECDSA_CYCLES: u64 = 26_153_846_153;
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+enum EcdsaCurve {
+    #[serde(rename = "secp256k1")]
+    Secp256k1,
+}
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+struct EcdsaKeyId {
+    curve: EcdsaCurve,
+    name: String,
+}
+
+#[derive(CandidType, Clone, Debug)]
+struct EcdsaPublicKeyArgument {
+    canister_id: Option<Principal>,
+    derivation_path: Vec<Vec<u8>>,
+    key_id: EcdsaKeyId,
+}
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+struct EcdsaPublicKeyResponse {
+    public_key: Vec<u8>,
+    chain_code: Vec<u8>,
+}
+
+#[derive(CandidType, Clone, Debug)]
+struct SignWithEcdsaArgument {
+    message_hash: Vec<u8>,
+    derivation_path: Vec<Vec<u8>>,
+    key_id: EcdsaKeyId,
+}
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+struct SignWithEcdsaResponse {
+    signature: Vec<u8>,
+}
+
+fn key_id(key_name: &str) -> EcdsaKeyId {
+    EcdsaKeyId {
+        curve: EcdsaCurve::Secp256k1,
+        name: key_name.to_string(),
+    }
+}
+
+/// get the sec1 compressed public key of this canister for the derivation path
+pub(crate) async fn public_key(key_name: &str, derivation_path: Vec<Vec<u8>>) -> Result<Vec<u8>, &'static str> {
+    let request = EcdsaPublicKeyArgument {
+        canister_id: None,
+        derivation_path,
+        key_id: key_id(key_name),
+    };
+    let result: CallResult<(EcdsaPublicKeyResponse, )> = call(
+        Principal::management_canister(),
+        "ecdsa_public_key",
+        (request, ),
+    ).await;
+    match result {
+        Ok((res, )) => { Ok(res.public_key) }
+        Err(_) => { Err("Error in getting ecdsa public key") }
+    }
+}
+
+/// sign a 32 bytes message hash, return the 64 bytes (r, s) signature
+pub(crate) async fn sign(key_name: &str, derivation_path: Vec<Vec<u8>>, message_hash: Vec<u8>) -> Result<Vec<u8>, &'static str> {
+    let request = SignWithEcdsaArgument {
+        message_hash,
+        derivation_path,
+        key_id: key_id(key_name),
+    };
+    let result: CallResult<(SignWithEcdsaResponse, )> = call_with_payment(
+        Principal::management_canister(),
+        "sign_with_ecdsa",
+        (request, ),
+        SIGN_WITH_ECDSA_CYCLES,
+    ).await;
+    match result {
+        Ok((res, )) => { Ok(res.signature) }
+        Err(_) => { Err("Error in signing with ecdsa") }
+    }
+}
@@ -0,0 +1,279 @@
+/**
+ * Module     : ethereum.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+use std::collections::HashMap;
+use ic_kit::candid::{CandidType, Deserialize};
+use k256::ecdsa::{recoverable, Signature, VerifyingKey};
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use k256::{FieldBytes, PublicKey};
+use sha3::{Digest, Keccak256};
+use crate::ecdsa;
+use crate::oracle::{self, HttpHeader, HttpMethod, HttpOutcallResponse, TransformArgs};
+use crate::timelock::EthereumTx;
+
+/// EIP-2718 type of EIP-1559 transactions
+const EIP1559_TX_TYPE: u8 = 0x02;
+
+/// transform query normalizing eth_sendRawTransaction responses
+pub const ETHEREUM_TRANSFORM: &str = "ethereumTransform";
+
+/// prefix of a normalized response carrying a json-rpc error code
+const RPC_ERROR_PREFIX: &str = "error:";
+
+#[derive(Deserialize, CandidType, Clone, Default)]
+pub struct EthereumSigner {
+    /// name of the threshold ecdsa key
+    pub(crate) key_name: String,
+    /// json-rpc endpoint used to broadcast signed transactions
+    pub(crate) rpc_url: String,
+    /// cycles attached to the rpc http outcall
+    pub(crate) rpc_cycles: u64,
+    /// next nonce for each chain id
+    pub(crate) nonces: HashMap<u64, u64>,
+    /// cached sec1 compressed public key
+    pub(crate) public_key: Option<Vec<u8>>,
+}
+
+impl EthereumSigner {
+    pub(crate) fn set_config(&mut self, key_name: String, rpc_url: String, rpc_cycles: u64) {
+        if self.key_name != key_name {
+            self.public_key = None;
+        }
+        self.key_name = key_name;
+        self.rpc_url = rpc_url;
+        self.rpc_cycles = rpc_cycles;
+    }
+
+    pub(crate) fn set_nonce(&mut self, chain_id: u64, nonce: u64) {
+        self.nonces.insert(chain_id, nonce);
+    }
+
+    /// reserve the next nonce of the chain
+    pub(crate) fn take_nonce(&mut self, chain_id: u64) -> u64 {
+        let nonce = self.nonces.entry(chain_id).or_insert(0);
+        *nonce += 1;
+        *nonce - 1
+    }
+
+    /// give back a reserved nonce if no later nonce has been taken
+    pub(crate) fn release_nonce(&mut self, chain_id: u64, nonce: u64) {
+        if let Some(next) = self.nonces.get_mut(&chain_id) {
+            if *next == nonce + 1 {
+                *next = nonce;
+            }
+        }
+    }
+}
+
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(2 + bytes.len() * 2);
+    s.push_str("0x");
+    for b in bytes {
+        s.push_str(&format!("{:02x}", b));
+    }
+    s
+}
+
+/// ethereum address of a sec1 encoded public key
+pub(crate) fn address(public_key: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let key = PublicKey::from_sec1_bytes(public_key).map_err(|_| "Invalid public key")?;
+    let point = key.to_encoded_point(false);
+    Ok(keccak256(&point.as_bytes()[1..])[12..].to_vec())
+}
+
+fn rlp_length(out: &mut Vec<u8>, len: usize, offset: u8) {
+    if len < 56 {
+        out.push(offset + len as u8);
+    } else {
+        let be = trim_leading_zeros(&(len as u64).to_be_bytes()).to_vec();
+        out.push(offset + 55 + be.len() as u8);
+        out.extend_from_slice(&be);
+    }
+}
+
+fn rlp_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        out.push(bytes[0]);
+    } else {
+        rlp_length(out, bytes.len(), 0x80);
+        out.extend_from_slice(bytes);
+    }
+}
+
+fn trim_leading_zeros(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+/// rlp encode a big-endian unsigned integer
+fn rlp_uint(out: &mut Vec<u8>, be: &[u8]) {
+    rlp_bytes(out, trim_leading_zeros(be));
+}
+
+fn rlp_list(payload: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    rlp_length(&mut out, payload.len(), 0xc0);
+    out.extend_from_slice(payload);
+    out
+}
+
+/// rlp payload of the EIP-1559 transaction fields, without signature
+fn tx_fields(tx: &EthereumTx, nonce: u64) -> Vec<u8> {
+    let mut out = vec![];
+    rlp_uint(&mut out, &tx.chain_id.to_be_bytes());
+    rlp_uint(&mut out, &nonce.to_be_bytes());
+    rlp_uint(&mut out, &tx.max_priority_fee_per_gas.to_be_bytes());
+    rlp_uint(&mut out, &tx.max_fee_per_gas.to_be_bytes());
+    rlp_uint(&mut out, &tx.gas_limit.to_be_bytes());
+    rlp_bytes(&mut out, &tx.to);
+    rlp_uint(&mut out, &tx.value.0.to_bytes_be());
+    rlp_bytes(&mut out, &tx.data);
+    // empty access list
+    out.extend_from_slice(&rlp_list(&[]));
+    out
+}
+
+fn typed(payload: &[u8]) -> Vec<u8> {
+    let mut out = vec![EIP1559_TX_TYPE];
+    out.extend_from_slice(&rlp_list(payload));
+    out
+}
+
+/// find the y parity making the signature recover to the public key
+fn y_parity(hash: &[u8; 32], signature: &[u8], public_key: &[u8]) -> Result<u8, &'static str> {
+    let expected = VerifyingKey::from_sec1_bytes(public_key).map_err(|_| "Invalid public key")?;
+    let signature = Signature::try_from(signature).map_err(|_| "Invalid signature")?;
+    for parity in 0..2u8 {
+        let id = recoverable::Id::new(parity).map_err(|_| "Invalid recovery id")?;
+        let signature = recoverable::Signature::new(&signature, id).map_err(|_| "Invalid signature")?;
+        if let Ok(key) = signature.recover_verifying_key_from_digest_bytes(FieldBytes::from_slice(hash)) {
+            if key == expected {
+                return Ok(parity);
+            }
+        }
+    }
+    Err("Signature does not match public key")
+}
+
+/// sign the transaction, return the raw signed transaction
+pub(crate) async fn sign_transaction(
+    signer: &EthereumSigner,
+    public_key: &[u8],
+    tx: &EthereumTx,
+    nonce: u64,
+) -> Result<Vec<u8>, &'static str> {
+    let fields = tx_fields(tx, nonce);
+    let hash = keccak256(&typed(&fields));
+    let signature = ecdsa::sign(&signer.key_name, vec![], hash.to_vec()).await?;
+    if signature.len() != 64 {
+        return Err("Invalid signature");
+    }
+    let parity = y_parity(&hash, &signature, public_key)?;
+
+    let mut signed = fields;
+    rlp_uint(&mut signed, &[parity]);
+    rlp_uint(&mut signed, &signature[..32]);
+    rlp_uint(&mut signed, &signature[32..]);
+    Ok(typed(&signed))
+}
+
+#[derive(Deserialize)]
+struct RpcError {
+    code: i64,
+}
+
+#[derive(Deserialize)]
+struct RpcResponse {
+    result: Option<String>,
+    error: Option<RpcError>,
+}
+
+/// why a broadcast failed, the nonce may only be reused if the transaction was surely not accepted
+#[derive(Debug, PartialEq)]
+pub(crate) enum BroadcastError {
+    /// the rpc answered with this json-rpc error code
+    Rejected(i64),
+    /// the outcall failed or its response could not be read, the transaction may have been accepted
+    Ambiguous(&'static str),
+}
+
+impl BroadcastError {
+    pub(crate) fn message(&self) -> &'static str {
+        match self {
+            BroadcastError::Rejected(_) => { "Ethereum rpc rejected the transaction" }
+            BroadcastError::Ambiguous(msg) => { msg }
+        }
+    }
+}
+
+/// reduce a json-rpc response body to the transaction hash or the error code,
+/// so replicas agree on it whatever else the node sent
+pub(crate) fn normalize_response(body: &[u8]) -> Vec<u8> {
+    match serde_json::from_slice::<RpcResponse>(body) {
+        Ok(RpcResponse { result: Some(hash), error: None }) => { hash.into_bytes() }
+        Ok(RpcResponse { result: None, error: Some(error) }) => {
+            format!("{}{}", RPC_ERROR_PREFIX, error.code).into_bytes()
+        }
+        _ => { vec![] }
+    }
+}
+
+/// transform of broadcast outcalls, see `normalize_response`
+pub(crate) fn transform(args: TransformArgs) -> HttpOutcallResponse {
+    HttpOutcallResponse {
+        status: args.response.status,
+        headers: vec![],
+        body: normalize_response(&args.response.body),
+    }
+}
+
+/// transaction hash of a normalized response
+pub(crate) fn parse_normalized(body: &[u8]) -> Result<Vec<u8>, BroadcastError> {
+    let text = std::str::from_utf8(body).map_err(|_| BroadcastError::Ambiguous("Invalid ethereum rpc response"))?;
+    if let Some(code) = text.strip_prefix(RPC_ERROR_PREFIX) {
+        let code = code.parse().map_err(|_| BroadcastError::Ambiguous("Invalid ethereum rpc response"))?;
+        return Err(BroadcastError::Rejected(code));
+    }
+    from_hex(text).filter(|hash| hash.len() == 32)
+        .ok_or(BroadcastError::Ambiguous("Invalid ethereum rpc response"))
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    let digits = text.strip_prefix("0x")?;
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+    (0..digits.len()).step_by(2)
+        .map(|i| u8::from_str_radix(digits.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// broadcast the raw transaction with eth_sendRawTransaction, return the transaction hash
+pub(crate) async fn broadcast(signer: &EthereumSigner, raw: &[u8]) -> Result<Vec<u8>, BroadcastError> {
+    let body = format!(
+        "{{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"eth_sendRawTransaction\",\"params\":[\"{}\"]}}",
+        to_hex(raw)
+    );
+    let response = oracle::http_outcall(
+        signer.rpc_url.clone(),
+        HttpMethod::Post,
+        vec![HttpHeader {
+            name: "Content-Type".to_string(),
+            value: "application/json".to_string(),
+        }],
+        Some(body.into_bytes()),
+        None,
+        ETHEREUM_TRANSFORM.to_string(),
+        signer.rpc_cycles,
+    ).await.map_err(BroadcastError::Ambiguous)?;
+    parse_normalized(&response.body)
+}
@@ -0,0 +1,222 @@
+/**
+ * Module     : export.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+use ic_kit::candid::{decode_args, encode_args, CandidType, Deserialize};
+use ic_kit::Principal;
+use crate::governance::{GovernorBravo, ProposalInfo, ProposalState, ReceiptInfo};
+use crate::metrics::state_label;
+use crate::stable::crc32;
+
+#[derive(Deserialize, CandidType, Clone, Copy, PartialEq, Debug)]
+pub enum ExportFormat {
+    /// comma separated values, header row on the first chunk only
+    Csv,
+    /// a json array of objects per chunk
+    Json,
+}
+
+#[derive(CandidType, Clone, Debug)]
+pub struct ExportChunk {
+    /// encoded rows
+    pub data: String,
+    /// offset of the next chunk, none once everything is exported
+    pub next: Option<usize>,
+}
+
+/// chunk of a full backup of the governor
+#[derive(CandidType, Clone, Debug)]
+pub struct BackupChunk {
+    pub data: Vec<u8>,
+    /// number of chunks of the backup
+    pub chunks: usize,
+    /// CRC-32 of the whole backup, checked when it is restored
+    pub checksum: u32,
+}
+
+enum Value {
+    Text(String),
+    Number(String),
+    Bool(bool),
+    Null,
+}
+
+type Row = Vec<(&'static str, Value)>;
+
+const PROPOSAL_COLUMNS: [&str; 16] = [
+    "id", "proposer", "title", "description", "state", "start_time", "end_time", "support_votes",
+    "against_votes", "abstain_votes", "canceled", "executing", "executed", "target", "method", "eta",
+];
+
+const RECEIPT_COLUMNS: [&str; 4] = ["voter", "vote_type", "votes", "reason"];
+
+fn proposal_row(p: &ProposalInfo, state: &ProposalState) -> Row {
+    vec![
+        ("id", Value::Number(p.id.to_string())),
+        ("proposer", Value::Text(p.proposer.to_text())),
+        ("title", Value::Text(p.title.clone())),
+        ("description", Value::Text(p.description.clone())),
+        ("state", Value::Text(state_label(state).to_string())),
+        ("start_time", Value::Number(p.start_time.to_string())),
+        ("end_time", Value::Number(p.end_time.to_string())),
+        ("support_votes", Value::Number(p.support_votes.0.to_string())),
+        ("against_votes", Value::Number(p.against_votes.0.to_string())),
+        ("abstain_votes", Value::Number(p.abstain_votes.0.to_string())),
+        ("canceled", Value::Bool(p.canceled)),
+        ("executing", Value::Bool(p.executing)),
+        ("executed", Value::Bool(p.executed)),
+        ("target", Value::Text(p.task.target.to_text())),
+        ("method", Value::Text(p.task.method.clone())),
+        ("eta", Value::Number(p.task.eta.to_string())),
+    ]
+}
+
+fn receipt_row(voter: &Principal, r: &ReceiptInfo) -> Row {
+    vec![
+        ("voter", Value::Text(voter.to_text())),
+        ("vote_type", Value::Text(r.vote_type.label().to_string())),
+        ("votes", Value::Number(r.votes.0.to_string())),
+        ("reason", match &r.reason {
+            Some(reason) => { Value::Text(reason.clone()) }
+            None => { Value::Null }
+        }),
+    ]
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains(|c: char| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => { out.push_str("\\\"") }
+            '\\' => { out.push_str("\\\\") }
+            '\n' => { out.push_str("\\n") }
+            '\r' => { out.push_str("\\r") }
+            '\t' => { out.push_str("\\t") }
+            c if (c as u32) < 0x20 => { out.push_str(&format!("\\u{:04x}", c as u32)) }
+            c => { out.push(c) }
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn encode(format: ExportFormat, columns: &[&str], rows: Vec<Row>, header: bool) -> String {
+    match format {
+        ExportFormat::Csv => {
+            let mut out = String::new();
+            if header {
+                out.push_str(&columns.join(","));
+                out.push('\n');
+            }
+            for row in rows {
+                let fields = row.into_iter().map(|(_, value)| {
+                    match value {
+                        Value::Text(s) => { csv_field(&s) }
+                        Value::Number(n) => { n }
+                        Value::Bool(b) => { b.to_string() }
+                        Value::Null => { String::new() }
+                    }
+                }).collect::<Vec<String>>();
+                out.push_str(&fields.join(","));
+                out.push('\n');
+            }
+            out
+        }
+        ExportFormat::Json => {
+            let objects = rows.into_iter().map(|row| {
+                let fields = row.into_iter().map(|(name, value)| {
+                    let value = match value {
+                        // numbers may exceed 2^53, keep them as strings
+                        Value::Text(s) | Value::Number(s) => { json_string(&s) }
+                        Value::Bool(b) => { b.to_string() }
+                        Value::Null => { "null".to_string() }
+                    };
+                    format!("\"{}\":{}", name, value)
+                }).collect::<Vec<String>>();
+                format!("{{{}}}", fields.join(","))
+            }).collect::<Vec<String>>();
+            format!("[{}]", objects.join(","))
+        }
+    }
+}
+
+fn next_offset(offset: usize, rows: usize, total: usize) -> Option<usize> {
+    if rows > 0 && offset + rows < total {
+        Some(offset + rows)
+    } else {
+        None
+    }
+}
+
+/// export a chunk of proposals, starting from proposal id `offset`
+pub(crate) fn export_proposals(
+    bravo: &GovernorBravo,
+    format: ExportFormat,
+    offset: usize,
+    limit: usize,
+    timestamp: u64,
+) -> Result<ExportChunk, &'static str> {
+    let proposals = bravo.export_proposals(offset, limit, timestamp)?;
+    let next = next_offset(offset, proposals.len(), bravo.proposal_count());
+    let rows = proposals.iter().map(|(p, state)| proposal_row(p, state)).collect();
+    Ok(ExportChunk {
+        data: encode(format, &PROPOSAL_COLUMNS, rows, offset == 0),
+        next,
+    })
+}
+
+/// export a chunk of the receipts of a proposal, ordered by voter
+pub(crate) fn export_receipts(
+    bravo: &GovernorBravo,
+    id: usize,
+    format: ExportFormat,
+    offset: usize,
+    limit: usize,
+) -> Result<ExportChunk, &'static str> {
+    let (receipts, total) = bravo.export_receipts(id, offset, limit)?;
+    let next = next_offset(offset, receipts.len(), total);
+    let rows = receipts.iter().map(|(voter, r)| receipt_row(voter, r)).collect();
+    Ok(ExportChunk {
+        data: encode(format, &RECEIPT_COLUMNS, rows, offset == 0),
+        next,
+    })
+}
+
+/// chunk of the candid encoding of the governor and its stable memory records
+pub(crate) fn backup_chunk(bravo: &GovernorBravo, chunk: usize) -> Result<BackupChunk, &'static str> {
+    let records = bravo.stable_records()?;
+    let backup = encode_args((bravo, records)).map_err(|_| "backup encoding error")?;
+    let size = GovernorBravo::MAX_EXPORT_CHUNK;
+    let chunks = (backup.len() + size - 1) / size;
+    if chunk >= chunks {
+        return Err("invalid backup chunk");
+    }
+    let start = chunk * size;
+    let end = (start + size).min(backup.len());
+    Ok(BackupChunk {
+        data: backup[start..end].to_vec(),
+        chunks,
+        checksum: crc32(&backup),
+    })
+}
+
+/// the governor and its stable memory records from the chunks of a backup put back together
+pub(crate) fn decode_backup(backup: &[u8], checksum: u32) -> Result<(GovernorBravo, Vec<u8>), &'static str> {
+    if crc32(backup) != checksum {
+        return Err("backup checksum mismatch");
+    }
+    decode_args(backup).map_err(|_| "backup decoding error")
+}
@@ -0,0 +1,139 @@
+/**
+ * Module     : guardian.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+use std::collections::{BTreeSet, HashMap};
+use ic_kit::candid::{CandidType, Deserialize};
+use ic_kit::Principal;
+
+/// pending actions expire after 1 day
+const ACTION_TTL: u64 = 24 * 3600 * 1_000_000_000;
+
+#[derive(Deserialize, CandidType, Clone, PartialEq, Debug)]
+pub enum GuardianAction {
+    /// cancel the proposal
+    Veto(usize),
+    /// stop proposing, voting, queueing and executing
+    Pause,
+    Unpause,
+}
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub struct PendingAction {
+    pub id: usize,
+    pub action: GuardianAction,
+    pub confirmations: BTreeSet<Principal>,
+    pub expires_at: u64,
+}
+
+/// k-of-n guardian set, actions run once confirmed by threshold members
+#[derive(Deserialize, CandidType, Clone, Default)]
+pub struct Guardian {
+    pub(crate) members: BTreeSet<Principal>,
+    pub(crate) threshold: usize,
+    pub(crate) paused: bool,
+    pub(crate) next_id: usize,
+    pub(crate) pending: HashMap<usize, PendingAction>,
+}
+
+#[derive(CandidType, Clone, Debug)]
+pub struct GuardianInfo {
+    pub members: Vec<Principal>,
+    pub threshold: usize,
+    pub paused: bool,
+    pub pending: Vec<PendingAction>,
+}
+
+impl Guardian {
+    /// replace the members, pending actions are dropped
+    pub(crate) fn set_members(&mut self, members: Vec<Principal>, threshold: usize) -> Result<(), &'static str> {
+        let members: BTreeSet<Principal> = members.into_iter().collect();
+        if threshold == 0 || threshold > members.len() {
+            return Err("threshold must be between 1 and the number of guardians");
+        }
+        self.members = members;
+        self.threshold = threshold;
+        self.pending.clear();
+        Ok(())
+    }
+
+    fn check_member(&self, caller: &Principal) -> Result<(), &'static str> {
+        if !self.members.contains(caller) {
+            return Err("caller is not a guardian");
+        }
+        Ok(())
+    }
+
+    fn prune(&mut self, timestamp: u64) {
+        self.pending.retain(|_, p| p.expires_at > timestamp);
+    }
+
+    /// open an action confirmed by the caller, return its id
+    pub(crate) fn propose(&mut self, caller: Principal, action: GuardianAction, timestamp: u64) -> Result<usize, &'static str> {
+        self.check_member(&caller)?;
+        self.prune(timestamp);
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.insert(id, PendingAction {
+            id,
+            action,
+            confirmations: BTreeSet::new(),
+            expires_at: timestamp + ACTION_TTL,
+        });
+        Ok(id)
+    }
+
+    /// add the confirmation of the caller, return the action once the threshold is reached
+    pub(crate) fn confirm(&mut self, caller: Principal, id: usize, timestamp: u64) -> Result<Option<GuardianAction>, &'static str> {
+        self.check_member(&caller)?;
+        self.prune(timestamp);
+        let pending = self.pending.get_mut(&id).ok_or("pending action not found or expired")?;
+        pending.confirmations.insert(caller);
+        if pending.confirmations.len() < self.threshold {
+            return Ok(None);
+        }
+        let action = self.pending.remove(&id).map(|p| p.action);
+        if let Some(GuardianAction::Pause) = action {
+            self.paused = true;
+        } else if let Some(GuardianAction::Unpause) = action {
+            self.paused = false;
+        }
+        Ok(action)
+    }
+
+    /// withdraw the confirmation of the caller
+    pub(crate) fn revoke(&mut self, caller: Principal, id: usize, timestamp: u64) -> Result<(), &'static str> {
+        self.check_member(&caller)?;
+        self.prune(timestamp);
+        let pending = self.pending.get_mut(&id).ok_or("pending action not found or expired")?;
+        if !pending.confirmations.remove(&caller) {
+            return Err("action not confirmed by caller");
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_not_paused(&self) -> Result<(), &'static str> {
+        if self.paused {
+            return Err("governor is paused by the guardians");
+        }
+        Ok(())
+    }
+
+    pub(crate) fn info(&self, timestamp: u64) -> GuardianInfo {
+        let mut pending: Vec<PendingAction> = self.pending.values()
+            .filter(|p| p.expires_at > timestamp)
+            .cloned()
+            .collect();
+        pending.sort_by_key(|p| p.id);
+        GuardianInfo {
+            members: self.members.iter().cloned().collect(),
+            threshold: self.threshold,
+            paused: self.paused,
+            pending,
+        }
+    }
+}
@@ -0,0 +1,67 @@
+/**
+ * Module     : identity.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+use ic_cdk::api::call::CallResult;
+use ic_cdk::call;
+use ic_kit::candid::{CandidType, Deserialize, Nat};
+use ic_kit::Principal;
+
+#[derive(Deserialize, CandidType, Clone, Copy, PartialEq, Debug)]
+pub enum IdentityMode {
+    /// verified voters vote with their token votes
+    Hybrid,
+    /// verified voters get one vote each, quorum counts persons
+    PersonWeighted,
+}
+
+/// identity canister, e.g. a proof-of-humanity registry, checked on every vote
+#[derive(Deserialize, CandidType, Clone)]
+pub struct IdentityVerifier {
+    /// none disables the check
+    pub canister: Option<Principal>,
+    /// `(principal) -> (bool)` query of the identity canister
+    pub method: String,
+    pub mode: IdentityMode,
+}
+
+impl Default for IdentityVerifier {
+    fn default() -> Self {
+        Self {
+            canister: None,
+            method: "isVerified".to_string(),
+            mode: IdentityMode::Hybrid,
+        }
+    }
+}
+
+impl IdentityVerifier {
+    pub(crate) fn set(&mut self, canister: Option<Principal>, method: String, mode: IdentityMode) {
+        self.canister = canister;
+        self.method = method;
+        self.mode = mode;
+    }
+
+    /// votes counted for the voter holding the token votes, error if not verified
+    pub(crate) async fn votes(&self, voter: Principal, token_votes: Nat) -> Result<Nat, &'static str> {
+        let canister = match self.canister {
+            Some(c) => { c }
+            None => { return Ok(token_votes); }
+        };
+        let result: CallResult<(bool, )> = call(canister, &self.method, (voter, )).await;
+        match result {
+            Ok((true, )) => {
+                match self.mode {
+                    IdentityMode::Hybrid => { Ok(token_votes) }
+                    IdentityMode::PersonWeighted => { Ok(Nat::from(1)) }
+                }
+            }
+            Ok((false, )) => { Err("voter is not verified by the identity canister") }
+            Err(_) => { Err("Error in verifying voter identity") }
+        }
+    }
+}
@@ -0,0 +1,63 @@
+/**
+ * Module     : inspect.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+use std::collections::HashMap;
+use ic_kit::candid::Nat;
+use ic_kit::Principal;
+use crate::governance::{GovernorBravo, ProposalState};
+
+/// how long votes fetched from the gov token are trusted when filtering ingress, 10 minutes
+const VOTES_CACHE_TTL: u64 = 600 * 1_000_000_000;
+
+/// whether principals had votes on a proposal when last fetched from the gov token, not persisted.
+/// votes differ between proposals, e.g. by their ramp, so they are cached by proposal
+#[derive(Default)]
+pub struct VotesCache {
+    entries: HashMap<(Principal, usize), (bool, u64)>,
+}
+
+impl VotesCache {
+    pub(crate) fn record(&mut self, who: Principal, id: usize, votes: &Nat, timestamp: u64) {
+        self.entries.insert((who, id), (*votes > Nat::from(0), timestamp));
+    }
+
+    /// the principal had no votes on the proposal at a recent fetch
+    pub(crate) fn known_without_votes(&self, who: &Principal, id: usize, timestamp: u64) -> bool {
+        match self.entries.get(&(*who, id)) {
+            Some((has_votes, fetched_at)) => {
+                !has_votes && timestamp < fetched_at + VOTES_CACHE_TTL
+            }
+            None => { false }
+        }
+    }
+}
+
+/// reject ingress messages which would obviously fail
+pub(crate) fn check_caller(caller: &Principal) -> Result<(), &'static str> {
+    if *caller == Principal::anonymous() {
+        return Err("anonymous caller");
+    }
+    Ok(())
+}
+
+/// reject votes on closed proposals and from principals known to hold no votes
+pub(crate) fn check_vote(
+    bravo: &GovernorBravo,
+    cache: &VotesCache,
+    caller: &Principal,
+    id: usize,
+    timestamp: u64,
+) -> Result<(), &'static str> {
+    if bravo.get_state(id, timestamp)? != ProposalState::Active {
+        return Err("voting is closed");
+    }
+    if cache.known_without_votes(caller, id, timestamp) {
+        return Err("caller has no votes");
+    }
+    Ok(())
+}
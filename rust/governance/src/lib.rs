@@ -6,32 +6,115 @@
  * Stability  : Experimental
  */
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use ic_cdk::call;
 use cap_sdk::{CapEnv, handshake, IndefiniteEventBuilder, insert};
-use cap_sdk::DetailValue::U64;
-use ic_cdk::api::call::CallResult;
-use ic_kit::candid::{export_service, candid_method, Nat};
+use cap_sdk::DetailValue::{Text, U64};
+use ic_cdk::api::call::{notify, CallResult};
+use ic_kit::candid::{decode_args, encode_args, encode_one, export_service, candid_method, CandidType, Nat, Reserved};
+use ic_kit::candid::utils::ArgumentEncoder;
 use ic_kit::{ic, Principal};
-use ic_kit::ic::{stable_restore, stable_store};
+use ic_kit::ic::stable_store;
 use ic_kit::macros::*;
-use crate::cap::{AcceptAdminEvent, CancelEvent, ExecuteEvent, GovEvent, ProposeEvent, QueueEvent, SetPendingAdminEvent, VoteEvent};
-use crate::governance::{GovernorBravo, GovernorBravoInfo, ProposalDigest, ProposalInfo, ProposalState, Receipt, ReceiptDigest, ReceiptInfo, VoteType};
-use crate::timelock::{Task};
+use governance_types::TxReceipt;
+use governance_core::{clock, governance, stable, timelock};
+use cap_sdk::DetailsBuilder;
+use crate::clock::{IcClock, TimeProvider};
+#[cfg(any(test, feature = "testing"))]
+use crate::clock::MockClock;
+use crate::cap::{AcceptAdminEvent, CancelEvent, ExecuteEvent, ExpireEvent, GovEvent, ProposeEvent, QueueEvent, QuorumReachedEvent, SatelliteTallyEvent, SetPendingAdminEvent, TransitionEvent, VoteEvent};
+use crate::governance::{CommentInfo, CountingMode, DepositStatus, GovernorBravo, GovernorBravoInfo, ProposalDigest, ProposalImport, ProposalInfo, ProposalSort, ProposalState, ProposeEligibility, QuorumMode, QuorumStatus, Receipt, ReceiptCounts, ReceiptDigest, ReceiptInfo, Tally, TieRule, Timeline, UpcomingExecution, Violation, VoteType, VotesBreakdown};
+use crate::audit::{AuditLog, AuditRecord};
+use crate::bitcoin::{BitcoinNetwork, BitcoinWallet};
+use crate::cycles::{CyclesTreasury, CyclesTreasuryInfo};
+use crate::ethereum::{BroadcastError, EthereumSigner};
+use crate::export::{BackupChunk, ExportChunk, ExportFormat};
+use crate::guardian::{Guardian, GuardianAction, GuardianInfo};
+use crate::identity::{IdentityMode, IdentityVerifier};
+use crate::inspect::VotesCache;
+use crate::metrics::{HealthStatus, HttpRequest, HttpResponse, VersionInfo};
+use crate::monitor::{CanisterLogRequest, CanisterLogResponse, CanisterMetrics, GetMetricsParameters, MethodMetrics, Monitor};
+use crate::notification::{Notifier, Subscriber};
+use crate::offchain::{OffchainTally, SignerCommittee, SignerCommitteeInfo};
+use crate::oracle::{HttpOutcallResponse, TransformArgs};
+use crate::proxy::Proxies;
+use crate::stable::Memory;
+use crate::relay::{BallotSignature, Relayers};
+use crate::scheduler::{Scheduler, SchedulerInfo};
+use crate::search::SearchIndex;
+use crate::streams::{Stream, Streams};
+use crate::timelock::{AnnouncedCall, AnnouncedChange, Asset, Backend, BitcoinTransfer, Call, EthereumTx, ExecutionWindow, GovernorParam, GrantStream, Oracle, ParamChange, Task, TreasurySpend};
+use crate::treasury::{RegisteredAsset, Treasury, TreasuryInfo};
+use crate::upgrade::{StableState, StateV1};
 
-mod timelock;
-mod governance;
-mod stable;
+mod audit;
+mod bitcoin;
 mod cap;
+mod cycles;
+mod ecdsa;
+mod ethereum;
+mod export;
+mod guardian;
+mod identity;
+mod inspect;
+mod metrics;
+mod monitor;
+mod notification;
+mod offchain;
+mod oracle;
+mod proxy;
+mod relay;
+mod scheduler;
+mod search;
+mod streams;
+mod treasury;
+mod upgrade;
 #[cfg(test)]
 mod test;
 
 thread_local! {
     static BRAVO : RefCell<GovernorBravo> = RefCell::new(GovernorBravo::default());
+    static ETHEREUM : RefCell<EthereumSigner> = RefCell::new(EthereumSigner::default());
+    static BITCOIN : RefCell<BitcoinWallet> = RefCell::new(BitcoinWallet::default());
+    static NOTIFIER : RefCell<Notifier> = RefCell::new(Notifier::default());
+    /// times of the upgrades, oldest first
+    static UPGRADES : RefCell<Vec<u64>> = RefCell::new(vec![]);
+    static MONITOR : RefCell<Monitor> = RefCell::new(Monitor::default());
+    static TREASURY : RefCell<Treasury> = RefCell::new(Treasury::default());
+    static CYCLES : RefCell<CyclesTreasury> = RefCell::new(CyclesTreasury::default());
+    static VOTES_CACHE : RefCell<VotesCache> = RefCell::new(VotesCache::default());
+    static STREAMS : RefCell<Streams> = RefCell::new(Streams::default());
+    static SEARCH : RefCell<SearchIndex> = RefCell::new(SearchIndex::default());
+    static IDENTITY : RefCell<IdentityVerifier> = RefCell::new(IdentityVerifier::default());
+    static RELAYERS : RefCell<Relayers> = RefCell::new(Relayers::default());
+    static GUARDIAN : RefCell<Guardian> = RefCell::new(Guardian::default());
+    static OFFCHAIN : RefCell<SignerCommittee> = RefCell::new(SignerCommittee::default());
+    static PROXIES : RefCell<Proxies> = RefCell::new(Proxies::default());
+    static AUDIT : RefCell<AuditLog> = RefCell::new(AuditLog::default());
+    static SCHEDULER : RefCell<Scheduler> = RefCell::new(Scheduler::default());
+    /// chunks of a backup staged until it is restored
+    static RESTORE_BUFFER : RefCell<Vec<u8>> = RefCell::new(vec![]);
+    /// earliest time the heartbeat sweeps the proposals again
+    static NEXT_SWEEP : Cell<u64> = Cell::new(0);
+    static CLOCK : RefCell<Box<dyn TimeProvider>> = RefCell::new(Box::new(IcClock));
 }
 
 type Response<R> = Result<R, &'static str>;
 
+/// interval between two heartbeat sweeps, 10 minutes
+const SWEEP_INTERVAL: u64 = 10 * 60 * 1_000_000_000;
+
+/// current time used by the governor
+fn now() -> u64 {
+    CLOCK.with(|clock| clock.borrow().now())
+}
+
+/// replace the clock of the governor, for tests and `testing` builds
+#[cfg(any(test, feature = "testing"))]
+fn set_clock<T: TimeProvider + 'static>(clock: T) {
+    CLOCK.with(|c| *c.borrow_mut() = Box::new(clock));
+}
+
 fn is_admin() -> Result<(), String> {
     BRAVO.with(|bravo| {
         let bravo = bravo.borrow();
@@ -43,18 +126,56 @@ fn is_admin() -> Result<(), String> {
     })
 }
 
+/// only the governor itself, i.e. an executed proposal
+fn is_self() -> Result<(), String> {
+    if ic::caller() == ic::id() {
+        Ok(())
+    } else {
+        Err("Only callable by proposals".to_string())
+    }
+}
+
+/// the admin, or the governor itself through an executed proposal
+fn is_admin_or_self() -> Result<(), String> {
+    is_self().or_else(|_| is_admin())
+}
+
+/// the admin, or a member of the guardian set
+fn is_admin_or_guardian() -> Result<(), String> {
+    is_admin().or_else(|_| {
+        if GUARDIAN.with(|guardian| guardian.borrow().members.contains(&ic::caller())) {
+            Ok(())
+        } else {
+            Err("Unauthorized".to_string())
+        }
+    })
+}
+
+/// record the guarded admin call with its raw arguments, called before any await
+fn audit(method: &str) {
+    #[cfg(not(test))]
+    let args = ic_cdk::api::call::arg_data_raw();
+    #[cfg(test)]
+    let args = vec![];
+    AUDIT.with(|audit| {
+        let mut audit = audit.borrow_mut();
+        audit.record(ic::caller(), method, args, ic::time());
+    });
+}
+
 #[init]
 #[candid_method(init)]
 fn initialize(
     admin: Principal,
     name: String,
-    quorum_votes: u64,
+    quorum_votes: Nat,
     voting_delay: u64,
     voting_period: u64,
-    proposal_threshold: u64,
+    proposal_threshold: Nat,
     timelock_delay: u64,
     gov_token: Principal,
     cap: Principal,
+    quorum_mode: Option<QuorumMode>,
 ) {
     // assert!(voting_delay >= GovernorBravo::MIN_VOTING_DELAY && voting_delay <= GovernorBravo::MAX_VOTING_DELAY);
     // assert!(voting_period >= GovernorBravo::MIN_VOTING_PERIOD && voting_period <= GovernorBravo::MAX_VOTING_PERIOD);
@@ -71,10 +192,170 @@ fn initialize(
             timelock_delay,
             gov_token,
         );
+        bravo.set_quorum_mode(quorum_mode.unwrap_or_default());
     });
     handshake(1_000_000_000_000, Some(cap));
 }
 
+/// push proposals which changed state to the notifier and the subscribers,
+/// and remind the subscribers of close deadlines
+fn notify_state_changes() {
+    let (changes, reminders) = NOTIFIER.with(|n| {
+        BRAVO.with(|b| {
+            let mut notifier = n.borrow_mut();
+            let bravo = b.borrow();
+            let changes = notifier.collect(&bravo, now());
+            (changes, notifier.collect_reminders(&bravo, now()))
+        })
+    });
+    if changes.is_empty() && reminders.is_empty() {
+        return;
+    }
+    let (target, subscribers) = NOTIFIER.with(|n| {
+        let notifier = n.borrow();
+        (
+            notifier.canister.map(|c| (c, notifier.method.clone())),
+            notifier.subscribers.values().cloned().collect::<Vec<Subscriber>>(),
+        )
+    });
+    for (id, state) in changes {
+        let digest = match BRAVO.with(|bravo| bravo.borrow().get_proposal_digest(id)) {
+            Ok(digest) => { digest }
+            Err(_) => { continue; }
+        };
+        if let Some((canister, method)) = &target {
+            if Notifier::is_notified(&state) {
+                #[cfg(not(test))]
+                let _ = notify(*canister, method, (digest.clone(), state.clone(), ));
+            }
+        }
+        for subscriber in subscribers.iter() {
+            #[cfg(not(test))]
+            deliver(subscriber.canister, subscriber.method.clone(), digest.clone(), state.clone());
+        }
+    }
+    for (id, reminder) in reminders {
+        let digest = match BRAVO.with(|bravo| bravo.borrow().get_proposal_digest(id)) {
+            Ok(digest) => { digest }
+            Err(_) => { continue; }
+        };
+        for subscriber in subscribers.iter() {
+            if let Some(method) = &subscriber.reminder_method {
+                #[cfg(not(test))]
+                deliver_reminder(subscriber.canister, method.clone(), digest.clone(), reminder);
+            }
+        }
+    }
+}
+
+/// push the digest of a proposal which just reached quorum to the subscribers asking for it
+fn notify_quorum_reached(id: usize) {
+    let digest = match BRAVO.with(|bravo| bravo.borrow().get_proposal_digest(id)) {
+        Ok(digest) => { digest }
+        Err(_) => { return; }
+    };
+    let subscribers = NOTIFIER.with(|n| n.borrow().subscribers.values().cloned().collect::<Vec<Subscriber>>());
+    for subscriber in subscribers {
+        if let Some(method) = subscriber.quorum_method {
+            #[cfg(not(test))]
+            deliver_quorum_reached(subscriber.canister, method, digest.clone());
+        }
+    }
+}
+
+#[cfg(not(test))]
+fn deliver_quorum_reached(canister: Principal, method: String, digest: ProposalDigest) {
+    ic_cdk::spawn(async move {
+        let result : CallResult<()> = call(canister, &method, (digest, )).await;
+        NOTIFIER.with(|notifier| {
+            let mut notifier = notifier.borrow_mut();
+            notifier.record_delivery(canister, result.is_ok());
+        });
+    });
+}
+
+#[cfg(not(test))]
+fn deliver_reminder(canister: Principal, method: String, digest: ProposalDigest, reminder: notification::Reminder) {
+    ic_cdk::spawn(async move {
+        let result : CallResult<()> = call(canister, &method, (digest, reminder, )).await;
+        NOTIFIER.with(|notifier| {
+            let mut notifier = notifier.borrow_mut();
+            notifier.record_delivery(canister, result.is_ok());
+        });
+    });
+}
+
+/// sweep the proposals for state changes and reminders every SWEEP_INTERVAL, without a keeper
+#[cfg(not(test))]
+#[export_name = "canister_heartbeat"]
+fn heartbeat() {
+    let timestamp = now();
+    if NEXT_SWEEP.with(|next| next.get()) > timestamp {
+        return;
+    }
+    NEXT_SWEEP.with(|next| next.set(timestamp + SWEEP_INTERVAL));
+    notify_state_changes();
+    if SCHEDULER.with(|scheduler| scheduler.borrow_mut().start(ic::time())) {
+        ic_cdk::spawn(run_scheduler());
+    }
+}
+
+/// queue the succeeded proposals and execute the first due task as the canister itself,
+/// failures are logged and the task is retried on the next sweep up to MAX_FAILURES times
+async fn run_scheduler() {
+    let canister = ic::id();
+    let succeeded = BRAVO.with(|bravo| bravo.borrow().succeeded_proposals(now()));
+    for id in succeeded {
+        if let Err(msg) = queue_proposal(canister, id).await {
+            MONITOR.with(|monitor| monitor.borrow_mut().log(ic::time(), format!("scheduler failed to queue proposal {}: {}", id, msg)));
+        }
+    }
+    let executable = BRAVO.with(|bravo| bravo.borrow().executable_proposals(now()));
+    if let Some(id) = SCHEDULER.with(|scheduler| scheduler.borrow().pick(executable)) {
+        let result = execute_proposal(canister, id).await;
+        SCHEDULER.with(|scheduler| scheduler.borrow_mut().record_execution(id, result.is_ok()));
+        if let Err(msg) = result {
+            MONITOR.with(|monitor| monitor.borrow_mut().log(ic::time(), format!("scheduler failed to execute proposal {}: {}", id, msg)));
+        }
+    }
+    SCHEDULER.with(|scheduler| scheduler.borrow_mut().finish());
+}
+
+/// call the subscriber and record whether it accepted the notification
+#[cfg(not(test))]
+fn deliver(canister: Principal, method: String, digest: ProposalDigest, state: ProposalState) {
+    ic_cdk::spawn(async move {
+        let result : CallResult<()> = call(canister, &method, (digest, state, )).await;
+        NOTIFIER.with(|notifier| {
+            let mut notifier = notifier.borrow_mut();
+            notifier.record_delivery(canister, result.is_ok());
+        });
+    });
+}
+
+/// sample canister metrics, counting an update call if any
+fn collect_metrics(update_call: bool) {
+    let stable_memory_size = BRAVO.with(|bravo| {
+        let bravo = bravo.borrow();
+        bravo.stable_memory.size() as u64
+    });
+    let heap_memory_size = metrics::heap_memory_size();
+    MONITOR.with(|monitor| {
+        let mut monitor = monitor.borrow_mut();
+        monitor.collect(ic::time(), ic::balance(), heap_memory_size, heap_memory_size + stable_memory_size, update_call);
+    });
+}
+
+/// count the call of the endpoint and its failure
+fn tracked<R>(method: &str, result: Response<R>) -> Response<R> {
+    collect_metrics(true);
+    MONITOR.with(|monitor| {
+        let mut monitor = monitor.borrow_mut();
+        monitor.record_call(ic::time(), method, result.as_ref().err().copied());
+    });
+    result
+}
+
 #[query(name = "getGovernorBravoInfo")]
 #[candid_method(query, rename = "getGovernorBravoInfo")]
 fn get_governor_bravo_info() -> Response<GovernorBravoInfo> {
@@ -90,7 +371,7 @@ fn get_proposal(id: usize) -> Response<(ProposalInfo, ProposalState)> {
     BRAVO.with(|bravo| {
         let bravo = bravo.borrow();
         let proposal = bravo.get_proposal(id)?;
-        let state = bravo.get_state(id, ic::time())?;
+        let state = bravo.get_state(id, now())?;
         Ok((proposal.to_owned(), state))
     })
 }
@@ -100,17 +381,47 @@ fn get_proposal(id: usize) -> Response<(ProposalInfo, ProposalState)> {
 fn get_proposal_state(id: usize) -> Response<ProposalState> {
     BRAVO.with(|bravo| {
         let bravo = bravo.borrow();
-        let state = bravo.get_state(id, ic::time())?;
+        let state = bravo.get_state(id, now())?;
         Ok(state)
     })
 }
 
+/// state the proposal will have at the timestamp, unless votes or calls change it first
+#[query(name = "getProposalStateAt")]
+#[candid_method(query, rename = "getProposalStateAt")]
+fn get_proposal_state_at(id: usize, timestamp: u64) -> Response<ProposalState> {
+    BRAVO.with(|bravo| {
+        let bravo = bravo.borrow();
+        bravo.get_state(id, timestamp)
+    })
+}
+
+/// tallies of the proposal against its quorum, and whether it would pass if voting ended now
+#[query(name = "getQuorumStatus")]
+#[candid_method(query, rename = "getQuorumStatus")]
+fn get_quorum_status(id: usize) -> Response<QuorumStatus> {
+    BRAVO.with(|bravo| {
+        let bravo = bravo.borrow();
+        bravo.quorum_status(id)
+    })
+}
+
+#[query(name = "getTimeline")]
+#[candid_method(query, rename = "getTimeline")]
+fn get_timeline(id: usize) -> Response<Timeline> {
+    BRAVO.with(|bravo| {
+        let bravo = bravo.borrow();
+        bravo.timeline(id, now())
+    })
+}
+
+/// page of proposals, newest first unless another order is given
 #[query(name = "getProposals")]
 #[candid_method(query, rename = "getProposals")]
-fn get_proposals(page: usize, num: usize) -> Response<Vec<(ProposalDigest, ProposalState)>> {
+fn get_proposals(page: usize, num: usize, sort: Option<ProposalSort>) -> Response<Vec<(ProposalDigest, ProposalState)>> {
     BRAVO.with(|bravo| {
         let bravo = bravo.borrow();
-        let res = bravo.get_proposal_pages(page, num, ic::time())?;
+        let res = bravo.get_proposal_pages(page, num, sort.unwrap_or_default(), now())?;
         Ok(res)
     })
 }
@@ -125,6 +436,22 @@ fn get_task(id: usize) -> Response<Task> {
     })
 }
 
+/// proposals whose title or description contain every word of the query, newest first
+#[query(name = "searchProposals")]
+#[candid_method(query, rename = "searchProposals")]
+fn search_proposals(query: String, page: usize, num: usize) -> Response<Vec<(ProposalDigest, ProposalState)>> {
+    let ids = SEARCH.with(|search| search.borrow().search(&query));
+    BRAVO.with(|bravo| {
+        let bravo = bravo.borrow();
+        let timestamp = now();
+        let mut res = vec![];
+        for id in ids.into_iter().skip(page.saturating_mul(num)).take(num) {
+            res.push((bravo.get_proposal_digest(id)?, bravo.get_state(id, timestamp)?));
+        }
+        Ok(res)
+    })
+}
+
 #[query(name = "getReceipt")]
 #[candid_method(query, rename = "getReceipt")]
 fn get_receipt(id: usize, voter: Principal) -> Response<ReceiptInfo> {
@@ -135,244 +462,2175 @@ fn get_receipt(id: usize, voter: Principal) -> Response<ReceiptInfo> {
     })
 }
 
+/// page of the receipts of the proposal, only those of the vote type if given
 #[query(name = "getReceipts")]
 #[candid_method(query, rename = "getReceipts")]
-fn get_receipts(id: usize, page: usize, num: usize) -> Response<Vec<(Principal, ReceiptDigest)>> {
+fn get_receipts(id: usize, page: usize, num: usize, vote_type: Option<VoteType>) -> Response<Vec<(Principal, ReceiptDigest)>> {
     BRAVO.with(|bravo| {
         let bravo = bravo.borrow();
-        let receipts = bravo.get_receipt_pages(id, page, num)?;
+        let receipts = bravo.get_receipt_pages(id, page, num, vote_type)?;
         Ok(receipts)
     })
 }
 
-#[update(name = "propose")]
-#[candid_method(update, rename = "propose")]
-async fn propose(
-    title: String,
-    description: String,
-    target: Principal,
-    method: String,
-    arguments: Vec<u8>,
-    cycles: u64,
-) -> Response<usize> {
+/// number of voters of the proposal by vote type
+#[query(name = "getReceiptCounts")]
+#[candid_method(query, rename = "getReceiptCounts")]
+fn get_receipt_counts(id: usize) -> Response<ReceiptCounts> {
+    BRAVO.with(|bravo| bravo.borrow().get_receipt_counts(id))
+}
+
+/// comment on a proposal, return id of the comment
+#[update(name = "addComment")]
+#[candid_method(update, rename = "addComment")]
+async fn add_comment(id: usize, text: String) -> Response<usize> {
     let caller = ic::caller();
-    let gov_token = BRAVO.with(|bravo| {
+    let (gov_token, min_balance) = BRAVO.with(|bravo| {
         let bravo = bravo.borrow();
-        bravo.gov_token
+        (bravo.gov_token, bravo.min_comment_balance())
     });
-    
-    let result : CallResult<(Nat, )> = call(gov_token, "getCurrentVotes", (caller, )).await;
-    let proposer_votes : Nat = match result {
-        Ok(res) => {
-            res.0
-        }
-        Err(_) => {
-            return Err("Error in getting proposer's vote")
+    if min_balance > 0 {
+        let result : CallResult<(Nat, )> = call(gov_token, "balanceOf", (caller, )).await;
+        match result {
+            Ok((balance, )) if balance >= min_balance => {}
+            Ok(_) => { return Err("balance below the minimum to comment"); }
+            Err(_) => { return Err("Error in getting commenter's balance"); }
         }
-    };
-    let id = BRAVO.with(|bravo| {
+    }
+    BRAVO.with(|bravo| {
         let mut bravo = bravo.borrow_mut();
-        bravo.propose(
-            caller,
-            proposer_votes,
-            title.clone(),
-            description.clone(),
-            target,
-            method.clone(),
-            arguments.clone(),
-            cycles,
-            ic::time(),
-        )
-    })?;
-    #[cfg(not(test))]
-    insert(ProposeEvent::new(
-        caller,
-        id as u64,
-        title,
-        description,
-        target,
-        method,
-        arguments,
-        cycles
-    )
-        .to_indefinite_event()
-    ).await.map_err(|_| "Cap error")?;
-
-    Ok(id)
+        bravo.add_comment(id, caller, text, now())
+    })
 }
 
-#[update(name = "queue")]
-#[candid_method(update, rename = "queue")]
-async fn queue(id: usize) -> Response<u64> {
-    let caller = ic::caller();
-    let eta = BRAVO.with(|bravo| {
+#[update(name = "editComment")]
+#[candid_method(update, rename = "editComment")]
+fn edit_comment(id: usize, comment_id: usize, text: String) -> Response<()> {
+    BRAVO.with(|bravo| {
         let mut bravo = bravo.borrow_mut();
-        bravo.queue(id, ic::time())
+        bravo.edit_comment(id, comment_id, ic::caller(), text, now())
+    })
+}
 
-    })?;
-    #[cfg(not(test))]
-    insert(QueueEvent::new(caller, id as u64, eta).to_indefinite_event()).await.map_err(|_| "Cap error")?;
-    Ok(eta)
+#[update(name = "deleteComment")]
+#[candid_method(update, rename = "deleteComment")]
+fn delete_comment(id: usize, comment_id: usize) -> Response<()> {
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.delete_comment(id, comment_id, ic::caller())
+    })
 }
 
-#[update(name = "cancel")]
-#[candid_method(update, rename = "cancel")]
-async fn cancel(id: usize) -> Response<()> {
-    let caller = ic::caller();
-    let proposer = BRAVO.with(|bravo| {
-        let bravo = bravo.borrow();
-        match bravo.get_proposal(id) {
-            Ok(p) => { Ok(p.to_owned()) }
-            Err(msg) => { Err(msg) }
-        }
-    })?;
-    let gov_token = BRAVO.with(|bravo| {
-        let bravo = bravo.borrow();
-        bravo.gov_token
-    });
-    let result : CallResult<(Nat, )> = call(gov_token, "getCurrentVotes", (proposer, )).await;
-    let proposer_votes : Nat = match result {
-        Ok(res) => {
-            res.0
-        }
-        Err(_) => {
-            return Err("Error in getting proposer's vote")
-        }
-    };
+/// replace an abusive vote reason by a tombstone, the vote itself is kept
+/// not timelocked: moderation only
+#[update(name = "redactVoteReason", guard = "is_admin_or_guardian")]
+#[candid_method(update, rename = "redactVoteReason")]
+async fn redact_vote_reason(id: usize, voter: Principal) -> Response<()> {
+    audit("redactVoteReason");
     BRAVO.with(|bravo| {
         let mut bravo = bravo.borrow_mut();
-        bravo.cancel(id, ic::time(), caller, proposer_votes)
+        bravo.redact_reason(id, voter)
     })?;
     #[cfg(not(test))]
-    insert(CancelEvent::new(caller, id as u64).to_indefinite_event()).await.map_err(|_| "Cap error")?;
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("redactVoteReason")
+        .details(DetailsBuilder::new().insert("proposalId", id as u64).insert("voter", voter).build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
     Ok(())
 }
 
-#[update(name = "execute")]
-#[candid_method(update, rename = "execute")]
-async fn execute(id: usize) -> Response<Vec<u8>> {
-    let caller = ic::caller();
-    let timestamp = ic::time();
+#[query(name = "getComments")]
+#[candid_method(query, rename = "getComments")]
+fn get_comments(id: usize, page: usize, num: usize) -> Response<Vec<CommentInfo>> {
     BRAVO.with(|bravo| {
-        let mut bravo = bravo.borrow_mut();
-        bravo.pre_execute(id, timestamp)
-    })?;
+        let bravo = bravo.borrow();
+        bravo.get_comment_pages(id, page, num)
+    })
+}
 
-    let task = BRAVO.with(|bravo| {
+/// queued proposals in execution order, for keepers executing them
+#[query(name = "getUpcomingExecutions")]
+#[candid_method(query, rename = "getUpcomingExecutions")]
+fn get_upcoming_executions(limit: usize) -> Vec<UpcomingExecution> {
+    BRAVO.with(|bravo| bravo.borrow().upcoming_executions(limit, now()))
+}
+
+#[query(name = "exportProposals")]
+#[candid_method(query, rename = "exportProposals")]
+fn export_proposals(format: ExportFormat, offset: usize, limit: usize) -> Response<ExportChunk> {
+    BRAVO.with(|bravo| {
         let bravo = bravo.borrow();
-        bravo.get_task(id)
-    })?;
-    let result = ic::call_raw(
-        task.target,
-        task.method.to_owned(),
-        task.arguments.to_owned(),
-        task.cycles,
-    ).await;
+        export::export_proposals(&bravo, format, offset, limit, now())
+    })
+}
 
-    let ret = BRAVO.with(move |bravo| {
-        let mut bravo = bravo.borrow_mut();
-        match result {
-            Ok(ret) => {
-                bravo.post_execute(id, true, timestamp)?;
-                Ok(ret)
-            }
-            Err(_) => {
-                bravo.post_execute(id, false, timestamp)?;
-                Err("Execute error")
-            }
-        }
-    })?;
-    #[cfg(not(test))]
-    insert(ExecuteEvent::new(caller, id as u64, ret.clone()).to_indefinite_event()).await.map_err(|_| "Cap error")?;
-    Ok(ret)
+#[query(name = "exportReceipts")]
+#[candid_method(query, rename = "exportReceipts")]
+fn export_receipts(id: usize, format: ExportFormat, offset: usize, limit: usize) -> Response<ExportChunk> {
+    BRAVO.with(|bravo| {
+        let bravo = bravo.borrow();
+        export::export_receipts(&bravo, id, format, offset, limit)
+    })
 }
 
-#[update(name = "castVote")]
-#[candid_method(update, rename = "castVote")]
-async fn cast_vote(id: usize, vote_type: VoteType, reason: Option<String>) -> Response<Receipt> {
-    let caller = ic::caller();
-    let timestamp = ic::time();
-    let gov_token = BRAVO.with(|bravo| {
+#[query(name = "exportStableRange", guard = "is_admin")]
+#[candid_method(query, rename = "exportStableRange")]
+fn export_stable_range(offset: usize, len: usize) -> Response<Vec<u8>> {
+    BRAVO.with(|bravo| {
         let bravo = bravo.borrow();
-        bravo.gov_token
-    });
-    let result : CallResult<(Nat, )> = call(gov_token, "getPriorVotes", (caller, Nat::from(timestamp), )).await;
-    let votes : Nat = match result {
-        Ok(res) => {
-            res.0
-        }
-        Err(_) => {
-            return Err("Error in getting proposer's prior vote");
+        bravo.export_stable_range(offset, len)
+    })
+}
+
+/// chunk of a full backup of the governor, its proposals, receipts and stable memory records
+#[query(name = "backupState", guard = "is_admin")]
+#[candid_method(query, rename = "backupState")]
+fn backup_state(chunk: usize) -> Response<BackupChunk> {
+    BRAVO.with(|bravo| {
+        let bravo = bravo.borrow();
+        export::backup_chunk(&bravo, chunk)
+    })
+}
+
+/// stage chunks of a backup in order, the backup is restored once the checksum of the whole is given
+/// not timelocked: disaster recovery cannot wait on a governor whose state is broken
+#[update(name = "restoreState", guard = "is_admin")]
+#[candid_method(update, rename = "restoreState")]
+async fn restore_state(chunks: Vec<Vec<u8>>, checksum: Option<u32>) -> Response<()> {
+    audit("restoreState");
+    RESTORE_BUFFER.with(|buffer| {
+        let mut buffer = buffer.borrow_mut();
+        for chunk in chunks {
+            buffer.extend(chunk);
         }
+    });
+    let checksum = match checksum {
+        Some(checksum) => { checksum }
+        None => { return Ok(()); }
     };
-    let receipt = BRAVO.with(|bravo| {
+    let backup = RESTORE_BUFFER.with(|buffer| std::mem::take(&mut *buffer.borrow_mut()));
+    let (restored, records) = export::decode_backup(&backup, checksum)?;
+    BRAVO.with(|bravo| {
         let mut bravo = bravo.borrow_mut();
-        bravo.cast_vote(
-            id,
-            vote_type.clone(),
-            votes.clone(),
-            reason,
-            caller,
-            timestamp,
-        )
+        bravo.restore(restored, &records)
     })?;
-    #[cfg(not(test))]
-    insert(VoteEvent::new(caller, id as u64, votes, vote_type).to_indefinite_event()).await.map_err(|_| "Cap error")?;
-    Ok(receipt)
-}
-
-#[update(name = "setPendingAdmin", guard = "is_admin")]
-#[candid_method(update, rename = "setPendingAdmin")]
-async fn set_pending_admin(pending_admin: Principal) -> Response<()> {
-    let caller = ic::caller();
+    // the search index is rebuilt from the restored proposals
     BRAVO.with(|bravo| {
-        let mut bravo = bravo.borrow_mut();
-        bravo.set_pending_admin(pending_admin);
+        SEARCH.with(|search| {
+            let mut search = search.borrow_mut();
+            *search = SearchIndex::default();
+            search.sync(&bravo.borrow());
+        })
     });
+    #[cfg_attr(test, allow(unused_variables))]
+    let proposals = BRAVO.with(|bravo| bravo.borrow().proposal_count());
     #[cfg(not(test))]
-    insert(SetPendingAdminEvent::new(caller, pending_admin).to_indefinite_event()).await.map_err(|_| "Cap error")?;
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("restoreState")
+        .details(vec![("proposals".to_string(), U64(proposals as u64))])
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
     Ok(())
 }
 
-#[update(name = "acceptAdmin")]
-#[candid_method(update, rename = "setAdmin")]
-async fn accept_admin() -> Response<()> {
-    let caller = ic::caller();
+#[query(name = "http_request")]
+#[candid_method(query, rename = "http_request")]
+fn http_request(req: HttpRequest) -> HttpResponse {
     BRAVO.with(|bravo| {
-        let mut bravo = bravo.borrow_mut();
-        if bravo.pending_admin != Some(caller) {
-            Err("Unauthorized")
-        } else {
-            bravo.accept_admin();
+        let bravo = bravo.borrow();
+        metrics::serve(&req, &bravo, now(), ic::balance())
+    })
+}
+
+#[query(name = "healthCheck")]
+#[candid_method(query, rename = "healthCheck")]
+fn health_check() -> HealthStatus {
+    BRAVO.with(|bravo| {
+        let bravo = bravo.borrow();
+        let last_upgrade = UPGRADES.with(|u| u.borrow().last().copied().unwrap_or(0));
+        metrics::health(&bravo, ic::balance(), last_upgrade)
+    })
+}
+
+/// version, build commit and candid interface hash of the running code, with its upgrade history
+#[query(name = "getVersion")]
+#[candid_method(query, rename = "getVersion")]
+fn get_version() -> VersionInfo {
+    metrics::version(&export_candid(), UPGRADES.with(|u| u.borrow().clone()))
+}
+
+/// consistency report of the governor state, empty when healthy
+#[query(name = "checkInvariants")]
+#[candid_method(query, rename = "checkInvariants")]
+fn check_invariants() -> Vec<Violation> {
+    BRAVO.with(|bravo| {
+        let bravo = bravo.borrow();
+        bravo.check_invariants(now())
+    })
+}
+
+#[update(name = "collectCanisterMetrics")]
+#[candid_method(update, rename = "collectCanisterMetrics")]
+fn collect_canister_metrics() {
+    collect_metrics(false);
+}
+
+#[query(name = "getCanisterMetrics")]
+#[candid_method(query, rename = "getCanisterMetrics")]
+fn get_canister_metrics(params: GetMetricsParameters) -> Option<CanisterMetrics> {
+    MONITOR.with(|monitor| {
+        let monitor = monitor.borrow();
+        Some(monitor.get_metrics(&params))
+    })
+}
+
+#[query(name = "getCanisterLog", guard = "is_admin")]
+#[candid_method(query, rename = "getCanisterLog")]
+fn get_canister_log(request: Option<CanisterLogRequest>) -> Option<CanisterLogResponse> {
+    MONITOR.with(|monitor| {
+        let monitor = monitor.borrow();
+        monitor.get_log(request)
+    })
+}
+
+/// guarded admin calls from the id on, oldest first, and the number of calls recorded
+#[query(name = "getAuditLog")]
+#[candid_method(query, rename = "getAuditLog")]
+fn get_audit_log(start: usize, limit: usize) -> (Vec<AuditRecord>, usize) {
+    AUDIT.with(|audit| {
+        let audit = audit.borrow();
+        (audit.page(start, limit), audit.len())
+    })
+}
+
+#[query(name = "getMethodMetrics")]
+#[candid_method(query, rename = "getMethodMetrics")]
+fn get_method_metrics() -> Vec<MethodMetrics> {
+    MONITOR.with(|monitor| {
+        let monitor = monitor.borrow();
+        monitor.method_metrics()
+    })
+}
+
+#[query(name = "oracleTransform")]
+#[candid_method(query, rename = "oracleTransform")]
+fn oracle_transform(args: TransformArgs) -> HttpOutcallResponse {
+    oracle::strip_headers(args)
+}
+
+/// transform of ethereum broadcasts, keeping only the transaction hash or the rpc error code
+#[query(name = "ethereumTransform")]
+#[candid_method(query, rename = "ethereumTransform")]
+fn ethereum_transform(args: TransformArgs) -> HttpOutcallResponse {
+    ethereum::transform(args)
+}
+
+#[update(name = "propose")]
+#[candid_method(update, rename = "propose")]
+async fn propose(
+    title: String,
+    description: String,
+    target: Principal,
+    method: String,
+    arguments: Vec<u8>,
+    cycles: u64,
+    priority: Option<u8>,
+    voting_period: Option<u64>,
+) -> Response<usize> {
+    let task = Task::new(target, method, arguments, cycles);
+    tracked("propose", propose_task_with(ic::caller(), title, description, task, priority, voting_period).await)
+}
+
+#[update(name = "proposeWithOracle")]
+#[candid_method(update, rename = "proposeWithOracle")]
+async fn propose_with_oracle(
+    title: String,
+    description: String,
+    target: Principal,
+    method: String,
+    arguments: Vec<u8>,
+    cycles: u64,
+    oracle: Oracle,
+) -> Response<usize> {
+    let task = Task::new(target, method, arguments, cycles).with_oracle(oracle);
+    tracked("proposeWithOracle", propose_task(ic::caller(), title, description, task).await)
+}
+
+/// whether the principal could propose now, with the reason if not
+#[update(name = "canPropose")]
+#[candid_method(update, rename = "canPropose")]
+async fn can_propose(principal: Principal) -> Response<ProposeEligibility> {
+    let gov_token = BRAVO.with(|bravo| bravo.borrow().gov_token);
+    let result : CallResult<(Nat, )> = call(gov_token, "getCurrentVotes", (principal, )).await;
+    let votes : Nat = match result {
+        Ok(res) => { res.0 }
+        Err(_) => { return Err("Error in getting proposer's vote"); }
+    };
+    let votes = held_votes(principal, votes).await?;
+    let mut eligibility = BRAVO.with(|bravo| bravo.borrow().propose_eligibility(principal, votes, now()));
+    if let Err(msg) = GUARDIAN.with(|guardian| guardian.borrow().check_not_paused()) {
+        eligibility.eligible = false;
+        eligibility.reason = Some(msg.to_string());
+    }
+    Ok(eligibility)
+}
+
+async fn propose_task(
+    caller: Principal,
+    title: String,
+    description: String,
+    task: Task,
+) -> Response<usize> {
+    propose_task_with(caller, title, description, task, None, None).await
+}
+
+/// propose the task with the priority and voting period requested by the proposer, checked before anything is created
+async fn propose_task_with(
+    caller: Principal,
+    title: String,
+    description: String,
+    task: Task,
+    priority: Option<u8>,
+    voting_period: Option<u64>,
+) -> Response<usize> {
+    GUARDIAN.with(|guardian| guardian.borrow().check_not_paused())?;
+    let proposer_votes = proposer_votes(caller).await?;
+    let id = create_proposal(caller, || BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.propose_with(
+            caller,
+            proposer_votes,
+            title.clone(),
+            description.clone(),
+            task.clone(),
+            priority,
+            voting_period,
+            now(),
+        )
+    })).await?;
+    BRAVO.with(|bravo| {
+        SEARCH.with(|search| search.borrow_mut().sync(&bravo.borrow()))
+    });
+    notify_state_changes();
+    #[cfg(not(test))]
+    insert(ProposeEvent::new(
+        caller,
+        id as u64,
+        title,
+        description,
+        task,
+    )
+        .to_indefinite_event()
+    ).await.map_err(|_| "Cap error")?;
+
+    Ok(id)
+}
+
+/// create the proposal with its quorum fixed from the total supply if set as a fraction, taking the proposal
+/// deposit from the proposer, the deposit is handed back if the proposal is refused, or kept claimable if that fails
+async fn create_proposal(caller: Principal, create: impl FnOnce() -> Response<usize>) -> Response<usize> {
+    let total_supply = if BRAVO.with(|bravo| bravo.borrow().quorum_fraction()) > 0 {
+        Some(total_supply().await?)
+    } else {
+        None
+    };
+    let amount = BRAVO.with(|bravo| bravo.borrow().proposal_deposit());
+    if amount > 0u64 {
+        let gov_token = BRAVO.with(|bravo| bravo.borrow().gov_token);
+        let result : CallResult<(TxReceipt, )> = call(gov_token, "transferFrom", (caller, ic::id(), amount.clone(), )).await;
+        if !matches!(result, Ok((Ok(_), ))) {
+            return Err("Error in taking the proposal deposit");
+        }
+    }
+    let id = match create() {
+        Ok(id) => { id }
+        Err(msg) => {
+            if amount > 0u64 && transfer_gov_token(caller, amount.clone()).await.is_err() {
+                BRAVO.with(|bravo| bravo.borrow_mut().record_unpaid_refund(caller, amount));
+            }
+            return Err(msg);
+        }
+    };
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        if let Some(total_supply) = total_supply {
+            bravo.apply_quorum_fraction(id, total_supply)?;
+        }
+        if amount > 0u64 {
+            bravo.record_deposit(id, caller, amount)?;
+        }
+        Ok(id)
+    })
+}
+
+/// current total supply of the gov token
+async fn total_supply() -> Response<Nat> {
+    let gov_token = BRAVO.with(|bravo| bravo.borrow().gov_token);
+    let result : CallResult<(Nat, )> = call(gov_token, "totalSupply", ()).await;
+    result.map(|res| res.0).map_err(|_| "Error in getting the total supply")
+}
+
+/// payer of the fee of a gov token transfer, the case of the gov token's variant used by the governor
+#[derive(CandidType, Clone, Copy, Debug, PartialEq)]
+enum FeePayer {
+    /// the recipient, out of the value received
+    Recipient,
+}
+
+/// arguments of a gov token transfer of deposited tokens, deposits come in through transferFrom with
+/// the fee paid by the proposer, so the recipient pays the fee and the governor pays out what it received
+fn deposit_transfer_args(to: Principal, amount: Nat) -> (Principal, Nat, Option<FeePayer>) {
+    (to, amount, Some(FeePayer::Recipient))
+}
+
+/// pay back or slash deposited gov tokens
+async fn transfer_gov_token(to: Principal, amount: Nat) -> Response<()> {
+    let gov_token = BRAVO.with(|bravo| bravo.borrow().gov_token);
+    let result : CallResult<(TxReceipt, )> = call(gov_token, "transfer", deposit_transfer_args(to, amount)).await;
+    match result {
+        Ok((Ok(_), )) => { Ok(()) }
+        _ => { Err("Error in transferring gov tokens") }
+    }
+}
+
+/// refund the deposit of a proposal once it succeeded, or slash it to the deposit treasury once it was defeated
+/// or canceled for spam, return the settled status
+#[update(name = "claimDeposit")]
+#[candid_method(update, rename = "claimDeposit")]
+async fn claim_deposit(id: usize) -> Response<DepositStatus> {
+    tracked("claimDeposit", do_claim_deposit(id).await)
+}
+
+async fn do_claim_deposit(id: usize) -> Response<DepositStatus> {
+    let (recipient, deposit) = BRAVO.with(|bravo| bravo.borrow_mut().settle_deposit(id, now()))?;
+    if let Some(recipient) = recipient {
+        if let Err(msg) = transfer_gov_token(recipient, deposit.amount.clone()).await {
+            BRAVO.with(|bravo| bravo.borrow_mut().unsettle_deposit(id))?;
+            return Err(msg);
+        }
+    }
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("claimDeposit")
+        .details(DetailsBuilder::new()
+            .insert("id", id as u64)
+            .insert("amount", deposit.amount.clone())
+            .insert("status", format!("{:?}", deposit.status))
+            .build()
+        )
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(deposit.status)
+}
+
+/// pay the caller the deposits of refused proposals whose refund failed, return the amount paid
+#[update(name = "claimRefund")]
+#[candid_method(update, rename = "claimRefund")]
+async fn claim_refund() -> Response<Nat> {
+    tracked("claimRefund", do_claim_refund().await)
+}
+
+async fn do_claim_refund() -> Response<Nat> {
+    let caller = ic::caller();
+    let amount = BRAVO.with(|bravo| bravo.borrow_mut().take_unpaid_refund(caller))?;
+    if let Err(msg) = transfer_gov_token(caller, amount.clone()).await {
+        BRAVO.with(|bravo| bravo.borrow_mut().record_unpaid_refund(caller, amount));
+        return Err(msg);
+    }
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(caller)
+        .operation("claimRefund")
+        .details(DetailsBuilder::new()
+            .insert("amount", amount.clone())
+            .build()
+        )
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(amount)
+}
+
+/// votes of the proposer
+async fn proposer_votes(caller: Principal) -> Response<Nat> {
+    let gov_token = BRAVO.with(|bravo| {
+        let bravo = bravo.borrow();
+        bravo.gov_token
+    });
+
+    let result : CallResult<(Nat, )> = call(gov_token, "getCurrentVotes", (caller, )).await;
+    let proposer_votes : Nat = match result {
+        Ok(res) => {
+            res.0
+        }
+        Err(_) => {
+            return Err("Error in getting proposer's vote")
+        }
+    };
+    held_votes(caller, proposer_votes).await
+}
+
+/// lowest votes the proposer held over the minimum delegation age, the current votes if disabled,
+/// so tokens borrowed just before proposing do not count
+async fn held_votes(proposer: Principal, current: Nat) -> Response<Nat> {
+    let (gov_token, min_delegation_age) = BRAVO.with(|bravo| {
+        let bravo = bravo.borrow();
+        (bravo.gov_token, bravo.min_delegation_age())
+    });
+    if min_delegation_age == 0 {
+        return Ok(current);
+    }
+    let timestamp = now();
+    let from = Nat::from(timestamp.saturating_sub(min_delegation_age));
+    let result : CallResult<(Nat, )> = call(gov_token, "getMinPriorVotes", (proposer, from, Nat::from(timestamp), )).await;
+    match result {
+        Ok(res) => { Ok(res.0.min(current)) }
+        Err(_) => { Err("Error in getting proposer's prior vote") }
+    }
+}
+
+/// propose again a defeated or expired proposal, its description is not uploaded again
+#[update(name = "repropose")]
+#[candid_method(update, rename = "repropose")]
+async fn repropose(id: usize) -> Response<usize> {
+    tracked("repropose", do_repropose(ic::caller(), id).await)
+}
+
+async fn do_repropose(caller: Principal, id: usize) -> Response<usize> {
+    GUARDIAN.with(|guardian| guardian.borrow().check_not_paused())?;
+    let proposer_votes = proposer_votes(caller).await?;
+    let new_id = create_proposal(caller, || BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.repropose(id, caller, proposer_votes, now())
+    })).await?;
+    BRAVO.with(|bravo| {
+        SEARCH.with(|search| search.borrow_mut().sync(&bravo.borrow()))
+    });
+    notify_state_changes();
+    #[cfg(not(test))]
+    {
+        let proposal = BRAVO.with(|bravo| bravo.borrow().get_proposal(new_id))?;
+        insert(ProposeEvent::new(
+            caller,
+            new_id as u64,
+            proposal.title,
+            proposal.description,
+            proposal.task,
+        )
+            .to_indefinite_event()
+        ).await.map_err(|_| "Cap error")?;
+    }
+
+    Ok(new_id)
+}
+
+/// import closed proposals of a previous governance canister, before the first proposal
+/// not timelocked: imported proposals are closed and never executed
+#[update(name = "importProposals", guard = "is_admin")]
+#[candid_method(update, rename = "importProposals")]
+async fn import_proposals(imports: Vec<ProposalImport>) -> Response<Vec<usize>> {
+    audit("importProposals");
+    let ids = BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.import_proposals(imports, now())
+    })?;
+    BRAVO.with(|bravo| {
+        SEARCH.with(|search| search.borrow_mut().sync(&bravo.borrow()))
+    });
+    #[cfg(not(test))]
+    {
+        if let (Some(first), Some(last)) = (ids.first(), ids.last()) {
+            insert(IndefiniteEventBuilder::new()
+                .caller(ic::caller())
+                .operation("importProposals")
+                .details(DetailsBuilder::new()
+                    .insert("firstId", *first as u64)
+                    .insert("lastId", *last as u64)
+                    .build()
+                )
+                .build()
+                .unwrap()
+            ).await.map_err(|_| "Cap error")?;
+        }
+    }
+    Ok(ids)
+}
+
+#[update(name = "proposeEthereum")]
+#[candid_method(update, rename = "proposeEthereum")]
+async fn propose_ethereum(
+    title: String,
+    description: String,
+    tx: EthereumTx,
+) -> Response<usize> {
+    tracked("proposeEthereum", propose_task(ic::caller(), title, description, Task::ethereum(tx)).await)
+}
+
+#[update(name = "proposeBitcoinTransfer")]
+#[candid_method(update, rename = "proposeBitcoinTransfer")]
+async fn propose_bitcoin_transfer(
+    title: String,
+    description: String,
+    address: String,
+    amount: u64,
+) -> Response<usize> {
+    let network = BITCOIN.with(|wallet| {
+        let wallet = wallet.borrow();
+        wallet.network
+    });
+    if let Err(msg) = bitcoin::decode_address(network, &address) {
+        return tracked("proposeBitcoinTransfer", Err(msg));
+    }
+    let task = Task::bitcoin(BitcoinTransfer { address, amount });
+    tracked("proposeBitcoinTransfer", propose_task(ic::caller(), title, description, task).await)
+}
+
+#[update(name = "proposeTreasurySpend")]
+#[candid_method(update, rename = "proposeTreasurySpend")]
+async fn propose_treasury_spend(
+    title: String,
+    description: String,
+    spend: TreasurySpend,
+) -> Response<usize> {
+    if let Err(msg) = TREASURY.with(|treasury| treasury.borrow().check(&spend.asset)) {
+        return tracked("proposeTreasurySpend", Err(msg));
+    }
+    tracked("proposeTreasurySpend", propose_task(ic::caller(), title, description, Task::treasury(spend)).await)
+}
+
+/// propose a non-binding signal vote without a task, closed instead of queued once succeeded
+#[update(name = "proposeText")]
+#[candid_method(update, rename = "proposeText")]
+async fn propose_text(title: String, description: String) -> Response<usize> {
+    tracked("proposeText", propose_signal(ic::caller(), title, description, vec![]).await)
+}
+
+/// propose a vote between labeled options without a task, closed with the option of most votes
+#[update(name = "proposeOptions")]
+#[candid_method(update, rename = "proposeOptions")]
+async fn propose_options(title: String, description: String, options: Vec<String>) -> Response<usize> {
+    tracked("proposeOptions", propose_signal(ic::caller(), title, description, options).await)
+}
+
+/// propose a text proposal, or a multi-choice one if options are given
+async fn propose_signal(caller: Principal, title: String, description: String, options: Vec<String>) -> Response<usize> {
+    GUARDIAN.with(|guardian| guardian.borrow().check_not_paused())?;
+    let proposer_votes = proposer_votes(caller).await?;
+    let id = create_proposal(caller, || BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        if options.is_empty() {
+            bravo.propose_text(caller, proposer_votes, title.clone(), description.clone(), now())
+        } else {
+            bravo.propose_options(caller, proposer_votes, title.clone(), description.clone(), options, now())
+        }
+    })).await?;
+    BRAVO.with(|bravo| {
+        SEARCH.with(|search| search.borrow_mut().sync(&bravo.borrow()))
+    });
+    notify_state_changes();
+    #[cfg(not(test))]
+    insert(ProposeEvent::new(
+        caller,
+        id as u64,
+        title,
+        description,
+        Task::text(),
+    )
+        .to_indefinite_event()
+    ).await.map_err(|_| "Cap error")?;
+
+    Ok(id)
+}
+
+/// propose calls to several canisters, executed in order
+#[update(name = "proposeCalls")]
+#[candid_method(update, rename = "proposeCalls")]
+async fn propose_calls(
+    title: String,
+    description: String,
+    calls: Vec<Call>,
+) -> Response<usize> {
+    tracked("proposeCalls", propose_task(ic::caller(), title, description, Task::calls(calls)).await)
+}
+
+#[update(name = "proposeStream")]
+#[candid_method(update, rename = "proposeStream")]
+async fn propose_stream(
+    title: String,
+    description: String,
+    grant: GrantStream,
+) -> Response<usize> {
+    if let Err(msg) = TREASURY.with(|treasury| treasury.borrow().check(&grant.asset)) {
+        return tracked("proposeStream", Err(msg));
+    }
+    tracked("proposeStream", propose_task(ic::caller(), title, description, Task::stream(grant)).await)
+}
+
+/// propose a typed governor parameter change, bounds are checked at propose time
+#[update(name = "proposeParamChange")]
+#[candid_method(update, rename = "proposeParamChange")]
+async fn propose_param_change(
+    title: String,
+    description: String,
+    change: ParamChange,
+) -> Response<usize> {
+    tracked("proposeParamChange", propose_task(ic::caller(), title, description, Task::param_change(change)).await)
+}
+
+/// propose calling the governor parameter setter with the value
+async fn propose_param<T: CandidType>(title: String, description: String, method: &str, value: T) -> Response<usize> {
+    let arguments = encode_one(value).map_err(|_| "Encode error")?;
+    let task = Task::new(ic::id(), method.to_string(), arguments, 0);
+    propose_task(ic::caller(), title, description, task).await
+}
+
+#[update(name = "proposeSetQuorum")]
+#[candid_method(update, rename = "proposeSetQuorum")]
+async fn propose_set_quorum(title: String, description: String, quorum: Nat) -> Response<usize> {
+    tracked("proposeSetQuorum", propose_param(title, description, "setQuorumVotes", quorum).await)
+}
+
+#[update(name = "proposeSetVotePeriod")]
+#[candid_method(update, rename = "proposeSetVotePeriod")]
+async fn propose_set_vote_period(title: String, description: String, period: u64) -> Response<usize> {
+    tracked("proposeSetVotePeriod", propose_param(title, description, "setVotePeriod", period).await)
+}
+
+#[update(name = "proposeSetVoteDelay")]
+#[candid_method(update, rename = "proposeSetVoteDelay")]
+async fn propose_set_vote_delay(title: String, description: String, delay: u64) -> Response<usize> {
+    tracked("proposeSetVoteDelay", propose_param(title, description, "setVoteDelay", delay).await)
+}
+
+#[update(name = "proposeSetProposalThreshold")]
+#[candid_method(update, rename = "proposeSetProposalThreshold")]
+async fn propose_set_proposal_threshold(title: String, description: String, threshold: Nat) -> Response<usize> {
+    tracked("proposeSetProposalThreshold", propose_param(title, description, "setProposalThreshold", threshold).await)
+}
+
+#[update(name = "proposeSetTimelockDelay")]
+#[candid_method(update, rename = "proposeSetTimelockDelay")]
+async fn propose_set_timelock_delay(title: String, description: String, delay: u64) -> Response<usize> {
+    tracked("proposeSetTimelockDelay", propose_param(title, description, "setTimelockDelay", delay).await)
+}
+
+#[update(name = "proposeSetMinHoldingDuration")]
+#[candid_method(update, rename = "proposeSetMinHoldingDuration")]
+async fn propose_set_min_holding_duration(title: String, description: String, duration: u64) -> Response<usize> {
+    tracked("proposeSetMinHoldingDuration", propose_param(title, description, "setMinHoldingDuration", duration).await)
+}
+
+#[update(name = "proposeSetMinDelegationAge")]
+#[candid_method(update, rename = "proposeSetMinDelegationAge")]
+async fn propose_set_min_delegation_age(title: String, description: String, age: u64) -> Response<usize> {
+    tracked("proposeSetMinDelegationAge", propose_param(title, description, "setMinDelegationAge", age).await)
+}
+
+#[update(name = "queue")]
+#[candid_method(update, rename = "queue")]
+async fn queue(id: usize) -> Response<u64> {
+    tracked("queue", queue_proposal(ic::caller(), id).await)
+}
+
+async fn queue_proposal(caller: Principal, id: usize) -> Response<u64> {
+    GUARDIAN.with(|guardian| guardian.borrow().check_not_paused())?;
+    // expired tasks are pruned lazily, a failure must not block queueing
+    let _ = prune_expired_tasks().await;
+    let _ = record_transitions(caller).await;
+    let eta = BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.queue(id, now())
+
+    })?;
+    notify_state_changes();
+    #[cfg(not(test))]
+    insert(QueueEvent::new(caller, id as u64, eta).to_indefinite_event()).await.map_err(|_| "Cap error")?;
+    Ok(eta)
+}
+
+/// record the states proposals reached through their votes and times, to CAP and the canister log
+async fn record_transitions(caller: Principal) -> Response<()> {
+    let transitions = BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.record_transitions(now())
+    });
+    MONITOR.with(|monitor| {
+        let mut monitor = monitor.borrow_mut();
+        for (id, state) in transitions.iter() {
+            monitor.log(ic::time(), format!("proposal {} {:?}", id, state));
+        }
+    });
+    #[cfg(not(test))]
+    for (id, state) in transitions {
+        insert(TransitionEvent::new(caller, id as u64, state).to_indefinite_event()).await.map_err(|_| "Cap error")?;
+    }
+    Ok(())
+}
+
+/// drop the timelock tasks of proposals expired unexecuted, return their ids
+#[update(name = "pruneExpiredTasks")]
+#[candid_method(update, rename = "pruneExpiredTasks")]
+async fn prune_expired_tasks() -> Response<Vec<usize>> {
+    let caller = ic::caller();
+    let expired = BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.prune_expired(now())
+    });
+    #[cfg(not(test))]
+    for id in expired.iter() {
+        insert(ExpireEvent::new(caller, *id as u64).to_indefinite_event()).await.map_err(|_| "Cap error")?;
+    }
+    Ok(expired)
+}
+
+#[update(name = "cancel")]
+#[candid_method(update, rename = "cancel")]
+async fn cancel(id: usize) -> Response<()> {
+    tracked("cancel", do_cancel(id).await)
+}
+
+async fn do_cancel(id: usize) -> Response<()> {
+    let caller = ic::caller();
+    let _ = record_transitions(caller).await;
+    let proposer = BRAVO.with(|bravo| {
+        let bravo = bravo.borrow();
+        match bravo.get_proposal(id) {
+            Ok(p) => { Ok(p.to_owned()) }
+            Err(msg) => { Err(msg) }
+        }
+    })?;
+    let gov_token = BRAVO.with(|bravo| {
+        let bravo = bravo.borrow();
+        bravo.gov_token
+    });
+    let result : CallResult<(Nat, )> = call(gov_token, "getCurrentVotes", (proposer, )).await;
+    let proposer_votes : Nat = match result {
+        Ok(res) => {
+            res.0
+        }
+        Err(_) => {
+            return Err("Error in getting proposer's vote")
+        }
+    };
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.cancel(id, now(), caller, proposer_votes)
+    })?;
+    notify_state_changes();
+    #[cfg(not(test))]
+    insert(CancelEvent::new(caller, id as u64).to_indefinite_event()).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// restrict execution to the executors, e.g. an ops multisig, an empty list lets anyone execute
+#[update(name = "setExecutors", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setExecutors")]
+async fn set_executors(executors: Vec<Principal>) -> Response<()> {
+    audit("setExecutors");
+    if ic::caller() != ic::id() {
+        return announce_call("setExecutors", (executors, )).await;
+    }
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.set_executors(executors.clone());
+    });
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setExecutors")
+        .details(DetailsBuilder::new()
+            .insert("executors", executors.iter().map(|e| e.to_text()).collect::<Vec<String>>().join(","))
+            .build()
+        )
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// cap the cycles a task may attach, checked at propose time, 0 to disable
+#[update(name = "setMaxTaskCycles", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setMaxTaskCycles")]
+async fn set_max_task_cycles(cycles: u64) -> Response<()> {
+    audit("setMaxTaskCycles");
+    if ic::caller() != ic::id() {
+        return announce_call("setMaxTaskCycles", (cycles, )).await;
+    }
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.set_max_task_cycles(cycles);
+    });
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setMaxTaskCycles")
+        .details(DetailsBuilder::new().insert("cycles", cycles).build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// cap the cycles a task calling the target may attach, none removes the target's cap
+#[update(name = "setTargetCycleLimit", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setTargetCycleLimit")]
+async fn set_target_cycle_limit(target: Principal, limit: Option<u64>) -> Response<()> {
+    audit("setTargetCycleLimit");
+    if ic::caller() != ic::id() {
+        return announce_call("setTargetCycleLimit", (target, limit, )).await;
+    }
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.set_target_cycle_limit(target, limit);
+    });
+    let mut details = DetailsBuilder::new().insert("target", target);
+    if let Some(limit) = limit {
+        details = details.insert("cycles", limit);
+    }
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setTargetCycleLimit")
+        .details(details.build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+#[update(name = "setGuardians", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setGuardians")]
+async fn set_guardians(members: Vec<Principal>, threshold: usize) -> Response<()> {
+    audit("setGuardians");
+    if ic::caller() != ic::id() {
+        return announce_call("setGuardians", (members, threshold, )).await;
+    }
+    GUARDIAN.with(|guardian| guardian.borrow_mut().set_members(members, threshold))
+}
+
+#[query(name = "getGuardian")]
+#[candid_method(query, rename = "getGuardian")]
+fn get_guardian() -> GuardianInfo {
+    GUARDIAN.with(|guardian| guardian.borrow().info(now()))
+}
+
+/// open a guardian action confirmed by the caller, return its id
+#[update(name = "proposeGuardianAction")]
+#[candid_method(update, rename = "proposeGuardianAction")]
+async fn propose_guardian_action(action: GuardianAction) -> Response<usize> {
+    let caller = ic::caller();
+    let id = GUARDIAN.with(|guardian| guardian.borrow_mut().propose(caller, action, now()))?;
+    confirm_guardian(caller, id).await?;
+    Ok(id)
+}
+
+/// confirm a guardian action, return whether it ran
+#[update(name = "confirmGuardianAction")]
+#[candid_method(update, rename = "confirmGuardianAction")]
+async fn confirm_guardian_action(id: usize) -> Response<bool> {
+    confirm_guardian(ic::caller(), id).await
+}
+
+#[update(name = "revokeGuardianAction")]
+#[candid_method(update, rename = "revokeGuardianAction")]
+fn revoke_guardian_action(id: usize) -> Response<()> {
+    GUARDIAN.with(|guardian| guardian.borrow_mut().revoke(ic::caller(), id, now()))
+}
+
+/// add the confirmation and run the action once the threshold is reached
+async fn confirm_guardian(caller: Principal, id: usize) -> Response<bool> {
+    let action = match GUARDIAN.with(|guardian| guardian.borrow_mut().confirm(caller, id, now()))? {
+        Some(action) => { action }
+        None => { return Ok(false); }
+    };
+    if let GuardianAction::Veto(proposal) = action {
+        BRAVO.with(|bravo| bravo.borrow_mut().veto(proposal, now()))?;
+        notify_state_changes();
+    }
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(caller)
+        .operation("guardianAction")
+        .details(match action {
+            GuardianAction::Veto(proposal) => DetailsBuilder::new().insert("action", "veto".to_string()).insert("proposalId", proposal as u64).build(),
+            GuardianAction::Pause => DetailsBuilder::new().insert("action", "pause".to_string()).build(),
+            GuardianAction::Unpause => DetailsBuilder::new().insert("action", "unpause".to_string()).build(),
+        })
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(true)
+}
+
+#[update(name = "execute")]
+#[candid_method(update, rename = "execute")]
+async fn execute(id: usize) -> Response<Vec<u8>> {
+    tracked("execute", do_execute(id).await)
+}
+
+/// execute the executable proposal with the highest priority, for keepers, return its id and the result
+#[update(name = "executeNext")]
+#[candid_method(update, rename = "executeNext")]
+async fn execute_next() -> Response<(usize, Vec<u8>)> {
+    tracked("executeNext", async {
+        let id = BRAVO.with(|bravo| bravo.borrow().next_execution(now())).ok_or("no proposal is executable")?;
+        let ret = do_execute(id).await?;
+        Ok((id, ret))
+    }.await)
+}
+
+/// let the heartbeat queue succeeded proposals and execute due tasks as the canister itself
+#[update(name = "setScheduler", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setScheduler")]
+async fn set_scheduler(enabled: bool) -> Response<()> {
+    audit("setScheduler");
+    if ic::caller() != ic::id() {
+        return announce_call("setScheduler", (enabled, )).await;
+    }
+    SCHEDULER.with(|scheduler| {
+        let mut scheduler = scheduler.borrow_mut();
+        scheduler.set_enabled(enabled);
+    });
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setScheduler")
+        .details(DetailsBuilder::new().insert("enabled", enabled.to_string()).build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+#[query(name = "getScheduler")]
+#[candid_method(query, rename = "getScheduler")]
+fn get_scheduler() -> SchedulerInfo {
+    SCHEDULER.with(|scheduler| scheduler.borrow().info())
+}
+
+async fn do_execute(id: usize) -> Response<Vec<u8>> {
+    let caller = ic::caller();
+    if !BRAVO.with(|bravo| bravo.borrow().can_execute(&caller)) {
+        return Err("caller is not an executor");
+    }
+    execute_proposal(caller, id).await
+}
+
+async fn execute_proposal(caller: Principal, id: usize) -> Response<Vec<u8>> {
+    GUARDIAN.with(|guardian| guardian.borrow().check_not_paused())?;
+    let _ = record_transitions(caller).await;
+    let timestamp = now();
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.pre_execute(id, timestamp)
+    })?;
+
+    let task = BRAVO.with(|bravo| {
+        let bravo = bravo.borrow();
+        bravo.get_task(id)
+    })?;
+    let result = match &task.backend {
+        Backend::Canister => { call_canister(id, &task).await }
+        Backend::Ethereum(tx) => { execute_ethereum(tx).await }
+        Backend::Bitcoin(transfer) => { execute_bitcoin(transfer).await }
+        Backend::Treasury(spend) => { execute_treasury(spend).await }
+        Backend::Stream(grant) => { execute_stream(grant) }
+        Backend::ParamChange(change) => { execute_param_change(change) }
+        Backend::Calls(calls) => { execute_calls(id, calls).await }
+    };
+
+    let ret = BRAVO.with(move |bravo| {
+        let mut bravo = bravo.borrow_mut();
+        match result {
+            Ok(ret) => {
+                bravo.post_execute(id, true, timestamp)?;
+                Ok(ret)
+            }
+            Err(msg) => {
+                bravo.post_execute(id, false, timestamp)?;
+                Err(msg)
+            }
+        }
+    })?;
+    #[cfg(not(test))]
+    insert(ExecuteEvent::new(caller, id as u64, ret.clone()).to_indefinite_event()).await.map_err(|_| "Cap error")?;
+    Ok(ret)
+}
+
+/// call the target canister, appending the oracle data if any, the cycles come out of the deposit pool
+async fn call_canister(id: usize, task: &Task) -> Response<Vec<u8>> {
+    let arguments = match &task.oracle {
+        Some(o) => {
+            let data = oracle::fetch(o).await?;
+            oracle::append_argument(&task.arguments, data)?
+        }
+        None => { task.arguments.to_owned() }
+    };
+    CYCLES.with(|cycles| cycles.borrow_mut().spend(task.cycles))?;
+    let result = ic::call_raw(
+        task.target,
+        task.method.to_owned(),
+        arguments,
+        task.cycles,
+    ).await.map_err(|_| "Execute error");
+    record_execution_cycles(id, task.cycles);
+    result
+}
+
+/// call the canisters of a multi-call task in order from the first call not executed yet,
+/// return the encoded replies of the calls made
+async fn execute_calls(id: usize, calls: &[Call]) -> Response<Vec<u8>> {
+    let executed = BRAVO.with(|bravo| bravo.borrow().executed_calls(id))?;
+    let mut replies = vec![];
+    for task_call in calls.iter().skip(executed) {
+        CYCLES.with(|cycles| cycles.borrow_mut().spend(task_call.cycles))?;
+        let result = ic::call_raw(
+            task_call.target,
+            task_call.method.to_owned(),
+            task_call.arguments.to_owned(),
+            task_call.cycles,
+        ).await.map_err(|_| "Execute error");
+        record_execution_cycles(id, task_call.cycles);
+        replies.push(result?);
+        BRAVO.with(|bravo| bravo.borrow_mut().record_executed_call(id, now()))?;
+    }
+    encode_one(replies).map_err(|_| "Encode error")
+}
+
+/// account the cycles of the last task call, the refunded part goes back to the deposit pool
+fn record_execution_cycles(id: usize, attached: u64) {
+    if attached == 0 {
+        return;
+    }
+    let refunded = ic::msg_cycles_refunded();
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        let _ = bravo.record_execution_cycles(id, attached, refunded);
+    });
+    CYCLES.with(|cycles| {
+        let mut cycles = cycles.borrow_mut();
+        cycles.record_refund(refunded);
+    });
+}
+
+async fn ethereum_public_key() -> Response<Vec<u8>> {
+    let (key_name, cached) = ETHEREUM.with(|ethereum| {
+        let ethereum = ethereum.borrow();
+        (ethereum.key_name.clone(), ethereum.public_key.clone())
+    });
+    if let Some(public_key) = cached {
+        return Ok(public_key);
+    }
+    if key_name.is_empty() {
+        return Err("Ethereum signer is not configured");
+    }
+    let public_key = ecdsa::public_key(&key_name, vec![]).await?;
+    ETHEREUM.with(|ethereum| {
+        let mut ethereum = ethereum.borrow_mut();
+        ethereum.public_key = Some(public_key.clone());
+    });
+    Ok(public_key)
+}
+
+/// sign the transaction with threshold ecdsa and broadcast it
+async fn execute_ethereum(tx: &EthereumTx) -> Response<Vec<u8>> {
+    let public_key = ethereum_public_key().await?;
+    let (signer, nonce) = ETHEREUM.with(|ethereum| {
+        let mut ethereum = ethereum.borrow_mut();
+        let nonce = ethereum.take_nonce(tx.chain_id);
+        (ethereum.clone(), nonce)
+    });
+    let release_nonce = || ETHEREUM.with(|ethereum| {
+        let mut ethereum = ethereum.borrow_mut();
+        ethereum.release_nonce(tx.chain_id, nonce);
+    });
+    let raw = match ethereum::sign_transaction(&signer, &public_key, tx, nonce).await {
+        Ok(raw) => { raw }
+        Err(msg) => {
+            release_nonce();
+            return Err(msg);
+        }
+    };
+    match ethereum::broadcast(&signer, &raw).await {
+        Ok(hash) => { Ok(hash) }
+        Err(error) => {
+            // after an ambiguous outcall the transaction may be on its way, so its nonce stays taken
+            // until the admin resets it
+            if let BroadcastError::Rejected(_) = error {
+                release_nonce();
+            }
+            Err(error.message())
+        }
+    }
+}
+
+async fn bitcoin_public_key() -> Response<Vec<u8>> {
+    let (key_name, cached) = BITCOIN.with(|wallet| {
+        let wallet = wallet.borrow();
+        (wallet.key_name.clone(), wallet.public_key.clone())
+    });
+    if let Some(public_key) = cached {
+        return Ok(public_key);
+    }
+    if key_name.is_empty() {
+        return Err("Bitcoin wallet is not configured");
+    }
+    let public_key = ecdsa::public_key(&key_name, bitcoin::derivation_path()).await?;
+    BITCOIN.with(|wallet| {
+        let mut wallet = wallet.borrow_mut();
+        wallet.public_key = Some(public_key.clone());
+    });
+    Ok(public_key)
+}
+
+/// spend treasury utxos to the recipient, return the txid
+async fn execute_bitcoin(transfer: &BitcoinTransfer) -> Response<Vec<u8>> {
+    let public_key = bitcoin_public_key().await?;
+    let wallet = BITCOIN.with(|wallet| {
+        let wallet = wallet.borrow();
+        wallet.clone()
+    });
+    let own_address = bitcoin::address(wallet.network, &public_key);
+    let destination = bitcoin::decode_address(wallet.network, &transfer.address)?;
+    let utxos = bitcoin::get_utxos(wallet.network, own_address).await?;
+    let fee_per_byte = bitcoin::fee_per_byte(wallet.network).await?;
+    let (inputs, fee) = BITCOIN.with(|wallet| {
+        let mut wallet = wallet.borrow_mut();
+        wallet.select_utxos(utxos, transfer.amount, fee_per_byte)
+    })?;
+    let result = bitcoin::send(&wallet, &public_key, &inputs, &destination, transfer.amount, fee).await;
+    if result.is_err() {
+        BITCOIN.with(|wallet| {
+            let mut wallet = wallet.borrow_mut();
+            wallet.release(&inputs);
+        });
+    }
+    result
+}
+
+/// transfer tokens held by the governor
+async fn execute_treasury(spend: &TreasurySpend) -> Response<Vec<u8>> {
+    let treasury = TREASURY.with(|treasury| treasury.borrow().clone());
+    let gov_token = BRAVO.with(|bravo| bravo.borrow().gov_token);
+    let result = treasury::spend(&treasury, gov_token, spend).await;
+    if result.is_ok() {
+        // keep the cached balance of the asset current, best effort
+        if let Ok(balance) = treasury::balance(&treasury, gov_token, &spend.asset).await {
+            TREASURY.with(|treasury| {
+                treasury.borrow_mut().update_balance(spend.asset, balance, now());
+            });
+        }
+    }
+    result
+}
+
+/// open the grant stream, return its id
+fn execute_stream(grant: &GrantStream) -> Response<Vec<u8>> {
+    let id = STREAMS.with(|streams| streams.borrow_mut().create(grant, now()));
+    encode_one(id).map_err(|_| "Encode error")
+}
+
+/// apply the parameter change, return the previous value
+fn execute_param_change(change: &ParamChange) -> Response<Vec<u8>> {
+    let previous = BRAVO.with(|bravo| bravo.borrow_mut().apply_param_change(change))?;
+    encode_one(previous).map_err(|_| "Encode error")
+}
+
+#[update(name = "claimStream")]
+#[candid_method(update, rename = "claimStream")]
+async fn claim_stream(id: usize) -> Response<Nat> {
+    tracked("claimStream", do_claim_stream(id).await)
+}
+
+async fn do_claim_stream(id: usize) -> Response<Nat> {
+    let caller = ic::caller();
+    let (stream, amount) = STREAMS.with(|streams| {
+        streams.borrow_mut().reserve_claim(id, &caller, now())
+    })?;
+    let spend = TreasurySpend {
+        asset: stream.asset,
+        to: stream.recipient,
+        amount: amount.clone(),
+    };
+    if let Err(msg) = execute_treasury(&spend).await {
+        STREAMS.with(|streams| streams.borrow_mut().release_claim(id, &amount));
+        return Err(msg);
+    }
+    Ok(amount)
+}
+
+#[update(name = "cancelStream", guard = "is_self")]
+#[candid_method(update, rename = "cancelStream")]
+fn cancel_stream(id: usize) -> Response<()> {
+    audit("cancelStream");
+    STREAMS.with(|streams| streams.borrow_mut().cancel(id, now()))
+}
+
+#[query(name = "getStream")]
+#[candid_method(query, rename = "getStream")]
+fn get_stream(id: usize) -> Response<(Stream, Nat)> {
+    STREAMS.with(|streams| {
+        let streams = streams.borrow();
+        let stream = streams.get(id).ok_or("stream not found")?;
+        Ok((stream.clone(), stream.claimable(now())))
+    })
+}
+
+#[query(name = "getStreams")]
+#[candid_method(query, rename = "getStreams")]
+fn get_streams(recipient: Option<Principal>) -> Vec<Stream> {
+    STREAMS.with(|streams| streams.borrow().list(recipient))
+}
+
+/// cast the caller's vote, casting again replaces the previous ballot until voting ends
+#[update(name = "castVote")]
+#[candid_method(update, rename = "castVote")]
+async fn cast_vote(id: usize, vote_type: VoteType, reason: Option<String>) -> Response<Receipt> {
+    tracked("castVote", do_cast_vote(ic::caller(), id, vote_type, None, reason).await)
+}
+
+/// vote for an option of a multi-choice proposal
+#[update(name = "castVoteOption")]
+#[candid_method(update, rename = "castVoteOption")]
+async fn cast_vote_option(id: usize, option: usize, reason: Option<String>) -> Response<Receipt> {
+    tracked("castVoteOption", do_cast_vote(ic::caller(), id, VoteType::Support, Some(option), reason).await)
+}
+
+/// cast a ballot signed by the voter, only callable by allowlisted relayers
+#[update(name = "castVoteOnBehalf")]
+#[candid_method(update, rename = "castVoteOnBehalf")]
+async fn cast_vote_on_behalf(voter: Principal, id: usize, vote_type: VoteType, signature: BallotSignature) -> Response<Receipt> {
+    tracked("castVoteOnBehalf", do_cast_vote_on_behalf(voter, id, vote_type, signature).await)
+}
+
+async fn do_cast_vote_on_behalf(voter: Principal, id: usize, vote_type: VoteType, signature: BallotSignature) -> Response<Receipt> {
+    if !RELAYERS.with(|relayers| relayers.borrow().is_allowed(&ic::caller())) {
+        return Err("caller is not an allowed relayer");
+    }
+    let message = relay::ballot_message(&ic::id(), id, &vote_type);
+    relay::verify_ballot(&voter, &message, &signature)?;
+    // unlike castVote a relayed ballot cannot replace an earlier one: the signed message carries no nonce,
+    // so a relayer could replay an old ballot to undo the voter's later change
+    if BRAVO.with(|bravo| bravo.borrow().get_receipt(id, voter).is_ok()) {
+        return Err("voter already voted");
+    }
+    do_cast_vote(voter, id, vote_type, None, None).await
+}
+
+/// cast the vote of a delegator, only callable by the delegator's standing proxy
+#[update(name = "castVoteFor")]
+#[candid_method(update, rename = "castVoteFor")]
+async fn cast_vote_for(delegator: Principal, id: usize, vote_type: VoteType, reason: Option<String>) -> Response<Receipt> {
+    tracked("castVoteFor", do_cast_vote_for(delegator, id, vote_type, reason).await)
+}
+
+async fn do_cast_vote_for(delegator: Principal, id: usize, vote_type: VoteType, reason: Option<String>) -> Response<Receipt> {
+    PROXIES.with(|proxies| proxies.borrow().check_proxy(&delegator, &ic::caller()))?;
+    // unlike castVote a proxy cannot replace a ballot: receipts do not record who cast them,
+    // so a proxy could overturn the delegator's own vote
+    if BRAVO.with(|bravo| bravo.borrow().get_receipt(id, delegator).is_ok()) {
+        return Err("voter already voted");
+    }
+    do_cast_vote(delegator, id, vote_type, None, reason).await
+}
+
+/// register a standing proxy voting for the caller on all proposals until revoked
+#[update(name = "setVotingProxy")]
+#[candid_method(update, rename = "setVotingProxy")]
+async fn set_voting_proxy(proxy: Principal) -> Response<()> {
+    let caller = ic::caller();
+    PROXIES.with(|proxies| proxies.borrow_mut().set(caller, proxy))?;
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(caller)
+        .operation("setVotingProxy")
+        .details(DetailsBuilder::new().insert("proxy", proxy).build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+#[update(name = "revokeVotingProxy")]
+#[candid_method(update, rename = "revokeVotingProxy")]
+async fn revoke_voting_proxy() -> Response<()> {
+    let caller = ic::caller();
+    #[cfg_attr(test, allow(unused_variables))]
+    let proxy = PROXIES.with(|proxies| proxies.borrow_mut().revoke(&caller))?;
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(caller)
+        .operation("revokeVotingProxy")
+        .details(DetailsBuilder::new().insert("proxy", proxy).build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+#[query(name = "getVotingProxy")]
+#[candid_method(query, rename = "getVotingProxy")]
+fn get_voting_proxy(delegator: Principal) -> Option<Principal> {
+    PROXIES.with(|proxies| proxies.borrow().proxy_of(&delegator))
+}
+
+/// delegators the proxy may vote for
+#[query(name = "getProxyDelegators")]
+#[candid_method(query, rename = "getProxyDelegators")]
+fn get_proxy_delegators(proxy: Principal) -> Vec<Principal> {
+    PROXIES.with(|proxies| proxies.borrow().delegators_of(&proxy))
+}
+
+async fn do_cast_vote(caller: Principal, id: usize, vote_type: VoteType, option: Option<usize>, reason: Option<String>) -> Response<Receipt> {
+    GUARDIAN.with(|guardian| guardian.borrow().check_not_paused())?;
+    let timestamp = now();
+    let ramp = BRAVO.with(|bravo| bravo.borrow().vote_ramp(id))?;
+    let votes = prior_votes(caller, timestamp, ramp).await?;
+    let total_supply = if BRAVO.with(|bravo| bravo.borrow().proposal_early_finish(id))? {
+        prior_total_supply(timestamp).await
+    } else {
+        None
+    };
+    VOTES_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.record(caller, id, &votes, timestamp);
+    });
+    let breakdown = votes_breakdown(caller, &votes).await;
+    let verifier = IDENTITY.with(|identity| identity.borrow().clone());
+    let votes = verifier.votes(caller, votes).await?;
+    let (receipt, quorum_crossed) = BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        let reached = bravo.quorum_reached(id)?;
+        let mut receipt = match option {
+            Some(option) => { bravo.cast_vote_option(id, option, votes.clone(), reason, caller, timestamp)? }
+            None => { bravo.cast_vote(id, vote_type.clone(), votes.clone(), reason, caller, timestamp)? }
+        };
+        if let Some(breakdown) = breakdown {
+            receipt = bravo.set_receipt_breakdown(id, caller, breakdown)?;
+        }
+        if let Some(total_supply) = total_supply {
+            bravo.record_max_votes(id, total_supply)?;
+        }
+        Ok((receipt, !reached && bravo.quorum_reached(id)?))
+    })?;
+    notify_state_changes();
+    if quorum_crossed {
+        notify_quorum_reached(id);
+    }
+    #[cfg(not(test))]
+    insert(VoteEvent::new(caller, id as u64, votes, vote_type, option).to_indefinite_event()).await.map_err(|_| "Cap error")?;
+    #[cfg(not(test))]
+    if quorum_crossed {
+        let support_votes = BRAVO.with(|bravo| bravo.borrow().get_proposal(id).map(|p| p.support_votes))?;
+        insert(QuorumReachedEvent::new(caller, id as u64, support_votes).to_indefinite_event()).await.map_err(|_| "Cap error")?;
+    }
+    Ok(receipt)
+}
+
+/// token votes of the voter at the timestamp, only votes held throughout the holding duration count,
+/// with a ramp votes held for less than it count linearly less instead
+async fn prior_votes(voter: Principal, timestamp: u64, ramp: Option<u64>) -> Response<Nat> {
+    let (gov_token, min_holding_duration) = BRAVO.with(|bravo| {
+        let bravo = bravo.borrow();
+        (bravo.gov_token, bravo.min_holding_duration())
+    });
+    let result : CallResult<(Nat, )> = if let Some(ramp) = ramp {
+        call(gov_token, "getRampedPriorVotes", (voter, Nat::from(timestamp), ramp, )).await
+    } else if min_holding_duration == 0 {
+        call(gov_token, "getPriorVotes", (voter, Nat::from(timestamp), )).await
+    } else {
+        let from = Nat::from(timestamp.saturating_sub(min_holding_duration));
+        call(gov_token, "getMinPriorVotes", (voter, from, Nat::from(timestamp), )).await
+    };
+    match result {
+        Ok(res) => { Ok(res.0) }
+        Err(_) => { Err("Error in getting proposer's prior vote") }
+    }
+}
+
+/// total supply of the token at the timestamp, none if unavailable
+async fn prior_total_supply(timestamp: u64) -> Option<Nat> {
+    let gov_token = BRAVO.with(|bravo| bravo.borrow().gov_token);
+    let result : CallResult<(Option<Nat>, )> = call(gov_token, "getPriorTotalSupply", (Nat::from(timestamp), )).await;
+    result.ok()?.0
+}
+
+/// split of the voter's token votes between its own balance and delegations, none if unavailable
+async fn votes_breakdown(voter: Principal, votes: &Nat) -> Option<VotesBreakdown> {
+    let gov_token = BRAVO.with(|bravo| bravo.borrow().gov_token);
+    let result : CallResult<((Nat, Nat), )> = call(gov_token, "getVotesBreakdown", (voter, )).await;
+    let (own, _) = result.ok()?.0;
+    // counted votes may be below the current ones, e.g. with a holding duration
+    let own = own.min(votes.clone());
+    Some(VotesBreakdown {
+        delegated: votes.clone() - own.clone(),
+        own,
+    })
+}
+
+/// votes the principal would have counted if voting on the proposal now
+#[update(name = "getVotingPower")]
+#[candid_method(update, rename = "getVotingPower")]
+async fn get_voting_power(principal: Principal, id: usize) -> Response<Nat> {
+    let ramp = BRAVO.with(|bravo| bravo.borrow().vote_ramp(id))?;
+    let votes = prior_votes(principal, now(), ramp).await?;
+    let verifier = IDENTITY.with(|identity| identity.borrow().clone());
+    let votes = verifier.votes(principal, votes).await?;
+    BRAVO.with(|bravo| bravo.borrow().counted_votes(id, votes))
+}
+
+/// pull the tallies of satellites still pending for an ended proposal, return satellites left
+#[update(name = "aggregateSatelliteTallies")]
+#[candid_method(update, rename = "aggregateSatelliteTallies")]
+async fn aggregate_satellite_tallies(id: usize) -> Response<Vec<Principal>> {
+    let caller = ic::caller();
+    let satellites = BRAVO.with(|bravo| {
+        let bravo = bravo.borrow();
+        bravo.pending_satellites(id)
+    })?;
+    let mut merged = vec![];
+    for satellite in satellites {
+        let result : CallResult<(Tally, )> = call(satellite, "getProposalTally", (id, )).await;
+        if let Ok((tally, )) = result {
+            let res = BRAVO.with(|bravo| {
+                let mut bravo = bravo.borrow_mut();
+                bravo.merge_satellite_tally(id, satellite, tally, now())
+            });
+            if res.is_ok() {
+                merged.push(satellite);
+            }
+        }
+    }
+    #[cfg(not(test))]
+    for satellite in merged {
+        insert(SatelliteTallyEvent::new(caller, id as u64, satellite).to_indefinite_event()).await.map_err(|_| "Cap error")?;
+    }
+    BRAVO.with(|bravo| {
+        let bravo = bravo.borrow();
+        bravo.pending_satellites(id)
+    })
+}
+
+/// set the committee whose threshold signatures attest off-chain tallies
+#[update(name = "setSignerCommittee", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setSignerCommittee")]
+async fn set_signer_committee(public_keys: Vec<Vec<u8>>, threshold: usize) -> Response<()> {
+    audit("setSignerCommittee");
+    if ic::caller() != ic::id() {
+        return announce_call("setSignerCommittee", (public_keys, threshold, )).await;
+    }
+    OFFCHAIN.with(|offchain| offchain.borrow_mut().set(public_keys, threshold))
+}
+
+#[query(name = "getSignerCommittee")]
+#[candid_method(query, rename = "getSignerCommittee")]
+fn get_signer_committee() -> SignerCommitteeInfo {
+    OFFCHAIN.with(|offchain| offchain.borrow().info())
+}
+
+/// merge an off-chain tally signed by threshold members of the signer committee
+#[update(name = "importOffchainVotes")]
+#[candid_method(update, rename = "importOffchainVotes")]
+async fn import_offchain_votes(tally: OffchainTally, signatures: Vec<Vec<u8>>) -> Response<()> {
+    let caller = ic::caller();
+    let message = offchain::tally_message(&ic::id(), &tally)?;
+    OFFCHAIN.with(|offchain| {
+        let offchain = offchain.borrow();
+        offchain.check_not_imported(&tally)?;
+        offchain.verify(&message, &signatures)
+    })?;
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.merge_offchain_tally(tally.proposal_id, tally.tally.clone(), now())
+    })?;
+    OFFCHAIN.with(|offchain| offchain.borrow_mut().mark_imported(&tally));
+    notify_state_changes();
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(caller)
+        .operation("importOffchainVotes")
+        .details(DetailsBuilder::new()
+            .insert("proposalId", tally.proposal_id as u64)
+            .insert("snapshot", tally.snapshot)
+            .insert("supportVotes", tally.tally.support_votes.0.to_string())
+            .insert("againstVotes", tally.tally.against_votes.0.to_string())
+            .insert("abstainVotes", tally.tally.abstain_votes.0.to_string())
+            .build()
+        )
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+#[update(name = "addSatellite", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "addSatellite")]
+async fn add_satellite(satellite: Principal) -> Response<()> {
+    audit("addSatellite");
+    if ic::caller() != ic::id() {
+        return announce_call("addSatellite", (satellite, )).await;
+    }
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.add_satellite(satellite)
+    })?;
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("addSatellite")
+        .details(DetailsBuilder::new().insert("satellite", satellite).build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+#[update(name = "removeSatellite", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "removeSatellite")]
+async fn remove_satellite(satellite: Principal) -> Response<()> {
+    audit("removeSatellite");
+    if ic::caller() != ic::id() {
+        return announce_call("removeSatellite", (satellite, )).await;
+    }
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.remove_satellite(satellite)
+    })?;
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("removeSatellite")
+        .details(DetailsBuilder::new().insert("satellite", satellite).build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// notify proposals which changed state without any call, e.g. voting started
+#[update(name = "notifyStateChanges")]
+#[candid_method(update, rename = "notifyStateChanges")]
+async fn notify_state_changes_endpoint() {
+    notify_state_changes();
+    let _ = record_transitions(ic::caller()).await;
+}
+
+/// subscribe a canister to proposal state transitions from now on, by the admin or governance
+/// as the governor pays for every notification
+#[update(name = "subscribe", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "subscribe")]
+fn subscribe(canister: Principal, method: String) -> Response<()> {
+    audit("subscribe");
+    BRAVO.with(|bravo| {
+        NOTIFIER.with(|notifier| {
+            let mut notifier = notifier.borrow_mut();
+            notifier.subscribe(canister, method, &bravo.borrow(), now())
+        })
+    })
+}
+
+/// set the method a subscriber is called with once a proposal reaches quorum, none to stop
+#[update(name = "subscribeQuorumReached")]
+#[candid_method(update, rename = "subscribeQuorumReached")]
+fn subscribe_quorum_reached(canister: Principal, method: Option<String>) -> Response<()> {
+    let caller = ic::caller();
+    if caller != canister && is_admin().is_err() {
+        return Err("Unauthorized");
+    }
+    NOTIFIER.with(|notifier| {
+        let mut notifier = notifier.borrow_mut();
+        notifier.set_quorum_method(canister, method)
+    })
+}
+
+/// set the method a subscriber is called with as voting ends or a queued proposal expires, none to stop
+#[update(name = "subscribeReminders")]
+#[candid_method(update, rename = "subscribeReminders")]
+fn subscribe_reminders(canister: Principal, method: Option<String>) -> Response<()> {
+    let caller = ic::caller();
+    if caller != canister && is_admin().is_err() {
+        return Err("Unauthorized");
+    }
+    NOTIFIER.with(|notifier| {
+        let mut notifier = notifier.borrow_mut();
+        notifier.set_reminder_method(canister, method)
+    })
+}
+
+#[update(name = "unsubscribe")]
+#[candid_method(update, rename = "unsubscribe")]
+fn unsubscribe(canister: Principal) -> Response<()> {
+    let caller = ic::caller();
+    if caller != canister && is_admin().is_err() {
+        return Err("Unauthorized");
+    }
+    NOTIFIER.with(|notifier| {
+        let mut notifier = notifier.borrow_mut();
+        notifier.unsubscribe(canister)
+    })
+}
+
+#[query(name = "getSubscribers")]
+#[candid_method(query, rename = "getSubscribers")]
+fn get_subscribers() -> Vec<Subscriber> {
+    NOTIFIER.with(|notifier| {
+        let notifier = notifier.borrow();
+        notifier.subscribers.values().cloned().collect()
+    })
+}
+
+/// not timelocked: notifications do not affect proposals
+#[update(name = "setNotifier", guard = "is_admin")]
+#[candid_method(update, rename = "setNotifier")]
+async fn set_notifier(canister: Option<Principal>, method: String) -> Response<()> {
+    audit("setNotifier");
+    NOTIFIER.with(|notifier| {
+        let mut notifier = notifier.borrow_mut();
+        notifier.set_target(canister, method.clone());
+    });
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setNotifier")
+        .details(match canister {
+            Some(c) => DetailsBuilder::new().insert("canister", c).insert("method", method).build(),
+            None => DetailsBuilder::new().insert("method", method).build(),
+        })
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+#[update(name = "setIdentityVerifier", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setIdentityVerifier")]
+async fn set_identity_verifier(canister: Option<Principal>, method: String, mode: IdentityMode) -> Response<()> {
+    audit("setIdentityVerifier");
+    if ic::caller() != ic::id() {
+        return announce_call("setIdentityVerifier", (canister, method, mode, )).await;
+    }
+    IDENTITY.with(|identity| {
+        let mut identity = identity.borrow_mut();
+        identity.set(canister, method.clone(), mode);
+    });
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setIdentityVerifier")
+        .details(match canister {
+            Some(c) => DetailsBuilder::new().insert("canister", c).insert("method", method).build(),
+            None => DetailsBuilder::new().insert("method", method).build(),
+        })
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+#[query(name = "getIdentityVerifier")]
+#[candid_method(query, rename = "getIdentityVerifier")]
+fn get_identity_verifier() -> IdentityVerifier {
+    IDENTITY.with(|identity| identity.borrow().clone())
+}
+
+/// not timelocked: relayers only submit ballots signed by the voters
+#[update(name = "addRelayer", guard = "is_admin")]
+#[candid_method(update, rename = "addRelayer")]
+async fn add_relayer(relayer: Principal) -> Response<()> {
+    audit("addRelayer");
+    RELAYERS.with(|relayers| relayers.borrow_mut().add(relayer))?;
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("addRelayer")
+        .details(DetailsBuilder::new().insert("relayer", relayer).build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// not timelocked: relayers only submit ballots signed by the voters
+#[update(name = "removeRelayer", guard = "is_admin")]
+#[candid_method(update, rename = "removeRelayer")]
+async fn remove_relayer(relayer: Principal) -> Response<()> {
+    audit("removeRelayer");
+    RELAYERS.with(|relayers| relayers.borrow_mut().remove(&relayer))?;
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("removeRelayer")
+        .details(DetailsBuilder::new().insert("relayer", relayer).build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+#[query(name = "getRelayers")]
+#[candid_method(query, rename = "getRelayers")]
+fn get_relayers() -> Vec<Principal> {
+    RELAYERS.with(|relayers| relayers.borrow().relayers.iter().cloned().collect())
+}
+
+/// not timelocked: the pending admin gets no more power than the admin has, once accepted
+#[update(name = "setPendingAdmin", guard = "is_admin")]
+#[candid_method(update, rename = "setPendingAdmin")]
+async fn set_pending_admin(pending_admin: Principal) -> Response<()> {
+    audit("setPendingAdmin");
+    let caller = ic::caller();
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.set_pending_admin(pending_admin);
+    });
+    #[cfg(not(test))]
+    insert(SetPendingAdminEvent::new(caller, pending_admin).to_indefinite_event()).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+#[update(name = "acceptAdmin")]
+#[candid_method(update, rename = "setAdmin")]
+async fn accept_admin() -> Response<()> {
+    let caller = ic::caller();
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        if bravo.pending_admin != Some(caller) {
+            Err("Unauthorized")
+        } else {
+            bravo.accept_admin();
             Ok(())
         }
     })?;
     #[cfg(not(test))]
-    insert(AcceptAdminEvent::new(caller).to_indefinite_event()).await.map_err(|_| "Cap error")?;
+    insert(AcceptAdminEvent::new(caller).to_indefinite_event()).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// announce an admin parameter change through the timelock, it applies once the delay has passed
+async fn announce_param_change(field: GovernorParam, new_value: Nat) -> Response<()> {
+    let change = ParamChange { field, new_value };
+    #[cfg_attr(test, allow(unused_variables))]
+    let announced = BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.announce_param_change(change, ic::caller(), now())
+    })?;
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("announceParamChange")
+        .details(DetailsBuilder::new()
+            .insert("changeId", announced.id as u64)
+            .insert("field", format!("{:?}", announced.change.field))
+            .insert("newValue", announced.change.new_value.clone())
+            .insert("eta", announced.eta)
+            .build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// apply an announced parameter change once its eta has passed, return the previous value
+#[update(name = "applyParamChange")]
+#[candid_method(update, rename = "applyParamChange")]
+async fn apply_param_change(id: usize) -> Response<Nat> {
+    tracked("applyParamChange", do_apply_param_change(id).await)
+}
+
+async fn do_apply_param_change(id: usize) -> Response<Nat> {
+    GUARDIAN.with(|guardian| guardian.borrow().check_not_paused())?;
+    #[cfg_attr(test, allow(unused_variables))]
+    let (change, previous) = BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.apply_announced_change(id, now())
+    })?;
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("applyParamChange")
+        .details(DetailsBuilder::new()
+            .insert("changeId", id as u64)
+            .insert("field", format!("{:?}", change.field))
+            .insert("newValue", change.new_value)
+            .build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(previous)
+}
+
+/// drop an announced parameter change before it is applied
+#[update(name = "cancelParamChange", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "cancelParamChange")]
+async fn cancel_param_change(id: usize) -> Response<()> {
+    audit("cancelParamChange");
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.cancel_announced_change(id)
+    })?;
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("cancelParamChange")
+        .details(DetailsBuilder::new().insert("changeId", id as u64).build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+#[query(name = "getAnnouncedChanges")]
+#[candid_method(query, rename = "getAnnouncedChanges")]
+fn get_announced_changes() -> Vec<AnnouncedChange> {
+    BRAVO.with(|bravo| bravo.borrow().timelock.announced_changes.clone())
+}
+
+/// announce a call of the setter by the governor itself through the timelock, it is made once the delay
+/// has passed. every admin setter which can change the outcome of proposals or what the governor may do
+/// is either a parameter change or announced this way, the setters left immediate say why
+async fn announce_call<T: ArgumentEncoder>(method: &str, arguments: T) -> Response<()> {
+    let arguments = encode_args(arguments).map_err(|_| "Encode error")?;
+    #[cfg_attr(test, allow(unused_variables))]
+    let announced = BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.timelock.announce_call(method.to_string(), arguments, ic::caller(), now())
+    });
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("announceCall")
+        .details(DetailsBuilder::new()
+            .insert("callId", announced.id as u64)
+            .insert("method", announced.method.clone())
+            .insert("eta", announced.eta)
+            .build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// make an announced setter call once its eta has passed, a failed call can be made again until it expires
+#[update(name = "applyAnnouncedCall")]
+#[candid_method(update, rename = "applyAnnouncedCall")]
+async fn apply_announced_call(id: usize) -> Response<()> {
+    tracked("applyAnnouncedCall", do_apply_announced_call(id).await)
+}
+
+async fn do_apply_announced_call(id: usize) -> Response<()> {
+    GUARDIAN.with(|guardian| guardian.borrow().check_not_paused())?;
+    let announced = BRAVO.with(|bravo| bravo.borrow_mut().timelock.take_call(id, now()))?;
+    let result = ic::call_raw(ic::id(), announced.method.clone(), announced.arguments.clone(), 0).await;
+    // setters answer with a result, a reply of any other shape is taken as success
+    let applied = match result {
+        Ok(reply) => {
+            decode_args::<(Result<Reserved, String>, )>(&reply).map_or(true, |(r, )| r.is_ok())
+        }
+        Err(_) => { false }
+    };
+    if !applied {
+        BRAVO.with(|bravo| bravo.borrow_mut().timelock.restore_call(announced));
+        return Err("Announced call failed");
+    }
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("applyAnnouncedCall")
+        .details(DetailsBuilder::new()
+            .insert("callId", id as u64)
+            .insert("method", announced.method)
+            .build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// drop an announced setter call before it is made
+#[update(name = "cancelAnnouncedCall", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "cancelAnnouncedCall")]
+async fn cancel_announced_call(id: usize) -> Response<()> {
+    audit("cancelAnnouncedCall");
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.timelock.cancel_call(id)
+    })?;
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("cancelAnnouncedCall")
+        .details(DetailsBuilder::new().insert("callId", id as u64).build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+#[query(name = "getAnnouncedCalls")]
+#[candid_method(query, rename = "getAnnouncedCalls")]
+fn get_announced_calls() -> Vec<AnnouncedCall> {
+    BRAVO.with(|bravo| bravo.borrow().timelock.announced_calls.clone())
+}
+
+#[update(name = "setQuorumVotes", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setQuorumVotes")]
+async fn set_quorum_votes(quorum: Nat) -> Response<()> {
+    audit("setQuorumVotes");
+    if ic::caller() != ic::id() {
+        return announce_param_change(GovernorParam::QuorumVotes, quorum).await;
+    }
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.set_quorum_votes(quorum.clone());
+    });
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setQuorumVotes")
+        .details(DetailsBuilder::new().insert("quorumVotes", quorum).build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// make the quorum of later proposals a fraction of the total supply in basis points, 0 to use the quorum votes
+#[update(name = "setQuorumFraction", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setQuorumFraction")]
+async fn set_quorum_fraction(bps: u64) -> Response<()> {
+    audit("setQuorumFraction");
+    if ic::caller() != ic::id() {
+        return announce_param_change(GovernorParam::QuorumFraction, Nat::from(bps)).await;
+    }
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.set_quorum_fraction(bps)
+    })?;
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setQuorumFraction")
+        .details(DetailsBuilder::new().insert("quorumFraction", bps).build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// bounds of the quorum proposals may be given, none disables overrides
+#[update(name = "setQuorumOverrideBounds", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setQuorumOverrideBounds")]
+async fn set_quorum_override_bounds(bounds: Option<(Nat, Nat)>) -> Response<()> {
+    audit("setQuorumOverrideBounds");
+    if ic::caller() != ic::id() {
+        return announce_call("setQuorumOverrideBounds", (bounds, )).await;
+    }
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.set_quorum_override_bounds(bounds.clone())
+    })?;
+    let mut details = DetailsBuilder::new();
+    if let Some((min, max)) = bounds {
+        details = details.insert("minQuorum", min).insert("maxQuorum", max);
+    }
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setQuorumOverrideBounds")
+        .details(details.build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// allow proposers to request a voting period within the bounds, none disables overrides
+#[update(name = "setVotingPeriodBounds", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setVotingPeriodBounds")]
+async fn set_voting_period_bounds(bounds: Option<(u64, u64)>) -> Response<()> {
+    audit("setVotingPeriodBounds");
+    if ic::caller() != ic::id() {
+        return announce_call("setVotingPeriodBounds", (bounds, )).await;
+    }
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.set_voting_period_bounds(bounds)
+    })?;
+    let mut details = DetailsBuilder::new();
+    if let Some((min, max)) = bounds {
+        details = details.insert("minVotingPeriod", min).insert("maxVotingPeriod", max);
+    }
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setVotingPeriodBounds")
+        .details(details.build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// override the quorum of a proposal before voting starts, none restores the governor's
+/// not timelocked: bounded by the override bounds, which are timelocked
+#[update(name = "setQuorumOverride", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setQuorumOverride")]
+async fn set_quorum_override(id: usize, quorum: Option<Nat>) -> Response<()> {
+    audit("setQuorumOverride");
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.set_quorum_override(id, quorum.clone(), now())
+    })?;
+    let mut details = DetailsBuilder::new().insert("proposalId", id as u64);
+    if let Some(quorum) = quorum {
+        details = details.insert("quorumVotes", quorum);
+    }
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setQuorumOverride")
+        .details(details.build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
     Ok(())
 }
 
-#[update(name = "setQuorumVotes", guard = "is_admin")]
-#[candid_method(update, rename = "setQuorumVotes")]
-async fn set_quorum_votes(quorum: u64) -> Response<()> {
+/// set the execution priority of a proposal until it is executed, higher executes first
+/// not timelocked: only orders the execution of proposals which passed
+#[update(name = "setProposalPriority", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setProposalPriority")]
+async fn set_proposal_priority(id: usize, priority: u8) -> Response<()> {
+    audit("setProposalPriority");
     BRAVO.with(|bravo| {
         let mut bravo = bravo.borrow_mut();
-        bravo.set_quorum_votes(quorum);
-    });
+        bravo.set_priority(id, priority, now())
+    })?;
     #[cfg(not(test))]
     insert(IndefiniteEventBuilder::new()
         .caller(ic::caller())
-        .operation("setQuorumVotes")
-        .details(vec![("quorumVotes".to_string(), U64(quorum))])
+        .operation("setProposalPriority")
+        .details(DetailsBuilder::new().insert("proposalId", id as u64).insert("priority", priority as u64).build())
         .build()
         .unwrap()
     ).await.map_err(|_| "Cap error")?;
     Ok(())
 }
 
-#[update(name = "setVotePeriod", guard = "is_admin")]
+#[update(name = "setVotePeriod", guard = "is_admin_or_self")]
 #[candid_method(update, rename = "setVotePeriod")]
 async fn set_vote_period(period: u64) -> Response<()> {
+    audit("setVotePeriod");
+    if ic::caller() != ic::id() {
+        return announce_param_change(GovernorParam::VotingPeriod, Nat::from(period)).await;
+    }
     // if period < GovernorBravo::MIN_VOTING_PERIOD {
     //     return Err("Invalid vote period: too small");
     // }
@@ -394,9 +2652,13 @@ async fn set_vote_period(period: u64) -> Response<()> {
     Ok(())
 }
 
-#[update(name = "setVoteDelay", guard = "is_admin")]
+#[update(name = "setVoteDelay", guard = "is_admin_or_self")]
 #[candid_method(update, rename = "setVoteDelay")]
 async fn set_vote_delay(delay: u64) -> Response<()> {
+    audit("setVoteDelay");
+    if ic::caller() != ic::id() {
+        return announce_param_change(GovernorParam::VotingDelay, Nat::from(delay)).await;
+    }
     // if delay < GovernorBravo::MIN_VOTING_DELAY {
     //     return Err("Invalid vote delay: too small");
     // }
@@ -418,9 +2680,13 @@ async fn set_vote_delay(delay: u64) -> Response<()> {
     Ok(())
 }
 
-#[update(name = "setProposalThreshold", guard = "is_admin")]
+#[update(name = "setProposalThreshold", guard = "is_admin_or_self")]
 #[candid_method(update, rename = "setProposalThreshold")]
-async fn set_proposal_threshold(threshold: u64) -> Response<()> {
+async fn set_proposal_threshold(threshold: Nat) -> Response<()> {
+    audit("setProposalThreshold");
+    if ic::caller() != ic::id() {
+        return announce_param_change(GovernorParam::ProposalThreshold, threshold).await;
+    }
     // if threshold < GovernorBravo::MIN_PROPOSAL_THRESHOLD {
     //     return Err("Invalid proposal threshold: too small");
     // }
@@ -429,22 +2695,273 @@ async fn set_proposal_threshold(threshold: u64) -> Response<()> {
     // }
     BRAVO.with(|bravo| {
         let mut bravo = bravo.borrow_mut();
-        bravo.set_proposal_threshold(threshold);
+        bravo.set_proposal_threshold(threshold.clone());
     });
     #[cfg(not(test))]
     insert(IndefiniteEventBuilder::new()
         .caller(ic::caller())
         .operation("setProposalThreshold")
-        .details(vec![("proposalThreshold".to_string(), U64(threshold))])
+        .details(DetailsBuilder::new().insert("proposalThreshold", threshold).build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// set how votes are counted on proposals of the type, e.g. "canister", applies to new proposals
+#[update(name = "setCountingMode", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setCountingMode")]
+async fn set_counting_mode(kind: String, mode: CountingMode) -> Response<()> {
+    audit("setCountingMode");
+    if ic::caller() != ic::id() {
+        return announce_call("setCountingMode", (kind, mode, )).await;
+    }
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.set_counting_mode(kind.clone(), mode)
+    })?;
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setCountingMode")
+        .details(counting_details(DetailsBuilder::new().insert("kind", kind), mode).build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// set how votes are counted on proposals of types without their own counting mode
+#[update(name = "setDefaultCountingMode", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setDefaultCountingMode")]
+async fn set_default_counting_mode(mode: CountingMode) -> Response<()> {
+    audit("setDefaultCountingMode");
+    if ic::caller() != ic::id() {
+        return announce_call("setDefaultCountingMode", (mode, )).await;
+    }
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.set_default_counting_mode(mode)
+    })?;
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setDefaultCountingMode")
+        .details(counting_details(DetailsBuilder::new(), mode).build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// cap details of the counting mode
+#[cfg(not(test))]
+fn counting_details(details: DetailsBuilder, mode: CountingMode) -> DetailsBuilder {
+    let details = details.insert("mode", mode.name().to_string());
+    match mode {
+        CountingMode::OnePrincipalOneVote(min_votes) => details.insert("minVotes", min_votes),
+        CountingMode::Supermajority(threshold_bps) | CountingMode::Approval(threshold_bps) => details.insert("thresholdBps", threshold_bps),
+        CountingMode::Ramped(ramp) => details.insert("ramp", ramp),
+        CountingMode::TokenWeighted | CountingMode::Quadratic => details,
+    }
+}
+
+/// set when queued proposals of the type may be executed, none restores the grace period after the eta
+#[update(name = "setExecutionWindow", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setExecutionWindow")]
+async fn set_execution_window(kind: String, window: Option<ExecutionWindow>) -> Response<()> {
+    audit("setExecutionWindow");
+    if ic::caller() != ic::id() {
+        return announce_call("setExecutionWindow", (kind, window, )).await;
+    }
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.set_execution_window(kind.clone(), window)
+    })?;
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setExecutionWindow")
+        .details(match window {
+            Some(window) => DetailsBuilder::new().insert("kind", kind).insert("offset", window.offset).insert("length", window.length).build(),
+            None => DetailsBuilder::new().insert("kind", kind).build(),
+        })
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+#[update(name = "setMinHoldingDuration", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setMinHoldingDuration")]
+async fn set_min_holding_duration(duration: u64) -> Response<()> {
+    audit("setMinHoldingDuration");
+    if ic::caller() != ic::id() {
+        return announce_param_change(GovernorParam::MinHoldingDuration, Nat::from(duration)).await;
+    }
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.set_min_holding_duration(duration);
+    });
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setMinHoldingDuration")
+        .details(vec![("minHoldingDuration".to_string(), U64(duration))])
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// proposer votes must have been above the proposal threshold for the age before proposing, 0 to disable
+#[update(name = "setMinDelegationAge", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setMinDelegationAge")]
+async fn set_min_delegation_age(age: u64) -> Response<()> {
+    audit("setMinDelegationAge");
+    if ic::caller() != ic::id() {
+        return announce_param_change(GovernorParam::MinDelegationAge, Nat::from(age)).await;
+    }
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.set_min_delegation_age(age);
+    });
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setMinDelegationAge")
+        .details(vec![("minDelegationAge".to_string(), U64(age))])
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// how proposals with as many support as against votes are settled
+#[update(name = "setTieRule", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setTieRule")]
+async fn set_tie_rule(rule: TieRule) -> Response<()> {
+    audit("setTieRule");
+    if ic::caller() != ic::id() {
+        return announce_call("setTieRule", (rule, )).await;
+    }
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.set_tie_rule(rule);
+    });
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setTieRule")
+        .details(DetailsBuilder::new().insert("tieRule", format!("{:?}", rule)).build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// whether later proposals close voting once their outcome can no longer change
+#[update(name = "setEarlyFinish", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setEarlyFinish")]
+async fn set_early_finish(enabled: bool) -> Response<()> {
+    audit("setEarlyFinish");
+    if ic::caller() != ic::id() {
+        return announce_call("setEarlyFinish", (enabled, )).await;
+    }
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.set_early_finish(enabled);
+    });
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setEarlyFinish")
+        .details(DetailsBuilder::new().insert("enabled", enabled.to_string()).build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// not timelocked: comments do not affect proposals
+#[update(name = "setMinCommentBalance", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setMinCommentBalance")]
+async fn set_min_comment_balance(balance: u64) -> Response<()> {
+    audit("setMinCommentBalance");
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.set_min_comment_balance(balance);
+    });
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setMinCommentBalance")
+        .details(vec![("minCommentBalance".to_string(), U64(balance))])
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// set the gov tokens locked by later proposers and where slashed deposits go, 0 to disable
+#[update(name = "setProposalDeposit", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setProposalDeposit")]
+async fn set_proposal_deposit(amount: Nat, treasury: Option<Principal>) -> Response<()> {
+    audit("setProposalDeposit");
+    if ic::caller() != ic::id() {
+        return announce_call("setProposalDeposit", (amount, treasury, )).await;
+    }
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.set_proposal_deposit(amount.clone(), treasury);
+    });
+    #[cfg(not(test))]
+    {
+        let mut details = DetailsBuilder::new().insert("amount", amount);
+        if let Some(treasury) = treasury {
+            details = details.insert("treasury", treasury);
+        }
+        insert(IndefiniteEventBuilder::new()
+            .caller(ic::caller())
+            .operation("setProposalDeposit")
+            .details(details.build())
+            .build()
+            .unwrap()
+        ).await.map_err(|_| "Cap error")?;
+    }
+    Ok(())
+}
+
+/// set how long after voting ended a task identical to a defeated proposal's is refused, 0 to disable
+#[update(name = "setDefeatCooldown", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setDefeatCooldown")]
+async fn set_defeat_cooldown(cooldown: u64) -> Response<()> {
+    audit("setDefeatCooldown");
+    if ic::caller() != ic::id() {
+        return announce_call("setDefeatCooldown", (cooldown, )).await;
+    }
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.set_defeat_cooldown(cooldown);
+    });
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setDefeatCooldown")
+        .details(vec![("defeatCooldown".to_string(), U64(cooldown))])
         .build()
         .unwrap()
     ).await.map_err(|_| "Cap error")?;
     Ok(())
 }
 
-#[update(name = "setTimelockDelay", guard = "is_admin")]
+#[update(name = "setTimelockDelay", guard = "is_admin_or_self")]
 #[candid_method(update, rename = "setTimelockDelay")]
 async fn set_timelock_delay(delay: u64) -> Response<()> {
+    audit("setTimelockDelay");
+    if ic::caller() != ic::id() {
+        return announce_param_change(GovernorParam::TimelockDelay, Nat::from(delay)).await;
+    }
     // if delay < Timelock::MIN_DELAY {
     //     return Err("Invalid timelock delay: too small");
     // }
@@ -466,22 +2983,457 @@ async fn set_timelock_delay(delay: u64) -> Response<()> {
     Ok(())
 }
 
+#[update(name = "getEthereumAddress")]
+#[candid_method(update, rename = "getEthereumAddress")]
+async fn get_ethereum_address() -> Response<String> {
+    let public_key = ethereum_public_key().await?;
+    let address = ethereum::address(&public_key)?;
+    Ok(ethereum::to_hex(&address))
+}
+
+#[update(name = "setEthereumSigner", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setEthereumSigner")]
+async fn set_ethereum_signer(key_name: String, rpc_url: String, rpc_cycles: u64) -> Response<()> {
+    audit("setEthereumSigner");
+    if ic::caller() != ic::id() {
+        return announce_call("setEthereumSigner", (key_name, rpc_url, rpc_cycles, )).await;
+    }
+    ETHEREUM.with(|ethereum| {
+        let mut ethereum = ethereum.borrow_mut();
+        ethereum.set_config(key_name.clone(), rpc_url.clone(), rpc_cycles);
+    });
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setEthereumSigner")
+        .details(vec![
+            ("keyName".to_string(), Text(key_name)),
+            ("rpcUrl".to_string(), Text(rpc_url)),
+            ("rpcCycles".to_string(), U64(rpc_cycles)),
+        ])
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// not timelocked: resyncs the nonce after a lost transaction, the chain refuses reused nonces
+#[update(name = "setEthereumNonce", guard = "is_admin")]
+#[candid_method(update, rename = "setEthereumNonce")]
+async fn set_ethereum_nonce(chain_id: u64, nonce: u64) -> Response<()> {
+    audit("setEthereumNonce");
+    ETHEREUM.with(|ethereum| {
+        let mut ethereum = ethereum.borrow_mut();
+        ethereum.set_nonce(chain_id, nonce);
+    });
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setEthereumNonce")
+        .details(vec![
+            ("chainId".to_string(), U64(chain_id)),
+            ("nonce".to_string(), U64(nonce)),
+        ])
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+#[update(name = "getBitcoinAddress")]
+#[candid_method(update, rename = "getBitcoinAddress")]
+async fn get_bitcoin_address() -> Response<String> {
+    let public_key = bitcoin_public_key().await?;
+    let network = BITCOIN.with(|wallet| {
+        let wallet = wallet.borrow();
+        wallet.network
+    });
+    Ok(bitcoin::address(network, &public_key))
+}
+
+/// satoshi held by the bitcoin treasury
+#[update(name = "getBitcoinBalance")]
+#[candid_method(update, rename = "getBitcoinBalance")]
+async fn get_bitcoin_balance() -> Response<u64> {
+    let address = get_bitcoin_address().await?;
+    let network = BITCOIN.with(|wallet| {
+        let wallet = wallet.borrow();
+        wallet.network
+    });
+    bitcoin::get_balance(network, address).await
+}
+
+#[update(name = "setBitcoinWallet", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setBitcoinWallet")]
+async fn set_bitcoin_wallet(network: BitcoinNetwork, key_name: String) -> Response<()> {
+    audit("setBitcoinWallet");
+    if ic::caller() != ic::id() {
+        return announce_call("setBitcoinWallet", (network, key_name, )).await;
+    }
+    BITCOIN.with(|wallet| {
+        let mut wallet = wallet.borrow_mut();
+        wallet.set_config(network, key_name.clone());
+    });
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setBitcoinWallet")
+        .details(vec![
+            ("network".to_string(), Text(format!("{:?}", network))),
+            ("keyName".to_string(), Text(key_name)),
+        ])
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+/// drop ingress messages which would obviously fail before they consume cycles
+#[cfg(not(test))]
+#[export_name = "canister_inspect_message"]
+fn inspect_message() {
+    let caller = ic::caller();
+    let method = ic_cdk::api::call::method_name();
+    let result = inspect::check_caller(&caller).and_then(|_| {
+        if method == "castVoteOnBehalf" && !RELAYERS.with(|relayers| relayers.borrow().is_allowed(&caller)) {
+            return Err("caller is not an allowed relayer");
+        }
+        if method == "castVoteFor" {
+            let (delegator, _, _, _, ): (Principal, usize, VoteType, Option<String>, ) = ic_cdk::api::call::arg_data();
+            return PROXIES.with(|proxies| proxies.borrow().check_proxy(&delegator, &caller));
+        }
+        let id = match method.as_str() {
+            "castVote" => {
+                let (id, _, _, ): (usize, VoteType, Option<String>, ) = ic_cdk::api::call::arg_data();
+                id
+            }
+            "castVoteOption" => {
+                let (id, _, _, ): (usize, usize, Option<String>, ) = ic_cdk::api::call::arg_data();
+                id
+            }
+            _ => { return Ok(()); }
+        };
+        BRAVO.with(|bravo| {
+            VOTES_CACHE.with(|cache| {
+                inspect::check_vote(&bravo.borrow(), &cache.borrow(), &caller, id, now())
+            })
+        })
+    });
+    if result.is_ok() {
+        ic_cdk::api::call::accept_message();
+    }
+}
+
+#[query(name = "getTreasury")]
+#[candid_method(query, rename = "getTreasury")]
+fn get_treasury() -> TreasuryInfo {
+    TREASURY.with(|treasury| treasury.borrow().info())
+}
+
+/// refresh the cached balances of every treasury asset, return the assets failed to refresh
+#[update(name = "refreshTreasury")]
+#[candid_method(update, rename = "refreshTreasury")]
+async fn refresh_treasury() -> Vec<Asset> {
+    let treasury = TREASURY.with(|treasury| treasury.borrow().clone());
+    let gov_token = BRAVO.with(|bravo| bravo.borrow().gov_token);
+    let mut failed = Vec::new();
+    for asset in treasury.all_assets() {
+        match treasury::balance(&treasury, gov_token, &asset).await {
+            Ok(balance) => {
+                TREASURY.with(|treasury| {
+                    treasury.borrow_mut().update_balance(asset, balance, now());
+                });
+            }
+            Err(_) => { failed.push(asset); }
+        }
+    }
+    failed
+}
+
+#[update(name = "registerAsset", guard = "is_self")]
+#[candid_method(update, rename = "registerAsset")]
+fn register_asset(asset: RegisteredAsset) {
+    audit("registerAsset");
+    TREASURY.with(|treasury| treasury.borrow_mut().register(asset));
+}
+
+#[update(name = "unregisterAsset", guard = "is_self")]
+#[candid_method(update, rename = "unregisterAsset")]
+fn unregister_asset(ledger: Principal) -> Response<()> {
+    audit("unregisterAsset");
+    TREASURY.with(|treasury| treasury.borrow_mut().unregister(ledger))
+}
+
+#[update(name = "setIcpLedger", guard = "is_admin_or_self")]
+#[candid_method(update, rename = "setIcpLedger")]
+async fn set_icp_ledger(ledger: Principal) -> Response<()> {
+    audit("setIcpLedger");
+    if ic::caller() != ic::id() {
+        return announce_call("setIcpLedger", (ledger, )).await;
+    }
+    TREASURY.with(|treasury| {
+        let mut treasury = treasury.borrow_mut();
+        treasury.icp_ledger = ledger;
+    });
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(ic::caller())
+        .operation("setIcpLedger")
+        .details(DetailsBuilder::new().insert("ledger", ledger).build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(())
+}
+
+#[update(name = "wallet_receive")]
+#[candid_method(update, rename = "wallet_receive")]
+fn wallet_receive() {
+    let amount = ic_cdk::api::call::msg_cycles_available();
+    if amount == 0 {
+        return;
+    }
+    let accepted = ic_cdk::api::call::msg_cycles_accept(amount);
+    CYCLES.with(|cycles| {
+        let mut cycles = cycles.borrow_mut();
+        cycles.receive(accepted);
+    });
+}
+
+/// deposit the attached cycles into the pool task cycles are paid from, return the accepted amount
+#[update(name = "depositCycles")]
+#[candid_method(update, rename = "depositCycles")]
+fn deposit_cycles() -> u64 {
+    let amount = ic_cdk::api::call::msg_cycles_available();
+    if amount == 0 {
+        return 0;
+    }
+    let accepted = ic_cdk::api::call::msg_cycles_accept(amount);
+    CYCLES.with(|cycles| {
+        let mut cycles = cycles.borrow_mut();
+        cycles.deposit(ic::caller(), accepted);
+    });
+    accepted
+}
+
+/// add the attached cycles to the task of a proposal not queued yet, up to the cycle limits,
+/// the cycles above are refunded, return the cycles added
+#[update(name = "fundProposal")]
+#[candid_method(update, rename = "fundProposal")]
+async fn fund_proposal(id: usize) -> Response<u64> {
+    let caller = ic::caller();
+    let available = ic_cdk::api::call::msg_cycles_available();
+    if available == 0 {
+        return Err("no cycles attached");
+    }
+    let added = BRAVO.with(|bravo| bravo.borrow_mut().fund_task(id, caller, available, now()))?;
+    let accepted = ic_cdk::api::call::msg_cycles_accept(added);
+    CYCLES.with(|cycles| {
+        let mut cycles = cycles.borrow_mut();
+        cycles.deposit(caller, accepted);
+    });
+    #[cfg(not(test))]
+    insert(IndefiniteEventBuilder::new()
+        .caller(caller)
+        .operation("fundProposal")
+        .details(DetailsBuilder::new().insert("id", id as u64).insert("cycles", accepted).build())
+        .build()
+        .unwrap()
+    ).await.map_err(|_| "Cap error")?;
+    Ok(accepted)
+}
+
+/// cycles deposited by the principal
+#[query(name = "getCyclesDeposit")]
+#[candid_method(query, rename = "getCyclesDeposit")]
+fn get_cycles_deposit(depositor: Principal) -> u128 {
+    CYCLES.with(|cycles| cycles.borrow().deposit_of(&depositor))
+}
+
+#[query(name = "getCyclesTreasury")]
+#[candid_method(query, rename = "getCyclesTreasury")]
+fn get_cycles_treasury() -> CyclesTreasuryInfo {
+    CYCLES.with(|cycles| {
+        let cycles = cycles.borrow();
+        cycles.info(ic::balance())
+    })
+}
+
+/// top up the canister once its reported cycles fall below threshold, set by proposals
+#[update(name = "setTopUpTarget", guard = "is_self")]
+#[candid_method(update, rename = "setTopUpTarget")]
+fn set_top_up_target(canister: Principal, threshold: u64, amount: u64) {
+    audit("setTopUpTarget");
+    CYCLES.with(|cycles| {
+        let mut cycles = cycles.borrow_mut();
+        cycles.set_target(canister, threshold, amount);
+    });
+}
+
+#[update(name = "removeTopUpTarget", guard = "is_self")]
+#[candid_method(update, rename = "removeTopUpTarget")]
+fn remove_top_up_target(canister: Principal) -> Response<()> {
+    audit("removeTopUpTarget");
+    CYCLES.with(|cycles| {
+        let mut cycles = cycles.borrow_mut();
+        cycles.remove_target(canister)
+    })
+}
+
+/// top up the targets running low on cycles, return the canisters topped up
+#[update(name = "topUpCanisters")]
+#[candid_method(update, rename = "topUpCanisters")]
+async fn top_up_canisters() -> Vec<Principal> {
+    let targets = CYCLES.with(|cycles| {
+        let cycles = cycles.borrow();
+        cycles.due_targets(now())
+    });
+    let mut topped_up = vec![];
+    for target in targets {
+        match cycles::reported_cycles(target.canister).await {
+            Ok(reported) if reported < target.threshold => {}
+            _ => { continue; }
+        }
+        let reserved = CYCLES.with(|cycles| {
+            let mut cycles = cycles.borrow_mut();
+            cycles.reserve_top_up(target.canister, ic::balance(), now())
+        });
+        if !reserved {
+            continue;
+        }
+        let result = cycles::deposit(target.canister, target.amount).await;
+        CYCLES.with(|cycles| {
+            let mut cycles = cycles.borrow_mut();
+            cycles.record_top_up(target.canister, target.amount, result.is_ok());
+        });
+        if result.is_ok() {
+            topped_up.push(target.canister);
+        }
+    }
+    topped_up
+}
+
+/// pin the governor clock, only in `testing` builds
+#[cfg(feature = "testing")]
+#[update(name = "set_mock_time", guard = "is_admin")]
+#[candid_method(update, rename = "set_mock_time")]
+fn set_mock_time(timestamp: u64) {
+    audit("set_mock_time");
+    set_clock(MockClock::new(timestamp));
+}
+
+/// move the governor clock forward, return the new time, only in `testing` builds
+#[cfg(feature = "testing")]
+#[update(name = "advance_time", guard = "is_admin")]
+#[candid_method(update, rename = "advance_time")]
+fn advance_time(duration: u64) -> u64 {
+    audit("advance_time");
+    let timestamp = now() + duration;
+    set_clock(MockClock::new(timestamp));
+    timestamp
+}
+
 #[pre_upgrade]
 fn pre_upgrade() {
-    BRAVO.with(|b| {
-        let bravo = b.borrow();
-        stable_store((bravo.to_owned(), CapEnv::to_archive(), )).unwrap();
-    });
+    let state = StateV1 {
+        bravo: BRAVO.with(|b| b.borrow().to_owned()),
+        ethereum: ETHEREUM.with(|e| e.borrow().to_owned()),
+        wallet: BITCOIN.with(|w| w.borrow().to_owned()),
+        notifier: NOTIFIER.with(|n| n.borrow().to_owned()),
+        monitor: MONITOR.with(|m| m.borrow().to_owned()),
+        treasury: TREASURY.with(|t| t.borrow().to_owned()),
+        cycles: CYCLES.with(|c| c.borrow().to_owned()),
+        streams: STREAMS.with(|s| s.borrow().to_owned()),
+        search: SEARCH.with(|s| s.borrow().to_owned()),
+        identity: IDENTITY.with(|i| i.borrow().to_owned()),
+        relayers: RELAYERS.with(|r| r.borrow().to_owned()),
+        guardian: GUARDIAN.with(|g| g.borrow().to_owned()),
+        offchain: OFFCHAIN.with(|o| o.borrow().to_owned()),
+        proxies: PROXIES.with(|p| p.borrow().to_owned()),
+        audit: AUDIT.with(|a| a.borrow().to_owned()),
+        upgrades: UPGRADES.with(|u| u.borrow().to_owned()),
+        scheduler: SCHEDULER.with(|s| s.borrow().to_owned()),
+    };
+    stable_store((StableState::V1(state), CapEnv::to_archive(), )).unwrap();
 }
 
 #[post_upgrade]
 fn post_upgrade() {
-    let (bravo, cap_env, ): (GovernorBravo, CapEnv, ) = stable_restore().unwrap();
+    let (state, cap_env) = upgrade::restore();
     BRAVO.with(|b| {
         let mut b_mut = b.borrow_mut();
-        *b_mut = bravo;
+        *b_mut = state.bravo;
+    });
+    ETHEREUM.with(|e| {
+        let mut e_mut = e.borrow_mut();
+        *e_mut = state.ethereum;
+    });
+    BITCOIN.with(|w| {
+        let mut w_mut = w.borrow_mut();
+        *w_mut = state.wallet;
+    });
+    NOTIFIER.with(|n| {
+        let mut n_mut = n.borrow_mut();
+        *n_mut = state.notifier;
+    });
+    MONITOR.with(|m| {
+        let mut m_mut = m.borrow_mut();
+        *m_mut = state.monitor;
+    });
+    TREASURY.with(|t| {
+        let mut t_mut = t.borrow_mut();
+        *t_mut = state.treasury;
+    });
+    CYCLES.with(|c| {
+        let mut c_mut = c.borrow_mut();
+        *c_mut = state.cycles;
+    });
+    STREAMS.with(|s| {
+        let mut s_mut = s.borrow_mut();
+        *s_mut = state.streams;
+    });
+    SEARCH.with(|s| {
+        let mut s_mut = s.borrow_mut();
+        *s_mut = state.search;
+        // index proposals made before the index existed
+        BRAVO.with(|b| s_mut.sync(&b.borrow()));
+    });
+    IDENTITY.with(|i| {
+        let mut i_mut = i.borrow_mut();
+        *i_mut = state.identity;
+    });
+    RELAYERS.with(|r| {
+        let mut r_mut = r.borrow_mut();
+        *r_mut = state.relayers;
+    });
+    GUARDIAN.with(|g| {
+        let mut g_mut = g.borrow_mut();
+        *g_mut = state.guardian;
+    });
+    OFFCHAIN.with(|o| {
+        let mut o_mut = o.borrow_mut();
+        *o_mut = state.offchain;
+    });
+    PROXIES.with(|p| {
+        let mut p_mut = p.borrow_mut();
+        *p_mut = state.proxies;
+    });
+    AUDIT.with(|a| {
+        let mut a_mut = a.borrow_mut();
+        *a_mut = state.audit;
+    });
+    SCHEDULER.with(|s| {
+        let mut s_mut = s.borrow_mut();
+        *s_mut = state.scheduler;
+        // a run cut short by the upgrade must not block the next ones
+        s_mut.finish();
     });
     CapEnv::load_from_archive(cap_env);
+    UPGRADES.with(|u| {
+        let mut u_mut = u.borrow_mut();
+        *u_mut = state.upgrades;
+        u_mut.push(ic::time());
+    });
 }
 
 // needed to export candid on save
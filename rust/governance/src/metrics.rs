@@ -0,0 +1,195 @@
+/**
+ * Module     : metrics.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+use std::fmt::Write;
+use ic_kit::candid::{CandidType, Deserialize};
+use sha2::{Digest, Sha256};
+use crate::ethereum::to_hex;
+use crate::governance::{GovernorBravo, ProposalState};
+use crate::stable::Memory;
+
+/// size of a wasm memory page in bytes
+#[cfg(target_arch = "wasm32")]
+const WASM_PAGE_SIZE: u64 = 65536;
+
+#[derive(Deserialize, CandidType)]
+pub struct HttpRequest {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+#[derive(CandidType)]
+pub struct HttpResponse {
+    status_code: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+#[derive(CandidType, Clone, Debug)]
+pub struct HealthStatus {
+    /// cycles balance of the canister
+    pub cycles: u64,
+    /// heap memory size in bytes
+    pub heap_memory_size: u64,
+    /// stable memory size in bytes
+    pub stable_memory_size: u64,
+    /// tasks waiting in the timelock queue
+    pub queued_tasks: usize,
+    /// proposals left executing
+    pub stuck_executing: Vec<usize>,
+    /// time of the last upgrade, 0 if never upgraded
+    pub last_upgrade: u64,
+}
+
+/// code running on the canister, for operators to verify a deployment
+#[derive(CandidType, Clone, Debug)]
+pub struct VersionInfo {
+    /// semantic version of the canister crate
+    pub version: String,
+    /// git commit the wasm was built from, "unknown" if built outside a checkout
+    pub git_hash: String,
+    /// hex sha256 of the candid interface
+    pub candid_hash: String,
+    /// times of the upgrades, oldest first
+    pub upgrades: Vec<u64>,
+}
+
+impl HttpRequest {
+    /// url without the query string
+    fn path(&self) -> &str {
+        self.url.split('?').next().unwrap_or("/")
+    }
+}
+
+impl HttpResponse {
+    fn new(status_code: u16, content_type: &str, body: String) -> Self {
+        Self {
+            status_code,
+            headers: vec![
+                ("Content-Type".to_string(), content_type.to_string()),
+                ("Content-Length".to_string(), body.len().to_string()),
+            ],
+            body: body.into_bytes(),
+        }
+    }
+}
+
+/// current heap memory size in bytes
+pub(crate) fn heap_memory_size() -> u64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        core::arch::wasm32::memory_size(0) as u64 * WASM_PAGE_SIZE
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        0
+    }
+}
+
+const STATE_LABELS: [&str; 11] = [
+    "pending", "active", "tallying", "canceled", "defeated", "succeeded", "queued", "executing", "executed", "expired",
+    "closed",
+];
+
+/// index of the state in STATE_LABELS
+fn state_index(state: &ProposalState) -> usize {
+    match state {
+        ProposalState::Pending => { 0 }
+        ProposalState::Active => { 1 }
+        ProposalState::Tallying => { 2 }
+        ProposalState::Canceled => { 3 }
+        ProposalState::Defeated => { 4 }
+        ProposalState::Succeeded => { 5 }
+        ProposalState::Queued => { 6 }
+        ProposalState::Executing => { 7 }
+        ProposalState::Executed => { 8 }
+        ProposalState::Expired => { 9 }
+        ProposalState::Closed => { 10 }
+    }
+}
+
+/// health summary for uptime monitors
+pub(crate) fn health(bravo: &GovernorBravo, cycles: u64, last_upgrade: u64) -> HealthStatus {
+    HealthStatus {
+        cycles,
+        heap_memory_size: heap_memory_size(),
+        stable_memory_size: bravo.stable_memory.size() as u64,
+        queued_tasks: bravo.timelock.queued_transactions.len(),
+        stuck_executing: bravo.executing_proposals(),
+        last_upgrade,
+    }
+}
+
+/// version of the running code, the git hash is set by the build script
+pub(crate) fn version(candid: &str, upgrades: Vec<u64>) -> VersionInfo {
+    VersionInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_hash: env!("GIT_HASH").to_string(),
+        candid_hash: to_hex(&Sha256::digest(candid.as_bytes()))[2..].to_string(),
+        upgrades,
+    }
+}
+
+/// lowercase label of the state
+pub(crate) fn state_label(state: &ProposalState) -> &'static str {
+    STATE_LABELS[state_index(state)]
+}
+
+/// encode governor metrics in prometheus text exposition format
+pub(crate) fn encode(bravo: &GovernorBravo, timestamp: u64, cycles: u64) -> Result<String, std::fmt::Error> {
+    let mut counts = [0usize; STATE_LABELS.len()];
+    for id in 0..bravo.proposal_count() {
+        if let Ok(state) = bravo.get_state(id, timestamp) {
+            counts[state_index(&state)] += 1;
+        }
+    }
+    let (support, against, abstain) = bravo.vote_totals();
+
+    let mut w = String::new();
+    writeln!(w, "# HELP governance_proposals Number of proposals by state.")?;
+    writeln!(w, "# TYPE governance_proposals gauge")?;
+    for (label, count) in STATE_LABELS.iter().zip(counts.iter()) {
+        writeln!(w, "governance_proposals{{state=\"{}\"}} {}", label, count)?;
+    }
+    writeln!(w, "# HELP governance_votes_total Votes cast over all proposals by vote type.")?;
+    writeln!(w, "# TYPE governance_votes_total counter")?;
+    writeln!(w, "governance_votes_total{{vote_type=\"support\"}} {}", support.0)?;
+    writeln!(w, "governance_votes_total{{vote_type=\"against\"}} {}", against.0)?;
+    writeln!(w, "governance_votes_total{{vote_type=\"abstain\"}} {}", abstain.0)?;
+    writeln!(w, "# HELP governance_queue_depth Tasks waiting in the timelock queue.")?;
+    writeln!(w, "# TYPE governance_queue_depth gauge")?;
+    writeln!(w, "governance_queue_depth {}", bravo.timelock.queued_transactions.len())?;
+    writeln!(w, "# HELP governance_cycles_balance Cycles balance of the canister.")?;
+    writeln!(w, "# TYPE governance_cycles_balance gauge")?;
+    writeln!(w, "governance_cycles_balance {}", cycles)?;
+    writeln!(w, "# HELP governance_heap_memory_bytes Heap memory size in bytes.")?;
+    writeln!(w, "# TYPE governance_heap_memory_bytes gauge")?;
+    writeln!(w, "governance_heap_memory_bytes {}", heap_memory_size())?;
+    writeln!(w, "# HELP governance_stable_memory_bytes Stable memory size in bytes.")?;
+    writeln!(w, "# TYPE governance_stable_memory_bytes gauge")?;
+    writeln!(w, "governance_stable_memory_bytes {}", bravo.stable_memory.size())?;
+    writeln!(w, "# HELP governance_stable_memory_used_bytes Stable memory written in bytes.")?;
+    writeln!(w, "# TYPE governance_stable_memory_used_bytes gauge")?;
+    writeln!(w, "governance_stable_memory_used_bytes {}", bravo.stable_memory.offset)?;
+    Ok(w)
+}
+
+/// serve the http gateway request, only `/metrics` is available
+pub(crate) fn serve(req: &HttpRequest, bravo: &GovernorBravo, timestamp: u64, cycles: u64) -> HttpResponse {
+    match req.path() {
+        "/metrics" => {
+            match encode(bravo, timestamp, cycles) {
+                Ok(body) => HttpResponse::new(200, "text/plain; version=0.0.4", body),
+                Err(_) => HttpResponse::new(500, "text/plain", "Failed to encode metrics".to_string()),
+            }
+        }
+        _ => HttpResponse::new(404, "text/plain", "Not found".to_string()),
+    }
+}
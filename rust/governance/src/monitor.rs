@@ -0,0 +1,355 @@
+/**
+ * Module     : monitor.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+use std::collections::{BTreeMap, VecDeque};
+use std::convert::TryFrom;
+use ic_kit::candid::{CandidType, Deserialize, Int, Nat};
+
+/// one hour in nanoseconds
+const HOUR: u64 = 3600 * 1_000_000_000;
+/// metrics are sampled in 5 minutes intervals, 12 per hour
+const INTERVALS_PER_HOUR: u64 = 12;
+const INTERVAL: u64 = HOUR / INTERVALS_PER_HOUR;
+/// hourly metrics are kept for 90 days
+const RETENTION_HOURS: u64 = 90 * 24;
+/// maximum log messages kept, the oldest are dropped first
+const MAX_LOG_MESSAGES: usize = 1000;
+
+#[derive(Deserialize, CandidType, Clone, Copy, Debug)]
+pub enum MetricsGranularity {
+    #[serde(rename = "hourly")]
+    Hourly,
+    #[serde(rename = "daily")]
+    Daily,
+}
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub struct GetMetricsParameters {
+    #[serde(rename = "dateFromMillis")]
+    pub date_from_millis: Nat,
+    #[serde(rename = "dateToMillis")]
+    pub date_to_millis: Nat,
+    pub granularity: MetricsGranularity,
+}
+
+#[derive(CandidType, Clone, Debug)]
+pub struct HourlyMetricsData {
+    #[serde(rename = "timeMillis")]
+    time_millis: Int,
+    #[serde(rename = "updateCalls")]
+    update_calls: Vec<u64>,
+    #[serde(rename = "canisterHeapMemorySize")]
+    canister_heap_memory_size: Vec<u64>,
+    #[serde(rename = "canisterCycles")]
+    canister_cycles: Vec<u64>,
+    #[serde(rename = "canisterMemorySize")]
+    canister_memory_size: Vec<u64>,
+}
+
+#[derive(CandidType, Clone, Debug, Default)]
+pub struct NumericEntity {
+    avg: u64,
+    max: u64,
+    min: u64,
+    first: u64,
+    last: u64,
+}
+
+#[derive(CandidType, Clone, Debug)]
+pub struct DailyMetricsData {
+    #[serde(rename = "timeMillis")]
+    time_millis: Int,
+    #[serde(rename = "updateCalls")]
+    update_calls: u64,
+    #[serde(rename = "canisterHeapMemorySize")]
+    canister_heap_memory_size: NumericEntity,
+    #[serde(rename = "canisterCycles")]
+    canister_cycles: NumericEntity,
+    #[serde(rename = "canisterMemorySize")]
+    canister_memory_size: NumericEntity,
+}
+
+#[derive(CandidType, Clone, Debug)]
+pub enum CanisterMetricsData {
+    #[serde(rename = "hourly")]
+    Hourly(Vec<HourlyMetricsData>),
+    #[serde(rename = "daily")]
+    Daily(Vec<DailyMetricsData>),
+}
+
+#[derive(CandidType, Clone, Debug)]
+pub struct CanisterMetrics {
+    data: CanisterMetricsData,
+}
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub struct GetLogMessagesFilter {
+    #[serde(rename = "analyzeCount")]
+    pub analyze_count: u32,
+    #[serde(rename = "messageContains")]
+    pub message_contains: Option<String>,
+    /// not supported, see the features of getMessagesInfo
+    #[serde(rename = "messageRegex")]
+    pub message_regex: Option<String>,
+}
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub struct GetLogMessagesParameters {
+    pub count: u32,
+    pub filter: Option<GetLogMessagesFilter>,
+    #[serde(rename = "fromTimeNanos")]
+    pub from_time_nanos: Option<u64>,
+}
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub struct GetLatestLogMessagesParameters {
+    pub count: u32,
+    pub filter: Option<GetLogMessagesFilter>,
+    #[serde(rename = "upToTimeNanos")]
+    pub up_to_time_nanos: Option<u64>,
+}
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub enum CanisterLogRequest {
+    #[serde(rename = "getMessagesInfo")]
+    GetMessagesInfo,
+    #[serde(rename = "getMessages")]
+    GetMessages(GetLogMessagesParameters),
+    #[serde(rename = "getLatestMessages")]
+    GetLatestMessages(GetLatestLogMessagesParameters),
+}
+
+#[derive(CandidType, Clone, Debug)]
+pub enum CanisterLogFeature {
+    #[serde(rename = "filterMessageByContains")]
+    FilterMessageByContains,
+    #[serde(rename = "filterMessageByRegex")]
+    FilterMessageByRegex,
+}
+
+#[derive(CandidType, Clone, Debug)]
+pub struct CanisterLogMessagesInfo {
+    count: u32,
+    #[serde(rename = "firstTimeNanos")]
+    first_time_nanos: Option<u64>,
+    #[serde(rename = "lastTimeNanos")]
+    last_time_nanos: Option<u64>,
+    features: Vec<Option<CanisterLogFeature>>,
+}
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub struct LogMessageData {
+    #[serde(rename = "timeNanos")]
+    time_nanos: u64,
+    message: String,
+}
+
+#[derive(CandidType, Clone, Debug)]
+pub struct CanisterLogMessages {
+    data: Vec<LogMessageData>,
+    #[serde(rename = "lastAnalyzedMessageTimeNanos")]
+    last_analyzed_message_time_nanos: Option<u64>,
+}
+
+#[derive(CandidType, Clone, Debug)]
+pub enum CanisterLogResponse {
+    #[serde(rename = "messagesInfo")]
+    MessagesInfo(CanisterLogMessagesInfo),
+    #[serde(rename = "messages")]
+    Messages(CanisterLogMessages),
+}
+
+/// update calls and failures of an endpoint
+#[derive(Deserialize, CandidType, Clone, Debug, Default)]
+pub struct MethodMetrics {
+    pub method: String,
+    pub calls: u64,
+    pub errors: u64,
+}
+
+/// samples of one hour, one per 5 minutes interval
+#[derive(Deserialize, CandidType, Clone, Debug)]
+struct HourlySamples {
+    update_calls: Vec<u64>,
+    heap_memory_size: Vec<u64>,
+    cycles: Vec<u64>,
+    memory_size: Vec<u64>,
+}
+
+impl Default for HourlySamples {
+    fn default() -> Self {
+        let empty = vec![0; INTERVALS_PER_HOUR as usize];
+        Self {
+            update_calls: empty.clone(),
+            heap_memory_size: empty.clone(),
+            cycles: empty.clone(),
+            memory_size: empty,
+        }
+    }
+}
+
+#[derive(Deserialize, CandidType, Clone, Default)]
+pub struct Monitor {
+    /// samples by hour since epoch
+    hours: BTreeMap<u64, HourlySamples>,
+    /// call and error counters by method
+    methods: BTreeMap<String, MethodMetrics>,
+    log: VecDeque<LogMessageData>,
+}
+
+fn to_millis(nat: &Nat) -> u64 {
+    u64::try_from(&nat.0).unwrap_or(u64::MAX)
+}
+
+/// aggregate the samples taken, zero meaning no sample in the interval
+fn numeric_entity(samples: &[u64]) -> NumericEntity {
+    let taken = samples.iter().cloned().filter(|v| *v > 0).collect::<Vec<u64>>();
+    if taken.is_empty() {
+        return NumericEntity::default();
+    }
+    NumericEntity {
+        avg: (taken.iter().map(|v| *v as u128).sum::<u128>() / taken.len() as u128) as u64,
+        max: *taken.iter().max().unwrap(),
+        min: *taken.iter().min().unwrap(),
+        first: taken[0],
+        last: taken[taken.len() - 1],
+    }
+}
+
+impl Monitor {
+    /// sample canister metrics into the current interval
+    pub(crate) fn collect(&mut self, timestamp: u64, cycles: u64, heap_memory_size: u64, memory_size: u64, update_call: bool) {
+        let hour = timestamp / HOUR;
+        let interval = ((timestamp % HOUR) / INTERVAL) as usize;
+        let samples = self.hours.entry(hour).or_default();
+        if update_call {
+            samples.update_calls[interval] += 1;
+        }
+        samples.heap_memory_size[interval] = heap_memory_size;
+        samples.cycles[interval] = cycles;
+        samples.memory_size[interval] = memory_size;
+        if hour > RETENTION_HOURS {
+            self.hours = self.hours.split_off(&(hour - RETENTION_HOURS));
+        }
+    }
+
+    /// count a call of the method, logging the error if it failed
+    pub(crate) fn record_call(&mut self, timestamp: u64, method: &str, error: Option<&str>) {
+        let metrics = self.methods.entry(method.to_string()).or_insert_with(|| MethodMetrics {
+            method: method.to_string(),
+            ..Default::default()
+        });
+        metrics.calls += 1;
+        if let Some(msg) = error {
+            metrics.errors += 1;
+            self.log(timestamp, format!("{} failed: {}", method, msg));
+        }
+    }
+
+    pub(crate) fn log(&mut self, timestamp: u64, message: String) {
+        if self.log.len() >= MAX_LOG_MESSAGES {
+            self.log.pop_front();
+        }
+        self.log.push_back(LogMessageData {
+            time_nanos: timestamp,
+            message,
+        });
+    }
+
+    pub(crate) fn method_metrics(&self) -> Vec<MethodMetrics> {
+        self.methods.values().cloned().collect()
+    }
+
+    pub(crate) fn get_metrics(&self, params: &GetMetricsParameters) -> CanisterMetrics {
+        let from = to_millis(&params.date_from_millis).saturating_mul(1_000_000) / HOUR;
+        let to = to_millis(&params.date_to_millis).saturating_mul(1_000_000) / HOUR;
+        let hours = self.hours.range(from..=to.max(from));
+        let data = match params.granularity {
+            MetricsGranularity::Hourly => {
+                CanisterMetricsData::Hourly(hours.map(|(hour, samples)| {
+                    HourlyMetricsData {
+                        time_millis: Int::from(hour * HOUR / 1_000_000),
+                        update_calls: samples.update_calls.clone(),
+                        canister_heap_memory_size: samples.heap_memory_size.clone(),
+                        canister_cycles: samples.cycles.clone(),
+                        canister_memory_size: samples.memory_size.clone(),
+                    }
+                }).collect())
+            }
+            MetricsGranularity::Daily => {
+                let mut days: BTreeMap<u64, Vec<&HourlySamples>> = BTreeMap::new();
+                for (hour, samples) in hours {
+                    days.entry(hour / 24).or_default().push(samples);
+                }
+                CanisterMetricsData::Daily(days.into_iter().map(|(day, samples)| {
+                    let concat = |f: fn(&HourlySamples) -> &Vec<u64>| {
+                        samples.iter().flat_map(|s| f(s).iter().cloned()).collect::<Vec<u64>>()
+                    };
+                    DailyMetricsData {
+                        time_millis: Int::from(day * 24 * HOUR / 1_000_000),
+                        update_calls: concat(|s| &s.update_calls).iter().sum(),
+                        canister_heap_memory_size: numeric_entity(&concat(|s| &s.heap_memory_size)),
+                        canister_cycles: numeric_entity(&concat(|s| &s.cycles)),
+                        canister_memory_size: numeric_entity(&concat(|s| &s.memory_size)),
+                    }
+                }).collect())
+            }
+        };
+        CanisterMetrics { data }
+    }
+
+    pub(crate) fn get_log(&self, request: Option<CanisterLogRequest>) -> Option<CanisterLogResponse> {
+        match request? {
+            CanisterLogRequest::GetMessagesInfo => {
+                Some(CanisterLogResponse::MessagesInfo(CanisterLogMessagesInfo {
+                    count: self.log.len() as u32,
+                    first_time_nanos: self.log.front().map(|m| m.time_nanos),
+                    last_time_nanos: self.log.back().map(|m| m.time_nanos),
+                    features: vec![Some(CanisterLogFeature::FilterMessageByContains)],
+                }))
+            }
+            CanisterLogRequest::GetMessages(params) => {
+                let from = params.from_time_nanos.unwrap_or(0);
+                let messages = self.log.iter().filter(|m| m.time_nanos >= from);
+                Some(CanisterLogResponse::Messages(Self::select(messages, params.count, &params.filter)))
+            }
+            CanisterLogRequest::GetLatestMessages(params) => {
+                let up_to = params.up_to_time_nanos.unwrap_or(u64::MAX);
+                let messages = self.log.iter().rev().filter(|m| m.time_nanos <= up_to);
+                Some(CanisterLogResponse::Messages(Self::select(messages, params.count, &params.filter)))
+            }
+        }
+    }
+
+    /// take up to count messages matching the filter, analyzing at most filter.analyze_count messages
+    fn select<'a>(
+        messages: impl Iterator<Item = &'a LogMessageData>,
+        count: u32,
+        filter: &Option<GetLogMessagesFilter>,
+    ) -> CanisterLogMessages {
+        let (analyze, contains) = match filter {
+            Some(f) => { (f.analyze_count as usize, f.message_contains.clone()) }
+            None => { (usize::MAX, None) }
+        };
+        let mut data = vec![];
+        let mut last_analyzed = None;
+        for m in messages.take(analyze) {
+            if data.len() >= count as usize {
+                break;
+            }
+            last_analyzed = Some(m.time_nanos);
+            if contains.as_ref().map_or(true, |c| m.message.contains(c.as_str())) {
+                data.push(m.clone());
+            }
+        }
+        CanisterLogMessages {
+            data,
+            last_analyzed_message_time_nanos: last_analyzed,
+        }
+    }
+}
@@ -0,0 +1,204 @@
+/**
+ * Module     : notification.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+use std::collections::{HashMap, HashSet};
+use ic_kit::candid::{CandidType, Deserialize};
+use ic_kit::Principal;
+use crate::governance::{GovernorBravo, ProposalState};
+
+/// consecutive failed deliveries after which a subscriber is dropped
+pub const MAX_SUBSCRIBER_FAILURES: u32 = 5;
+/// subscribers the governor pays notifications for
+pub const MAX_SUBSCRIBERS: usize = 32;
+/// subscribers are reminded this long before voting ends, 24 hours
+pub const VOTING_END_REMINDER: u64 = 24 * 3600 * 1_000_000_000;
+/// subscribers are reminded this long before a queued proposal expires, 48 hours
+pub const EXPIRY_REMINDER: u64 = 48 * 3600 * 1_000_000_000;
+
+/// deadline of a proposal subscribers are reminded of, once per proposal
+#[derive(Deserialize, CandidType, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Reminder {
+    /// voting ends within VOTING_END_REMINDER
+    VotingEndsSoon,
+    /// the queued proposal expires within EXPIRY_REMINDER unless executed
+    ExpiresSoon,
+}
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub struct Subscriber {
+    /// canister receiving state transitions
+    pub(crate) canister: Principal,
+    /// method called with (ProposalDigest, ProposalState)
+    pub(crate) method: String,
+    /// consecutive failed deliveries
+    pub(crate) failures: u32,
+    /// method called with (ProposalDigest) once a proposal reaches quorum, if any
+    pub(crate) quorum_method: Option<String>,
+    /// method called with (ProposalDigest, Reminder) as deadlines come close, if any
+    pub(crate) reminder_method: Option<String>,
+}
+
+#[derive(Deserialize, CandidType, Clone, Default)]
+pub struct Notifier {
+    /// canister notified when a proposal changes state
+    pub(crate) canister: Option<Principal>,
+    /// method called on the notified canister with (ProposalDigest, ProposalState)
+    pub(crate) method: String,
+    /// canisters receiving every state transition
+    pub(crate) subscribers: HashMap<Principal, Subscriber>,
+    /// last state notified for proposals which may still change state
+    live: HashMap<usize, Option<ProposalState>>,
+    /// proposals with smaller id are tracked already
+    next_id: usize,
+    /// reminders sent for proposals which may still change state
+    reminded: HashSet<(usize, Reminder)>,
+}
+
+impl Notifier {
+    pub(crate) fn set_target(&mut self, canister: Option<Principal>, method: String) {
+        self.canister = canister;
+        self.method = method;
+    }
+
+    /// register the subscriber, it is notified of changes from the current state on
+    pub(crate) fn subscribe(&mut self, canister: Principal, method: String, bravo: &GovernorBravo, timestamp: u64) -> Result<(), &'static str> {
+        if !self.subscribers.contains_key(&canister) && self.subscribers.len() >= MAX_SUBSCRIBERS {
+            return Err("too many subscribers");
+        }
+        // nothing was tracked without a target, catch up silently so past transitions are not pushed
+        let idle = self.canister.is_none() && self.subscribers.is_empty();
+        self.subscribers.insert(canister, Subscriber {
+            canister,
+            method,
+            failures: 0,
+            quorum_method: None,
+            reminder_method: None,
+        });
+        if idle {
+            self.collect(bravo, timestamp);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn set_quorum_method(&mut self, canister: Principal, method: Option<String>) -> Result<(), &'static str> {
+        match self.subscribers.get_mut(&canister) {
+            Some(subscriber) => {
+                subscriber.quorum_method = method;
+                Ok(())
+            }
+            None => { Err("subscriber not found") }
+        }
+    }
+
+    pub(crate) fn set_reminder_method(&mut self, canister: Principal, method: Option<String>) -> Result<(), &'static str> {
+        match self.subscribers.get_mut(&canister) {
+            Some(subscriber) => {
+                subscriber.reminder_method = method;
+                Ok(())
+            }
+            None => { Err("subscriber not found") }
+        }
+    }
+
+    pub(crate) fn unsubscribe(&mut self, canister: Principal) -> Result<(), &'static str> {
+        match self.subscribers.remove(&canister) {
+            Some(_) => { Ok(()) }
+            None => { Err("subscriber not found") }
+        }
+    }
+
+    /// track the delivery result, dropping subscribers failing too often
+    pub(crate) fn record_delivery(&mut self, canister: Principal, success: bool) {
+        if let Some(subscriber) = self.subscribers.get_mut(&canister) {
+            if success {
+                subscriber.failures = 0;
+            } else {
+                subscriber.failures += 1;
+                if subscriber.failures >= MAX_SUBSCRIBER_FAILURES {
+                    self.subscribers.remove(&canister);
+                }
+            }
+        }
+    }
+
+    /// states pushed to the notifier canister
+    pub(crate) fn is_notified(state: &ProposalState) -> bool {
+        matches!(
+            state,
+            ProposalState::Pending | ProposalState::Active | ProposalState::Succeeded | ProposalState::Queued
+        )
+    }
+
+    fn is_final(state: &ProposalState) -> bool {
+        matches!(
+            state,
+            ProposalState::Canceled | ProposalState::Defeated | ProposalState::Executed | ProposalState::Expired
+                | ProposalState::Closed
+        )
+    }
+
+    /// collect proposals which changed state since the last sweep
+    pub(crate) fn collect(&mut self, bravo: &GovernorBravo, timestamp: u64) -> Vec<(usize, ProposalState)> {
+        if self.canister.is_none() && self.subscribers.is_empty() {
+            return vec![];
+        }
+        for id in self.next_id..bravo.proposal_count() {
+            self.live.insert(id, None);
+        }
+        self.next_id = bravo.proposal_count();
+
+        let mut changes = vec![];
+        let mut finished = vec![];
+        for (id, last) in self.live.iter_mut() {
+            let state = match bravo.get_state(*id, timestamp) {
+                Ok(state) => { state }
+                Err(_) => { continue; }
+            };
+            if last.as_ref() != Some(&state) {
+                changes.push((*id, state.clone()));
+                *last = Some(state.clone());
+            }
+            if Self::is_final(&state) {
+                finished.push(*id);
+            }
+        }
+        for id in finished {
+            self.live.remove(&id);
+        }
+        changes.sort_by_key(|(id, _)| *id);
+        changes
+    }
+    /// deadlines of tracked proposals within their reminder period, each reminded once,
+    /// to call after `collect`
+    pub(crate) fn collect_reminders(&mut self, bravo: &GovernorBravo, timestamp: u64) -> Vec<(usize, Reminder)> {
+        let live = &self.live;
+        self.reminded.retain(|(id, _)| live.contains_key(id));
+
+        let mut reminders = vec![];
+        for id in self.live.keys() {
+            let timeline = match bravo.timeline(*id, timestamp) {
+                Ok(timeline) => { timeline }
+                Err(_) => { continue; }
+            };
+            let reminder = match bravo.get_state(*id, timestamp) {
+                Ok(ProposalState::Active) if timeline.until_voting_end <= VOTING_END_REMINDER => {
+                    Reminder::VotingEndsSoon
+                }
+                Ok(ProposalState::Queued) if timeline.until_expiry.map_or(false, |t| t <= EXPIRY_REMINDER) => {
+                    Reminder::ExpiresSoon
+                }
+                _ => { continue; }
+            };
+            if self.reminded.insert((*id, reminder)) {
+                reminders.push((*id, reminder));
+            }
+        }
+        reminders.sort_by_key(|(id, _)| *id);
+        reminders
+    }
+}
@@ -0,0 +1,112 @@
+/**
+ * Module     : offchain.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+use std::collections::{BTreeSet, HashSet};
+use ic_kit::candid::{encode_one, CandidType, Deserialize};
+use ic_kit::Principal;
+use k256::ecdsa::signature::Verifier;
+use k256::ecdsa::{Signature, VerifyingKey};
+use crate::governance::Tally;
+
+/// domain separator of signed off-chain tallies
+const TALLY_DOMAIN: &[u8] = b"\x13governance-offchain";
+
+/// ballots aggregated off-chain, e.g. by a Snapshot space
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub struct OffchainTally {
+    pub proposal_id: usize,
+    /// id of the off-chain vote, imported once per proposal
+    pub snapshot: String,
+    pub tally: Tally,
+}
+
+/// committee signing off-chain tallies, threshold of its keys must sign
+#[derive(Deserialize, CandidType, Clone, Default)]
+pub struct SignerCommittee {
+    /// SEC1 encoded secp256k1 public keys
+    pub(crate) public_keys: Vec<Vec<u8>>,
+    pub(crate) threshold: usize,
+    /// off-chain votes already imported, by proposal
+    pub(crate) imported: HashSet<(usize, String)>,
+}
+
+#[derive(CandidType, Clone, Debug)]
+pub struct SignerCommitteeInfo {
+    pub public_keys: Vec<Vec<u8>>,
+    pub threshold: usize,
+}
+
+/// bytes signed by the committee: domain separator, governor and candid encoded tally
+pub(crate) fn tally_message(governor: &Principal, tally: &OffchainTally) -> Result<Vec<u8>, &'static str> {
+    let mut message = TALLY_DOMAIN.to_vec();
+    message.extend_from_slice(governor.as_slice());
+    message.extend(encode_one(tally).map_err(|_| "Encode error")?);
+    Ok(message)
+}
+
+impl SignerCommittee {
+    pub(crate) fn set(&mut self, public_keys: Vec<Vec<u8>>, threshold: usize) -> Result<(), &'static str> {
+        if threshold == 0 || threshold > public_keys.len() {
+            return Err("threshold must be between 1 and the number of signers");
+        }
+        for key in public_keys.iter() {
+            VerifyingKey::from_sec1_bytes(key).map_err(|_| "invalid signer public key")?;
+        }
+        self.public_keys = public_keys;
+        self.threshold = threshold;
+        Ok(())
+    }
+
+    pub(crate) fn info(&self) -> SignerCommitteeInfo {
+        SignerCommitteeInfo {
+            public_keys: self.public_keys.clone(),
+            threshold: self.threshold,
+        }
+    }
+
+    /// check threshold distinct signers signed the message, each signature is 64 bytes r || s
+    pub(crate) fn verify(&self, message: &[u8], signatures: &[Vec<u8>]) -> Result<(), &'static str> {
+        if self.threshold == 0 {
+            return Err("signer committee is not configured");
+        }
+        let mut signers = BTreeSet::new();
+        for signature in signatures {
+            let signature = match Signature::try_from(signature.as_slice()) {
+                Ok(s) => { s }
+                Err(_) => { continue; }
+            };
+            for (i, key) in self.public_keys.iter().enumerate() {
+                if signers.contains(&i) {
+                    continue;
+                }
+                let valid = VerifyingKey::from_sec1_bytes(key)
+                    .map(|k| k.verify(message, &signature).is_ok())
+                    .unwrap_or(false);
+                if valid {
+                    signers.insert(i);
+                    break;
+                }
+            }
+        }
+        if signers.len() < self.threshold {
+            return Err("not enough committee signatures");
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_not_imported(&self, tally: &OffchainTally) -> Result<(), &'static str> {
+        if self.imported.contains(&(tally.proposal_id, tally.snapshot.clone())) {
+            return Err("off-chain votes already imported");
+        }
+        Ok(())
+    }
+
+    pub(crate) fn mark_imported(&mut self, tally: &OffchainTally) {
+        self.imported.insert((tally.proposal_id, tally.snapshot.clone()));
+    }
+}
@@ -0,0 +1,156 @@
+/**
+ * Module     : oracle.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+use ic_cdk::api::call::{call_with_payment, CallResult};
+use ic_kit::candid::{CandidType, Deserialize, Func, Nat};
+use ic_kit::candid::parser::types::FuncMode;
+use ic_kit::candid::parser::value::{IDLArgs, IDLValue};
+use ic_kit::candid::types::{Function, Serializer, Type};
+use ic_kit::{ic, Principal};
+use crate::timelock::Oracle;
+
+/// default transform query exposed by the governor
+pub const DEFAULT_TRANSFORM: &str = "oracleTransform";
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub struct HttpHeader {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub(crate) enum HttpMethod {
+    #[serde(rename = "get")]
+    Get,
+    #[serde(rename = "post")]
+    Post,
+}
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub struct HttpOutcallResponse {
+    pub status: Nat,
+    pub headers: Vec<HttpHeader>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub struct TransformArgs {
+    pub response: HttpOutcallResponse,
+    pub context: Vec<u8>,
+}
+
+/// reference to a transform query, typed the way the management canister expects
+#[derive(Clone, Debug)]
+struct TransformFunc(Func);
+
+impl CandidType for TransformFunc {
+    fn _ty() -> Type {
+        Type::Func(Function {
+            modes: vec![FuncMode::Query],
+            args: vec![TransformArgs::ty()],
+            rets: vec![HttpOutcallResponse::ty()],
+        })
+    }
+
+    fn idl_serialize<S: Serializer>(&self, serializer: S) -> Result<(), S::Error> {
+        serializer.serialize_function(self.0.principal.as_slice(), &self.0.method)
+    }
+}
+
+#[derive(CandidType, Clone, Debug)]
+struct TransformContext {
+    function: TransformFunc,
+    context: Vec<u8>,
+}
+
+#[derive(CandidType, Clone, Debug)]
+struct CanisterHttpRequestArgument {
+    url: String,
+    max_response_bytes: Option<u64>,
+    method: HttpMethod,
+    headers: Vec<HttpHeader>,
+    body: Option<Vec<u8>>,
+    transform: Option<TransformContext>,
+}
+
+/// perform an https outcall through the management canister
+pub(crate) async fn http_outcall(
+    url: String,
+    method: HttpMethod,
+    headers: Vec<HttpHeader>,
+    body: Option<Vec<u8>>,
+    max_response_bytes: Option<u64>,
+    transform: String,
+    cycles: u64,
+) -> Result<HttpOutcallResponse, &'static str> {
+    let request = CanisterHttpRequestArgument {
+        url,
+        max_response_bytes,
+        method,
+        headers,
+        body,
+        transform: Some(TransformContext {
+            function: TransformFunc(Func {
+                principal: ic::id(),
+                method: transform,
+            }),
+            context: vec![],
+        }),
+    };
+    let result: CallResult<(HttpOutcallResponse, )> = call_with_payment(
+        Principal::management_canister(),
+        "http_request",
+        (request, ),
+        cycles,
+    ).await;
+    match result {
+        Ok((response, )) => {
+            if response.status != Nat::from(200) {
+                return Err("Http outcall response status is not 200");
+            }
+            Ok(response)
+        }
+        Err(_) => {
+            Err("Http outcall error")
+        }
+    }
+}
+
+/// fetch the oracle url, return the response body
+pub(crate) async fn fetch(oracle: &Oracle) -> Result<Vec<u8>, &'static str> {
+    let response = http_outcall(
+        oracle.url.clone(),
+        HttpMethod::Get,
+        vec![],
+        None,
+        oracle.max_response_bytes,
+        oracle.transform.clone().unwrap_or(DEFAULT_TRANSFORM.to_string()),
+        oracle.cycles,
+    ).await?;
+    Ok(response.body)
+}
+
+/// append oracle data to the encoded task arguments as a trailing blob argument
+pub(crate) fn append_argument(arguments: &[u8], data: Vec<u8>) -> Result<Vec<u8>, &'static str> {
+    let mut args = if arguments.is_empty() {
+        vec![]
+    } else {
+        IDLArgs::from_bytes(arguments).map_err(|_| "Invalid task arguments")?.args
+    };
+    args.push(IDLValue::Vec(data.into_iter().map(IDLValue::Nat8).collect()));
+    IDLArgs::new(&args).to_bytes().map_err(|_| "Oracle argument encoding error")
+}
+
+/// default transform: drop headers so replicas agree on the response
+pub(crate) fn strip_headers(args: TransformArgs) -> HttpOutcallResponse {
+    HttpOutcallResponse {
+        status: args.response.status,
+        headers: vec![],
+        body: args.response.body,
+    }
+}
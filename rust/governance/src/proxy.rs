@@ -0,0 +1,56 @@
+/**
+ * Module     : proxy.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+use std::collections::HashMap;
+use ic_kit::candid::{CandidType, Deserialize};
+use ic_kit::Principal;
+
+/// standing voting proxies by delegator, the votes stay with the delegator
+#[derive(Deserialize, CandidType, Clone, Default)]
+pub struct Proxies {
+    pub(crate) proxies: HashMap<Principal, Principal>,
+}
+
+impl Proxies {
+    pub(crate) fn set(&mut self, delegator: Principal, proxy: Principal) -> Result<(), &'static str> {
+        if delegator == proxy {
+            return Err("cannot register self as proxy");
+        }
+        if proxy == Principal::anonymous() {
+            return Err("proxy cannot be anonymous");
+        }
+        self.proxies.insert(delegator, proxy);
+        Ok(())
+    }
+
+    pub(crate) fn revoke(&mut self, delegator: &Principal) -> Result<Principal, &'static str> {
+        self.proxies.remove(delegator).ok_or("no proxy registered")
+    }
+
+    pub(crate) fn proxy_of(&self, delegator: &Principal) -> Option<Principal> {
+        self.proxies.get(delegator).cloned()
+    }
+
+    /// check the proxy may vote for the delegator
+    pub(crate) fn check_proxy(&self, delegator: &Principal, proxy: &Principal) -> Result<(), &'static str> {
+        match self.proxies.get(delegator) {
+            Some(p) if p == proxy => { Ok(()) }
+            _ => { Err("caller is not the proxy of the delegator") }
+        }
+    }
+
+    /// delegators the proxy votes for
+    pub(crate) fn delegators_of(&self, proxy: &Principal) -> Vec<Principal> {
+        let mut delegators = self.proxies.iter()
+            .filter(|(_, p)| *p == proxy)
+            .map(|(d, _)| *d)
+            .collect::<Vec<Principal>>();
+        delegators.sort();
+        delegators
+    }
+}
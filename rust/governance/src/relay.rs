@@ -0,0 +1,79 @@
+/**
+ * Module     : relay.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+use std::collections::HashSet;
+use ic_kit::candid::{CandidType, Deserialize};
+use ic_kit::Principal;
+use k256::ecdsa::signature::Verifier;
+use k256::ecdsa::{Signature, VerifyingKey};
+use crate::governance::VoteType;
+
+/// DER prefix of a secp256k1 SubjectPublicKeyInfo holding an uncompressed point
+pub(crate) const SECP256K1_DER_PREFIX: [u8; 23] = [
+    0x30, 0x56, 0x30, 0x10, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x05, 0x2b,
+    0x81, 0x04, 0x00, 0x0a, 0x03, 0x42, 0x00,
+];
+/// domain separator of signed ballots
+const BALLOT_DOMAIN: &[u8] = b"\x0Fgovernance-vote";
+
+/// ballot signed by the voter's key, submitted by a relayer
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub struct BallotSignature {
+    /// DER encoded secp256k1 public key the voter principal is derived from
+    pub public_key: Vec<u8>,
+    /// 64 bytes r || s ecdsa signature over the sha256 of the ballot message
+    pub signature: Vec<u8>,
+}
+
+#[derive(Deserialize, CandidType, Clone, Default)]
+pub struct Relayers {
+    pub(crate) relayers: HashSet<Principal>,
+}
+
+impl Relayers {
+    pub(crate) fn add(&mut self, relayer: Principal) -> Result<(), &'static str> {
+        if !self.relayers.insert(relayer) {
+            return Err("relayer already allowed");
+        }
+        Ok(())
+    }
+
+    pub(crate) fn remove(&mut self, relayer: &Principal) -> Result<(), &'static str> {
+        if !self.relayers.remove(relayer) {
+            return Err("relayer not allowed");
+        }
+        Ok(())
+    }
+
+    pub(crate) fn is_allowed(&self, relayer: &Principal) -> bool {
+        self.relayers.contains(relayer)
+    }
+}
+
+/// bytes signed by the voter: domain separator, governor, proposal id and vote type
+pub(crate) fn ballot_message(governor: &Principal, id: usize, vote_type: &VoteType) -> Vec<u8> {
+    let mut message = BALLOT_DOMAIN.to_vec();
+    message.extend_from_slice(governor.as_slice());
+    message.extend_from_slice(&(id as u64).to_be_bytes());
+    message.extend_from_slice(vote_type.label().as_bytes());
+    message
+}
+
+/// check the ballot is signed by the key of the voter
+pub(crate) fn verify_ballot(voter: &Principal, message: &[u8], ballot: &BallotSignature) -> Result<(), &'static str> {
+    if ballot.public_key.len() != SECP256K1_DER_PREFIX.len() + 65 || !ballot.public_key.starts_with(&SECP256K1_DER_PREFIX) {
+        return Err("public key must be a DER encoded secp256k1 key");
+    }
+    if Principal::self_authenticating(&ballot.public_key) != *voter {
+        return Err("public key does not match the voter");
+    }
+    let key = VerifyingKey::from_sec1_bytes(&ballot.public_key[SECP256K1_DER_PREFIX.len()..])
+        .map_err(|_| "invalid public key")?;
+    let signature = Signature::try_from(ballot.signature.as_slice()).map_err(|_| "invalid signature encoding")?;
+    key.verify(message, &signature).map_err(|_| "invalid ballot signature")
+}
@@ -0,0 +1,93 @@
+/**
+ * Module     : scheduler.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+//! Automatic queueing and execution of proposals. The runs are started from
+//! the canister heartbeat rather than from timers: timers need the global
+//! timer of a newer ic-cdk than the 0.5 this canister builds against, and the
+//! heartbeat already drives the proposal sweep every `SWEEP_INTERVAL`.
+//!
+//! A run spans several inter-canister calls. If one of its callbacks traps the
+//! run never reaches `finish`, so a run older than `STALE_RUN` is taken to be
+//! lost and the next heartbeat starts a new one.
+
+use std::collections::HashMap;
+use ic_kit::candid::{CandidType, Deserialize};
+
+/// automatic executions of a proposal stop after this many failures, it can still be executed by hand
+pub const MAX_FAILURES: u32 = 3;
+
+/// a run in flight for longer than this is taken to have trapped, 30 minutes
+pub const STALE_RUN: u64 = 30 * 60 * 1_000_000_000;
+
+/// queues succeeded proposals and executes due tasks from the heartbeat, without a keeper
+#[derive(Deserialize, CandidType, Clone, Default)]
+pub struct Scheduler {
+    pub(crate) enabled: bool,
+    /// start time of the run in flight, the heartbeat does not start another one until it is stale
+    started: Option<u64>,
+    /// failed automatic executions by proposal id
+    failures: HashMap<usize, u32>,
+}
+
+#[derive(CandidType, Clone, Debug)]
+pub struct SchedulerInfo {
+    pub enabled: bool,
+    pub running: bool,
+    pub failures: Vec<(usize, u32)>,
+}
+
+impl Scheduler {
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if enabled {
+            self.failures.clear();
+        }
+    }
+
+    /// begin a run if the scheduler is enabled and idle, or the run in flight is stale
+    pub(crate) fn start(&mut self, time: u64) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        if let Some(started) = self.started {
+            if time < started.saturating_add(STALE_RUN) {
+                return false;
+            }
+        }
+        self.started = Some(time);
+        true
+    }
+
+    pub(crate) fn finish(&mut self) {
+        self.started = None;
+    }
+
+    /// first of the executable proposals which has not failed too often
+    pub(crate) fn pick(&self, executable: Vec<usize>) -> Option<usize> {
+        executable.into_iter()
+            .find(|id| self.failures.get(id).copied().unwrap_or(0) < MAX_FAILURES)
+    }
+
+    pub(crate) fn record_execution(&mut self, id: usize, success: bool) {
+        if success {
+            self.failures.remove(&id);
+        } else {
+            *self.failures.entry(id).or_insert(0) += 1;
+        }
+    }
+
+    pub(crate) fn info(&self) -> SchedulerInfo {
+        let mut failures: Vec<(usize, u32)> = self.failures.iter().map(|(id, n)| (*id, *n)).collect();
+        failures.sort();
+        SchedulerInfo {
+            enabled: self.enabled,
+            running: self.started.is_some(),
+            failures,
+        }
+    }
+}
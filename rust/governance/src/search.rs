@@ -0,0 +1,73 @@
+/**
+ * Module     : search.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+use std::collections::{BTreeSet, HashMap};
+use ic_kit::candid::{CandidType, Deserialize};
+use crate::governance::GovernorBravo;
+
+/// tokens shorter than this are not indexed
+const MIN_TOKEN_LEN: usize = 2;
+
+/// inverted index of proposal titles and descriptions
+#[derive(Deserialize, CandidType, Clone, Default)]
+pub struct SearchIndex {
+    /// ids of the proposals containing each token
+    pub(crate) tokens: HashMap<String, BTreeSet<usize>>,
+    /// proposals below this id are indexed
+    pub(crate) indexed: usize,
+}
+
+/// lowercase alphanumeric words of the text
+fn tokenize(text: &str) -> BTreeSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| t.chars().count() >= MIN_TOKEN_LEN)
+        .map(|t| t.to_lowercase())
+        .collect()
+}
+
+impl SearchIndex {
+    /// index the proposal, replacing the tokens previously indexed for it
+    pub(crate) fn index(&mut self, id: usize, title: &str, description: &str) {
+        for ids in self.tokens.values_mut() {
+            ids.remove(&id);
+        }
+        self.tokens.retain(|_, ids| !ids.is_empty());
+        let mut tokens = tokenize(title);
+        tokens.extend(tokenize(description));
+        for token in tokens {
+            self.tokens.entry(token).or_default().insert(id);
+        }
+    }
+
+    /// index the proposals made since the last sync
+    pub(crate) fn sync(&mut self, bravo: &GovernorBravo) {
+        while self.indexed < bravo.proposal_count() {
+            let id = self.indexed;
+            if let Ok(p) = bravo.get_proposal(id) {
+                self.index(id, &p.title, &p.description);
+            }
+            self.indexed += 1;
+        }
+    }
+
+    /// ids of the proposals containing every word of the query, newest first
+    pub(crate) fn search(&self, query: &str) -> Vec<usize> {
+        let mut result: Option<BTreeSet<usize>> = None;
+        for token in tokenize(query) {
+            let ids = match self.tokens.get(&token) {
+                Some(ids) => { ids }
+                None => { return vec![]; }
+            };
+            result = Some(match result {
+                Some(r) => { r.intersection(ids).cloned().collect() }
+                None => { ids.clone() }
+            });
+        }
+        result.map_or(vec![], |r| r.into_iter().rev().collect())
+    }
+}
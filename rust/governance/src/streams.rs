@@ -0,0 +1,118 @@
+/**
+ * Module     : streams.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+use std::collections::HashMap;
+use ic_kit::candid::{CandidType, Deserialize, Nat};
+use ic_kit::Principal;
+use crate::timelock::{Asset, GrantStream};
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub struct Stream {
+    pub id: usize,
+    pub asset: Asset,
+    pub recipient: Principal,
+    /// amount accrued per second
+    pub rate: Nat,
+    pub start: u64,
+    pub end: u64,
+    /// amount claimed so far, including claims in flight
+    pub claimed: Nat,
+    /// set when the stream is stopped by a proposal
+    pub canceled: bool,
+}
+
+#[derive(Deserialize, CandidType, Clone, Default)]
+pub struct Streams {
+    pub(crate) next_id: usize,
+    pub(crate) streams: HashMap<usize, Stream>,
+}
+
+impl Stream {
+    /// amount accrued until the timestamp
+    pub fn accrued(&self, timestamp: u64) -> Nat {
+        let elapsed = timestamp.min(self.end).saturating_sub(self.start);
+        Nat::from(elapsed) * self.rate.clone() / Nat::from(NANOS_PER_SECOND)
+    }
+
+    /// amount accrued and not claimed yet
+    pub fn claimable(&self, timestamp: u64) -> Nat {
+        let accrued = self.accrued(timestamp);
+        if accrued > self.claimed {
+            accrued - self.claimed.clone()
+        } else {
+            Nat::from(0)
+        }
+    }
+}
+
+impl Streams {
+    /// open a stream starting at the timestamp, return its id
+    pub(crate) fn create(&mut self, grant: &GrantStream, timestamp: u64) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.streams.insert(id, Stream {
+            id,
+            asset: grant.asset,
+            recipient: grant.recipient,
+            rate: grant.rate.clone(),
+            start: timestamp,
+            end: timestamp.saturating_add(grant.duration),
+            claimed: Nat::from(0),
+            canceled: false,
+        });
+        id
+    }
+
+    /// stop the stream, the amount accrued so far stays claimable
+    pub(crate) fn cancel(&mut self, id: usize, timestamp: u64) -> Result<(), &'static str> {
+        let stream = self.streams.get_mut(&id).ok_or("stream not found")?;
+        if stream.canceled {
+            return Err("stream already canceled");
+        }
+        stream.end = stream.end.min(timestamp);
+        stream.canceled = true;
+        Ok(())
+    }
+
+    pub(crate) fn get(&self, id: usize) -> Option<&Stream> {
+        self.streams.get(&id)
+    }
+
+    /// streams of the recipient, all streams if none
+    pub(crate) fn list(&self, recipient: Option<Principal>) -> Vec<Stream> {
+        let mut streams: Vec<Stream> = self.streams.values()
+            .filter(|s| recipient.map_or(true, |r| s.recipient == r))
+            .cloned()
+            .collect();
+        streams.sort_by_key(|s| s.id);
+        streams
+    }
+
+    /// reserve the claimable amount of the stream for the caller before transferring
+    pub(crate) fn reserve_claim(&mut self, id: usize, caller: &Principal, timestamp: u64) -> Result<(Stream, Nat), &'static str> {
+        let stream = self.streams.get_mut(&id).ok_or("stream not found")?;
+        if stream.recipient != *caller {
+            return Err("caller is not the recipient");
+        }
+        let amount = stream.claimable(timestamp);
+        if amount == Nat::from(0) {
+            return Err("nothing to claim");
+        }
+        stream.claimed += amount.clone();
+        Ok((stream.clone(), amount))
+    }
+
+    /// give back a reserved claim whose transfer failed
+    pub(crate) fn release_claim(&mut self, id: usize, amount: &Nat) {
+        if let Some(stream) = self.streams.get_mut(&id) {
+            stream.claimed -= amount.clone();
+        }
+    }
+}
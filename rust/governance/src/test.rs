@@ -1,5 +1,3 @@
-use std::thread::sleep;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use ic_kit::{Method, MockContext, async_test};
 use ic_kit::mock_principals::{alice, bob};
 use crate::VoteType::Support;
@@ -24,19 +22,27 @@ fn set_up() -> &'static mut MockContext {
         .inject()
 }
 
+/// install a mock clock starting at the current time
+fn set_up_clock() -> MockClock {
+    let clock = MockClock::new(IcClock.now());
+    set_clock(clock.clone());
+    clock
+}
+
 #[async_test]
 async fn test_propose() -> Result<(), String> {
     let ctx = set_up();
+    set_up_clock();
 
     BRAVO.with(|bravo| {
         let mut bravo = bravo.borrow_mut();
         bravo.initialize(
             alice(),
             "Test".to_string(),
-            100,
+            Nat::from(100),
             10e9 as u64,
             10e9 as u64,
-            500,
+            Nat::from(500),
             10e9 as u64,
             Principal::anonymous(),
         );
@@ -49,6 +55,8 @@ async fn test_propose() -> Result<(), String> {
         "test".to_string(),
         vec![],
         0,
+        None,
+        None,
     ).await?;
 
     let (_, state) = get_proposal(0)?;
@@ -68,10 +76,10 @@ async fn test_propose_fail_below_threshold() -> Result<(), String> {
         bravo.initialize(
             alice(),
             "Test".to_string(),
-            1000,
+            Nat::from(1000),
             10e9 as u64,
             10e9 as u64,
-            5001,
+            Nat::from(5001),
             10e9 as u64,
             Principal::anonymous(),
         );
@@ -85,25 +93,70 @@ async fn test_propose_fail_below_threshold() -> Result<(), String> {
                  "test".to_string(),
                  vec![],
                  0,
+                 None,
+                 None,
              ).await.unwrap_err()
     );
 
     Ok(())
 }
 
+#[async_test]
+async fn test_propose_rejected_voting_period() -> Result<(), String> {
+    set_up();
+    set_up_clock();
+
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.initialize(
+            alice(),
+            "Test".to_string(),
+            Nat::from(100),
+            10e9 as u64,
+            10e9 as u64,
+            Nat::from(500),
+            10e9 as u64,
+            Principal::anonymous(),
+        );
+    });
+    let propose_for = |period: u64| propose(
+        "test".to_string(),
+        "test".to_string(),
+        Principal::management_canister(),
+        "test".to_string(),
+        vec![],
+        0,
+        Some(1),
+        Some(period),
+    );
+
+    // overrides are disabled, nothing is created
+    assert!(propose_for(20e9 as u64).await.is_err());
+    BRAVO.with(|bravo| bravo.borrow_mut().set_voting_period_bounds(Some((5e9 as u64, 50e9 as u64))))?;
+    assert!(propose_for(60e9 as u64).await.is_err());
+    assert_eq!(BRAVO.with(|bravo| bravo.borrow().proposal_count()), 0);
+
+    let id = propose_for(20e9 as u64).await?;
+    let (proposal, _) = get_proposal(id)?;
+    assert_eq!(proposal.end_time - proposal.start_time, 20e9 as u64);
+
+    Ok(())
+}
+
 #[async_test]
 async fn test_cast_vote() -> Result<(), String> {
     let ctx = set_up();
+    let clock = set_up_clock();
 
     BRAVO.with(|bravo| {
         let mut bravo = bravo.borrow_mut();
         bravo.initialize(
             alice(),
             "Test".to_string(),
-            1000,
+            Nat::from(1000),
             1e9 as u64,
             10e9 as u64,
-            5000,
+            Nat::from(5000),
             10e9 as u64,
             Principal::anonymous(),
         );
@@ -113,18 +166,12 @@ async fn test_cast_vote() -> Result<(), String> {
             Nat::from(10000),
             "Test".to_string(),
             "".to_string(),
-            Principal::management_canister(),
-            "test".to_string(),
-            vec![],
-            0,
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_nanos() as u64,
+            Task::new(Principal::management_canister(), "test".to_string(), vec![], 0),
+            clock.now(),
         );
     });
 
-    sleep(Duration::from_secs(1));
+    clock.advance(1e9 as u64);
     cast_vote(0, Support, None).await?;
 
     let (proposal, state) = get_proposal(0)?;
@@ -141,16 +188,17 @@ async fn test_cast_vote() -> Result<(), String> {
 #[async_test]
 async fn test_queue() -> Result<(), String> {
     let ctx = set_up();
+    let clock = set_up_clock();
 
     BRAVO.with(|bravo| {
         let mut bravo = bravo.borrow_mut();
         bravo.initialize(
             alice(),
             "Test".to_string(),
-            1000,
+            Nat::from(1000),
             0 as u64,
             3e9 as u64,
-            5000,
+            Nat::from(5000),
             10e9 as u64,
             Principal::anonymous(),
         );
@@ -160,14 +208,8 @@ async fn test_queue() -> Result<(), String> {
             Nat::from(10000),
             "Test".to_string(),
             "".to_string(),
-            Principal::management_canister(),
-            "test".to_string(),
-            vec![],
-            0,
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_nanos() as u64,
+            Task::new(Principal::management_canister(), "test".to_string(), vec![], 0),
+            clock.now(),
         );
 
         bravo.cast_vote(
@@ -176,14 +218,11 @@ async fn test_queue() -> Result<(), String> {
             Nat::from(5000),
             None,
             alice(),
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_nanos() as u64,
+            clock.now(),
         )
     });
 
-    sleep(Duration::from_secs(3));
+    clock.advance(3e9 as u64);
     queue(0).await?;
     let state = get_proposal_state(0)?;
     if state != ProposalState::Queued {
@@ -196,16 +235,17 @@ async fn test_queue() -> Result<(), String> {
 #[async_test]
 async fn test_queue_fail_quorum_limit() -> Result<(), String> {
     let ctx = set_up();
+    let clock = set_up_clock();
 
     BRAVO.with(|bravo| {
         let mut bravo = bravo.borrow_mut();
         bravo.initialize(
             alice(),
             "Test".to_string(),
-            5001,
+            Nat::from(5001),
             0 as u64,
             3e9 as u64,
-            5000,
+            Nat::from(5000),
             10e9 as u64,
             Principal::anonymous(),
         );
@@ -215,14 +255,8 @@ async fn test_queue_fail_quorum_limit() -> Result<(), String> {
             Nat::from(10000),
             "Test".to_string(),
             "".to_string(),
-            Principal::management_canister(),
-            "test".to_string(),
-            vec![],
-            0,
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_nanos() as u64,
+            Task::new(Principal::management_canister(), "test".to_string(), vec![], 0),
+            clock.now(),
         );
 
         bravo.cast_vote(
@@ -231,14 +265,11 @@ async fn test_queue_fail_quorum_limit() -> Result<(), String> {
             Nat::from(5000),
             None,
             alice(),
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_nanos() as u64,
+            clock.now(),
         )
     });
 
-    sleep(Duration::from_secs(3));
+    clock.advance(3e9 as u64);
     println!("{}", queue(0).await.unwrap_err());
 
     Ok(())
@@ -247,16 +278,17 @@ async fn test_queue_fail_quorum_limit() -> Result<(), String> {
 #[async_test]
 async fn test_queue_fail_not_end() -> Result<(), String> {
     let ctx = set_up();
+    let clock = set_up_clock();
 
     BRAVO.with(|bravo| {
         let mut bravo = bravo.borrow_mut();
         bravo.initialize(
             alice(),
             "Test".to_string(),
-            5000,
+            Nat::from(5000),
             0 as u64,
             3e9 as u64,
-            5000,
+            Nat::from(5000),
             10e9 as u64,
             Principal::anonymous(),
         );
@@ -266,14 +298,8 @@ async fn test_queue_fail_not_end() -> Result<(), String> {
             Nat::from(10000),
             "Test".to_string(),
             "".to_string(),
-            Principal::management_canister(),
-            "test".to_string(),
-            vec![],
-            0,
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_nanos() as u64,
+            Task::new(Principal::management_canister(), "test".to_string(), vec![], 0),
+            clock.now(),
         );
 
         bravo.cast_vote(
@@ -282,14 +308,11 @@ async fn test_queue_fail_not_end() -> Result<(), String> {
             Nat::from(5001),
             None,
             alice(),
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_nanos() as u64,
+            clock.now(),
         )
     });
 
-    sleep(Duration::from_secs(2));
+    clock.advance(2e9 as u64);
     println!("{}", queue(0).await.unwrap_err());
 
     Ok(())
@@ -298,16 +321,17 @@ async fn test_queue_fail_not_end() -> Result<(), String> {
 #[async_test]
 async fn test_execute() -> Result<(), String> {
     let ctx = set_up();
+    let clock = set_up_clock();
 
     BRAVO.with(|bravo| {
         let mut bravo = bravo.borrow_mut();
         bravo.initialize(
             alice(),
             "Test".to_string(),
-            5000,
+            Nat::from(5000),
             0 as u64,
             1e9 as u64,
-            5000,
+            Nat::from(5000),
             1e9 as u64,
             Principal::anonymous(),
         );
@@ -317,14 +341,8 @@ async fn test_execute() -> Result<(), String> {
             Nat::from(10000),
             "Test".to_string(),
             "".to_string(),
-            Principal::management_canister(),
-            "test".to_string(),
-            vec![],
-            0,
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_nanos() as u64,
+            Task::new(Principal::management_canister(), "test".to_string(), vec![], 0),
+            clock.now(),
         );
 
         bravo.cast_vote(
@@ -333,23 +351,17 @@ async fn test_execute() -> Result<(), String> {
             Nat::from(5001),
             None,
             alice(),
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_nanos() as u64,
+            clock.now(),
         );
 
-        sleep(Duration::from_secs(1));
+        clock.advance(1e9 as u64);
 
         bravo.queue(0,
-                    SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .expect("Time went backwards")
-                        .as_nanos() as u64,
+                    clock.now(),
         );
     });
 
-    sleep(Duration::from_secs(1));
+    clock.advance(1e9 as u64);
     execute(0).await?;
 
     let (_, state) = get_proposal(0)?;
@@ -363,16 +375,17 @@ async fn test_execute() -> Result<(), String> {
 #[async_test]
 async fn test_execute_fail_before_timelock() -> Result<(), String> {
     let ctx = set_up();
+    let clock = set_up_clock();
 
     BRAVO.with(|bravo| {
         let mut bravo = bravo.borrow_mut();
         bravo.initialize(
             alice(),
             "Test".to_string(),
-            5000,
+            Nat::from(5000),
             0 as u64,
             1e9 as u64,
-            5000,
+            Nat::from(5000),
             1e9 as u64,
             Principal::anonymous(),
         );
@@ -382,14 +395,8 @@ async fn test_execute_fail_before_timelock() -> Result<(), String> {
             Nat::from(10000),
             "Test".to_string(),
             "".to_string(),
-            Principal::management_canister(),
-            "test".to_string(),
-            vec![],
-            0,
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_nanos() as u64,
+            Task::new(Principal::management_canister(), "test".to_string(), vec![], 0),
+            clock.now(),
         );
 
         bravo.cast_vote(
@@ -398,19 +405,13 @@ async fn test_execute_fail_before_timelock() -> Result<(), String> {
             Nat::from(5001),
             None,
             alice(),
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_nanos() as u64,
+            clock.now(),
         );
 
-        sleep(Duration::from_secs(1));
+        clock.advance(1e9 as u64);
 
         bravo.queue(0,
-                    SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .expect("Time went backwards")
-                        .as_nanos() as u64,
+                    clock.now(),
         );
     });
 
@@ -422,16 +423,17 @@ async fn test_execute_fail_before_timelock() -> Result<(), String> {
 #[async_test]
 async fn test_cancel() -> Result<(), String> {
     let ctx = set_up();
+    let clock = set_up_clock();
 
     BRAVO.with(|bravo| {
         let mut bravo = bravo.borrow_mut();
         bravo.initialize(
             alice(),
             "Test".to_string(),
-            5000,
+            Nat::from(5000),
             0 as u64,
             1e9 as u64,
-            5000,
+            Nat::from(5000),
             1e9 as u64,
             Principal::anonymous(),
         );
@@ -441,14 +443,8 @@ async fn test_cancel() -> Result<(), String> {
             Nat::from(10000),
             "Test".to_string(),
             "".to_string(),
-            Principal::management_canister(),
-            "test".to_string(),
-            vec![],
-            0,
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_nanos() as u64,
+            Task::new(Principal::management_canister(), "test".to_string(), vec![], 0),
+            clock.now(),
         );
     });
 
@@ -465,16 +461,17 @@ async fn test_cancel() -> Result<(), String> {
 #[async_test]
 async fn test_cancel_below_threshold() -> Result<(), String> {
     let ctx = set_up();
+    let clock = set_up_clock();
 
     BRAVO.with(|bravo| {
         let mut bravo = bravo.borrow_mut();
         bravo.initialize(
             alice(),
             "Test".to_string(),
-            5000,
+            Nat::from(5000),
             0 as u64,
             1e9 as u64,
-            6000,
+            Nat::from(6000),
             1e9 as u64,
             Principal::anonymous(),
         );
@@ -484,14 +481,8 @@ async fn test_cancel_below_threshold() -> Result<(), String> {
             Nat::from(10000),
             "Test".to_string(),
             "".to_string(),
-            Principal::management_canister(),
-            "test".to_string(),
-            vec![],
-            0,
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_nanos() as u64,
+            Task::new(Principal::management_canister(), "test".to_string(), vec![], 0),
+            clock.now(),
         );
     });
 
@@ -508,16 +499,17 @@ async fn test_cancel_below_threshold() -> Result<(), String> {
 #[async_test]
 async fn test_cancel_fail() -> Result<(), String> {
     let ctx = set_up();
+    let clock = set_up_clock();
 
     BRAVO.with(|bravo| {
         let mut bravo = bravo.borrow_mut();
         bravo.initialize(
             alice(),
             "Test".to_string(),
-            5000,
+            Nat::from(5000),
             0 as u64,
             1e9 as u64,
-            4000,
+            Nat::from(4000),
             1e9 as u64,
             Principal::anonymous(),
         );
@@ -527,18 +519,648 @@ async fn test_cancel_fail() -> Result<(), String> {
             Nat::from(10000),
             "Test".to_string(),
             "".to_string(),
-            Principal::management_canister(),
-            "test".to_string(),
-            vec![],
-            0,
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_nanos() as u64,
+            Task::new(Principal::management_canister(), "test".to_string(), vec![], 0),
+            clock.now(),
         );
     });
 
     cancel(0).await.unwrap_err();
 
     Ok(())
-}
\ No newline at end of file
+}
+#[async_test]
+async fn test_export_proposals() -> Result<(), String> {
+    set_up();
+    let clock = set_up_clock();
+
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.initialize(
+            alice(),
+            "Test".to_string(),
+            Nat::from(100),
+            10e9 as u64,
+            10e9 as u64,
+            Nat::from(500),
+            10e9 as u64,
+            Principal::anonymous(),
+        );
+    });
+
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.propose(
+            bob(),
+            Nat::from(5000),
+            "test, with comma".to_string(),
+            "test".to_string(),
+            Task::new(Principal::management_canister(), "test".to_string(), vec![], 0),
+            clock.now(),
+        )
+    })?;
+    // an identical task in a live proposal is rejected
+    propose(
+        "test".to_string(),
+        "test".to_string(),
+        Principal::management_canister(),
+        "other".to_string(),
+        vec![],
+        0,
+        None,
+        None,
+    ).await?;
+
+    let chunk = export_proposals(ExportFormat::Csv, 0, 1)?;
+    if chunk.next != Some(1) || chunk.data.lines().count() != 2 || !chunk.data.contains("\"test, with comma\"") {
+        return Err("First chunk must hold the header and one proposal".to_string());
+    }
+    let chunk = export_proposals(ExportFormat::Json, 1, 1)?;
+    if chunk.next.is_some() || !chunk.data.starts_with("[{\"id\":\"1\"") {
+        return Err("Last chunk must hold the second proposal".to_string());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_inspect_vote() {
+    let mut bravo = GovernorBravo::default();
+    bravo.initialize(alice(), "Test".to_string(), Nat::from(100), 1, 10e9 as u64, Nat::from(500), 10e9 as u64, Principal::anonymous());
+    bravo.propose(
+        alice(),
+        Nat::from(5000),
+        "Test".to_string(),
+        "".to_string(),
+        Task::new(Principal::management_canister(), "test".to_string(), vec![], 0),
+        0,
+    ).unwrap();
+    let mut cache = VotesCache::default();
+
+    assert!(inspect::check_caller(&Principal::anonymous()).is_err());
+    assert!(inspect::check_vote(&bravo, &cache, &bob(), 0, 0).is_err());
+    assert!(inspect::check_vote(&bravo, &cache, &bob(), 0, 1).is_ok());
+    cache.record(bob(), 0, &Nat::from(0), 1);
+    assert!(inspect::check_vote(&bravo, &cache, &bob(), 0, 1).is_err());
+    // no votes on one proposal says nothing about another
+    assert!(!cache.known_without_votes(&bob(), 1, 1));
+}
+
+#[test]
+fn test_stream_accrual() {
+    let mut streams = Streams::default();
+    let grant = GrantStream {
+        asset: Asset::GovToken,
+        recipient: bob(),
+        rate: Nat::from(10),
+        duration: 100e9 as u64,
+    };
+    let id = streams.create(&grant, 0);
+    let stream = streams.get(id).unwrap();
+    assert_eq!(stream.accrued(10e9 as u64), Nat::from(100));
+    assert_eq!(stream.accrued(200e9 as u64), Nat::from(1000));
+
+    assert!(streams.reserve_claim(id, &alice(), 10e9 as u64).is_err());
+    let (_, amount) = streams.reserve_claim(id, &bob(), 10e9 as u64).unwrap();
+    assert_eq!(amount, Nat::from(100));
+    assert!(streams.reserve_claim(id, &bob(), 10e9 as u64).is_err());
+    streams.release_claim(id, &amount);
+    assert_eq!(streams.get(id).unwrap().claimable(10e9 as u64), Nat::from(100));
+
+    streams.cancel(id, 50e9 as u64).unwrap();
+    assert_eq!(streams.get(id).unwrap().claimable(200e9 as u64), Nat::from(500));
+    assert!(streams.cancel(id, 60e9 as u64).is_err());
+}
+
+#[test]
+fn test_cycles_pool() {
+    let mut treasury = CyclesTreasury::default();
+    treasury.deposit(alice(), 1000);
+    treasury.deposit(bob(), 500);
+    treasury.deposit(alice(), 200);
+    assert_eq!(treasury.deposit_of(&alice()), 1200);
+    assert_eq!(treasury.deposit_of(&bob()), 500);
+
+    assert!(treasury.spend(2000).is_err());
+    treasury.spend(1500).unwrap();
+    // refunds of the target flow back into the pool
+    treasury.record_refund(300);
+    let info = treasury.info(0);
+    assert_eq!(info.pool, 500);
+    assert_eq!(info.total_deposited, 1700);
+    assert_eq!(info.total_spent, 1500);
+}
+
+#[test]
+fn test_audit_log() {
+    let mut log = AuditLog::default();
+    assert!(log.head().is_empty());
+    log.record(alice(), "setTieRule", vec![1, 2], 10);
+    log.record(bob(), "addRelayer", vec![3], 20);
+    let records = log.page(0, 10);
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[1].method, "addRelayer");
+    assert_eq!(log.head(), records[1].hash);
+
+    // the same calls chained after a different history hash differently
+    let mut other = AuditLog::default();
+    other.record(alice(), "setTieRule", vec![1], 10);
+    other.record(bob(), "addRelayer", vec![3], 20);
+    assert_ne!(other.head(), log.head());
+    assert_eq!(log.page(1, 10).len(), 1);
+}
+
+#[test]
+fn test_reminders() {
+    use crate::notification::{Reminder, EXPIRY_REMINDER, VOTING_END_REMINDER};
+
+    let day = 24 * 3600 * 1_000_000_000u64;
+    let mut bravo = GovernorBravo::default();
+    bravo.initialize(alice(), "Test".to_string(), Nat::from(100), 1, 3 * day, Nat::from(500), day, Principal::anonymous());
+    bravo.propose(alice(), Nat::from(5000), "Test".to_string(), "".to_string(),
+        Task::new(Principal::management_canister(), "test".to_string(), vec![], 0), 0).unwrap();
+    let mut notifier = Notifier::default();
+    notifier.subscribe(bob(), "onStateChange".to_string(), &bravo, 0).unwrap();
+    // the subscriber starts from the current state
+    assert_eq!(notifier.collect(&bravo, 0), vec![]);
+    assert_eq!(notifier.collect(&bravo, 1), vec![(0, ProposalState::Active)]);
+    assert!(notifier.collect_reminders(&bravo, 1).is_empty());
+    let soon = 1 + 3 * day - VOTING_END_REMINDER;
+    assert_eq!(notifier.collect_reminders(&bravo, soon), vec![(0, Reminder::VotingEndsSoon)]);
+    // each deadline is reminded once
+    assert!(notifier.collect_reminders(&bravo, soon + 1).is_empty());
+
+    bravo.cast_vote(0, VoteType::Support, Nat::from(200), None, alice(), soon).unwrap();
+    let ended = 1 + 3 * day;
+    bravo.queue(0, ended).unwrap();
+    notifier.collect(&bravo, ended);
+    assert!(notifier.collect_reminders(&bravo, ended).is_empty());
+    let expiry = bravo.get_task(0).unwrap().expires_at();
+    assert_eq!(notifier.collect_reminders(&bravo, expiry - EXPIRY_REMINDER), vec![(0, Reminder::ExpiresSoon)]);
+
+    // the registry is bounded, subscribers may still update their method
+    for i in 1..notification::MAX_SUBSCRIBERS {
+        notifier.subscribe(Principal::from_slice(&[i as u8]), "onStateChange".to_string(), &bravo, ended).unwrap();
+    }
+    assert!(notifier.subscribe(alice(), "onStateChange".to_string(), &bravo, ended).is_err());
+    assert!(notifier.subscribe(bob(), "onChange".to_string(), &bravo, ended).is_ok());
+}
+
+#[test]
+fn test_search_index() {
+    let mut index = SearchIndex::default();
+    index.index(0, "Fund the grants program", "Treasury spend for Q1 grants");
+    index.index(1, "Upgrade the token", "New token canister wasm");
+    index.index(2, "More grants", "Second round of grants, from the treasury");
+
+    assert_eq!(index.search("grants"), vec![2, 0]);
+    assert_eq!(index.search("Treasury GRANTS"), vec![2, 0]);
+    assert_eq!(index.search("grants token"), Vec::<usize>::new());
+    assert_eq!(index.search("wasm"), vec![1]);
+
+    index.index(1, "Upgrade the governor", "");
+    assert!(index.search("token").is_empty());
+}
+
+#[test]
+fn test_verify_ballot() {
+    use k256::ecdsa::signature::Signer;
+    use k256::ecdsa::{Signature, SigningKey};
+    use k256::elliptic_curve::sec1::ToEncodedPoint;
+
+    let key = SigningKey::from_bytes(&[7u8; 32]).unwrap();
+    let point = key.verifying_key().to_encoded_point(false);
+    let mut public_key = relay::SECP256K1_DER_PREFIX.to_vec();
+    public_key.extend_from_slice(point.as_bytes());
+    let voter = Principal::self_authenticating(&public_key);
+
+    let message = relay::ballot_message(&Principal::anonymous(), 0, &VoteType::Support);
+    let signature: Signature = key.sign(&message);
+    let ballot = BallotSignature {
+        public_key,
+        signature: signature.as_ref().to_vec(),
+    };
+    assert!(relay::verify_ballot(&voter, &message, &ballot).is_ok());
+    assert!(relay::verify_ballot(&bob(), &message, &ballot).is_err());
+    let other = relay::ballot_message(&Principal::anonymous(), 0, &VoteType::Against);
+    assert!(relay::verify_ballot(&voter, &other, &ballot).is_err());
+}
+
+
+#[test]
+fn test_guardian_threshold() {
+    let carol = Principal::from_slice(&[3]);
+    let mut guardian = Guardian::default();
+    assert!(guardian.set_members(vec![alice(), bob()], 3).is_err());
+    guardian.set_members(vec![alice(), bob(), carol], 2).unwrap();
+
+    assert!(guardian.propose(Principal::anonymous(), GuardianAction::Pause, 0).is_err());
+    let id = guardian.propose(alice(), GuardianAction::Pause, 0).unwrap();
+    assert_eq!(guardian.confirm(alice(), id, 0).unwrap(), None);
+    guardian.revoke(alice(), id, 0).unwrap();
+    assert_eq!(guardian.confirm(bob(), id, 0).unwrap(), None);
+    assert!(guardian.check_not_paused().is_ok());
+    assert_eq!(guardian.confirm(carol, id, 0).unwrap(), Some(GuardianAction::Pause));
+    assert!(guardian.check_not_paused().is_err());
+
+    let id = guardian.propose(alice(), GuardianAction::Unpause, 0).unwrap();
+    guardian.confirm(alice(), id, 0).unwrap();
+    assert!(guardian.confirm(bob(), id, 2 * 24 * 3600 * 1_000_000_000).is_err());
+}
+
+#[test]
+fn test_offchain_committee() {
+    use k256::ecdsa::signature::Signer;
+    use k256::ecdsa::{Signature, SigningKey};
+    use k256::elliptic_curve::sec1::ToEncodedPoint;
+
+    let keys: Vec<SigningKey> = (1u8..=3).map(|i| SigningKey::from_bytes(&[i; 32]).unwrap()).collect();
+    let public_keys = keys.iter()
+        .map(|k| k.verifying_key().to_encoded_point(true).as_bytes().to_vec())
+        .collect::<Vec<Vec<u8>>>();
+    let mut committee = SignerCommittee::default();
+    assert!(committee.set(public_keys.clone(), 4).is_err());
+    committee.set(public_keys, 2).unwrap();
+
+    let tally = OffchainTally {
+        proposal_id: 0,
+        snapshot: "0x01".to_string(),
+        tally: Tally {
+            support_votes: Nat::from(100),
+            against_votes: Nat::from(10),
+            abstain_votes: Nat::from(0),
+        },
+    };
+    let message = offchain::tally_message(&Principal::anonymous(), &tally).unwrap();
+    let sign = |k: &SigningKey| -> Vec<u8> {
+        let signature: Signature = k.sign(&message);
+        signature.as_ref().to_vec()
+    };
+    // the same signer twice does not reach the threshold
+    assert!(committee.verify(&message, &[sign(&keys[0]), sign(&keys[0])]).is_err());
+    assert!(committee.verify(&message, &[sign(&keys[0]), sign(&keys[2])]).is_ok());
+    let other = offchain::tally_message(&Principal::anonymous(), &OffchainTally { proposal_id: 1, ..tally.clone() }).unwrap();
+    assert!(committee.verify(&other, &[sign(&keys[0]), sign(&keys[2])]).is_err());
+
+    assert!(committee.check_not_imported(&tally).is_ok());
+    committee.mark_imported(&tally);
+    assert!(committee.check_not_imported(&tally).is_err());
+}
+
+#[async_test]
+async fn test_cast_vote_for() -> Result<(), String> {
+    let ctx = set_up();
+    let clock = set_up_clock();
+
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.initialize(
+            alice(),
+            "Test".to_string(),
+            Nat::from(1000),
+            1e9 as u64,
+            10e9 as u64,
+            Nat::from(5000),
+            10e9 as u64,
+            Principal::anonymous(),
+        );
+
+        bravo.propose(
+            alice(),
+            Nat::from(10000),
+            "Test".to_string(),
+            "".to_string(),
+            Task::new(Principal::management_canister(), "test".to_string(), vec![], 0),
+            clock.now(),
+        )
+    })?;
+    clock.advance(1e9 as u64);
+
+    set_voting_proxy(bob()).await?;
+    if get_voting_proxy(alice()) != Some(bob()) || get_proxy_delegators(bob()) != vec![alice()] {
+        return Err("Bob must be the proxy of alice".to_string());
+    }
+    // only the registered proxy votes for the delegator
+    cast_vote_for(alice(), 0, Support, None).await.unwrap_err();
+    ctx.update_caller(bob());
+    cast_vote_for(alice(), 0, Support, None).await?;
+    cast_vote_for(alice(), 0, Support, None).await.unwrap_err();
+    let (proposal, _) = get_proposal(0)?;
+    if proposal.support_votes != 5000 {
+        return Err("Votes of the delegator must be counted".to_string());
+    }
+
+    ctx.update_caller(alice());
+    revoke_voting_proxy().await?;
+    if get_voting_proxy(alice()).is_some() {
+        return Err("Proxy must be revoked".to_string());
+    }
+
+    Ok(())
+}
+
+#[async_test]
+async fn test_scheduler() -> Result<(), String> {
+    let _ctx = set_up();
+    let clock = set_up_clock();
+
+    BRAVO.with(|bravo| {
+        let mut bravo = bravo.borrow_mut();
+        bravo.initialize(
+            alice(),
+            "Test".to_string(),
+            Nat::from(5000),
+            0 as u64,
+            1e9 as u64,
+            Nat::from(5000),
+            1e9 as u64,
+            Principal::anonymous(),
+        );
+
+        bravo.propose(
+            alice(),
+            Nat::from(10000),
+            "Test".to_string(),
+            "".to_string(),
+            Task::new(Principal::management_canister(), "test".to_string(), vec![], 0),
+            clock.now(),
+        )?;
+
+        bravo.cast_vote(
+            0,
+            VoteType::Support,
+            Nat::from(5001),
+            None,
+            alice(),
+            clock.now(),
+        )
+    })?;
+
+    clock.advance(1e9 as u64);
+    run_scheduler().await;
+    if get_proposal_state(0)? != ProposalState::Queued {
+        return Err("Succeeded proposal must be queued".to_string());
+    }
+
+    clock.advance(1e9 as u64);
+    run_scheduler().await;
+    if get_proposal_state(0)? != ProposalState::Executed {
+        return Err("Due proposal must be executed".to_string());
+    }
+
+    // a failing task is retried until MAX_FAILURES
+    let mut scheduler = Scheduler::default();
+    scheduler.set_enabled(true);
+    assert!(scheduler.start(0));
+    assert!(!scheduler.start(1));
+    // a run whose callback trapped does not block the scheduler for good
+    assert!(scheduler.start(scheduler::STALE_RUN));
+    for _ in 0..scheduler::MAX_FAILURES {
+        assert_eq!(scheduler.pick(vec![1, 2]), Some(1));
+        scheduler.record_execution(1, false);
+    }
+    assert_eq!(scheduler.pick(vec![1, 2]), Some(2));
+
+    Ok(())
+}
+
+#[test]
+fn test_ethereum_broadcast_response() {
+    use crate::ethereum::{normalize_response, parse_normalized, BroadcastError};
+
+    let hash = format!("0x{}", "ab".repeat(32));
+    let accepted = format!("{{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":\"{}\"}}", hash);
+    assert_eq!(normalize_response(accepted.as_bytes()), hash.as_bytes().to_vec());
+    assert_eq!(parse_normalized(&normalize_response(accepted.as_bytes())), Ok(vec![0xab; 32]));
+
+    // a "result" inside the error message is not taken for an accepted transaction
+    let rejected = b"{\"jsonrpc\":\"2.0\",\"id\":1,\"error\":{\"code\":-32000,\"message\":\"\\\"result\\\" nonce too low\"}}";
+    assert_eq!(parse_normalized(&normalize_response(rejected)), Err(BroadcastError::Rejected(-32000)));
+
+    assert!(matches!(parse_normalized(&normalize_response(b"<html>bad gateway</html>")), Err(BroadcastError::Ambiguous(_))));
+}
+
+#[test]
+fn test_upgrade_from_baseline() {
+    use ic_kit::candid::decode_one;
+    use governance_core::legacy;
+    use crate::stable::StableMemory;
+
+    // layout stored by the first release, before the state was versioned
+    #[derive(CandidType)]
+    struct Task { target: Principal, method: String, arguments: Vec<u8>, cycles: u64, eta: u64 }
+    #[derive(CandidType)]
+    struct Timelock { delay: u64, queued_transactions: Vec<Task> }
+    #[derive(CandidType)]
+    struct Position { offset: usize, len: usize }
+    #[derive(CandidType)]
+    struct Receipt { vote_type: VoteType, votes: Nat, reason: Option<Position> }
+    #[derive(CandidType)]
+    struct Proposal {
+        id: usize, proposer: Principal, title: String, description: Position, task: Task,
+        start_time: u64, end_time: u64, support_votes: Nat, against_votes: Nat, abstain_votes: Nat,
+        canceled: bool, executing: bool, executed: bool, receipts: Vec<(Principal, Receipt)>,
+    }
+    #[derive(CandidType)]
+    struct GovernorBravo {
+        admin: Principal, pending_admin: Option<Principal>, name: String, quorum_votes: u64,
+        voting_delay: u64, voting_period: u64, proposal_threshold: u64, proposals: Vec<Proposal>,
+        latest_proposal_ids: Vec<(Principal, usize)>, initialized: bool, gov_token: Principal,
+        timelock: Timelock, stable_memory: StableMemory,
+    }
+
+    // descriptions were written without a header
+    let mut stable_memory = StableMemory::default();
+    stable_memory.write(b"description").unwrap();
+    let task = || Task { target: Principal::management_canister(), method: "test".to_string(), arguments: vec![], cycles: 0, eta: 0 };
+    let baseline = GovernorBravo {
+        admin: alice(),
+        pending_admin: None,
+        name: "Test".to_string(),
+        quorum_votes: 100,
+        voting_delay: 10,
+        voting_period: 100,
+        proposal_threshold: 500,
+        proposals: vec![Proposal {
+            id: 0,
+            proposer: alice(),
+            title: "title".to_string(),
+            description: Position { offset: 0, len: 11 },
+            task: task(),
+            start_time: 10,
+            end_time: 110,
+            support_votes: Nat::from(50),
+            against_votes: Nat::from(0),
+            abstain_votes: Nat::from(0),
+            canceled: false,
+            executing: false,
+            executed: false,
+            receipts: vec![(alice(), Receipt { vote_type: VoteType::Support, votes: Nat::from(50), reason: None })],
+        }],
+        latest_proposal_ids: vec![(alice(), 0)],
+        initialized: true,
+        gov_token: Principal::anonymous(),
+        timelock: Timelock { delay: 1000, queued_transactions: vec![] },
+        stable_memory,
+    };
+    let bytes = encode_one(&baseline).unwrap();
+
+    // the unversioned layout is not taken for a versioned state
+    assert!(decode_one::<StableState>(&bytes).is_err());
+    let state = StateV1::from(decode_one::<legacy::GovernorBravo>(&bytes).unwrap());
+    let bravo = &state.bravo;
+    let proposal = bravo.get_proposal(0).unwrap();
+    assert_eq!(proposal.description, "description");
+    assert_eq!(proposal.task.backend, Backend::Canister);
+    // judged by the nat64 quorum of the release it was made under
+    assert_eq!(bravo.get_state(0, 200).unwrap(), ProposalState::Defeated);
+    assert!(bravo.check_proposer(bob(), &Nat::from(500), 200).is_err());
+    assert!(bravo.check_proposer(bob(), &Nat::from(501), 200).is_ok());
+
+    let bytes = encode_one(StableState::V1(state)).unwrap();
+    let state = decode_one::<StableState>(&bytes).unwrap().migrate();
+    assert_eq!(state.bravo.get_proposal(0).unwrap().title, "title");
+}
+
+#[test]
+fn test_account_identifier() {
+    use crate::ethereum::to_hex;
+    use crate::treasury::account_identifier;
+
+    assert_eq!(
+        to_hex(&account_identifier(&Principal::anonymous())),
+        "0x1c7a48ba6a562aa9eaa2481a9049cdf0433b9738c992d698c31d8abf89cadc79",
+    );
+    assert_eq!(
+        to_hex(&account_identifier(&Principal::from_slice(&[1]))),
+        "0x5dc3ba9757ebd7cc99582cd3e7e48e374611c7220cc447601ddc744ecdf86090",
+    );
+}
+
+#[async_test]
+async fn test_treasury_spend() -> Result<(), String> {
+    use governance_types::TxError;
+    use crate::treasury::{self, AssetStandard};
+
+    let ledger = Principal::from_slice(&[9]);
+    let mut assets = Treasury::default();
+    assets.register(RegisteredAsset {
+        ledger,
+        symbol: "CKBTC".to_string(),
+        standard: AssetStandard::Icrc1,
+        fee: Nat::from(10),
+    });
+    let spend = |asset: Asset, amount: Nat| TreasurySpend { asset, to: bob(), amount };
+
+    MockContext::new()
+        .with_handler(Method::new().name("transfer").response(Ok::<Nat, TxError>(Nat::from(7))))
+        .inject();
+    let index = treasury::spend(&assets, Principal::anonymous(), &spend(Asset::GovToken, Nat::from(100))).await?;
+    assert_eq!(index, encode_one(Nat::from(7)).unwrap());
+
+    MockContext::new()
+        .with_handler(Method::new().name("transfer").response(Err::<Nat, TxError>(TxError::InsufficientBalance)))
+        .inject();
+    assert_eq!(treasury::spend(&assets, Principal::anonymous(), &spend(Asset::GovToken, Nat::from(100))).await, Err("DIP20 transfer failed"));
+
+    MockContext::new()
+        .with_handler(Method::new().name("transfer").response(Ok::<u64, String>(3)))
+        .inject();
+    let index = treasury::spend(&assets, Principal::anonymous(), &spend(Asset::Icp, Nat::from(100))).await?;
+    assert_eq!(index, encode_one(Nat::from(3)).unwrap());
+    let too_large = Nat::from(u64::MAX) + Nat::from(1);
+    assert_eq!(treasury::spend(&assets, Principal::anonymous(), &spend(Asset::Icp, too_large)).await, Err("ICP amount too large"));
+
+    MockContext::new()
+        .with_handler(Method::new().name("icrc1_transfer").response(Ok::<Nat, String>(Nat::from(9))))
+        .inject();
+    let index = treasury::spend(&assets, Principal::anonymous(), &spend(Asset::Ledger(ledger), Nat::from(100))).await?;
+    assert_eq!(index, encode_one(Nat::from(9)).unwrap());
+    let unregistered = Asset::Ledger(Principal::from_slice(&[10]));
+    assert!(assets.check(&unregistered).is_err());
+    assert_eq!(treasury::spend(&assets, Principal::anonymous(), &spend(unregistered, Nat::from(100))).await, Err("asset not registered"));
+
+    Ok(())
+}
+
+#[test]
+fn test_cycles_treasury() {
+    let mut cycles = CyclesTreasury::default();
+    cycles.deposit(alice(), 500);
+    assert!(cycles.spend(600).is_err());
+    cycles.spend(200).unwrap();
+    cycles.record_refund(50);
+    assert_eq!(cycles.pool, 350);
+    assert_eq!(cycles.deposit_of(&alice()), 500);
+    let info = cycles.info(7);
+    assert_eq!((info.total_deposited, info.total_spent, info.total_refunded), (500, 200, 50));
+
+    let cooldown = 600 * 1_000_000_000;
+    cycles.set_target(bob(), 10, 2_000_000_000_000);
+    assert!(cycles.due_targets(0).is_empty());
+    assert_eq!(cycles.due_targets(cooldown).len(), 1);
+    // the governor keeps 1T for itself
+    assert!(!cycles.reserve_top_up(bob(), 2_999_999_999_999, cooldown));
+    assert!(cycles.reserve_top_up(bob(), 3_000_000_000_000, cooldown));
+    assert!(cycles.due_targets(cooldown).is_empty());
+    // a failed top up is retried by the next sweep
+    cycles.record_top_up(bob(), 2_000_000_000_000, false);
+    assert_eq!(cycles.due_targets(cooldown).len(), 1);
+    assert!(cycles.reserve_top_up(bob(), 3_000_000_000_000, cooldown));
+    cycles.record_top_up(bob(), 2_000_000_000_000, true);
+    assert_eq!(cycles.info(0).targets[0].total_topped_up, 2_000_000_000_000);
+    assert!(cycles.due_targets(2 * cooldown - 1).is_empty());
+}
+
+#[test]
+fn test_bitcoin_wallet() {
+    use crate::bitcoin::{self, Outpoint, Utxo};
+    use crate::ethereum::to_hex;
+
+    // compressed public key of the secret key 1
+    let text = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    let public_key: Vec<u8> = (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect();
+    assert_eq!(bitcoin::address(BitcoinNetwork::Mainnet, &public_key), "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH");
+    assert_eq!(bitcoin::address(BitcoinNetwork::Testnet, &public_key), "mrCDrCybB6J1vRfbwM5hemdJz73FwDBC8r");
+    assert_eq!(
+        bitcoin::decode_address(BitcoinNetwork::Mainnet, "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH").map(|hash| to_hex(&hash)),
+        Ok("0x751e76e8199196d454941c45d1b3a323f1433bd6".to_string()),
+    );
+    assert!(bitcoin::decode_address(BitcoinNetwork::Testnet, "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH").is_err());
+    assert!(bitcoin::decode_address(BitcoinNetwork::Mainnet, "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMJ").is_err());
+
+    let utxo = |vout: u32, value: u64| Utxo { outpoint: Outpoint { txid: vec![0; 32], vout }, value, height: 1 };
+    let utxos = vec![utxo(0, 5000), utxo(1, 3000), utxo(2, 10000)];
+    let mut wallet = BitcoinWallet::default();
+    // 10 sat/byte over 3 inputs and 2 outputs of 522 bytes
+    let (selected, fee) = wallet.select_utxos(utxos.clone(), 6000, 10).unwrap();
+    assert_eq!((selected.len(), fee), (3, 5220));
+    // outputs pending in a sent transaction are not selected twice
+    assert!(wallet.select_utxos(utxos.clone(), 1000, 10).is_err());
+    wallet.release(&selected);
+    let (selected, fee) = wallet.select_utxos(utxos.clone(), 1000, 10).unwrap();
+    assert_eq!((selected[0].value, selected.len(), fee), (5000, 1, 2260));
+    // pending outputs are forgotten once spent
+    let (selected, _) = wallet.select_utxos(vec![utxo(1, 3000), utxo(2, 10000)], 1000, 10).unwrap();
+    assert_eq!(selected[0].outpoint.vout, 1);
+    assert!(wallet.pending_outpoints.iter().all(|o| o.vout != 0));
+}
+
+#[test]
+fn test_deposit_transfer_fee() {
+    use ic_kit::candid::{decode_args, Deserialize};
+
+    // the fee payer variant of the gov token
+    #[derive(Deserialize, Debug, PartialEq)]
+    enum GovTokenFeePayer { Sender, Recipient, Sponsor(Principal) }
+
+    // a token fee of 10 on a deposit of 100: transferFrom takes 100 from the proposer who pays the fee,
+    // the refund pays out the same 100 with the fee taken from the value received
+    let (fee, amount) = (Nat::from(10), Nat::from(100));
+    let args = encode_args(deposit_transfer_args(bob(), amount.clone())).unwrap();
+    let (to, value, payer): (Principal, Nat, Option<GovTokenFeePayer>) = decode_args(&args).unwrap();
+    assert_eq!((to, value.clone(), payer), (bob(), amount.clone(), Some(GovTokenFeePayer::Recipient)));
+    let governor_paid = value.clone();
+    let recipient_received = value - fee;
+    assert_eq!(governor_paid, amount);
+    assert_eq!(recipient_received, Nat::from(90));
+}
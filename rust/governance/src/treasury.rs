@@ -0,0 +1,270 @@
+/**
+ * Module     : treasury.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use ic_cdk::api::call::CallResult;
+use ic_cdk::call;
+use ic_kit::candid::{encode_one, CandidType, Deserialize, Nat, Reserved};
+use ic_kit::{ic, Principal};
+use governance_types::TxReceipt;
+use sha2::{Digest, Sha224};
+use crate::ethereum::to_hex;
+use crate::stable::crc32;
+use crate::timelock::{Asset, TreasurySpend};
+
+/// ICP ledger canister on the mainnet
+const MAINNET_ICP_LEDGER: &str = "ryjl3-tyaaa-aaaaa-aaaba-cai";
+/// ICP ledger transfer fee in e8s
+const ICP_FEE: u64 = 10_000;
+
+/// interface of a registered ledger
+#[derive(Deserialize, CandidType, Clone, Copy, PartialEq, Debug)]
+pub enum AssetStandard {
+    /// ICP ledger interface, accounts of the default subaccount
+    IcpLedger,
+    /// ICRC-1 ledger
+    Icrc1,
+    /// DIP20 token, like the gov token
+    Dip20,
+}
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub struct RegisteredAsset {
+    pub ledger: Principal,
+    pub symbol: String,
+    pub standard: AssetStandard,
+    /// transfer fee charged by the ledger
+    pub fee: Nat,
+}
+
+#[derive(Deserialize, CandidType, Clone, Debug)]
+pub struct CachedBalance {
+    pub asset: Asset,
+    pub balance: Nat,
+    /// time of the last refresh
+    pub updated_at: u64,
+}
+
+#[derive(Deserialize, CandidType, Clone)]
+pub struct Treasury {
+    /// ICP ledger holding the ICP of the governor
+    pub(crate) icp_ledger: Principal,
+    /// assets registered by proposals, by ledger
+    pub(crate) assets: HashMap<Principal, RegisteredAsset>,
+    /// balances fetched by the last refresh
+    pub(crate) balances: HashMap<Asset, CachedBalance>,
+}
+
+impl Default for Treasury {
+    fn default() -> Self {
+        Self {
+            icp_ledger: Principal::from_text(MAINNET_ICP_LEDGER).unwrap(),
+            assets: HashMap::new(),
+            balances: HashMap::new(),
+        }
+    }
+}
+
+#[derive(CandidType, Clone, Debug)]
+pub struct TreasuryInfo {
+    /// hex account identifier receiving ICP for the treasury
+    pub icp_account: String,
+    pub assets: Vec<RegisteredAsset>,
+    pub balances: Vec<CachedBalance>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Copy, Debug)]
+struct Tokens {
+    e8s: u64,
+}
+
+#[derive(CandidType, Clone, Copy, Debug)]
+struct TimeStamp {
+    timestamp_nanos: u64,
+}
+
+#[derive(CandidType, Clone, Debug)]
+struct TransferArgs {
+    memo: u64,
+    amount: Tokens,
+    fee: Tokens,
+    from_subaccount: Option<Vec<u8>>,
+    to: Vec<u8>,
+    created_at_time: Option<TimeStamp>,
+}
+
+#[derive(CandidType, Clone, Debug)]
+struct AccountBalanceArgs {
+    account: Vec<u8>,
+}
+
+#[derive(CandidType, Clone, Debug)]
+struct Account {
+    owner: Principal,
+    subaccount: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Clone, Debug)]
+struct Icrc1TransferArg {
+    from_subaccount: Option<Vec<u8>>,
+    to: Account,
+    amount: Nat,
+    fee: Option<Nat>,
+    memo: Option<Vec<u8>>,
+    created_at_time: Option<u64>,
+}
+
+/// ICP ledger account identifier of the default subaccount of the principal
+pub(crate) fn account_identifier(owner: &Principal) -> Vec<u8> {
+    let mut hasher = Sha224::new();
+    hasher.update(b"\x0Aaccount-id");
+    hasher.update(owner.as_slice());
+    hasher.update([0u8; 32]);
+    let hash = hasher.finalize();
+    let mut account = crc32(&hash).to_be_bytes().to_vec();
+    account.extend_from_slice(&hash);
+    account
+}
+
+impl Treasury {
+    pub(crate) fn register(&mut self, asset: RegisteredAsset) {
+        self.assets.insert(asset.ledger, asset);
+    }
+
+    pub(crate) fn unregister(&mut self, ledger: Principal) -> Result<(), &'static str> {
+        match self.assets.remove(&ledger) {
+            Some(_) => {
+                self.balances.remove(&Asset::Ledger(ledger));
+                Ok(())
+            }
+            None => { Err("asset not registered") }
+        }
+    }
+
+    /// check the asset can be spent
+    pub(crate) fn check(&self, asset: &Asset) -> Result<(), &'static str> {
+        match asset {
+            Asset::Ledger(ledger) if !self.assets.contains_key(ledger) => { Err("asset not registered") }
+            _ => { Ok(()) }
+        }
+    }
+
+    /// every asset held by the treasury
+    pub(crate) fn all_assets(&self) -> Vec<Asset> {
+        let mut assets = vec![Asset::GovToken, Asset::Icp];
+        assets.extend(self.assets.keys().map(|ledger| Asset::Ledger(*ledger)));
+        assets
+    }
+
+    pub(crate) fn update_balance(&mut self, asset: Asset, balance: Nat, timestamp: u64) {
+        self.balances.insert(asset, CachedBalance {
+            asset,
+            balance,
+            updated_at: timestamp,
+        });
+    }
+
+    pub(crate) fn info(&self) -> TreasuryInfo {
+        TreasuryInfo {
+            icp_account: to_hex(&account_identifier(&ic::id()))[2..].to_string(),
+            assets: self.assets.values().cloned().collect(),
+            balances: self.balances.values().cloned().collect(),
+        }
+    }
+
+    /// ledger and interface of the asset
+    fn ledger(&self, gov_token: Principal, asset: &Asset) -> Result<(Principal, AssetStandard), &'static str> {
+        match asset {
+            Asset::GovToken => { Ok((gov_token, AssetStandard::Dip20)) }
+            Asset::Icp => { Ok((self.icp_ledger, AssetStandard::IcpLedger)) }
+            Asset::Ledger(ledger) => {
+                let registered = self.assets.get(ledger).ok_or("asset not registered")?;
+                Ok((registered.ledger, registered.standard))
+            }
+        }
+    }
+}
+
+/// balance of the governor on the ledger of the asset
+pub(crate) async fn balance(treasury: &Treasury, gov_token: Principal, asset: &Asset) -> Result<Nat, &'static str> {
+    let (ledger, standard) = treasury.ledger(gov_token, asset)?;
+    match standard {
+        AssetStandard::Dip20 => {
+            let result: CallResult<(Nat, )> = call(ledger, "balanceOf", (ic::id(), )).await;
+            result.map(|(balance, )| balance).map_err(|_| "Error in getting DIP20 balance")
+        }
+        AssetStandard::IcpLedger => {
+            let args = AccountBalanceArgs {
+                account: account_identifier(&ic::id()),
+            };
+            let result: CallResult<(Tokens, )> = call(ledger, "account_balance", (args, )).await;
+            result.map(|(tokens, )| Nat::from(tokens.e8s)).map_err(|_| "Error in getting ICP balance")
+        }
+        AssetStandard::Icrc1 => {
+            let account = Account {
+                owner: ic::id(),
+                subaccount: None,
+            };
+            let result: CallResult<(Nat, )> = call(ledger, "icrc1_balance_of", (account, )).await;
+            result.map(|(balance, )| balance).map_err(|_| "Error in getting ICRC-1 balance")
+        }
+    }
+}
+
+/// perform the spend, return the candid encoded transaction index
+pub(crate) async fn spend(treasury: &Treasury, gov_token: Principal, spend: &TreasurySpend) -> Result<Vec<u8>, &'static str> {
+    let (ledger, standard) = treasury.ledger(gov_token, &spend.asset)?;
+    let index = match standard {
+        AssetStandard::Dip20 => {
+            let result: CallResult<(TxReceipt, )> = call(ledger, "transfer", (spend.to, spend.amount.clone(), )).await;
+            match result {
+                Ok((Ok(index), )) => { index }
+                Ok((Err(_), )) => { return Err("DIP20 transfer failed"); }
+                Err(_) => { return Err("DIP20 transfer error"); }
+            }
+        }
+        AssetStandard::IcpLedger => {
+            let e8s = u64::try_from(&spend.amount.0).map_err(|_| "ICP amount too large")?;
+            let args = TransferArgs {
+                memo: 0,
+                amount: Tokens { e8s },
+                fee: Tokens { e8s: ICP_FEE },
+                from_subaccount: None,
+                to: account_identifier(&spend.to),
+                created_at_time: Some(TimeStamp { timestamp_nanos: ic::time() }),
+            };
+            let result: CallResult<(Result<u64, Reserved>, )> = call(ledger, "transfer", (args, )).await;
+            match result {
+                Ok((Ok(block), )) => { Nat::from(block) }
+                Ok((Err(_), )) => { return Err("ICP transfer failed"); }
+                Err(_) => { return Err("ICP transfer error"); }
+            }
+        }
+        AssetStandard::Icrc1 => {
+            let args = Icrc1TransferArg {
+                from_subaccount: None,
+                to: Account {
+                    owner: spend.to,
+                    subaccount: None,
+                },
+                amount: spend.amount.clone(),
+                fee: treasury.assets.get(&ledger).map(|asset| asset.fee.clone()),
+                memo: None,
+                created_at_time: Some(ic::time()),
+            };
+            let result: CallResult<(Result<Nat, Reserved>, )> = call(ledger, "icrc1_transfer", (args, )).await;
+            match result {
+                Ok((Ok(index), )) => { index }
+                Ok((Err(_), )) => { return Err("ICRC-1 transfer failed"); }
+                Err(_) => { return Err("ICRC-1 transfer error"); }
+            }
+        }
+    };
+    encode_one(index).map_err(|_| "Encode error")
+}
@@ -0,0 +1,104 @@
+/**
+ * Module     : upgrade.rs
+ * Copyright  : 2021 Rocklabs
+ * License    : Apache 2.0 with LLVM Exception
+ * Maintainer : Rocklabs <hello@rocklabs.io>
+ * Stability  : Experimental
+ */
+
+//! State kept across upgrades. It is stored as a versioned record next to the
+//! cap archive, a change of its layout adds a version and post_upgrade migrates
+//! the older ones. Fields added to a version must default, so states stored
+//! before they existed still decode.
+
+use cap_sdk::CapEnv;
+use governance_core::legacy;
+use ic_kit::candid::{CandidType, Deserialize};
+use ic_kit::ic::stable_restore;
+use crate::audit::AuditLog;
+use crate::bitcoin::BitcoinWallet;
+use crate::cycles::CyclesTreasury;
+use crate::ethereum::EthereumSigner;
+use crate::governance::GovernorBravo;
+use crate::guardian::Guardian;
+use crate::identity::IdentityVerifier;
+use crate::monitor::Monitor;
+use crate::notification::Notifier;
+use crate::offchain::SignerCommittee;
+use crate::proxy::Proxies;
+use crate::relay::Relayers;
+use crate::scheduler::Scheduler;
+use crate::search::SearchIndex;
+use crate::streams::Streams;
+use crate::treasury::Treasury;
+
+#[derive(Deserialize, CandidType)]
+pub enum StableState {
+    V1(StateV1),
+}
+
+#[derive(Deserialize, CandidType, Default)]
+pub struct StateV1 {
+    pub bravo: GovernorBravo,
+    #[serde(default)]
+    pub ethereum: EthereumSigner,
+    #[serde(default)]
+    pub wallet: BitcoinWallet,
+    #[serde(default)]
+    pub notifier: Notifier,
+    #[serde(default)]
+    pub monitor: Monitor,
+    #[serde(default)]
+    pub treasury: Treasury,
+    #[serde(default)]
+    pub cycles: CyclesTreasury,
+    #[serde(default)]
+    pub streams: Streams,
+    #[serde(default)]
+    pub search: SearchIndex,
+    #[serde(default)]
+    pub identity: IdentityVerifier,
+    #[serde(default)]
+    pub relayers: Relayers,
+    #[serde(default)]
+    pub guardian: Guardian,
+    #[serde(default)]
+    pub offchain: SignerCommittee,
+    #[serde(default)]
+    pub proxies: Proxies,
+    #[serde(default)]
+    pub audit: AuditLog,
+    /// times of the upgrades, oldest first
+    #[serde(default)]
+    pub upgrades: Vec<u64>,
+    #[serde(default)]
+    pub scheduler: Scheduler,
+}
+
+impl StableState {
+    /// the state in the latest layout
+    pub fn migrate(self) -> StateV1 {
+        match self {
+            StableState::V1(state) => state,
+        }
+    }
+}
+
+impl From<legacy::GovernorBravo> for StateV1 {
+    /// the first release stored the governor alone
+    fn from(bravo: legacy::GovernorBravo) -> Self {
+        StateV1 {
+            bravo: bravo.into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// read the state stored by pre_upgrade, falling back to the unversioned layout of the first release
+pub fn restore() -> (StateV1, CapEnv) {
+    if let Ok((state, cap_env, )) = stable_restore::<(StableState, CapEnv, )>() {
+        return (state.migrate(), cap_env);
+    }
+    let (bravo, cap_env, ): (legacy::GovernorBravo, CapEnv, ) = stable_restore().expect("unknown stable state layout");
+    (bravo.into(), cap_env)
+}